@@ -0,0 +1,293 @@
+// Access Control Module
+// Role-based permissions and generation access policy
+//
+// The canister `owner` is always treated as Admin. Other principals are granted
+// roles explicitly. Guard functions below are referenced by name from the
+// `#[ic_cdk::update(guard = "...")]` attributes in lib.rs, so they must keep the
+// `fn() -> Result<(), String>` signature.
+
+use crate::STATE;
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Full control: roles, configuration, contract management
+    Admin,
+    /// Day-to-day operations: job control, disputes, transaction management
+    Operator,
+    /// May register IP and run generation regardless of the generation mode
+    Minter,
+    /// Read-only access to privileged queries (jobs, role holders, usage)
+    Auditor,
+}
+
+/// Who may call the generation endpoints besides Admins and Minters
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GenerationMode {
+    /// Any authenticated (non-anonymous) principal
+    Public,
+    /// Only principals on the generation allowlist
+    #[default]
+    Allowlist,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GenerationAccess {
+    pub mode: GenerationMode,
+    pub allowlist: Vec<Principal>,
+}
+
+/// Persistent role assignments and generation policy
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct AccessControl {
+    /// principal (text) -> roles
+    roles: BTreeMap<String, BTreeSet<Role>>,
+    generation_mode: GenerationMode,
+    generation_allowlist: BTreeSet<Principal>,
+}
+
+impl AccessControl {
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        self.roles
+            .get(&principal.to_text())
+            .is_some_and(|roles| roles.contains(&role))
+    }
+
+    pub fn roles_of(&self, principal: &Principal) -> Vec<Role> {
+        self.roles
+            .get(&principal.to_text())
+            .map(|roles| roles.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn grant(&mut self, principal: Principal, role: Role) -> bool {
+        self.roles
+            .entry(principal.to_text())
+            .or_default()
+            .insert(role)
+    }
+
+    pub fn revoke(&mut self, principal: &Principal, role: Role) -> bool {
+        let key = principal.to_text();
+        let removed = self
+            .roles
+            .get_mut(&key)
+            .is_some_and(|roles| roles.remove(&role));
+
+        if self.roles.get(&key).is_some_and(|roles| roles.is_empty()) {
+            self.roles.remove(&key);
+        }
+
+        removed
+    }
+
+    pub fn holders(&self, role: Role) -> Vec<Principal> {
+        self.roles
+            .iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            .filter_map(|(text, _)| Principal::from_text(text).ok())
+            .collect()
+    }
+
+    pub fn generation_access(&self) -> GenerationAccess {
+        GenerationAccess {
+            mode: self.generation_mode,
+            allowlist: self.generation_allowlist.iter().copied().collect(),
+        }
+    }
+
+    pub fn set_generation_mode(&mut self, mode: GenerationMode) {
+        self.generation_mode = mode;
+    }
+
+    pub fn add_to_allowlist(&mut self, principal: Principal) -> bool {
+        self.generation_allowlist.insert(principal)
+    }
+
+    pub fn remove_from_allowlist(&mut self, principal: &Principal) -> bool {
+        self.generation_allowlist.remove(principal)
+    }
+
+    fn may_generate(&self, principal: &Principal) -> bool {
+        match self.generation_mode {
+            GenerationMode::Public => true,
+            GenerationMode::Allowlist => self.generation_allowlist.contains(principal),
+        }
+    }
+}
+
+// ==============================================================================
+// Permission Checks
+// ==============================================================================
+
+/// True if `principal` is the owner or holds any of `roles`
+pub fn has_any_role(principal: &Principal, roles: &[Role]) -> bool {
+    if *principal == Principal::anonymous() {
+        return false;
+    }
+
+    STATE.with(|state| {
+        let state = state.borrow();
+        state.owner == *principal
+            || roles
+                .iter()
+                .any(|role| state.access.has_role(principal, *role))
+    })
+}
+
+fn require_any_role(roles: &[Role]) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if has_any_role(&caller, roles) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unauthorized: {} requires one of roles {:?}",
+            caller, roles
+        ))
+    }
+}
+
+// ==============================================================================
+// Guard Functions
+// ==============================================================================
+
+/// Only the canister owner
+pub fn guard_owner() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        if state.borrow().owner == caller {
+            Ok(())
+        } else {
+            Err("Unauthorized: Only owner can perform this action".to_string())
+        }
+    })
+}
+
+/// Owner or Admin
+pub fn guard_admin() -> Result<(), String> {
+    require_any_role(&[Role::Admin])
+}
+
+/// Owner, Admin or Operator
+pub fn guard_operator() -> Result<(), String> {
+    require_any_role(&[Role::Admin, Role::Operator])
+}
+
+/// Owner, Admin or Minter
+pub fn guard_minter() -> Result<(), String> {
+    require_any_role(&[Role::Admin, Role::Minter])
+}
+
+/// Owner, Admin, Operator or Auditor
+pub fn guard_auditor() -> Result<(), String> {
+    require_any_role(&[Role::Admin, Role::Operator, Role::Auditor])
+}
+
+/// Owner, Admin, Minter, or anyone admitted by the generation mode
+pub fn guard_generator() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Unauthorized: anonymous callers cannot generate".to_string());
+    }
+
+    if has_any_role(&caller, &[Role::Admin, Role::Minter]) {
+        return Ok(());
+    }
+
+    if STATE.with(|state| state.borrow().access.may_generate(&caller)) {
+        Ok(())
+    } else {
+        Err(format!("Unauthorized: {} is not on the generation allowlist", caller))
+    }
+}
+
+/// Any non-anonymous caller
+pub fn guard_authenticated() -> Result<(), String> {
+    if ic_cdk::caller() == Principal::anonymous() {
+        Err("Unauthorized: anonymous caller".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(byte: u8) -> Principal {
+        Principal::from_slice(&[byte])
+    }
+
+    #[test]
+    fn revoking_the_last_role_drops_the_principal() {
+        let mut access = AccessControl::default();
+        assert!(access.grant(principal(1), Role::Operator));
+        assert!(!access.grant(principal(1), Role::Operator));
+        assert!(access.grant(principal(1), Role::Auditor));
+        assert_eq!(access.roles_of(&principal(1)), vec![Role::Operator, Role::Auditor]);
+
+        assert!(access.revoke(&principal(1), Role::Operator));
+        assert!(!access.revoke(&principal(1), Role::Operator));
+        assert!(access.revoke(&principal(1), Role::Auditor));
+        assert!(access.roles.is_empty());
+        assert!(!access.revoke(&principal(2), Role::Admin));
+    }
+
+    #[test]
+    fn holders_are_listed_per_role() {
+        let mut access = AccessControl::default();
+        access.grant(principal(1), Role::Minter);
+        access.grant(principal(2), Role::Minter);
+        access.grant(principal(2), Role::Admin);
+
+        assert_eq!(access.holders(Role::Minter).len(), 2);
+        assert_eq!(access.holders(Role::Admin), vec![principal(2)]);
+        assert!(access.holders(Role::Auditor).is_empty());
+        assert!(access.has_role(&principal(2), Role::Admin));
+        assert!(!access.has_role(&principal(1), Role::Admin));
+    }
+
+    #[test]
+    fn generation_mode_decides_who_may_generate() {
+        let mut access = AccessControl::default();
+        assert_eq!(access.generation_access().mode, GenerationMode::Allowlist);
+        assert!(!access.may_generate(&principal(1)));
+
+        assert!(access.add_to_allowlist(principal(1)));
+        assert!(access.may_generate(&principal(1)));
+        assert!(!access.may_generate(&principal(2)));
+
+        access.set_generation_mode(GenerationMode::Public);
+        assert!(access.may_generate(&principal(2)));
+
+        access.set_generation_mode(GenerationMode::Allowlist);
+        assert!(access.remove_from_allowlist(&principal(1)));
+        assert!(!access.may_generate(&principal(1)));
+    }
+
+    #[test]
+    fn the_owner_holds_every_role_but_anonymous_none() {
+        let owner = principal(9);
+        STATE.with(|state| state.borrow_mut().owner = owner);
+
+        assert!(has_any_role(&owner, &[Role::Auditor]));
+        assert!(!has_any_role(&principal(1), &[Role::Admin, Role::Auditor]));
+
+        // Even if a role was granted to it
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .access
+                .grant(Principal::anonymous(), Role::Admin)
+        });
+        assert!(!has_any_role(&Principal::anonymous(), &[Role::Admin]));
+    }
+}
@@ -5,15 +5,14 @@
 // as commented code below for reference.
 // See: docs/architecture/MULTI_AI_PROVIDER_DESIGN.md
 
-use primitive_types::H160;
-use std::str::FromStr;
-
 // Uncomment when implementing Phase 6
 // use candid::{CandidType, Deserialize};
 
 // ==============================================================================
 // Story Protocol Configuration (Aeneid Testnet)
 // ==============================================================================
+// These seed the default entry of the runtime network registry (see `networks`).
+// Other networks (Story mainnet, local forks) are added by admins at runtime.
 
 /// Story Protocol Aeneid Testnet RPC URL
 /// Using official Story RPC (proven to work in Phase 2)
@@ -22,10 +21,38 @@ pub const STORY_RPC_URL: &str = "https://aeneid.storyrpc.io";
 /// Story Protocol Aeneid Testnet Chain ID
 pub const STORY_CHAIN_ID: u64 = 1315;
 
+/// Aeneid block explorer
+pub const STORY_EXPLORER_URL: &str = "https://aeneid.storyscan.io";
+
 /// Gas settings for Story Protocol transactions
+/// Only used as a fallback when estimation fails (see `fees`)
 pub const GAS_LIMIT: u64 = 3_000_000;
 pub const GAS_PRICE: u64 = 20_000_000_000; // 20 Gwei
 
+/// Priority fee (tip) used for EIP-1559 transactions when no estimate is available
+pub const MAX_PRIORITY_FEE_PER_GAS: u64 = 2_000_000_000; // 2 Gwei
+
+/// Safety margin applied to `eth_estimateGas` results, in percent (120 = +20%)
+pub const DEFAULT_GAS_LIMIT_MULTIPLIER_PERCENT: u32 = 120;
+
+/// Default upper bounds for estimated gas and fees (admin-adjustable)
+pub const DEFAULT_MAX_GAS_LIMIT: u64 = 5_000_000;
+pub const DEFAULT_MAX_FEE_PER_GAS: u64 = 200_000_000_000; // 200 Gwei
+pub const DEFAULT_MAX_PRIORITY_FEE_PER_GAS: u64 = 10_000_000_000; // 10 Gwei
+
+/// Blocks sampled by `eth_feeHistory` and the priority-fee percentile used
+pub const FEE_HISTORY_BLOCKS: u64 = 10;
+pub const FEE_HISTORY_REWARD_PERCENTILE: u8 = 50;
+
+/// Fee increase applied when replacing a stuck transaction (speed-up / cancel)
+pub const REPLACEMENT_FEE_BUMP_PERCENT: u64 = 25;
+
+/// Minimum increase nodes require to accept a replacement (geth default: 10%)
+pub const MIN_REPLACEMENT_FEE_BUMP_PERCENT: u64 = 10;
+
+/// Gas limit of a plain value transfer (used for cancellations)
+pub const TRANSFER_GAS_LIMIT: u64 = 21_000;
+
 // ==============================================================================
 // Story Protocol Contract Addresses (Aeneid Testnet - Chain ID 1315)
 // ==============================================================================
@@ -33,50 +60,49 @@ pub const GAS_PRICE: u64 = 20_000_000_000; // 20 Gwei
 // https://github.com/storyprotocol/protocol-periphery-v1/blob/main/deploy-out/deployment-1315.json
 // Documentation: https://docs.story.foundation/network/connect/aeneid
 
-/// IPAssetRegistry contract address (DEPRECATED - Use RegistrationWorkflows instead)
-pub fn ip_asset_registry_address() -> H160 {
-    H160::from_str("0x77319B4031e6eF1250907aa00018B8B1c67a244b")
-        .expect("Invalid IPAssetRegistry address")
-}
+/// IPAssetRegistry contract address
+pub const AENEID_IP_ASSET_REGISTRY: &str = "0x77319B4031e6eF1250907aa00018B8B1c67a244b";
 
 /// RegistrationWorkflows contract address (SPG - Story Protocol Gateway)
 /// This is the correct contract for registering IP via mintAndRegisterIp
-#[allow(dead_code)]
-pub fn registration_workflows_address() -> H160 {
-    H160::from_str("0xbe39E1C756e921BD25DF86e7AAa31106d1eb0424")
-        .expect("Invalid RegistrationWorkflows address")
-}
+pub const AENEID_REGISTRATION_WORKFLOWS: &str = "0xbe39E1C756e921BD25DF86e7AAa31106d1eb0424";
 
-/// Public SPG NFT Contract (Aeneid testnet)
+/// Public SPG NFT Contract
 /// This is a public collection provided by Story Protocol for testing
-#[allow(dead_code)]
-pub fn spg_nft_contract_address() -> H160 {
-    H160::from_str("0xc32A8a0FF3beDDDa58393d022aF433e78739FAbc")
-        .expect("Invalid SPG NFT Contract address")
-}
+pub const AENEID_SPG_NFT_CONTRACT: &str = "0xc32A8a0FF3beDDDa58393d022aF433e78739FAbc";
 
 /// LicensingModule contract address
-#[allow(dead_code)]
-pub fn licensing_module_address() -> H160 {
-    H160::from_str("0x04fbd8a2e56dd85CFD5500A4A4DfA955B9f1dE6f")
-        .expect("Invalid LicensingModule address")
-}
+pub const AENEID_LICENSING_MODULE: &str = "0x04fbd8a2e56dd85CFD5500A4A4DfA955B9f1dE6f";
 
 /// RoyaltyModule contract address
-#[allow(dead_code)]
-pub fn royalty_module_address() -> H160 {
-    // Placeholder - replace with actual address
-    H160::from_str("0xD2f60c40fEbccf6311f8B47c4f2Ec6b040400086")
-        .expect("Invalid RoyaltyModule address")
-}
+pub const AENEID_ROYALTY_MODULE: &str = "0xD2f60c40fEbccf6311f8B47c4f2Ec6b040400086";
 
 /// DisputeModule contract address
-#[allow(dead_code)]
-pub fn dispute_module_address() -> H160 {
-    // Placeholder - replace with actual address
-    H160::from_str("0x9b7A9c70AFF961C799110954fc06F3093aeb94C5")
-        .expect("Invalid DisputeModule address")
-}
+pub const AENEID_DISPUTE_MODULE: &str = "0x9b7A9c70AFF961C799110954fc06F3093aeb94C5";
+
+/// AccessController contract address (target of IP Account permission grants)
+pub const AENEID_ACCESS_CONTROLLER: &str = "0xcCF37d0a503Ee1D4C11208672e622ed3DFB2275a";
+
+/// PILicenseTemplate contract address (Programmable IP License terms)
+pub const AENEID_PIL_LICENSE_TEMPLATE: &str = "0x2E896b0b2Fdb7457499B56AAaA4AE55BCB4Cd316";
+
+/// LicenseAttachmentWorkflows contract address (SPG - mint, register and attach terms)
+pub const AENEID_LICENSE_ATTACHMENT_WORKFLOWS: &str = "0xcC2E862bCee5B6036Db0de6E06Ae87e524a79fd8";
+
+/// RoyaltyPolicyLAP contract address (royalty policy of commercial PIL terms)
+pub const AENEID_ROYALTY_POLICY_LAP: &str = "0xBe54FB168b3c982b7AaE60dB6CF75Bd8447b390E";
+
+/// Wrapped IP (WIP) token, the default currency of commercial PIL terms
+pub const AENEID_WIP_TOKEN: &str = "0x1514000000000000000000000000000000000000";
+
+/// DerivativeWorkflows contract address (SPG - mint and register as a derivative)
+pub const AENEID_DERIVATIVE_WORKFLOWS: &str = "0x9e2d496f72C547C2C535B167e06ED8729B374a4f";
+
+/// RoyaltyWorkflows contract address (claims revenue across royalty vaults)
+pub const AENEID_ROYALTY_WORKFLOWS: &str = "0x9515faE61E0c0447C6AC6dEe5628A2097aFE1890";
+
+/// ArbitrationPolicyUMA contract address (judges disputes; collects the bond)
+pub const AENEID_ARBITRATION_POLICY_UMA: &str = "0xfFD98c3877B8789124f02C7E8239A4b0Ef11E936";
 
 // ==============================================================================
 // Parent AI Model Configuration
@@ -85,9 +111,14 @@ pub fn dispute_module_address() -> H160 {
 /// Parent AI Model IP ID on Story Protocol
 /// This must be set after registering the AI Model as an IP Asset
 /// Run: npx ts-node scripts/register_ai_agent.ts
+/// The canister reads it at runtime from `set_parent_ip`, not from here
 #[allow(dead_code)]
 pub const PARENT_AI_MODEL_IP_ID: &str = "REPLACE_AFTER_REGISTRATION";
 
+/// Royalty percentage for parent AI model (10%)
+#[allow(dead_code)]
+pub const ROYALTY_PERCENTAGE: u64 = 10;
+
 // ==============================================================================
 // ICP Configuration
 // ==============================================================================
@@ -96,6 +127,146 @@ pub const PARENT_AI_MODEL_IP_ID: &str = "REPLACE_AFTER_REGISTRATION";
 /// Use "test_key_1" for playground/mainnet, "dfx_test_key" for local only
 pub const ECDSA_KEY_NAME: &str = "test_key_1";
 
+// ==============================================================================
+// Nonce Management
+// ==============================================================================
+
+/// Maximum age of the local nonce state before it is reconciled with the chain
+pub const NONCE_RECONCILE_INTERVAL_SECS: u64 = 60;
+
+/// A broadcast transaction missing from the pending pool for this long is
+/// considered dropped and its nonce reused
+pub const NONCE_DROP_GRACE_SECS: u64 = 180;
+
+/// A reservation never broadcast within this time is released (e.g. the call trapped)
+pub const NONCE_RESERVATION_TIMEOUT_SECS: u64 = 600;
+
+// ==============================================================================
+// Stuck Transaction Replacement
+// ==============================================================================
+
+/// Interval of the monitor that speeds up or cancels stuck transactions
+pub const TX_MONITOR_INTERVAL_SECS: u64 = 120;
+
+/// Default age after which a pending transaction is considered stuck
+pub const DEFAULT_STUCK_TX_AFTER_SECS: u64 = 600;
+
+/// Default number of automatic speed-ups before giving up (or cancelling)
+pub const DEFAULT_MAX_SPEED_UPS: u32 = 3;
+
+// ==============================================================================
+// Receipt Tracking
+// ==============================================================================
+
+/// Interval of the timer that polls receipts of tracked transactions
+pub const TX_RECEIPT_POLL_INTERVAL_SECS: u64 = 30;
+
+/// Default blocks (including the inclusion block) before a transaction is final
+pub const DEFAULT_CONFIRMATION_DEPTH: u64 = 2;
+
+/// Default time without a receipt before tracking gives up
+pub const DEFAULT_TX_TRACKING_TIMEOUT_SECS: u64 = 3_600;
+
+// ==============================================================================
+// EVM RPC Canister
+// ==============================================================================
+
+/// DFINITY EVM RPC canister (same ID on mainnet and in local dfx, see dfx.json)
+pub const EVM_RPC_CANISTER_ID: &str = "7hfb6-caaaa-aaaar-qadga-cai";
+
+/// Cycles attached per provider to an EVM RPC canister call (unused cycles are
+/// refunded)
+pub const DEFAULT_EVM_RPC_CYCLES_PER_PROVIDER: u64 = 10_000_000_000;
+
+// ==============================================================================
+// Orchestration Jobs
+// ==============================================================================
+
+/// Maximum attempts per pipeline step before a job is marked Failed
+pub const JOB_MAX_STEP_ATTEMPTS: u32 = 5;
+
+/// Base delay for exponential retry backoff (doubles per attempt)
+pub const JOB_RETRY_BASE_DELAY_SECS: u64 = 30;
+
+/// Upper bound for the retry backoff delay
+pub const JOB_RETRY_MAX_DELAY_SECS: u64 = 600;
+
+/// Interval of the sweeper that resumes overdue or interrupted jobs
+pub const JOB_SWEEP_INTERVAL_SECS: u64 = 300;
+
+// ==============================================================================
+// Quotas
+// ==============================================================================
+
+/// Estimated cycles per generation: 2T-cycle `http_post` call (Constellation),
+/// the 1 MB `make_http_request` outcalls (AI, nonce reconciliation, fee
+/// estimation, broadcast) and ECDSA signing, with headroom
+pub const ESTIMATED_GENERATION_CYCLES: u64 = 4_100_000_000_000;
+
+/// Estimated cycles of an endpoint making outcalls for its caller: a few 1 MB
+/// `make_http_request` reads or an ECDSA public key derivation, with headroom
+pub const ESTIMATED_OUTCALL_CYCLES: u64 = 100_000_000_000;
+
+/// Default charged calls (generations and outcall endpoints) allowed per window
+pub const DEFAULT_QUOTA_CALLS_PER_WINDOW: u32 = 10;
+
+/// Default rate-limit window (1 hour)
+pub const DEFAULT_QUOTA_WINDOW_SECS: u64 = 3_600;
+
+/// Default daily cycles budget per principal (~12 generations)
+pub const DEFAULT_QUOTA_DAILY_CYCLES: u64 = 50_000_000_000_000;
+
+/// Default daily gas budget per principal (10 x GAS_LIMIT)
+pub const DEFAULT_QUOTA_DAILY_GAS: u64 = 30_000_000;
+
+// ==============================================================================
+// Idempotency
+// ==============================================================================
+
+/// Default retention window for idempotency keys (24 hours)
+pub const DEFAULT_IDEMPOTENCY_RETENTION_SECS: u64 = 86_400;
+
+// ==============================================================================
+// Wallet Linking
+// ==============================================================================
+
+/// Validity of a wallet link challenge (10 minutes)
+pub const WALLET_CHALLENGE_TTL_SECS: u64 = 600;
+
+// ==============================================================================
+// Funds Monitoring
+// ==============================================================================
+
+/// Interval between balance samples (cycles and gas balances)
+pub const FUNDS_SAMPLE_INTERVAL_SECS: u64 = 600;
+
+/// Age after which the latest gas balance sample is too old to admit a job
+/// on (the balance is read live instead)
+pub const FUNDS_SAMPLE_MAX_AGE_SECS: u64 = 2 * FUNDS_SAMPLE_INTERVAL_SECS;
+
+/// Samples kept in the balance history (~1 week at the default interval)
+pub const BALANCE_HISTORY_LIMIT: usize = 1_000;
+
+/// Default cycles kept in reserve: new jobs must leave at least this much
+pub const DEFAULT_MIN_CYCLES_BALANCE: u64 = 1_000_000_000_000;
+
+/// Default gas balance (wei) a signing EVM address keeps in reserve
+pub const DEFAULT_MIN_GAS_BALANCE_WEI: u128 = 0;
+
+// ==============================================================================
+// License Tokens
+// ==============================================================================
+
+/// Maximum license tokens minted per `mint_license_tokens` call
+pub const MAX_LICENSE_TOKENS_PER_MINT: u64 = 100;
+
+// ==============================================================================
+// Disputes
+// ==============================================================================
+
+/// UMA liveness of a raised dispute (seconds during which it can be countered)
+pub const DISPUTE_LIVENESS_SECONDS: u64 = 30 * 24 * 60 * 60; // 30 days
+
 // ==============================================================================
 // ckBTC Configuration (Testnet)
 // ==============================================================================
@@ -112,10 +283,6 @@ pub const CKBTC_MINTER_CANISTER_ID: &str = "mqygn-kiaaa-aaaar-qaadq-cai";
 #[allow(dead_code)]
 pub const REQUIRED_PAYMENT_SATOSHIS: u64 = 100_000; // 0.001 ckBTC
 
-/// Royalty percentage for parent AI model (10%)
-#[allow(dead_code)]
-pub const ROYALTY_PERCENTAGE: u64 = 10;
-
 // ==============================================================================
 // Phase 6: Multi-AI Provider Configuration
 // ==============================================================================
@@ -0,0 +1,282 @@
+// Derivatives Module
+// Links generated works to the parent AI model IP as derivatives
+//
+// Each network needs a parent IP (the AI model's IP Asset) and the ID of PIL
+// terms attached to it before it accepts generations; admins set it with
+// `set_parent_ip`, there is no built-in default. New works are registered through
+// DerivativeWorkflows, which mints a license of those terms and makes the
+// work a derivative in the same transaction; existing IP Assets are linked
+// with `LicensingModule.registerDerivative`. The terms' royalty policy (LAP
+// for commercial terms) makes the derivative pay the parent's royalty vault,
+// from which the model owner claims.
+
+use crate::evm_util::{self, EvmAccount};
+use crate::licensing;
+use crate::networks::Network;
+use crate::transactions::{self, TxKind};
+use crate::STATE;
+use candid::{CandidType, Deserialize};
+use ethabi::{encode, Address, Token};
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// `maxRts`: royalty tokens the derivative may hand to its parents (all of them)
+const MAX_ROYALTY_TOKENS: u32 = 100_000_000;
+
+/// WorkflowStructs.MakeDerivative struct as an ABI tuple type
+const MAKE_DERIVATIVE_TUPLE: &str = "(address[],address,uint256[],bytes,uint256,uint32,uint32)";
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// Parent IP that generated works on a network derive from
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ParentIp {
+    /// IP Asset ID of the AI model (EIP-55)
+    pub ip_id: String,
+    /// PIL terms attached to the parent that derivatives are licensed under
+    pub license_terms_id: u64,
+    /// Highest minting fee paid for the parent's license (0 = no limit)
+    pub max_minting_fee: u64,
+    /// Highest revenue share of the parent's terms accepted, in percent
+    pub max_revenue_share_percent: u32,
+}
+
+impl ParentIp {
+    /// Validate and normalize the IP ID to its checksummed form
+    fn normalize(mut self) -> Result<Self, String> {
+        let ip_id = evm_util::parse_eip55_address(&self.ip_id)?;
+        self.ip_id = evm_util::to_checksum_address(&ip_id);
+
+        if self.license_terms_id == 0 {
+            return Err("License terms ID must be set (IDs start at 1)".to_string());
+        }
+        licensing::revenue_share(self.max_revenue_share_percent)?;
+        Ok(self)
+    }
+
+    fn address(&self) -> Result<H160, String> {
+        Ok(H160::from(evm_util::parse_eip55_address(&self.ip_id)?))
+    }
+}
+
+/// Parent IP per chain ID
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ParentIpRegistry {
+    parents: BTreeMap<u64, ParentIp>,
+}
+
+impl ParentIpRegistry {
+    pub fn get(&self, chain_id: u64) -> Option<ParentIp> {
+        self.parents.get(&chain_id).cloned()
+    }
+
+    /// Set (or with None, clear) the parent of a network
+    pub fn set(&mut self, chain_id: u64, parent: Option<ParentIp>) -> Result<(), String> {
+        match parent {
+            Some(parent) => {
+                self.parents.insert(chain_id, parent.normalize()?);
+            }
+            None => {
+                self.parents.remove(&chain_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parent IP of a network, if generated works there are derivatives
+pub fn parent_for(chain_id: u64) -> Option<ParentIp> {
+    STATE.with(|state| state.borrow().derivatives.get(chain_id))
+}
+
+// ==============================================================================
+// ABI Encoding
+// ==============================================================================
+
+/// `maxMintingFee`, `maxRts` and `maxRevenueShare` limits of `parent`
+fn limits(parent: &ParentIp) -> Result<[Token; 3], String> {
+    Ok([
+        Token::Uint(U256::from(parent.max_minting_fee)),
+        Token::Uint(U256::from(MAX_ROYALTY_TOKENS)),
+        Token::Uint(U256::from(licensing::revenue_share(
+            parent.max_revenue_share_percent,
+        )?)),
+    ])
+}
+
+/// WorkflowStructs.MakeDerivative for a single parent
+fn make_derivative(network: &Network, parent: &ParentIp) -> Result<Token, String> {
+    let mut fields = vec![
+        Token::Array(vec![Token::Address(parent.address()?)]),
+        Token::Address(network.pil_license_template()?),
+        Token::Array(vec![Token::Uint(U256::from(parent.license_terms_id))]),
+        Token::Bytes(vec![]), // royaltyContext
+    ];
+    fields.extend(limits(parent)?);
+    Ok(Token::Tuple(fields))
+}
+
+/// Build calldata for DerivativeWorkflows.mintAndRegisterIpAndMakeDerivative()
+///
+/// Function signature: mintAndRegisterIpAndMakeDerivative(address spgNftContract,
+/// MakeDerivative derivData, IPMetadata ipMetadata, address recipient,
+/// bool allowDuplicates)
+///
+/// # Arguments
+/// * `network` - Network to register on (SPG collection and license template)
+/// * `parent` - Parent IP and its license terms
+/// * `ip_metadata` - `IPMetadata` tuple (see `story_util::ip_metadata`)
+/// * `recipient` - Owner of the NFT and IP Account
+pub fn build_mint_and_register_ip_and_make_derivative_calldata(
+    network: &Network,
+    parent: &ParentIp,
+    ip_metadata: Token,
+    recipient: [u8; 20],
+) -> Result<Vec<u8>, String> {
+    let signature = format!(
+        "mintAndRegisterIpAndMakeDerivative(address,{},(string,bytes32,string,bytes32),address,bool)",
+        MAKE_DERIVATIVE_TUPLE
+    );
+    let mut calldata = evm_util::function_selector(&signature).to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(network.spg_nft_contract()?),
+        make_derivative(network, parent)?,
+        ip_metadata,
+        Token::Address(Address::from(recipient)),
+        // The content hash already makes the metadata unique
+        Token::Bool(true),
+    ]));
+    Ok(calldata)
+}
+
+/// Build calldata for LicensingModule.registerDerivative(address,address[],
+/// uint256[],address,bytes,uint256,uint32,uint32)
+pub fn build_register_derivative_calldata(
+    network: &Network,
+    child_ip_id: H160,
+    parent: &ParentIp,
+) -> Result<Vec<u8>, String> {
+    let mut calldata = evm_util::function_selector(
+        "registerDerivative(address,address[],uint256[],address,bytes,uint256,uint32,uint32)",
+    )
+    .to_vec();
+
+    let mut tokens = vec![
+        Token::Address(child_ip_id),
+        Token::Array(vec![Token::Address(parent.address()?)]),
+        Token::Array(vec![Token::Uint(U256::from(parent.license_terms_id))]),
+        Token::Address(network.pil_license_template()?),
+        Token::Bytes(vec![]), // royaltyContext
+    ];
+    tokens.extend(limits(parent)?);
+    calldata.extend_from_slice(&encode(&tokens));
+    Ok(calldata)
+}
+
+// ==============================================================================
+// Derivative Registration
+// ==============================================================================
+
+/// Make an existing IP Asset a derivative of the network's parent IP
+///
+/// The IP Asset must not have license terms of its own yet; Story rejects
+/// the call otherwise (see the simulated revert reason).
+///
+/// # Arguments
+/// * `network` - Network the IP Asset lives on
+/// * `account` - Account owning the IP Asset's NFT (signs the call)
+/// * `child_ip_id` - IP Asset to link
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+pub async fn register_derivative(
+    network: &Network,
+    account: EvmAccount,
+    child_ip_id: H160,
+) -> Result<String, String> {
+    let parent = parent_for(network.chain_id)
+        .ok_or_else(|| format!("No parent IP configured on {}", network.name))?;
+    let calldata = build_register_derivative_calldata(network, child_ip_id, &parent)?;
+
+    ic_cdk::println!(
+        "   🧬 Registering 0x{} as a derivative of {} (terms #{})",
+        hex::encode(child_ip_id.to_fixed_bytes()),
+        parent.ip_id,
+        parent.license_terms_id
+    );
+    let tx_hash = transactions::send(
+        network,
+        account,
+        TxKind::RegisterDerivative,
+        Some(network.licensing_module()?.to_fixed_bytes()),
+        calldata,
+        0, // No value transfer
+    )
+    .await?;
+
+    ic_cdk::println!("   ✅ Derivative registered: {}", network.tx_url(&tx_hash));
+    Ok(tx_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks;
+    use ethabi::param_type::Reader;
+
+    fn parent() -> ParentIp {
+        ParentIp {
+            ip_id: "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".to_string(),
+            license_terms_id: 7,
+            max_minting_fee: 0,
+            max_revenue_share_percent: 10,
+        }
+    }
+
+    #[test]
+    fn parents_are_checksummed_and_validated() {
+        let mut registry = ParentIpRegistry::default();
+        assert!(registry.get(1315).is_none());
+
+        registry.set(1315, Some(parent())).unwrap();
+        assert_eq!(
+            registry.get(1315).unwrap().ip_id,
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        );
+
+        let unset_terms = ParentIp {
+            license_terms_id: 0,
+            ..parent()
+        };
+        assert!(registry.set(1315, Some(unset_terms)).is_err());
+
+        registry.set(1315, None).unwrap();
+        assert!(registry.get(1315).is_none());
+    }
+
+    #[test]
+    fn make_derivative_encodes_as_the_workflow_tuple() {
+        let token = make_derivative(&networks::aeneid(), &parent()).unwrap();
+        let encoded = encode(std::slice::from_ref(&token));
+
+        let layout = Reader::read(MAKE_DERIVATIVE_TUPLE).unwrap();
+        let decoded = ethabi::decode(&[layout], &encoded).unwrap();
+        assert_eq!(decoded, vec![token]);
+    }
+
+    #[test]
+    fn register_derivative_selector_and_limits() {
+        let calldata =
+            build_register_derivative_calldata(&networks::aeneid(), H160::zero(), &parent())
+                .unwrap();
+        assert_eq!(hex::encode(&calldata[..4]), "4a4d56c5");
+
+        // maxRevenueShare is the eighth head word
+        let word = &calldata[4 + 7 * 32..4 + 8 * 32];
+        assert_eq!(U256::from_big_endian(word), U256::from(10_000_000u64));
+    }
+}
@@ -0,0 +1,683 @@
+// Disputes Module
+// Raises disputes against IP Assets through Story's DisputeModule and follows
+// them until they are settled
+//
+// A dispute names the target IP, a whitelisted tag (the infringement claimed)
+// and the evidence, referenced on-chain by the sha2-256 digest of its IPFS
+// CID. Disputes are judged by the ArbitrationPolicyUMA: the canister's address
+// posts the bond (approving the policy for it first) and the dispute stays
+// open for the UMA liveness. Once judged, the initiator may resolve an upheld
+// dispute; an open one can be cancelled where the policy allows it.
+
+use crate::erc20::{self, Approval};
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::Network;
+use crate::storage::{self, StableMap};
+use crate::transactions::{self, TxKind};
+use crate::{config, events, rpc_util, tracker, STATE};
+use candid::{CandidType, Deserialize, Principal};
+use ethabi::{encode, ParamType, Token};
+use primitive_types::{H160, U256};
+use serde::Serialize;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Multihash prefix of a sha2-256 digest (code 0x12, length 32)
+const SHA2_256_MULTIHASH: [u8; 2] = [0x12, 0x20];
+/// CIDv1 prefix of a dag-pb node (version 1, codec 0x70)
+const CID_V1_DAG_PB: [u8; 2] = [0x01, 0x70];
+
+/// Tag of a dispute under judgment
+const IN_DISPUTE: &str = "IN_DISPUTE";
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// Infringement claimed by a dispute (tags whitelisted by the DisputeModule)
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisputeTag {
+    ImproperRegistration,
+    ImproperUsage,
+    ImproperPayment,
+    ContentStandardsViolation,
+}
+
+impl DisputeTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisputeTag::ImproperRegistration => "IMPROPER_REGISTRATION",
+            DisputeTag::ImproperUsage => "IMPROPER_USAGE",
+            DisputeTag::ImproperPayment => "IMPROPER_PAYMENT",
+            DisputeTag::ContentStandardsViolation => "CONTENT_STANDARDS_VIOLATION",
+        }
+    }
+
+    pub fn to_bytes32(self) -> [u8; 32] {
+        tag_bytes32(self.name())
+    }
+}
+
+/// Tag name as bytes32: UTF-8, right-padded with zeros
+fn tag_bytes32(name: &str) -> [u8; 32] {
+    let mut tag = [0u8; 32];
+    tag[..name.len()].copy_from_slice(name.as_bytes());
+    tag
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DisputeStatus {
+    /// `raiseDispute` sent; the dispute ID is known once it is mined
+    #[default]
+    Submitted,
+    /// Raised and waiting for judgment
+    Raised,
+    /// Judged in favor of the initiator: the target IP carries the tag
+    Upheld,
+    /// Judged against the initiator (the bond is lost)
+    Rejected,
+    Cancelled,
+    /// An upheld dispute whose tag was removed by `resolveDispute`
+    Resolved,
+}
+
+/// Dispute raised by the canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DisputeRecord {
+    /// DisputeModule ID (None until `raiseDispute` is mined)
+    pub dispute_id: Option<u64>,
+    pub chain_id: u64,
+    /// EIP-55 IP Asset disputed
+    pub target_ip_id: String,
+    pub tag: DisputeTag,
+    pub evidence_cid: String,
+    /// `disputeEvidenceHash`: sha2-256 digest of the CID (0x-prefixed hex)
+    pub evidence_hash: String,
+    /// Bond posted with the dispute, in the smallest unit of `currency`
+    pub bond: u64,
+    /// EIP-55 bond token (WIP)
+    pub currency: String,
+    pub liveness_seconds: u64,
+    pub status: DisputeStatus,
+    /// Hash of `raiseDispute` (the mined one once confirmed)
+    pub tx_hash: String,
+    /// Hash of the latest `cancelDispute` / `resolveDispute`
+    pub settlement_tx_hash: Option<String>,
+    /// Principal that raised the dispute
+    pub raised_by: Principal,
+    /// IC time (nanoseconds)
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+storage::impl_json_storable!(DisputeRecord);
+
+/// Disputes raised by the canister (records live in stable memory, the
+/// sequence counter in the snapshot)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct DisputeStore {
+    next_seq: u64,
+}
+
+thread_local! {
+    /// sequence number -> dispute, oldest first
+    static DISPUTES: StableMap<u64, DisputeRecord> =
+        storage::stable_map(storage::DISPUTES_MEMORY_ID);
+}
+
+impl DisputeStore {
+    pub fn record(&mut self, dispute: DisputeRecord) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        DISPUTES.with(|disputes| disputes.borrow_mut().insert(seq, dispute));
+    }
+
+    pub fn get(&self, chain_id: u64, dispute_id: u64) -> Option<DisputeRecord> {
+        find(|d| d.chain_id == chain_id && d.dispute_id == Some(dispute_id))
+            .map(|(_, dispute)| dispute)
+    }
+
+    /// Disputes against `ip_id`, including those not mined yet
+    pub fn by_ip(&self, ip_id: &str) -> Vec<DisputeRecord> {
+        DISPUTES.with(|disputes| {
+            disputes
+                .borrow()
+                .values()
+                .filter(|d| d.target_ip_id.eq_ignore_ascii_case(ip_id))
+                .collect()
+        })
+    }
+
+    fn update(
+        &mut self,
+        chain_id: u64,
+        dispute_id: u64,
+        apply: impl FnOnce(&mut DisputeRecord),
+    ) -> Option<DisputeRecord> {
+        let (seq, mut dispute) =
+            find(|d| d.chain_id == chain_id && d.dispute_id == Some(dispute_id))?;
+        apply(&mut dispute);
+        dispute.updated_at = ic_cdk::api::time();
+        DISPUTES.with(|disputes| disputes.borrow_mut().insert(seq, dispute.clone()));
+        Some(dispute)
+    }
+
+    /// Set the ID of the dispute raised by one of `tx_hashes` (the original
+    /// and its replacements)
+    ///
+    /// # Returns
+    /// * `Option<u64>` - The dispute ID, if a dispute references the hashes
+    pub fn apply_raised(
+        &mut self,
+        tx_hashes: &[String],
+        mined_tx_hash: &str,
+        dispute_id: u64,
+    ) -> Option<u64> {
+        let (seq, mut dispute) = find(|d| tx_hashes.contains(&d.tx_hash.to_lowercase()))?;
+
+        dispute.dispute_id = Some(dispute_id);
+        dispute.tx_hash = mined_tx_hash.to_string();
+        if dispute.status == DisputeStatus::Submitted {
+            dispute.status = DisputeStatus::Raised;
+        }
+        dispute.updated_at = ic_cdk::api::time();
+        DISPUTES.with(|disputes| disputes.borrow_mut().insert(seq, dispute));
+        Some(dispute_id)
+    }
+
+    /// Mark the dispute settled by one of `tx_hashes` as `status`
+    pub fn apply_settled(
+        &mut self,
+        tx_hashes: &[String],
+        mined_tx_hash: &str,
+        status: DisputeStatus,
+    ) -> Option<u64> {
+        let (seq, mut dispute) = find(|d| {
+            d.settlement_tx_hash
+                .as_ref()
+                .is_some_and(|hash| tx_hashes.contains(&hash.to_lowercase()))
+        })?;
+
+        dispute.settlement_tx_hash = Some(mined_tx_hash.to_string());
+        dispute.status = status;
+        dispute.updated_at = ic_cdk::api::time();
+        let dispute_id = dispute.dispute_id;
+        DISPUTES.with(|disputes| disputes.borrow_mut().insert(seq, dispute));
+        dispute_id
+    }
+}
+
+/// First dispute (oldest first) matching `predicate`, with its sequence number
+fn find(predicate: impl Fn(&DisputeRecord) -> bool) -> Option<(u64, DisputeRecord)> {
+    DISPUTES.with(|disputes| {
+        disputes
+            .borrow()
+            .iter()
+            .find(|(_, dispute)| predicate(dispute))
+    })
+}
+
+// ==============================================================================
+// Evidence Hash
+// ==============================================================================
+
+fn decode_base58(input: &str) -> Result<Vec<u8>, String> {
+    // Big-endian base-256 digits, least significant last
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in input.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| format!("Invalid base58 character '{}'", c as char))?
+            as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, (carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    // Each leading '1' encodes a leading zero byte
+    let zeros = input.bytes().take_while(|c| *c == b'1').count();
+    let mut decoded = vec![0u8; zeros];
+    decoded.extend(bytes);
+    Ok(decoded)
+}
+
+/// RFC 4648 base32, lowercase and unpadded (multibase prefix removed)
+fn decode_base32(input: &str) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(input.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in input.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| format!("Invalid base32 character '{}'", c as char))?
+            as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(decoded)
+}
+
+/// `disputeEvidenceHash` of an IPFS CID: the sha2-256 digest it addresses
+///
+/// Accepts CIDv0 (`Qm...`) and base32 CIDv1 of dag-pb content (`bafy...`),
+/// optionally as an `ipfs://` URI, and yields the same hash for both forms of
+/// a CID, like the Story SDK.
+///
+/// # Returns
+/// * `Result<[u8; 32], String>` - Digest or why the CID is not supported
+pub fn evidence_hash(cid: &str) -> Result<[u8; 32], String> {
+    let cid = cid.trim().trim_start_matches("ipfs://");
+
+    let multihash = if cid.starts_with("Qm") {
+        decode_base58(cid)?
+    } else if let Some(encoded) = cid.strip_prefix('b') {
+        let bytes = decode_base32(encoded)?;
+        bytes
+            .strip_prefix(CID_V1_DAG_PB.as_slice())
+            .ok_or_else(|| format!("CID {} is not a dag-pb CIDv1", cid))?
+            .to_vec()
+    } else {
+        return Err(format!(
+            "Unsupported CID {} (expected CIDv0 'Qm...' or base32 CIDv1 'b...')",
+            cid
+        ));
+    };
+
+    multihash
+        .strip_prefix(SHA2_256_MULTIHASH.as_slice())
+        .and_then(|digest| <[u8; 32]>::try_from(digest).ok())
+        .ok_or_else(|| format!("CID {} does not address a sha2-256 digest", cid))
+}
+
+// ==============================================================================
+// ABI Encoding
+// ==============================================================================
+
+/// Build calldata for DisputeModule.raiseDispute(address,bytes32,bytes32,bytes)
+///
+/// `data` is the ArbitrationPolicyUMA's `abi.encode(uint64 liveness, address
+/// currency, uint256 bond)`.
+pub fn build_raise_dispute_calldata(
+    target_ip_id: H160,
+    evidence_hash: [u8; 32],
+    tag: DisputeTag,
+    liveness_seconds: u64,
+    currency: H160,
+    bond: u64,
+) -> Vec<u8> {
+    let policy_data = encode(&[
+        Token::Uint(U256::from(liveness_seconds)),
+        Token::Address(currency),
+        Token::Uint(U256::from(bond)),
+    ]);
+
+    let mut calldata =
+        evm_util::function_selector("raiseDispute(address,bytes32,bytes32,bytes)").to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(target_ip_id),
+        Token::FixedBytes(evidence_hash.to_vec()),
+        Token::FixedBytes(tag.to_bytes32().to_vec()),
+        Token::Bytes(policy_data),
+    ]));
+    calldata
+}
+
+/// Build calldata for DisputeModule.cancelDispute(uint256,bytes) or
+/// resolveDispute(uint256,bytes), without arbitration data
+pub fn build_settle_dispute_calldata(kind: TxKind, dispute_id: u64) -> Result<Vec<u8>, String> {
+    let signature = match kind {
+        TxKind::CancelDispute => "cancelDispute(uint256,bytes)",
+        TxKind::ResolveDispute => "resolveDispute(uint256,bytes)",
+        other => return Err(format!("{:?} does not settle a dispute", other)),
+    };
+
+    let mut calldata = evm_util::function_selector(signature).to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Uint(U256::from(dispute_id)),
+        Token::Bytes(vec![]),
+    ]));
+    Ok(calldata)
+}
+
+// ==============================================================================
+// Dispute Lifecycle
+// ==============================================================================
+
+/// Raise a dispute against an IP Asset from the canister's address
+///
+/// The ArbitrationPolicyUMA is approved for the bond first if needed; when
+/// that approval is not mined right away, nothing is raised and the approval
+/// hash is returned in the error.
+///
+/// # Arguments
+/// * `network` - Network of the target IP
+/// * `target_ip_id` - IP Asset disputed
+/// * `evidence_cid` - IPFS CID of the evidence
+/// * `tag` - Infringement claimed
+/// * `bond` - WIP posted with the dispute (smallest unit)
+///
+/// # Returns
+/// * `Result<DisputeRecord, String>` - The recorded dispute; its ID is set
+///   now if the transaction is already mined, else by the receipt tracker
+pub async fn raise_dispute(
+    network: &Network,
+    target_ip_id: &str,
+    evidence_cid: &str,
+    tag: DisputeTag,
+    bond: u64,
+) -> Result<DisputeRecord, String> {
+    let target = H160::from(evm_util::parse_eip55_address(target_ip_id)?);
+    let evidence_hash = evidence_hash(evidence_cid)?;
+    let currency = network.wip_token()?;
+    let dispute_module = network.dispute_module()?;
+
+    if bond > 0 {
+        let approval = erc20::ensure_allowance(
+            network,
+            EvmAccount::Canister,
+            currency,
+            network.arbitration_policy_uma()?,
+            bond,
+        )
+        .await?;
+        if let Approval::Pending(tx_hash) = approval {
+            return Err(format!(
+                "Bond approval {} is pending; raise the dispute again once it is mined",
+                tx_hash
+            ));
+        }
+    }
+
+    ic_cdk::println!(
+        "   🚨 Raising {} dispute against {} (evidence 0x{}, bond {})",
+        tag.name(),
+        target_ip_id,
+        hex::encode(evidence_hash),
+        bond
+    );
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::RaiseDispute,
+        Some(dispute_module.to_fixed_bytes()),
+        build_raise_dispute_calldata(
+            target,
+            evidence_hash,
+            tag,
+            config::DISPUTE_LIVENESS_SECONDS,
+            currency,
+            bond,
+        ),
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ Dispute sent: {}", network.tx_url(&tx_hash));
+
+    let now = ic_cdk::api::time();
+    let mut dispute = DisputeRecord {
+        dispute_id: None,
+        chain_id: network.chain_id,
+        target_ip_id: evm_util::to_checksum_address(&target.to_fixed_bytes()),
+        tag,
+        evidence_cid: evidence_cid.to_string(),
+        evidence_hash: format!("0x{}", hex::encode(evidence_hash)),
+        bond,
+        currency: evm_util::to_checksum_address(&currency.to_fixed_bytes()),
+        liveness_seconds: config::DISPUTE_LIVENESS_SECONDS,
+        status: DisputeStatus::Submitted,
+        tx_hash: tx_hash.clone(),
+        settlement_tx_hash: None,
+        raised_by: ic_cdk::caller(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    // The ID comes from the receipt now or from the receipt tracker later
+    match tracker::fetch_receipt(network.chain_id, &tx_hash).await {
+        Ok(Some(receipt)) => {
+            dispute.dispute_id = events::find_dispute_raised(&receipt, dispute_module);
+            if dispute.dispute_id.is_some() {
+                dispute.status = DisputeStatus::Raised;
+            }
+        }
+        Ok(None) => ic_cdk::println!("   ℹ️  Dispute pending; the receipt tracker will follow it"),
+        Err(e) => ic_cdk::println!("   ⚠️  Could not get receipt: {}", e),
+    }
+
+    STATE.with(|state| state.borrow_mut().disputes.record(dispute.clone()));
+    Ok(dispute)
+}
+
+/// Cancel (`TxKind::CancelDispute`) or resolve (`TxKind::ResolveDispute`) a
+/// dispute the canister raised
+///
+/// The status changes once the receipt tracker confirms the transaction.
+///
+/// # Returns
+/// * `Result<DisputeRecord, String>` - The dispute with its settlement hash
+pub async fn settle_dispute(
+    network: &Network,
+    dispute_id: u64,
+    kind: TxKind,
+) -> Result<DisputeRecord, String> {
+    let dispute = STATE
+        .with(|state| state.borrow().disputes.get(network.chain_id, dispute_id))
+        .ok_or_else(|| format!("Dispute #{} was not raised by the canister", dispute_id))?;
+    if matches!(
+        dispute.status,
+        DisputeStatus::Cancelled | DisputeStatus::Resolved
+    ) {
+        return Err(format!(
+            "Dispute #{} is already {:?}",
+            dispute_id, dispute.status
+        ));
+    }
+    let calldata = build_settle_dispute_calldata(kind, dispute_id)?;
+
+    ic_cdk::println!("   ⚖️  {:?} #{} on {}", kind, dispute_id, network.name);
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        kind,
+        Some(network.dispute_module()?.to_fixed_bytes()),
+        calldata,
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ Sent: {}", network.tx_url(&tx_hash));
+
+    STATE
+        .with(|state| {
+            state
+                .borrow_mut()
+                .disputes
+                .update(network.chain_id, dispute_id, |dispute| {
+                    dispute.settlement_tx_hash = Some(tx_hash)
+                })
+        })
+        .ok_or_else(|| format!("Dispute #{} disappeared", dispute_id))
+}
+
+/// Refresh a dispute's status from its current tag on the DisputeModule
+///
+/// Judgment happens outside the canister (UMA), so this is how an upheld or
+/// rejected dispute is noticed.
+pub async fn refresh_dispute(network: &Network, dispute_id: u64) -> Result<DisputeRecord, String> {
+    let dispute = STATE
+        .with(|state| state.borrow().disputes.get(network.chain_id, dispute_id))
+        .ok_or_else(|| format!("Dispute #{} was not raised by the canister", dispute_id))?;
+
+    let mut calldata = evm_util::function_selector("disputes(uint256)").to_vec();
+    calldata.extend_from_slice(&encode(&[Token::Uint(U256::from(dispute_id))]));
+    let data = rpc_util::eth_call(network.chain_id, network.dispute_module()?, &calldata).await?;
+
+    // (targetIpId, disputeInitiator, disputeTimestamp, arbitrationPolicy,
+    //  disputeEvidenceHash, targetTag, currentTag, infringerDisputeId)
+    let tokens = ethabi::decode(
+        &[
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Address,
+            ParamType::FixedBytes(32),
+            ParamType::FixedBytes(32),
+            ParamType::FixedBytes(32),
+            ParamType::Uint(256),
+        ],
+        &data,
+    )
+    .map_err(|e| format!("Failed to decode dispute #{}: {}", dispute_id, e))?;
+    let current_tag = match tokens.get(6) {
+        Some(Token::FixedBytes(tag)) => tag.clone(),
+        other => return Err(format!("Unexpected currentTag: {:?}", other)),
+    };
+
+    let status = if current_tag == tag_bytes32(IN_DISPUTE) {
+        DisputeStatus::Raised
+    } else if current_tag == dispute.tag.to_bytes32() {
+        DisputeStatus::Upheld
+    } else if current_tag.iter().all(|b| *b == 0) {
+        // Cleared: by our own cancel / resolve, or by a judgment against us
+        match dispute.status {
+            DisputeStatus::Cancelled | DisputeStatus::Resolved => dispute.status,
+            // Our cancel / resolve is mined; the tracker confirms it
+            _ if dispute.settlement_tx_hash.is_some() => dispute.status,
+            _ => DisputeStatus::Rejected,
+        }
+    } else {
+        return Err(format!(
+            "Dispute #{} has unexpected tag 0x{}",
+            dispute_id,
+            hex::encode(&current_tag)
+        ));
+    };
+
+    if status != dispute.status {
+        ic_cdk::println!(
+            "   ⚖️  Dispute #{}: {:?} -> {:?}",
+            dispute_id,
+            dispute.status,
+            status
+        );
+    }
+    STATE
+        .with(|state| {
+            state
+                .borrow_mut()
+                .disputes
+                .update(network.chain_id, dispute_id, |dispute| {
+                    dispute.status = status
+                })
+        })
+        .ok_or_else(|| format!("Dispute #{} disappeared", dispute_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CID_V0: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    const CID_V1: &str = "bafybeie5nqv6kd3qnfjupgvz34woh3oksc3iau6abmyajn7qvtf6d2ho34";
+
+    #[test]
+    fn evidence_hash_is_the_cid_digest() {
+        let expected = "9d6c2be50f706953479ab9df2ce3edca90b68053c00b3004b7f0accbe1e8eedf";
+        let v0 = evidence_hash(CID_V0).unwrap();
+
+        assert_eq!(hex::encode(v0), expected);
+        assert_eq!(evidence_hash(CID_V1).unwrap(), v0);
+        assert_eq!(evidence_hash(&format!("ipfs://{}", CID_V0)).unwrap(), v0);
+    }
+
+    #[test]
+    fn unsupported_cids_are_rejected() {
+        assert!(evidence_hash("QmInvalid0").is_err());
+        assert!(evidence_hash("zdj7WWeQ43G6JJvLWQWZpyHuAMq6uYWRjkBXFad11vE2LHhQ7").is_err());
+        // Valid base32, but not a dag-pb CIDv1
+        assert!(evidence_hash("bafkreie5nqv6kd3qnfjupgvz34woh3oksc3iau6abmyajn7qvtf6d2ho34").is_err());
+    }
+
+    #[test]
+    fn tags_are_right_padded() {
+        let name = DisputeTag::ImproperRegistration.name();
+        let tag = DisputeTag::ImproperRegistration.to_bytes32();
+
+        assert!(hex::encode(tag).starts_with("494d50524f5045525f"));
+        assert!(tag[name.len()..].iter().all(|b| *b == 0));
+        assert_eq!(tag_bytes32(IN_DISPUTE)[..IN_DISPUTE.len()], *IN_DISPUTE.as_bytes());
+    }
+
+    #[test]
+    fn raise_dispute_calldata_carries_the_policy_data() {
+        let target = H160::repeat_byte(0xaa);
+        let currency = H160::repeat_byte(0x15);
+        let digest = evidence_hash(CID_V0).unwrap();
+        let calldata = build_raise_dispute_calldata(
+            target,
+            digest,
+            DisputeTag::ImproperUsage,
+            2_592_000,
+            currency,
+            1_000_000,
+        );
+        assert_eq!(hex::encode(&calldata[..4]), "6a5def7f");
+
+        let tokens = ethabi::decode(
+            &[
+                ParamType::Address,
+                ParamType::FixedBytes(32),
+                ParamType::FixedBytes(32),
+                ParamType::Bytes,
+            ],
+            &calldata[4..],
+        )
+        .unwrap();
+        assert_eq!(tokens[0], Token::Address(target));
+        assert_eq!(tokens[1], Token::FixedBytes(digest.to_vec()));
+        assert_eq!(
+            tokens[2],
+            Token::FixedBytes(DisputeTag::ImproperUsage.to_bytes32().to_vec())
+        );
+
+        let policy_data = tokens[3].clone().into_bytes().unwrap();
+        let policy = ethabi::decode(
+            &[ParamType::Uint(64), ParamType::Address, ParamType::Uint(256)],
+            &policy_data,
+        )
+        .unwrap();
+        assert_eq!(
+            policy,
+            vec![
+                Token::Uint(U256::from(2_592_000u64)),
+                Token::Address(currency),
+                Token::Uint(U256::from(1_000_000u64)),
+            ]
+        );
+    }
+
+    #[test]
+    fn settle_calldata_only_differs_by_selector() {
+        let cancel = build_settle_dispute_calldata(TxKind::CancelDispute, 7).unwrap();
+        let resolve = build_settle_dispute_calldata(TxKind::ResolveDispute, 7).unwrap();
+
+        assert_eq!(hex::encode(&cancel[..4]), "c844825d");
+        assert_eq!(hex::encode(&resolve[..4]), "4471fabc");
+        assert_eq!(cancel[4..], resolve[4..]);
+        assert_eq!(U256::from_big_endian(&cancel[4..36]), U256::from(7));
+        assert!(build_settle_dispute_calldata(TxKind::RaiseDispute, 7).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+// EIP-712 Module
+// Typed structured data hashing and Chain-Key ECDSA signing
+//
+// Story's "WithSignature" flows accept `SignatureData` from the owner of an IP
+// Account authorizing a call made on its behalf: an `ExecuteWithSig` message
+// under the IP Account's EIP-712 domain, bound to the account's current state
+// (its nonce). The canister signs these for IP Accounts held by its own
+// addresses, and relays signatures made by other owners.
+
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::Network;
+use crate::{rpc_util, story_util};
+use candid::{CandidType, Deserialize};
+use ethabi::{encode, Address, Token};
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+
+/// EIP-712 domain name and version of Story IP Accounts
+pub const IP_ACCOUNT_DOMAIN_NAME: &str = "Story Protocol IP Account";
+pub const IP_ACCOUNT_DOMAIN_VERSION: &str = "1";
+
+const EIP712_DOMAIN_TYPE: &str =
+    "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+const EXECUTE_WITH_SIG_TYPE: &str =
+    "ExecuteWithSig(address to,uint256 value,bytes data,bytes32 nonce,uint256 deadline)";
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// Story `SignatureData`: who signed, until when it is valid, and the signature
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SignatureData {
+    pub signer: String,
+    /// Unix time (seconds) after which the signature is rejected
+    pub deadline: u64,
+    /// 65 bytes r ‖ s ‖ v (0x-prefixed hex)
+    pub signature: String,
+}
+
+/// Permission grant the canister signs for an IP Account it holds
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PermissionRequest {
+    /// IP Account granting the permission
+    pub ip_id: String,
+    /// Address receiving the permission (e.g. a relayer or workflow contract)
+    pub signer: String,
+    /// Module the permission applies to; None = all modules
+    pub to: Option<String>,
+    /// 4-byte function selector (0x-prefixed hex); None = all functions
+    pub func: Option<String>,
+    /// 0 = abstain, 1 = allow, 2 = deny
+    pub permission: u8,
+    /// Validity of the signature from now
+    pub valid_for_secs: u64,
+}
+
+/// Signed grant: submit `executeWithSig(to, 0, data, signer, deadline, signature)`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SignedPermission {
+    pub signature: SignatureData,
+    /// AccessController address
+    pub to: String,
+    /// setPermission calldata (0x-prefixed hex)
+    pub data: String,
+}
+
+pub struct Eip712Domain {
+    pub name: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub verifying_contract: H160,
+}
+
+// ==============================================================================
+// Hashing
+// ==============================================================================
+
+fn keccak(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+impl Eip712Domain {
+    /// Domain of the IP Account at `ip_account`
+    pub fn ip_account(chain_id: u64, ip_account: H160) -> Self {
+        Self {
+            name: IP_ACCOUNT_DOMAIN_NAME.to_string(),
+            version: IP_ACCOUNT_DOMAIN_VERSION.to_string(),
+            chain_id,
+            verifying_contract: ip_account,
+        }
+    }
+
+    pub fn separator(&self) -> [u8; 32] {
+        keccak(&encode(&[
+            Token::FixedBytes(keccak(EIP712_DOMAIN_TYPE.as_bytes()).to_vec()),
+            Token::FixedBytes(keccak(self.name.as_bytes()).to_vec()),
+            Token::FixedBytes(keccak(self.version.as_bytes()).to_vec()),
+            Token::Uint(U256::from(self.chain_id)),
+            Token::Address(Address::from(self.verifying_contract.to_fixed_bytes())),
+        ]))
+    }
+}
+
+/// Digest to sign: keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))
+pub fn typed_data_digest(domain: &Eip712Domain, struct_hash: [u8; 32]) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(66);
+    preimage.extend_from_slice(&[0x19, 0x01]);
+    preimage.extend_from_slice(&domain.separator());
+    preimage.extend_from_slice(&struct_hash);
+    keccak(&preimage)
+}
+
+/// hashStruct of an `ExecuteWithSig` message (dynamic `bytes` are hashed)
+pub fn execute_with_sig_hash(
+    to: H160,
+    value: U256,
+    data: &[u8],
+    nonce: [u8; 32],
+    deadline: u64,
+) -> [u8; 32] {
+    keccak(&encode(&[
+        Token::FixedBytes(keccak(EXECUTE_WITH_SIG_TYPE.as_bytes()).to_vec()),
+        Token::Address(Address::from(to.to_fixed_bytes())),
+        Token::Uint(value),
+        Token::FixedBytes(keccak(data).to_vec()),
+        Token::FixedBytes(nonce.to_vec()),
+        Token::Uint(U256::from(deadline)),
+    ]))
+}
+
+// ==============================================================================
+// IP Account Nonce
+// ==============================================================================
+
+/// Current `state()` of an IP Account (zero if it is not deployed yet)
+pub async fn ip_account_state(network: &Network, ip_account: H160) -> Result<[u8; 32], String> {
+    let result = rpc_util::call(
+        network.chain_id,
+        "eth_call",
+        json!([
+            {
+                "to": format!("0x{}", hex::encode(ip_account.to_fixed_bytes())),
+                "data": format!("0x{}", hex::encode(evm_util::function_selector("state()"))),
+            },
+            "latest"
+        ]),
+    )
+    .await?;
+
+    let hex_str = result
+        .as_str()
+        .ok_or_else(|| format!("Unexpected state() result: {}", result))?
+        .trim_start_matches("0x");
+    if hex_str.is_empty() {
+        return Ok([0u8; 32]);
+    }
+
+    hex::decode(hex_str)
+        .map_err(|e| format!("Invalid state() result: {}", e))?
+        .try_into()
+        .map_err(|_| format!("Unexpected state() length: 0x{}", hex_str))
+}
+
+/// Nonce an `ExecuteWithSig` must carry: the state the IP Account moves to
+/// when it executes the call, keccak256(abi.encode(state, execute calldata))
+pub fn next_ip_account_nonce(state: [u8; 32], to: H160, value: U256, data: &[u8]) -> [u8; 32] {
+    let mut execute_call = evm_util::function_selector("execute(address,uint256,bytes)").to_vec();
+    execute_call.extend_from_slice(&encode(&[
+        Token::Address(Address::from(to.to_fixed_bytes())),
+        Token::Uint(value),
+        Token::Bytes(data.to_vec()),
+    ]));
+
+    keccak(&encode(&[
+        Token::FixedBytes(state.to_vec()),
+        Token::Bytes(execute_call),
+    ]))
+}
+
+// ==============================================================================
+// Signing
+// ==============================================================================
+
+/// Sign an EIP-712 digest with the key of `account`
+///
+/// # Returns
+/// * `Result<Vec<u8>, String>` - 65 bytes r ‖ s ‖ v with v = 27 + recovery ID
+pub async fn sign_digest(digest: [u8; 32], account: &EvmAccount) -> Result<Vec<u8>, String> {
+    let mut signature = evm_util::sign_evm_transaction(digest.to_vec(), account).await?;
+    if signature.len() != 64 {
+        return Err(format!(
+            "Invalid signature length: {} (expected 64)",
+            signature.len()
+        ));
+    }
+
+    let public_key = evm_util::get_public_key(account).await?;
+    let recovery_id =
+        story_util::determine_recovery_id_with_pubkey(&digest, &signature, &public_key)?;
+
+    signature.push(27 + recovery_id);
+    Ok(signature)
+}
+
+/// Authorize `ip_account` to call `to` with `data` on behalf of its owner
+///
+/// `account` must hold the IP Account (own its NFT); the signature is bound to
+/// the account's current state, so it is consumed by the next execution.
+///
+/// # Arguments
+/// * `network` - Network the IP Account lives on
+/// * `account` - Owner of the IP Account
+/// * `ip_account` - IP Account (IP ID)
+/// * `to` - Contract the IP Account calls (e.g. the AccessController)
+/// * `data` - Calldata of that call
+/// * `deadline` - Unix time (seconds) the signature expires
+///
+/// # Returns
+/// * `Result<SignatureData, String>` - Signature for `executeWithSig` or a workflow
+pub async fn sign_execute_with_sig(
+    network: &Network,
+    account: &EvmAccount,
+    ip_account: H160,
+    to: H160,
+    data: &[u8],
+    deadline: u64,
+) -> Result<SignatureData, String> {
+    let signer = evm_util::get_evm_address(account).await?;
+
+    let state = ip_account_state(network, ip_account).await?;
+    let nonce = next_ip_account_nonce(state, to, U256::zero(), data);
+    let struct_hash = execute_with_sig_hash(to, U256::zero(), data, nonce, deadline);
+    let digest = typed_data_digest(
+        &Eip712Domain::ip_account(network.chain_id, ip_account),
+        struct_hash,
+    );
+
+    ic_cdk::println!(
+        "   ✍️  EIP-712 ExecuteWithSig for 0x{} (nonce 0x{}, deadline {})",
+        hex::encode(ip_account.to_fixed_bytes()),
+        hex::encode(nonce),
+        deadline
+    );
+
+    let signature = sign_digest(digest, account).await?;
+
+    Ok(SignatureData {
+        signer,
+        deadline,
+        signature: format!("0x{}", hex::encode(signature)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domain_separator_matches_the_eip_example() {
+        assert_eq!(
+            hex::encode(keccak(EIP712_DOMAIN_TYPE.as_bytes())),
+            "8b73c3c69bb8fe3d512ecc4cf759cc79239f7b179b0ffacaa9a75d522b39400f"
+        );
+
+        let domain = Eip712Domain {
+            name: "Ether Mail".to_string(),
+            version: "1".to_string(),
+            chain_id: 1,
+            verifying_contract: H160::repeat_byte(0xcc),
+        };
+        assert_eq!(
+            hex::encode(domain.separator()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+    }
+
+    #[test]
+    fn next_nonce_depends_on_state_and_call() {
+        let to = H160::repeat_byte(0x11);
+        let nonce = next_ip_account_nonce([0u8; 32], to, U256::zero(), b"call");
+
+        assert_ne!(nonce, next_ip_account_nonce([1u8; 32], to, U256::zero(), b"call"));
+        assert_ne!(nonce, next_ip_account_nonce([0u8; 32], to, U256::zero(), b"other"));
+        assert_eq!(nonce, next_ip_account_nonce([0u8; 32], to, U256::zero(), b"call"));
+    }
+
+    #[test]
+    fn function_selectors() {
+        assert_eq!(evm_util::function_selector("ownerOf(uint256)"), [0x63, 0x52, 0x21, 0x1e]);
+        assert_eq!(
+            evm_util::function_selector("safeTransferFrom(address,address,uint256)"),
+            [0x42, 0x84, 0x2e, 0x0e]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+// ERC-20 Module
+// Token allowances for Story fees paid from the canister's accounts
+//
+// Story modules pull license minting fees and royalty payments with
+// `transferFrom`, so the paying account must first approve the module that
+// collects them (the RoyaltyModule). Approvals are exact: the canister never
+// leaves an open-ended allowance behind.
+
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::Network;
+use crate::transactions::{self, TxKind};
+use crate::{rpc_util, tracker};
+use ethabi::{encode, ParamType, Token};
+use primitive_types::{H160, U256};
+
+// ==============================================================================
+// Reads
+// ==============================================================================
+
+/// `allowance(owner, spender)` of `token`
+pub async fn allowance(
+    network: &Network,
+    token: H160,
+    owner: H160,
+    spender: H160,
+) -> Result<u64, String> {
+    let mut calldata = evm_util::function_selector("allowance(address,address)").to_vec();
+    calldata.extend_from_slice(&encode(&[Token::Address(owner), Token::Address(spender)]));
+
+    let data = rpc_util::eth_call(network.chain_id, token, &calldata).await?;
+    // Saturate: an allowance above u64::MAX covers any fee we can express
+    match ethabi::decode(&[ParamType::Uint(256)], &data) {
+        Ok(tokens) => match tokens.first() {
+            Some(Token::Uint(value)) if value.bits() > 64 => Ok(u64::MAX),
+            Some(Token::Uint(value)) => Ok(value.low_u64()),
+            _ => Err(format!(
+                "Unexpected allowance result: 0x{}",
+                hex::encode(&data)
+            )),
+        },
+        Err(e) => Err(format!("Failed to decode allowance: {}", e)),
+    }
+}
+
+// ==============================================================================
+// Approvals
+// ==============================================================================
+
+/// Build calldata for ERC-20 approve(address,uint256)
+pub fn build_approve_calldata(spender: H160, amount: u64) -> Vec<u8> {
+    let mut calldata = evm_util::function_selector("approve(address,uint256)").to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(spender),
+        Token::Uint(U256::from(amount)),
+    ]));
+    calldata
+}
+
+/// Outcome of `ensure_allowance`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Approval {
+    /// The allowance already covers the amount
+    Sufficient,
+    /// An approval was sent and mined
+    Approved(String),
+    /// An approval was sent but is not mined yet; spending must wait for it
+    Pending(String),
+}
+
+/// Make sure `spender` may pull `amount` of `token` from `account`
+///
+/// Sends `approve(spender, amount)` if the current allowance is lower and
+/// checks once for its receipt, since the spending transaction would be
+/// simulated (and revert) before the approval is mined.
+///
+/// # Arguments
+/// * `network` - Network of the token
+/// * `account` - Paying account (signs the approval)
+/// * `token` - ERC-20 token contract
+/// * `spender` - Contract that will call `transferFrom`
+/// * `amount` - Amount in the token's smallest unit
+///
+/// # Returns
+/// * `Result<Approval, String>` - Whether spending can proceed now
+pub async fn ensure_allowance(
+    network: &Network,
+    account: EvmAccount,
+    token: H160,
+    spender: H160,
+    amount: u64,
+) -> Result<Approval, String> {
+    let owner = evm_util::get_evm_address(&account).await?;
+    let owner = H160::from(evm_util::parse_eip55_address(&owner)?);
+
+    let current = allowance(network, token, owner, spender).await?;
+    if current >= amount {
+        return Ok(Approval::Sufficient);
+    }
+
+    ic_cdk::println!(
+        "   🔓 Approving {} of token 0x{} for 0x{} (allowance {})",
+        amount,
+        hex::encode(token.to_fixed_bytes()),
+        hex::encode(spender.to_fixed_bytes()),
+        current
+    );
+    let tx_hash = transactions::send(
+        network,
+        account,
+        TxKind::ApproveToken,
+        Some(token.to_fixed_bytes()),
+        build_approve_calldata(spender, amount),
+        0, // No value transfer
+    )
+    .await?;
+
+    match tracker::fetch_receipt(network.chain_id, &tx_hash).await {
+        Ok(Some(receipt)) if receipt.success => Ok(Approval::Approved(tx_hash)),
+        Ok(Some(_)) => Err(format!("Approval {} reverted", tx_hash)),
+        Ok(None) => Ok(Approval::Pending(tx_hash)),
+        Err(e) => {
+            ic_cdk::println!("   ⚠️  Could not get approval receipt: {}", e);
+            Ok(Approval::Pending(tx_hash))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approve_calldata() {
+        let calldata = build_approve_calldata(H160::repeat_byte(0x22), 1_000);
+        assert_eq!(hex::encode(&calldata[..4]), "095ea7b3");
+        assert_eq!(
+            ethabi::decode(&[ParamType::Address, ParamType::Uint(256)], &calldata[4..]).unwrap(),
+            vec![
+                Token::Address(H160::repeat_byte(0x22)),
+                Token::Uint(U256::from(1_000u64))
+            ]
+        );
+    }
+}
@@ -0,0 +1,595 @@
+// Event Decoding Module
+// Typed decoding of the receipt logs the canister relies on
+//
+// A log is decoded only if it was emitted by the contract the caller expects
+// and its topic0 is the keccak256 hash of one of the event signatures below.
+// Indexed parameters are read from the topics and the rest is ABI-decoded from
+// the data with `ethabi`. Logs that do not match are skipped, so an unrelated
+// event in the same receipt is never mistaken for one of ours.
+
+use crate::transactions::{TxLog, TxReceipt};
+use ethabi::{ParamType, Token};
+use primitive_types::{H160, U256};
+use sha3::{Digest, Keccak256};
+
+/// IPAssetRegistry: `ipId` is not indexed, topic1 is the chain ID
+const IP_REGISTERED: &str = "IPRegistered(address,uint256,address,uint256,string,string,uint256)";
+/// ERC-721, and ERC-20 with three topics (the value is in the data)
+const TRANSFER: &str = "Transfer(address,address,uint256)";
+/// SimpleNFT (see `nft_deployment`)
+const NFT_MINTED: &str = "NFTMinted(address,uint256,string)";
+/// LicensingModule
+const LICENSE_TERMS_ATTACHED: &str = "LicenseTermsAttached(address,address,address,uint256)";
+/// LicensingModule: topics are caller, licensorIpId and licenseTermsId
+const LICENSE_TOKENS_MINTED: &str =
+    "LicenseTokensMinted(address,address,address,uint256,uint256,address,uint256)";
+/// PILicenseTemplate (the terms themselves are left encoded)
+const LICENSE_TERMS_REGISTERED: &str = "LicenseTermsRegistered(uint256,address,bytes)";
+/// DisputeModule: nothing is indexed
+const DISPUTE_RAISED: &str =
+    "DisputeRaised(uint256,address,address,uint256,address,bytes32,bytes32,bytes)";
+
+// ==============================================================================
+// Typed Events
+// ==============================================================================
+
+/// Addresses are lowercase 0x-prefixed hex, like the logs they come from
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpRegistered {
+    pub ip_id: String,
+    pub chain_id: u64,
+    pub token_contract: String,
+    pub token_id: u64,
+    pub name: String,
+    pub uri: String,
+    pub registration_date: u64,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub token_id: u64,
+}
+
+/// ERC-20 Transfer; the value is in the token's smallest unit, saturating at
+/// u64::MAX
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Erc20Transfer {
+    pub from: String,
+    pub to: String,
+    pub value: u64,
+}
+
+impl Transfer {
+    pub fn is_mint(&self) -> bool {
+        self.from == format!("0x{}", "0".repeat(40))
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftMinted {
+    pub to: String,
+    pub token_id: u64,
+    pub content_hash: String,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LicenseTermsAttached {
+    pub caller: String,
+    pub ip_id: String,
+    pub license_template: String,
+    pub license_terms_id: u64,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LicenseTermsRegistered {
+    pub license_terms_id: u64,
+    pub license_template: String,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LicenseTokensMinted {
+    pub caller: String,
+    pub licensor_ip_id: String,
+    pub license_template: String,
+    pub license_terms_id: u64,
+    pub amount: u64,
+    pub receiver: String,
+    pub start_license_token_id: u64,
+}
+
+impl LicenseTokensMinted {
+    /// IDs of the minted license tokens (consecutive from the start ID)
+    pub fn token_ids(&self) -> Vec<u64> {
+        (0..self.amount)
+            .map(|offset| self.start_license_token_id.saturating_add(offset))
+            .collect()
+    }
+}
+
+/// Hashes and tags are 0x-prefixed 32-byte hex
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisputeRaised {
+    pub dispute_id: u64,
+    pub target_ip_id: String,
+    pub dispute_initiator: String,
+    pub dispute_timestamp: u64,
+    pub arbitration_policy: String,
+    pub dispute_evidence_hash: String,
+    pub target_tag: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    IpRegistered(IpRegistered),
+    Transfer(Transfer),
+    Erc20Transfer(Erc20Transfer),
+    NftMinted(NftMinted),
+    LicenseTermsAttached(LicenseTermsAttached),
+    LicenseTermsRegistered(LicenseTermsRegistered),
+    LicenseTokensMinted(LicenseTokensMinted),
+    DisputeRaised(DisputeRaised),
+}
+
+// ==============================================================================
+// Topic and Data Decoding
+// ==============================================================================
+
+/// topic0 of an event: keccak256 of its canonical signature
+pub fn topic0(signature: &str) -> String {
+    format!("0x{}", hex::encode(Keccak256::digest(signature.as_bytes())))
+}
+
+fn address_hex(address: H160) -> String {
+    format!("0x{}", hex::encode(address.to_fixed_bytes()))
+}
+
+fn topic_word(topic: &str) -> Result<[u8; 32], String> {
+    hex::decode(topic.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid topic '{}': {}", topic, e))?
+        .try_into()
+        .map_err(|_| format!("Invalid topic length: {}", topic))
+}
+
+/// Indexed `address` parameter
+fn topic_address(topic: &str) -> Result<String, String> {
+    let word = topic_word(topic)?;
+    if word[..12].iter().any(|b| *b != 0) {
+        return Err(format!("Topic is not an address: {}", topic));
+    }
+    Ok(format!("0x{}", hex::encode(&word[12..])))
+}
+
+fn to_u64(value: U256, what: &str) -> Result<u64, String> {
+    if value.bits() > 64 {
+        return Err(format!("{} {} does not fit in 64 bits", what, value));
+    }
+    Ok(value.low_u64())
+}
+
+/// Indexed `uint256` parameter
+fn topic_u64(topic: &str, what: &str) -> Result<u64, String> {
+    to_u64(U256::from_big_endian(&topic_word(topic)?), what)
+}
+
+/// ABI-decode the non-indexed parameters of `log`
+fn data_tokens(log: &TxLog, types: &[ParamType]) -> Result<Vec<Token>, String> {
+    let data = hex::decode(log.data.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid log data: {}", e))?;
+    ethabi::decode(types, &data).map_err(|e| format!("Failed to decode log data: {}", e))
+}
+
+fn token_address(token: &Token) -> Result<String, String> {
+    match token {
+        Token::Address(address) => Ok(format!("0x{}", hex::encode(address.as_bytes()))),
+        other => Err(format!("Expected address, got {:?}", other)),
+    }
+}
+
+fn token_u64(token: &Token, what: &str) -> Result<u64, String> {
+    match token {
+        Token::Uint(value) => to_u64(*value, what),
+        other => Err(format!("Expected uint256, got {:?}", other)),
+    }
+}
+
+fn token_string(token: &Token) -> Result<String, String> {
+    match token {
+        Token::String(value) => Ok(value.clone()),
+        other => Err(format!("Expected string, got {:?}", other)),
+    }
+}
+
+fn token_bytes32(token: &Token) -> Result<String, String> {
+    match token {
+        Token::FixedBytes(bytes) if bytes.len() == 32 => Ok(format!("0x{}", hex::encode(bytes))),
+        other => Err(format!("Expected bytes32, got {:?}", other)),
+    }
+}
+
+fn expect_topics(log: &TxLog, count: usize) -> Result<(), String> {
+    if log.topics.len() != count {
+        return Err(format!(
+            "Expected {} topics, got {}",
+            count,
+            log.topics.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Decode one log by its topic0
+///
+/// # Returns
+/// * `Result<Option<Event>, String>` - None for events this module does not
+///   know, an error if a known event is malformed
+pub fn decode_log(log: &TxLog) -> Result<Option<Event>, String> {
+    let Some(signature) = log.topics.first() else {
+        return Ok(None);
+    };
+
+    let event = if *signature == topic0(IP_REGISTERED) {
+        expect_topics(log, 4)?;
+        let data = data_tokens(
+            log,
+            &[
+                ParamType::Address,
+                ParamType::String,
+                ParamType::String,
+                ParamType::Uint(256),
+            ],
+        )?;
+        Event::IpRegistered(IpRegistered {
+            ip_id: token_address(&data[0])?,
+            chain_id: topic_u64(&log.topics[1], "chainId")?,
+            token_contract: topic_address(&log.topics[2])?,
+            token_id: topic_u64(&log.topics[3], "tokenId")?,
+            name: token_string(&data[1])?,
+            uri: token_string(&data[2])?,
+            registration_date: token_u64(&data[3], "registrationDate")?,
+        })
+    } else if *signature == topic0(TRANSFER) && log.topics.len() == 3 {
+        let value = match data_tokens(log, &[ParamType::Uint(256)])?.first() {
+            Some(Token::Uint(value)) if value.bits() > 64 => u64::MAX,
+            Some(token) => token_u64(token, "value")?,
+            None => return Err("Missing Transfer value".to_string()),
+        };
+        Event::Erc20Transfer(Erc20Transfer {
+            from: topic_address(&log.topics[1])?,
+            to: topic_address(&log.topics[2])?,
+            value,
+        })
+    } else if *signature == topic0(TRANSFER) {
+        expect_topics(log, 4)?;
+        Event::Transfer(Transfer {
+            from: topic_address(&log.topics[1])?,
+            to: topic_address(&log.topics[2])?,
+            token_id: topic_u64(&log.topics[3], "tokenId")?,
+        })
+    } else if *signature == topic0(NFT_MINTED) {
+        expect_topics(log, 3)?;
+        let data = data_tokens(log, &[ParamType::String])?;
+        Event::NftMinted(NftMinted {
+            to: topic_address(&log.topics[1])?,
+            token_id: topic_u64(&log.topics[2], "tokenId")?,
+            content_hash: token_string(&data[0])?,
+        })
+    } else if *signature == topic0(LICENSE_TERMS_ATTACHED) {
+        expect_topics(log, 3)?;
+        let data = data_tokens(log, &[ParamType::Address, ParamType::Uint(256)])?;
+        Event::LicenseTermsAttached(LicenseTermsAttached {
+            caller: topic_address(&log.topics[1])?,
+            ip_id: topic_address(&log.topics[2])?,
+            license_template: token_address(&data[0])?,
+            license_terms_id: token_u64(&data[1], "licenseTermsId")?,
+        })
+    } else if *signature == topic0(LICENSE_TERMS_REGISTERED) {
+        expect_topics(log, 3)?;
+        Event::LicenseTermsRegistered(LicenseTermsRegistered {
+            license_terms_id: topic_u64(&log.topics[1], "licenseTermsId")?,
+            license_template: topic_address(&log.topics[2])?,
+        })
+    } else if *signature == topic0(LICENSE_TOKENS_MINTED) {
+        expect_topics(log, 4)?;
+        let data = data_tokens(
+            log,
+            &[
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::Uint(256),
+            ],
+        )?;
+        Event::LicenseTokensMinted(LicenseTokensMinted {
+            caller: topic_address(&log.topics[1])?,
+            licensor_ip_id: topic_address(&log.topics[2])?,
+            license_template: token_address(&data[0])?,
+            license_terms_id: topic_u64(&log.topics[3], "licenseTermsId")?,
+            amount: token_u64(&data[1], "amount")?,
+            receiver: token_address(&data[2])?,
+            start_license_token_id: token_u64(&data[3], "startLicenseTokenId")?,
+        })
+    } else if *signature == topic0(DISPUTE_RAISED) {
+        expect_topics(log, 1)?;
+        let data = data_tokens(
+            log,
+            &[
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::FixedBytes(32),
+                ParamType::FixedBytes(32),
+                ParamType::Bytes,
+            ],
+        )?;
+        Event::DisputeRaised(DisputeRaised {
+            dispute_id: token_u64(&data[0], "disputeId")?,
+            target_ip_id: token_address(&data[1])?,
+            dispute_initiator: token_address(&data[2])?,
+            dispute_timestamp: token_u64(&data[3], "disputeTimestamp")?,
+            arbitration_policy: token_address(&data[4])?,
+            dispute_evidence_hash: token_bytes32(&data[5])?,
+            target_tag: token_bytes32(&data[6])?,
+        })
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(event))
+}
+
+// ==============================================================================
+// Receipt Queries
+// ==============================================================================
+
+/// Known events emitted by `emitter` in `receipt`, in log order
+///
+/// Malformed logs of a known event are reported and skipped.
+pub fn events_from(receipt: &TxReceipt, emitter: H160) -> Vec<Event> {
+    let emitter = address_hex(emitter);
+
+    receipt
+        .logs
+        .iter()
+        .filter(|log| log.address == emitter)
+        .filter_map(|log| match decode_log(log) {
+            Ok(event) => event,
+            Err(e) => {
+                ic_cdk::println!(
+                    "   ⚠️  Skipping log #{} of {}: {}",
+                    log.log_index,
+                    emitter,
+                    e
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// `IPRegistered` emitted by `ip_asset_registry` for a token of `token_contract`
+pub fn find_ip_registered(
+    receipt: &TxReceipt,
+    ip_asset_registry: H160,
+    token_contract: H160,
+) -> Option<IpRegistered> {
+    let token_contract = address_hex(token_contract);
+
+    events_from(receipt, ip_asset_registry)
+        .into_iter()
+        .find_map(|event| match event {
+            Event::IpRegistered(registered) if registered.token_contract == token_contract => {
+                Some(registered)
+            }
+            _ => None,
+        })
+}
+
+/// Token minted by `nft_contract` in `receipt`: its `NFTMinted` event, or else
+/// an ERC-721 `Transfer` from the zero address
+pub fn find_minted_token(receipt: &TxReceipt, nft_contract: H160) -> Option<u64> {
+    let events = events_from(receipt, nft_contract);
+
+    let minted = events.iter().find_map(|event| match event {
+        Event::NftMinted(minted) => Some(minted.token_id),
+        _ => None,
+    });
+
+    minted.or_else(|| {
+        events.iter().find_map(|event| match event {
+            Event::Transfer(transfer) if transfer.is_mint() => Some(transfer.token_id),
+            _ => None,
+        })
+    })
+}
+
+/// ID of the license terms `license_template` registered in `receipt`
+pub fn find_license_terms_registered(receipt: &TxReceipt, license_template: H160) -> Option<u64> {
+    events_from(receipt, license_template)
+        .into_iter()
+        .find_map(|event| match event {
+            Event::LicenseTermsRegistered(registered) => Some(registered.license_terms_id),
+            _ => None,
+        })
+}
+
+/// IDs of the license tokens `licensing_module` minted in `receipt`
+pub fn find_license_tokens_minted(receipt: &TxReceipt, licensing_module: H160) -> Option<Vec<u64>> {
+    events_from(receipt, licensing_module)
+        .into_iter()
+        .find_map(|event| match event {
+            Event::LicenseTokensMinted(minted) => Some(minted.token_ids()),
+            _ => None,
+        })
+}
+
+/// Total `token` transferred to `to` in `receipt`, saturating at u64::MAX
+pub fn erc20_received(receipt: &TxReceipt, token: H160, to: H160) -> u64 {
+    let to = address_hex(to);
+
+    events_from(receipt, token)
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Erc20Transfer(transfer) if transfer.to == to => Some(transfer.value),
+            _ => None,
+        })
+        .fold(0u64, u64::saturating_add)
+}
+
+/// ID of the dispute `dispute_module` raised in `receipt`
+pub fn find_dispute_raised(receipt: &TxReceipt, dispute_module: H160) -> Option<u64> {
+    events_from(receipt, dispute_module)
+        .into_iter()
+        .find_map(|event| match event {
+            Event::DisputeRaised(raised) => Some(raised.dispute_id),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address_topic(address: H160) -> String {
+        format!("0x{}{}", "0".repeat(24), hex::encode(address.as_bytes()))
+    }
+
+    fn u64_topic(value: u64) -> String {
+        format!("0x{:064x}", value)
+    }
+
+    fn log(emitter: H160, topics: Vec<String>, data: &[Token]) -> TxLog {
+        TxLog {
+            address: address_hex(emitter),
+            topics,
+            data: format!("0x{}", hex::encode(ethabi::encode(data))),
+            log_index: 0,
+        }
+    }
+
+    fn receipt(logs: Vec<TxLog>) -> TxReceipt {
+        TxReceipt {
+            success: true,
+            block_number: 1,
+            block_hash: String::new(),
+            gas_used: 0,
+            effective_gas_price: None,
+            contract_address: None,
+            logs,
+        }
+    }
+
+    #[test]
+    fn transfer_topic0() {
+        assert_eq!(
+            topic0(TRANSFER),
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+
+    #[test]
+    fn ip_registered_reads_the_ip_id_from_the_data() {
+        let (registry, nft, ip_id) = (
+            H160::repeat_byte(0x01),
+            H160::repeat_byte(0x02),
+            H160::repeat_byte(0xab),
+        );
+        let registered = log(
+            registry,
+            vec![
+                topic0(IP_REGISTERED),
+                u64_topic(1315),
+                address_topic(nft),
+                u64_topic(42),
+            ],
+            &[
+                Token::Address(ip_id),
+                Token::String("Sunset".to_string()),
+                Token::String("ipfs://meta".to_string()),
+                Token::Uint(1_700_000_000u64.into()),
+            ],
+        );
+
+        let found = find_ip_registered(&receipt(vec![registered.clone()]), registry, nft).unwrap();
+        assert_eq!(found.ip_id, address_hex(ip_id));
+        assert_eq!(found.chain_id, 1315);
+        assert_eq!(found.token_id, 42);
+        assert_eq!(found.uri, "ipfs://meta");
+
+        // Other emitters and other token contracts are ignored
+        let receipt = receipt(vec![registered]);
+        assert!(find_ip_registered(&receipt, nft, nft).is_none());
+        assert!(find_ip_registered(&receipt, registry, registry).is_none());
+    }
+
+    #[test]
+    fn minted_token_falls_back_to_a_transfer_from_zero() {
+        let nft = H160::repeat_byte(0x02);
+        let transfer = |from: H160| {
+            log(
+                nft,
+                vec![
+                    topic0(TRANSFER),
+                    address_topic(from),
+                    address_topic(H160::repeat_byte(0x03)),
+                    u64_topic(7),
+                ],
+                &[],
+            )
+        };
+
+        assert_eq!(find_minted_token(&receipt(vec![transfer(H160::zero())]), nft), Some(7));
+        assert_eq!(find_minted_token(&receipt(vec![transfer(H160::repeat_byte(0x04))]), nft), None);
+    }
+
+    #[test]
+    fn unknown_and_malformed_logs() {
+        let emitter = H160::repeat_byte(0x01);
+        let unknown = log(emitter, vec![topic0("Unknown()")], &[]);
+        assert_eq!(decode_log(&unknown), Ok(None));
+
+        let not_an_address = format!("0x{}", "ff".repeat(32));
+        let malformed = log(
+            emitter,
+            vec![topic0(NFT_MINTED), not_an_address, u64_topic(1)],
+            &[Token::String("0xabc".to_string())],
+        );
+        assert!(decode_log(&malformed).is_err());
+    }
+
+    #[test]
+    fn license_tokens_minted_yields_consecutive_token_ids() {
+        let licensing_module = H160::repeat_byte(0x04);
+        let minted = log(
+            licensing_module,
+            vec![
+                topic0(LICENSE_TOKENS_MINTED),
+                address_topic(H160::repeat_byte(0x01)),
+                address_topic(H160::repeat_byte(0xab)),
+                u64_topic(7),
+            ],
+            &[
+                Token::Address(H160::repeat_byte(0x22)),
+                Token::Uint(3u64.into()),
+                Token::Address(H160::repeat_byte(0x33)),
+                Token::Uint(41u64.into()),
+            ],
+        );
+
+        assert_eq!(
+            find_license_tokens_minted(&receipt(vec![minted]), licensing_module),
+            Some(vec![41, 42, 43])
+        );
+    }
+}
@@ -0,0 +1,164 @@
+// EVM RPC Canister Module
+// Client for the DFINITY EVM RPC canister's `multi_request` endpoint
+//
+// The EVM RPC canister sends a raw JSON-RPC request to several providers and
+// compares their responses according to a `ConsensusStrategy`. Only the subset
+// of its candid interface used here is declared; parts of the response we do
+// not inspect are decoded as `Reserved`. The canister ID is configurable, so a
+// local instance (see dfx.json) or a stand-in canister can be used in tests.
+
+use candid::types::reserved::Reserved;
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::http_request::HttpHeader;
+use serde::Serialize;
+
+// ==============================================================================
+// Candid Types
+// ==============================================================================
+
+/// How provider responses must agree (mirrors the EVM RPC canister type)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ConsensusStrategy {
+    /// All providers must return the same response
+    Equality,
+    /// At least `min` of `total` providers must return the same response
+    Threshold { total: Option<u8>, min: u8 },
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+struct RpcApi {
+    url: String,
+    headers: Option<Vec<HttpHeader>>,
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+enum RpcServices {
+    Custom {
+        #[serde(rename = "chainId")]
+        chain_id: u64,
+        services: Vec<RpcApi>,
+    },
+}
+
+#[derive(CandidType, Serialize, Clone, Debug)]
+struct RpcConfig {
+    #[serde(rename = "responseSizeEstimate")]
+    response_size_estimate: Option<u64>,
+    #[serde(rename = "responseConsensus")]
+    response_consensus: Option<ConsensusStrategy>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+struct JsonRpcError {
+    code: i64,
+    message: String,
+}
+
+/// Variant names are fixed by the EVM RPC canister's interface
+#[allow(clippy::enum_variant_names)]
+#[derive(CandidType, Deserialize, Debug)]
+enum RpcError {
+    ProviderError(Reserved),
+    HttpOutcallError(Reserved),
+    JsonRpcError(JsonRpcError),
+    ValidationError(Reserved),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum RequestResult {
+    Ok(String),
+    Err(RpcError),
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum MultiRequestResult {
+    Consistent(RequestResult),
+    /// (RpcService, RequestResult) per provider
+    Inconsistent(Vec<(Reserved, RequestResult)>),
+}
+
+fn describe(error: &RpcError) -> String {
+    match error {
+        RpcError::ProviderError(_) => "provider error".to_string(),
+        RpcError::HttpOutcallError(_) => "HTTP outcall error".to_string(),
+        RpcError::JsonRpcError(e) => format!("JSON-RPC error {}: {}", e.code, e.message),
+        RpcError::ValidationError(_) => "validation error".to_string(),
+    }
+}
+
+// ==============================================================================
+// Requests
+// ==============================================================================
+
+/// Send a raw JSON-RPC request to `rpc_urls` through the EVM RPC canister
+///
+/// # Arguments
+/// * `canister_id` - EVM RPC canister (or a stand-in with the same interface)
+/// * `chain_id` - Chain the providers serve
+/// * `rpc_urls` - Providers to query
+/// * `payload` - JSON-RPC request body
+/// * `consensus` - How the providers' responses must agree
+/// * `cycles_per_provider` - Cycles attached per provider (surplus is refunded)
+///
+/// # Returns
+/// * `Result<String, String>` - The agreed raw JSON-RPC response body, or error
+pub async fn multi_request(
+    canister_id: Principal,
+    chain_id: u64,
+    rpc_urls: &[String],
+    payload: &str,
+    consensus: &ConsensusStrategy,
+    cycles_per_provider: u64,
+) -> Result<String, String> {
+    let services = RpcServices::Custom {
+        chain_id,
+        services: rpc_urls
+            .iter()
+            .map(|url| RpcApi {
+                url: url.clone(),
+                headers: None,
+            })
+            .collect(),
+    };
+    let config = RpcConfig {
+        response_size_estimate: None,
+        response_consensus: Some(consensus.clone()),
+    };
+    let cycles = cycles_per_provider as u128 * rpc_urls.len().max(1) as u128;
+
+    ic_cdk::println!(
+        "📡 EVM RPC canister: {} provider(s), {:?}, {} cycles",
+        rpc_urls.len(),
+        consensus,
+        cycles
+    );
+
+    let (result,): (MultiRequestResult,) = ic_cdk::api::call::call_with_payment128(
+        canister_id,
+        "multi_request",
+        (services, Some(config), payload.to_string()),
+        cycles,
+    )
+    .await
+    .map_err(|(code, msg)| format!("EVM RPC canister call failed: {:?} - {}", code, msg))?;
+
+    match result {
+        MultiRequestResult::Consistent(RequestResult::Ok(body)) => Ok(body),
+        MultiRequestResult::Consistent(RequestResult::Err(e)) => {
+            Err(format!("EVM RPC canister: {}", describe(&e)))
+        }
+        MultiRequestResult::Inconsistent(results) => {
+            let summary: Vec<String> = results
+                .iter()
+                .map(|(_, result)| match result {
+                    RequestResult::Ok(body) => format!("ok ({} bytes)", body.len()),
+                    RequestResult::Err(e) => describe(e),
+                })
+                .collect();
+            Err(format!(
+                "Providers returned inconsistent responses: [{}]",
+                summary.join(", ")
+            ))
+        }
+    }
+}
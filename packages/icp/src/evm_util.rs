@@ -1,11 +1,19 @@
 // EVM Utilities Module
 // Handles Chain-Key ECDSA for deriving canister-owned EVM addresses
+//
+// The canister signs for a shared address (empty derivation path) and for one
+// address per principal (`EvmAccount::User`), all controlled by the same
+// threshold key.
 
 use ic_cdk::api::management_canister::ecdsa::{
     ecdsa_public_key, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
 };
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
 use sha3::{Digest, Keccak256};
 use primitive_types::U256;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 
 // ==============================================================================
 // ECDSA Key Configuration
@@ -18,25 +26,75 @@ fn get_ecdsa_key_id() -> EcdsaKeyId {
     }
 }
 
+/// Domain tag of per-principal derivation paths (keeps them disjoint from the
+/// canister's empty root path and from any future path scheme)
+const USER_DERIVATION_TAG: &[u8] = b"user";
+
+/// Chain-Key ECDSA account the canister signs for
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum EvmAccount {
+    /// Shared canister address (empty derivation path)
+    #[default]
+    Canister,
+    /// Address derived for one principal; only the canister can sign for it
+    User(Principal),
+}
+
+impl EvmAccount {
+    /// Account of a caller (anonymous callers have no account of their own)
+    pub fn user(principal: Principal) -> Result<Self, String> {
+        if principal == Principal::anonymous() {
+            return Err("Anonymous callers have no derived EVM address".to_string());
+        }
+        Ok(EvmAccount::User(principal))
+    }
+
+    pub fn derivation_path(&self) -> Vec<Vec<u8>> {
+        match self {
+            EvmAccount::Canister => vec![],
+            EvmAccount::User(principal) => {
+                vec![USER_DERIVATION_TAG.to_vec(), principal.as_slice().to_vec()]
+            }
+        }
+    }
+}
+
 // ==============================================================================
 // Get Canister EVM Address
 // ==============================================================================
 
-/// Get the canister's raw public key (uncompressed, 65 bytes)
+thread_local! {
+    /// Uncompressed public keys already derived, per account
+    ///
+    /// The key name and derivation paths are fixed, so a derived key never
+    /// changes; the cache lives on the heap and is rebuilt after an upgrade.
+    static PUBLIC_KEYS: RefCell<BTreeMap<EvmAccount, Vec<u8>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Whether the public key of `account` is cached, i.e. its address can be
+/// returned without calling the management canister
+pub fn is_derived(account: &EvmAccount) -> bool {
+    PUBLIC_KEYS.with(|keys| keys.borrow().contains_key(account))
+}
+
+/// Get the raw public key of `account` (uncompressed, 65 bytes)
 ///
 /// This is used for signature verification and recovery ID determination.
+/// Only the first call per account reaches the management canister.
 ///
 /// # Returns
 /// * `Result<Vec<u8>, String>` - Uncompressed public key (65 bytes) or error
-pub async fn get_canister_public_key() -> Result<Vec<u8>, String> {
-    // Use empty derivation path for root key
-    let derivation_path = vec![];
+pub async fn get_public_key(account: &EvmAccount) -> Result<Vec<u8>, String> {
+    if let Some(public_key) = PUBLIC_KEYS.with(|keys| keys.borrow().get(account).cloned()) {
+        return Ok(public_key);
+    }
+
     let key_id = get_ecdsa_key_id();
 
     // IMPORTANT: Use Some(ic_cdk::id()) to ensure we get THIS canister's public key
     let request = EcdsaPublicKeyArgument {
         canister_id: Some(ic_cdk::id()),
-        derivation_path,
+        derivation_path: account.derivation_path(),
         key_id: key_id.clone(),
     };
 
@@ -65,71 +123,113 @@ pub async fn get_canister_public_key() -> Result<Vec<u8>, String> {
         ));
     };
 
+    PUBLIC_KEYS.with(|keys| keys.borrow_mut().insert(*account, uncompressed_key.clone()));
     Ok(uncompressed_key)
 }
 
-/// Derive the canister's Ethereum-compatible address using Chain-Key ECDSA
+/// Ethereum address of an uncompressed (65 byte) public key
+///
+/// Address = last 20 bytes of keccak256(public_key[1..]) (the 0x04 prefix is skipped)
+pub fn address_from_public_key(uncompressed_key: &[u8]) -> String {
+    let hash = Keccak256::digest(&uncompressed_key[1..]);
+    format!("0x{}", hex::encode(&hash[12..]))
+}
+
+/// Derive the Ethereum-compatible address of `account` using Chain-Key ECDSA
 ///
 /// This address can be used to sign transactions on Story Protocol (EVM chain)
 /// without ever storing a private key.
 ///
 /// # Returns
 /// * `Result<String, String>` - EVM address (0x...) or error
-pub async fn get_canister_evm_address() -> Result<String, String> {
-    ic_cdk::println!("   🔑 Deriving canister EVM address...");
+pub async fn get_evm_address(account: &EvmAccount) -> Result<String, String> {
+    ic_cdk::println!("   🔑 Deriving EVM address of {:?}...", account);
 
-    // Use empty derivation path for root key
-    let derivation_path = vec![];
+    // IC returns SEC1 encoded public keys which can be either compressed
+    // (33 bytes) or uncompressed (65 bytes); `get_public_key` normalizes them
+    let uncompressed_key = get_public_key(account).await?;
+    let address = address_from_public_key(&uncompressed_key);
 
-    let key_id = get_ecdsa_key_id();
+    ic_cdk::println!("   ✅ EVM Address: {}", address);
 
-    // Request public key from management canister
-    let request = EcdsaPublicKeyArgument {
-        canister_id: None,
-        derivation_path,
-        key_id: key_id.clone(),
-    };
+    Ok(address)
+}
 
-    let (response,) = ecdsa_public_key(request)
-        .await
-        .map_err(|e| format!("Failed to get ECDSA public key: {:?}", e))?;
+/// Derive the canister's shared EVM address (empty derivation path)
+///
+/// # Returns
+/// * `Result<String, String>` - EVM address (0x...) or error
+pub async fn get_canister_evm_address() -> Result<String, String> {
+    let address = get_evm_address(&EvmAccount::Canister).await?;
 
-    let public_key = response.public_key;
+    ic_cdk::println!("   💡 Fund this address with testnet IP tokens:");
+    ic_cdk::println!("      https://aeneid.faucet.story.foundation");
 
-    // Derive Ethereum address from public key
-    // IC returns SEC1 encoded public keys which can be either:
-    // - Compressed: 33 bytes [0x02/0x03, x (32 bytes)]
-    // - Uncompressed: 65 bytes [0x04, x (32 bytes), y (32 bytes)]
-    // Ethereum address = last 20 bytes of keccak256(uncompressed_public_key[1..])
+    Ok(address)
+}
 
-    let uncompressed_key = if public_key.len() == 33 {
-        // Compressed key - decompress it
-        ic_cdk::println!("   🔓 Decompressing SEC1 public key (33 -> 65 bytes)...");
-        decompress_public_key(&public_key)?
-    } else if public_key.len() == 65 {
-        // Already uncompressed
-        public_key
-    } else {
-        return Err(format!(
-            "Invalid public key length: {} (expected 33 or 65)",
-            public_key.len()
-        ));
-    };
+// ==============================================================================
+// EIP-55 Addresses
+// ==============================================================================
 
-    // Hash the public key (excluding the 0x04 prefix)
-    let hash = Keccak256::digest(&uncompressed_key[1..]);
+/// Mixed-case EIP-55 checksum encoding of an address
+pub fn to_checksum_address(address: &[u8; 20]) -> String {
+    let lower = hex::encode(address);
+    let hash = Keccak256::digest(lower.as_bytes());
+
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if c.is_ascii_alphabetic() && nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    format!("0x{}", checksummed)
+}
+
+/// Parse a user-supplied EVM address
+///
+/// Mixed-case input must carry a valid EIP-55 checksum; all-lowercase or
+/// all-uppercase input has no checksum to verify. The zero address is rejected
+/// because tokens sent there are lost.
+///
+/// # Returns
+/// * `Result<[u8; 20], String>` - Address bytes or error
+pub fn parse_eip55_address(address: &str) -> Result<[u8; 20], String> {
+    let hex_part = address
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("Address must start with 0x: {}", address))?;
+    if hex_part.len() != 40 {
+        return Err(format!("Address must have 40 hex digits: {}", address));
+    }
 
-    // Take the last 20 bytes
-    let address_bytes = &hash[12..];
+    let bytes: [u8; 20] = hex::decode(hex_part)
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))?
+        .try_into()
+        .map_err(|_| format!("Invalid address length: {}", address))?;
 
-    // Convert to hex string with 0x prefix
-    let address = format!("0x{}", hex::encode(address_bytes));
+    let mixed_case = hex_part.chars().any(|c| c.is_ascii_lowercase())
+        && hex_part.chars().any(|c| c.is_ascii_uppercase());
+    if mixed_case && to_checksum_address(&bytes) != address {
+        return Err(format!("Invalid EIP-55 checksum: {}", address));
+    }
+    if bytes == [0u8; 20] {
+        return Err("The zero address cannot receive assets".to_string());
+    }
 
-    ic_cdk::println!("   ✅ Canister EVM Address: {}", address);
-    ic_cdk::println!("   💡 Fund this address with testnet IP tokens:");
-    ic_cdk::println!("      https://aeneid.faucet.story.foundation");
+    Ok(bytes)
+}
 
-    Ok(address)
+/// 4-byte selector of a Solidity function signature, e.g. "state()"
+pub fn function_selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
 }
 
 // ==============================================================================
@@ -201,7 +301,7 @@ fn pow_mod(base: U256, exp: U256, m: U256) -> U256 {
             result = mul_mod(result, base, m);
         }
         base = mul_mod(base, base, m);
-        exp = exp >> 1;
+        exp >>= 1;
     }
 
     result
@@ -217,16 +317,20 @@ use ic_cdk::api::management_canister::ecdsa::{
 
 /// Sign a raw transaction hash using Chain-Key ECDSA
 ///
-/// This function signs an Ethereum transaction hash using the canister's
-/// ECDSA key, enabling the canister to send transactions on EVM chains
-/// without storing private keys.
+/// This function signs an Ethereum transaction hash using the ECDSA key
+/// derived for `account`, enabling the canister to send transactions on EVM
+/// chains without storing private keys.
 ///
 /// # Arguments
 /// * `message_hash` - The keccak256 hash of the raw transaction (32 bytes)
+/// * `account` - Account whose derived key signs
 ///
 /// # Returns
 /// * `Result<Vec<u8>, String>` - The signature (65 bytes: r, s, v) or error
-pub async fn sign_evm_transaction(message_hash: Vec<u8>) -> Result<Vec<u8>, String> {
+pub async fn sign_evm_transaction(
+    message_hash: Vec<u8>,
+    account: &EvmAccount,
+) -> Result<Vec<u8>, String> {
     ic_cdk::println!("   🔏 Signing EVM transaction with Chain-Key ECDSA...");
 
     if message_hash.len() != 32 {
@@ -243,7 +347,7 @@ pub async fn sign_evm_transaction(message_hash: Vec<u8>) -> Result<Vec<u8>, Stri
 
     let request = SignWithEcdsaArgument {
         message_hash,
-        derivation_path: vec![],
+        derivation_path: account.derivation_path(),
         key_id,
     };
 
@@ -308,6 +412,21 @@ pub fn build_evm_transaction(
     stream.out().to_vec()
 }
 
+/// Address of a contract created by `sender` with `nonce` (CREATE opcode)
+///
+/// Address = last 20 bytes of keccak256(rlp([sender, nonce]))
+pub fn contract_creation_address(sender: &[u8; 20], nonce: u64) -> [u8; 20] {
+    let mut stream = RlpStream::new();
+    stream.begin_list(2);
+    stream.append(&sender.as_ref());
+    stream.append(&nonce);
+
+    let hash = Keccak256::digest(stream.out());
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
 /// Calculate recovery ID (v) for ECDSA signature
 ///
 /// The recovery ID determines which of the 2-4 possible public keys
@@ -356,7 +475,9 @@ async fn calculate_recovery_id(
 /// * `recovery_id` - Recovery ID (0 or 1)
 ///
 /// # Returns
-/// * `Vec<u8>` - RLP-encoded signed transaction
+/// * `Result<Vec<u8>, String>` - RLP-encoded signed transaction, or error if
+///   the signature is not 64 bytes
+#[allow(clippy::too_many_arguments)]
 pub fn build_signed_transaction(
     nonce: u64,
     gas_price: u64,
@@ -367,9 +488,12 @@ pub fn build_signed_transaction(
     signature: &[u8],
     chain_id: u64,
     recovery_id: u8,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, String> {
     if signature.len() != 64 {
-        ic_cdk::println!("⚠️  Warning: Invalid signature length: {} (expected 64)", signature.len());
+        return Err(format!(
+            "Invalid signature length: {} (expected 64)",
+            signature.len()
+        ));
     }
 
     // Extract r and s from signature (64 bytes)
@@ -417,7 +541,7 @@ pub fn build_signed_transaction(
     ic_cdk::println!("      v value we calculated: {}", v);
     ic_cdk::println!("      v as hex: 0x{:x}", v);
 
-    signed_tx
+    Ok(signed_tx)
 }
 
 /// Build unsigned transaction for contract creation (EIP-155)
@@ -470,7 +594,9 @@ pub fn build_evm_transaction_for_creation(
 /// * `recovery_id` - Recovery ID (0 or 1)
 ///
 /// # Returns
-/// * `Vec<u8>` - RLP-encoded signed transaction
+/// * `Result<Vec<u8>, String>` - RLP-encoded signed transaction, or error if
+///   the signature is not 64 bytes
+#[allow(clippy::too_many_arguments)]
 pub fn build_signed_transaction_for_creation(
     nonce: u64,
     gas_price: u64,
@@ -480,9 +606,12 @@ pub fn build_signed_transaction_for_creation(
     signature: &[u8],
     chain_id: u64,
     recovery_id: u8,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, String> {
     if signature.len() != 64 {
-        ic_cdk::println!("⚠️  Warning: Invalid signature length: {} (expected 64)", signature.len());
+        return Err(format!(
+            "Invalid signature length: {} (expected 64)",
+            signature.len()
+        ));
     }
 
     // Extract r and s from signature (64 bytes)
@@ -524,5 +653,507 @@ pub fn build_signed_transaction_for_creation(
     ic_cdk::println!("   ✅ Signed transaction: {} bytes", signed_tx.len());
     ic_cdk::println!("   🔍 Raw signed TX: 0x{}", hex::encode(&signed_tx));
 
-    signed_tx
+    Ok(signed_tx)
+}
+
+// ==============================================================================
+// EIP-1559 (Type-2) Transactions (EIP-2718 typed envelope)
+// ==============================================================================
+
+/// EIP-2718 type byte for dynamic-fee transactions
+pub const EIP1559_TX_TYPE: u8 = 0x02;
+
+/// Transaction envelope used when signing for a chain
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxType {
+    /// Pre-EIP-2718 transaction with EIP-155 replay protection
+    Legacy,
+    /// EIP-1559 dynamic-fee transaction (type 0x02)
+    Eip1559,
+}
+
+/// EIP-2930 access list entry
+#[derive(Clone, Debug, Default)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+/// Fee fields of a transaction, by envelope type
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxFees {
+    Legacy {
+        gas_price: u64,
+    },
+    Eip1559 {
+        max_fee_per_gas: u64,
+        max_priority_fee_per_gas: u64,
+    },
+}
+
+impl TxFees {
+    /// Constant fees used when no fee estimate is available
+    pub fn fallback(tx_type: TxType) -> Self {
+        match tx_type {
+            TxType::Legacy => TxFees::Legacy {
+                gas_price: crate::config::GAS_PRICE,
+            },
+            TxType::Eip1559 => TxFees::Eip1559 {
+                max_fee_per_gas: crate::config::GAS_PRICE,
+                max_priority_fee_per_gas: crate::config::MAX_PRIORITY_FEE_PER_GAS,
+            },
+        }
+    }
+
+    pub fn tx_type(&self) -> TxType {
+        match self {
+            TxFees::Legacy { .. } => TxType::Legacy,
+            TxFees::Eip1559 { .. } => TxType::Eip1559,
+        }
+    }
+
+    /// Highest price per gas this transaction can pay
+    pub fn max_price_per_gas(&self) -> u64 {
+        match self {
+            TxFees::Legacy { gas_price } => *gas_price,
+            TxFees::Eip1559 { max_fee_per_gas, .. } => *max_fee_per_gas,
+        }
+    }
+}
+
+/// An unsigned EVM transaction of either envelope type
+#[derive(Clone, Debug)]
+pub struct EvmTransaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    /// Recipient; None for contract creation
+    pub to: Option<[u8; 20]>,
+    pub value: u64,
+    pub data: Vec<u8>,
+    pub fees: TxFees,
+    /// Only encoded for EIP-1559 transactions
+    pub access_list: Vec<AccessListItem>,
+}
+
+impl EvmTransaction {
+    pub fn tx_type(&self) -> TxType {
+        self.fees.tx_type()
+    }
+
+    /// Bytes whose keccak256 hash is signed
+    pub fn signing_payload(&self) -> Vec<u8> {
+        match (self.fees, self.to) {
+            (TxFees::Legacy { gas_price }, Some(to)) => build_evm_transaction(
+                self.nonce,
+                gas_price,
+                self.gas_limit,
+                &to,
+                self.value,
+                self.data.clone(),
+                self.chain_id,
+            ),
+            (TxFees::Legacy { gas_price }, None) => build_evm_transaction_for_creation(
+                self.nonce,
+                gas_price,
+                self.gas_limit,
+                self.value,
+                self.data.clone(),
+                self.chain_id,
+            ),
+            (
+                TxFees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+                to,
+            ) => build_eip1559_transaction(
+                self.chain_id,
+                self.nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                self.gas_limit,
+                to.as_ref(),
+                self.value,
+                self.data.clone(),
+                &self.access_list,
+            ),
+        }
+    }
+
+    /// keccak256 of the signing payload
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&Keccak256::digest(self.signing_payload()));
+        hash
+    }
+
+    /// Encode the signed transaction for `eth_sendRawTransaction`
+    pub fn encode_signed(&self, signature: &[u8], recovery_id: u8) -> Result<Vec<u8>, String> {
+        match (self.fees, self.to) {
+            (TxFees::Legacy { gas_price }, Some(to)) => build_signed_transaction(
+                self.nonce,
+                gas_price,
+                self.gas_limit,
+                &to,
+                self.value,
+                self.data.clone(),
+                signature,
+                self.chain_id,
+                recovery_id,
+            ),
+            (TxFees::Legacy { gas_price }, None) => build_signed_transaction_for_creation(
+                self.nonce,
+                gas_price,
+                self.gas_limit,
+                self.value,
+                self.data.clone(),
+                signature,
+                self.chain_id,
+                recovery_id,
+            ),
+            (
+                TxFees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+                to,
+            ) => build_signed_eip1559_transaction(
+                self.chain_id,
+                self.nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                self.gas_limit,
+                to.as_ref(),
+                self.value,
+                self.data.clone(),
+                &self.access_list,
+                signature,
+                recovery_id,
+            ),
+        }
+    }
+}
+
+/// Append the common EIP-1559 payload fields (everything before the signature)
+#[allow(clippy::too_many_arguments)]
+fn append_eip1559_fields(
+    stream: &mut RlpStream,
+    chain_id: u64,
+    nonce: u64,
+    max_priority_fee_per_gas: u64,
+    max_fee_per_gas: u64,
+    gas_limit: u64,
+    to: Option<&[u8; 20]>,
+    value: u64,
+    data: &[u8],
+    access_list: &[AccessListItem],
+) {
+    stream.append(&chain_id);
+    stream.append(&nonce);
+    stream.append(&max_priority_fee_per_gas);
+    stream.append(&max_fee_per_gas);
+    stream.append(&gas_limit);
+    match to {
+        Some(to) => stream.append(&to.as_ref()),
+        None => stream.append(&""), // Empty string for contract creation
+    };
+    stream.append(&value);
+    stream.append(&data);
+
+    stream.begin_list(access_list.len());
+    for item in access_list {
+        stream.begin_list(2);
+        stream.append(&item.address.as_ref());
+        stream.begin_list(item.storage_keys.len());
+        for key in &item.storage_keys {
+            stream.append(&key.as_ref());
+        }
+    }
+}
+
+/// Build the EIP-1559 signing payload
+///
+/// `0x02 || rlp([chain_id, nonce, max_priority_fee_per_gas, max_fee_per_gas,
+/// gas_limit, to, value, data, access_list])`
+///
+/// # Arguments
+/// * `chain_id` - Chain ID (replay protection is part of the payload)
+/// * `nonce` - Transaction nonce
+/// * `max_priority_fee_per_gas` - Tip paid to the block producer (wei)
+/// * `max_fee_per_gas` - Maximum total price per gas (wei)
+/// * `gas_limit` - Gas limit
+/// * `to` - Recipient address, or None for contract creation
+/// * `value` - Value to transfer in wei
+/// * `data` - Transaction data
+/// * `access_list` - EIP-2930 access list (usually empty)
+///
+/// # Returns
+/// * `Vec<u8>` - Typed payload to be keccak256-hashed and signed
+#[allow(clippy::too_many_arguments)]
+pub fn build_eip1559_transaction(
+    chain_id: u64,
+    nonce: u64,
+    max_priority_fee_per_gas: u64,
+    max_fee_per_gas: u64,
+    gas_limit: u64,
+    to: Option<&[u8; 20]>,
+    value: u64,
+    data: Vec<u8>,
+    access_list: &[AccessListItem],
+) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+    stream.begin_list(9);
+    append_eip1559_fields(
+        &mut stream,
+        chain_id,
+        nonce,
+        max_priority_fee_per_gas,
+        max_fee_per_gas,
+        gas_limit,
+        to,
+        value,
+        &data,
+        access_list,
+    );
+
+    let mut payload = vec![EIP1559_TX_TYPE];
+    payload.extend_from_slice(&stream.out());
+    payload
+}
+
+/// Build a signed EIP-1559 transaction
+///
+/// `0x02 || rlp([..payload fields.., y_parity, r, s])`. Unlike EIP-155, the
+/// chain ID is not folded into `v`: the recovery ID is stored as-is (0 or 1).
+///
+/// # Returns
+/// * `Result<Vec<u8>, String>` - Typed signed transaction for
+///   `eth_sendRawTransaction`, or error if the signature is not 64 bytes
+#[allow(clippy::too_many_arguments)]
+pub fn build_signed_eip1559_transaction(
+    chain_id: u64,
+    nonce: u64,
+    max_priority_fee_per_gas: u64,
+    max_fee_per_gas: u64,
+    gas_limit: u64,
+    to: Option<&[u8; 20]>,
+    value: u64,
+    data: Vec<u8>,
+    access_list: &[AccessListItem],
+    signature: &[u8],
+    recovery_id: u8,
+) -> Result<Vec<u8>, String> {
+    if signature.len() != 64 {
+        return Err(format!(
+            "Invalid signature length: {} (expected 64)",
+            signature.len()
+        ));
+    }
+
+    let r = &signature[0..32];
+    let s = &signature[32..64];
+
+    ic_cdk::println!("   📝 Building signed EIP-1559 transaction:");
+    ic_cdk::println!("      Nonce: {}", nonce);
+    ic_cdk::println!("      Max Fee: {} wei", max_fee_per_gas);
+    ic_cdk::println!("      Max Priority Fee: {} wei", max_priority_fee_per_gas);
+    ic_cdk::println!("      Gas Limit: {}", gas_limit);
+    match to {
+        Some(to) => ic_cdk::println!("      To: 0x{}", hex::encode(to)),
+        None => ic_cdk::println!("      To: <contract creation>"),
+    }
+    ic_cdk::println!("      Value: {} wei", value);
+    ic_cdk::println!("      Data: {} bytes", data.len());
+    ic_cdk::println!("      Chain ID: {}", chain_id);
+    ic_cdk::println!("      y-parity: {}", recovery_id);
+
+    let mut stream = RlpStream::new();
+    stream.begin_list(12);
+    append_eip1559_fields(
+        &mut stream,
+        chain_id,
+        nonce,
+        max_priority_fee_per_gas,
+        max_fee_per_gas,
+        gas_limit,
+        to,
+        value,
+        &data,
+        access_list,
+    );
+    stream.append(&recovery_id);
+    stream.append(&U256::from_big_endian(r));
+    stream.append(&U256::from_big_endian(s));
+
+    let mut signed_tx = vec![EIP1559_TX_TYPE];
+    signed_tx.extend_from_slice(&stream.out());
+
+    ic_cdk::println!("   ✅ Signed transaction: {} bytes", signed_tx.len());
+    ic_cdk::println!("   🔍 Raw signed TX: 0x{}", hex::encode(&signed_tx));
+
+    Ok(signed_tx)
+}
+
+// ==============================================================================
+// Sign Transaction (any envelope type)
+// ==============================================================================
+
+/// Sign a transaction with Chain-Key ECDSA and encode it for broadcast
+///
+/// Hashes the type-appropriate signing payload, signs it with the key of
+/// `account`, determines the recovery ID against that account's public key and
+/// returns the raw signed transaction.
+///
+/// # Returns
+/// * `Result<Vec<u8>, String>` - Raw signed transaction or error
+pub async fn sign_transaction(tx: &EvmTransaction, account: &EvmAccount) -> Result<Vec<u8>, String> {
+    let message_hash = tx.signing_hash().to_vec();
+
+    ic_cdk::println!("      TX type: {:?}", tx.tx_type());
+    ic_cdk::println!("      TX Hash for signing: 0x{}", hex::encode(&message_hash));
+
+    let signature = sign_evm_transaction(message_hash.clone(), account).await?;
+
+    if signature.len() != 64 {
+        return Err(format!(
+            "Invalid signature length: {} (expected 64)",
+            signature.len()
+        ));
+    }
+
+    // Get the signing account's public key for recovery ID determination
+    let public_key = get_public_key(account).await?;
+
+    let recovery_id = crate::story_util::determine_recovery_id_with_pubkey(
+        &message_hash,
+        &signature,
+        &public_key,
+    )?;
+    ic_cdk::println!("      ✅ Recovery ID: {}", recovery_id);
+
+    tx.encode_signed(&signature, recovery_id)
+}
+
+/// Hash of a raw signed transaction (0x-prefixed), as reported by the chain
+pub fn transaction_hash(signed_tx: &[u8]) -> String {
+    format!("0x{}", hex::encode(Keccak256::digest(signed_tx)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(hex_address: &str) -> [u8; 20] {
+        hex::decode(hex_address.trim_start_matches("0x"))
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    fn transaction(fees: TxFees, to: Option<[u8; 20]>) -> EvmTransaction {
+        EvmTransaction {
+            chain_id: 1315,
+            nonce: 7,
+            gas_limit: 3_000_000,
+            to,
+            value: 0,
+            data: vec![],
+            fees,
+            access_list: vec![],
+        }
+    }
+
+    #[test]
+    fn eip1559_payload_has_the_type_prefix_and_nine_fields() {
+        let fees = TxFees::Eip1559 {
+            max_fee_per_gas: 20_000_000_000,
+            max_priority_fee_per_gas: 2_000_000_000,
+        };
+        let payload = transaction(fees, Some([0x11; 20])).signing_payload();
+        assert_eq!(payload[0], EIP1559_TX_TYPE);
+
+        let rlp = rlp::Rlp::new(&payload[1..]);
+        assert_eq!(rlp.item_count().unwrap(), 9);
+        assert_eq!(rlp.val_at::<u64>(0).unwrap(), 1315);
+        assert_eq!(rlp.val_at::<u64>(1).unwrap(), 7);
+        assert_eq!(rlp.val_at::<u64>(2).unwrap(), 2_000_000_000);
+        assert_eq!(rlp.val_at::<u64>(3).unwrap(), 20_000_000_000);
+        assert_eq!(rlp.at(8).unwrap().item_count().unwrap(), 0);
+
+        // Contract creation encodes an empty recipient
+        let creation = transaction(fees, None).signing_payload();
+        assert!(rlp::Rlp::new(&creation[1..]).at(5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_payload_is_eip155_without_a_type_prefix() {
+        let fees = TxFees::Legacy {
+            gas_price: 10_000_000_000,
+        };
+        let payload = transaction(fees, Some([0x11; 20])).signing_payload();
+
+        let rlp = rlp::Rlp::new(&payload);
+        assert!(rlp.is_list());
+        assert_eq!(rlp.item_count().unwrap(), 9);
+        assert_eq!(rlp.val_at::<u64>(6).unwrap(), 1315);
+    }
+
+    #[test]
+    fn signed_transactions_need_a_64_byte_signature() {
+        let eip1559 = transaction(TxFees::fallback(TxType::Eip1559), Some([0x11; 20]));
+        let legacy = transaction(TxFees::fallback(TxType::Legacy), None);
+
+        assert!(eip1559.encode_signed(&[0u8; 63], 0).is_err());
+        assert!(legacy.encode_signed(&[0u8; 65], 0).is_err());
+    }
+
+    #[test]
+    fn user_paths_are_distinct_from_the_canister_path() {
+        let alice = EvmAccount::user(Principal::from_slice(&[1, 2, 3])).unwrap();
+        let bob = EvmAccount::user(Principal::from_slice(&[1, 2, 4])).unwrap();
+
+        assert!(EvmAccount::Canister.derivation_path().is_empty());
+        assert_eq!(alice.derivation_path(), vec![b"user".to_vec(), vec![1, 2, 3]]);
+        assert_ne!(alice.derivation_path(), bob.derivation_path());
+        assert!(EvmAccount::user(Principal::anonymous()).is_err());
+    }
+
+    #[test]
+    fn checksum_matches_the_eip55_vectors() {
+        for expected in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert_eq!(to_checksum_address(&address(expected)), expected);
+            assert_eq!(parse_eip55_address(expected), Ok(address(expected)));
+        }
+    }
+
+    #[test]
+    fn parse_rejects_bad_checksums_and_the_zero_address() {
+        let lowercase = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+        assert_eq!(parse_eip55_address(lowercase), Ok(address(lowercase)));
+
+        assert!(parse_eip55_address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+        assert!(parse_eip55_address("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_err());
+        assert!(parse_eip55_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea").is_err());
+        assert!(parse_eip55_address(&format!("0x{}", "0".repeat(40))).is_err());
+    }
+
+    #[test]
+    fn contract_creation_address_follows_sender_and_nonce() {
+        let sender = address("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+
+        assert_eq!(
+            contract_creation_address(&sender, 0),
+            address("0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+        );
+        assert_eq!(
+            contract_creation_address(&sender, 1),
+            address("0x343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+        );
+    }
 }
@@ -0,0 +1,537 @@
+// Fee Oracle Module
+// Gas limit estimation and fee selection for Story Protocol transactions
+//
+// Gas limits come from `eth_estimateGas` plus a safety margin. Legacy
+// transactions are priced with `eth_gasPrice`; EIP-1559 transactions use the
+// next block's base fee and a percentile of recent tips from `eth_feeHistory`.
+// Everything is clamped to admin-set maximums. The constants in `config` are
+// only used when the RPC cannot provide an estimate.
+
+use crate::config;
+use crate::evm_util::{TxFees, TxType};
+use crate::rpc_util;
+use crate::STATE;
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// Admin-adjustable fee policy
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct FeeSettings {
+    /// Margin applied to `eth_estimateGas`, in percent (120 = +20%)
+    pub gas_limit_multiplier_percent: u32,
+    /// Upper bound for the gas limit of any transaction
+    pub max_gas_limit: u64,
+    /// Upper bound for `gas_price` / `max_fee_per_gas` (wei)
+    pub max_fee_per_gas: u64,
+    /// Upper bound for `max_priority_fee_per_gas` (wei)
+    pub max_priority_fee_per_gas: u64,
+}
+
+impl Default for FeeSettings {
+    fn default() -> Self {
+        Self {
+            gas_limit_multiplier_percent: config::DEFAULT_GAS_LIMIT_MULTIPLIER_PERCENT,
+            max_gas_limit: config::DEFAULT_MAX_GAS_LIMIT,
+            max_fee_per_gas: config::DEFAULT_MAX_FEE_PER_GAS,
+            max_priority_fee_per_gas: config::DEFAULT_MAX_PRIORITY_FEE_PER_GAS,
+        }
+    }
+}
+
+impl FeeSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.gas_limit_multiplier_percent < 100 {
+            return Err("gas_limit_multiplier_percent must be at least 100".to_string());
+        }
+        if self.max_gas_limit == 0 || self.max_fee_per_gas == 0 {
+            return Err("max_gas_limit and max_fee_per_gas must be non-zero".to_string());
+        }
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            return Err("max_priority_fee_per_gas cannot exceed max_fee_per_gas".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Where a quoted value came from
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeeSource {
+    /// Taken from the RPC
+    Estimated,
+    /// Taken from the RPC, then clamped to the admin maximum
+    Capped,
+    /// RPC unavailable; `config` constants were used
+    Fallback,
+}
+
+/// Gas limit and fees chosen for one transaction
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FeeQuote {
+    pub gas_limit: u64,
+    /// Raw `eth_estimateGas` result, before the safety margin
+    pub gas_estimate: Option<u64>,
+    pub gas_limit_source: FeeSource,
+    pub fees: TxFees,
+    /// Next block's base fee (EIP-1559 only)
+    pub base_fee_per_gas: Option<u64>,
+    pub fee_source: FeeSource,
+}
+
+// ==============================================================================
+// Fee Selection
+// ==============================================================================
+
+/// Apply the safety margin to a gas estimate and clamp it
+fn gas_limit_from_estimate(estimate: u64, settings: &FeeSettings) -> (u64, FeeSource) {
+    let padded = (estimate as u128 * settings.gas_limit_multiplier_percent as u128 / 100)
+        .min(u64::MAX as u128) as u64;
+
+    if padded > settings.max_gas_limit {
+        (settings.max_gas_limit, FeeSource::Capped)
+    } else {
+        (padded, FeeSource::Estimated)
+    }
+}
+
+/// Clamp a legacy gas price
+fn legacy_fees(gas_price: u64, settings: &FeeSettings) -> (TxFees, FeeSource) {
+    if gas_price > settings.max_fee_per_gas {
+        (
+            TxFees::Legacy {
+                gas_price: settings.max_fee_per_gas,
+            },
+            FeeSource::Capped,
+        )
+    } else {
+        (TxFees::Legacy { gas_price }, FeeSource::Estimated)
+    }
+}
+
+/// `max_fee = 2 * base_fee + tip`, leaving room for the base fee to double
+/// before the transaction becomes unincludable
+fn eip1559_fees(base_fee: u64, tip: u64, settings: &FeeSettings) -> (TxFees, FeeSource) {
+    let priority = tip.min(settings.max_priority_fee_per_gas);
+    let max_fee = base_fee.saturating_mul(2).saturating_add(priority);
+    let capped_max_fee = max_fee.min(settings.max_fee_per_gas);
+
+    let source = if priority != tip || capped_max_fee != max_fee {
+        FeeSource::Capped
+    } else {
+        FeeSource::Estimated
+    };
+
+    (
+        TxFees::Eip1559 {
+            max_fee_per_gas: capped_max_fee,
+            max_priority_fee_per_gas: priority.min(capped_max_fee),
+        },
+        source,
+    )
+}
+
+/// Constant fees from `config`, clamped to the admin maximums
+fn fallback_fees(tx_type: TxType, settings: &FeeSettings) -> TxFees {
+    match TxFees::fallback(tx_type) {
+        TxFees::Legacy { gas_price } => TxFees::Legacy {
+            gas_price: gas_price.min(settings.max_fee_per_gas),
+        },
+        TxFees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        } => {
+            let max_fee = max_fee_per_gas.min(settings.max_fee_per_gas);
+            TxFees::Eip1559 {
+                max_fee_per_gas: max_fee,
+                max_priority_fee_per_gas: max_priority_fee_per_gas
+                    .min(settings.max_priority_fee_per_gas)
+                    .min(max_fee),
+            }
+        }
+    }
+}
+
+/// Median of the non-zero rewards reported by `eth_feeHistory`
+fn median_reward(fee_history: &Value) -> Option<u64> {
+    let mut rewards: Vec<u64> = fee_history
+        .get("reward")?
+        .as_array()?
+        .iter()
+        .filter_map(|block| block.get(0))
+        .filter_map(|reward| rpc_util::parse_quantity(reward).ok())
+        .filter(|reward| *reward > 0)
+        .collect();
+
+    if rewards.is_empty() {
+        return None;
+    }
+
+    rewards.sort_unstable();
+    Some(rewards[rewards.len() / 2])
+}
+
+// ==============================================================================
+// RPC Queries
+// ==============================================================================
+
+pub(crate) fn call_object(from: &str, to: Option<&[u8; 20]>, data: &[u8], value: u64) -> Value {
+    let mut call = json!({
+        "from": from,
+        "data": format!("0x{}", hex::encode(data)),
+        "value": format!("0x{:x}", value),
+    });
+    if let Some(to) = to {
+        call["to"] = json!(format!("0x{}", hex::encode(to)));
+    }
+    call
+}
+
+/// Ask the RPC how much gas the call would use
+pub async fn estimate_gas(
+    chain_id: u64,
+    from: &str,
+    to: Option<&[u8; 20]>,
+    data: &[u8],
+    value: u64,
+) -> Result<u64, String> {
+    let result = rpc_util::call(
+        chain_id,
+        "eth_estimateGas",
+        json!([call_object(from, to, data, value)]),
+    )
+    .await?;
+    rpc_util::parse_quantity(&result)
+}
+
+async fn fetch_gas_price(chain_id: u64) -> Result<u64, String> {
+    let result = rpc_util::call(chain_id, "eth_gasPrice", json!([])).await?;
+    rpc_util::parse_quantity(&result)
+}
+
+/// Next block's base fee and the median tip of recent blocks
+async fn fetch_fee_history(chain_id: u64) -> Result<(u64, u64), String> {
+    let result = rpc_util::call(
+        chain_id,
+        "eth_feeHistory",
+        json!([
+            format!("0x{:x}", config::FEE_HISTORY_BLOCKS),
+            "latest",
+            [config::FEE_HISTORY_REWARD_PERCENTILE]
+        ]),
+    )
+    .await?;
+
+    // The last entry is the base fee of the block after `latest`
+    let base_fee = result
+        .get("baseFeePerGas")
+        .and_then(|fees| fees.as_array())
+        .and_then(|fees| fees.last())
+        .ok_or_else(|| "No baseFeePerGas in eth_feeHistory response".to_string())
+        .and_then(rpc_util::parse_quantity)?;
+
+    let tip = median_reward(&result).unwrap_or(config::MAX_PRIORITY_FEE_PER_GAS);
+
+    Ok((base_fee, tip))
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+pub fn settings() -> FeeSettings {
+    STATE.with(|state| state.borrow().fees.clone())
+}
+
+/// Choose the gas limit and fees for a transaction
+///
+/// Never fails: each value that cannot be estimated falls back to the
+/// corresponding `config` constant (clamped to the admin maximums).
+///
+/// # Arguments
+/// * `chain_id` - Network the transaction is sent on
+/// * `tx_type` - Envelope the fees are for
+/// * `from` - Sender address (0x-prefixed)
+/// * `to` - Recipient, or None for contract creation
+/// * `data` - Calldata / init code
+/// * `value` - Value in wei
+///
+/// # Returns
+/// * `FeeQuote` - Chosen gas limit and fees, with their sources
+pub async fn quote(
+    chain_id: u64,
+    tx_type: TxType,
+    from: &str,
+    to: Option<&[u8; 20]>,
+    data: &[u8],
+    value: u64,
+) -> FeeQuote {
+    let settings = settings();
+
+    let (gas_estimate, gas_limit, gas_limit_source) =
+        match estimate_gas(chain_id, from, to, data, value).await {
+            Ok(estimate) => {
+                let (limit, source) = gas_limit_from_estimate(estimate, &settings);
+                (Some(estimate), limit, source)
+            }
+            Err(e) => {
+                ic_cdk::println!("   ⚠️  Gas estimation failed, using fallback: {}", e);
+                (
+                    None,
+                    config::GAS_LIMIT.min(settings.max_gas_limit),
+                    FeeSource::Fallback,
+                )
+            }
+        };
+
+    let (fees, base_fee_per_gas, fee_source) = current_fees(chain_id, tx_type, &settings).await;
+
+    ic_cdk::println!(
+        "   ⛽ Gas limit: {} ({:?}), fees: {:?} ({:?})",
+        gas_limit,
+        gas_limit_source,
+        fees,
+        fee_source
+    );
+
+    FeeQuote {
+        gas_limit,
+        gas_estimate,
+        gas_limit_source,
+        fees,
+        base_fee_per_gas,
+        fee_source,
+    }
+}
+
+/// Current market fees for `tx_type` on `chain_id`, clamped to the admin maximums
+///
+/// # Returns
+/// * `(TxFees, Option<u64>, FeeSource)` - Fees, next base fee (EIP-1559 only)
+///   and where the fees came from
+pub async fn current_fees(
+    chain_id: u64,
+    tx_type: TxType,
+    settings: &FeeSettings,
+) -> (TxFees, Option<u64>, FeeSource) {
+    let (fees, base_fee_per_gas, fee_source) = match tx_type {
+        TxType::Legacy => match fetch_gas_price(chain_id).await {
+            Ok(gas_price) => {
+                let (fees, source) = legacy_fees(gas_price, settings);
+                (fees, None, source)
+            }
+            Err(e) => {
+                ic_cdk::println!("   ⚠️  eth_gasPrice failed, using fallback: {}", e);
+                (fallback_fees(tx_type, settings), None, FeeSource::Fallback)
+            }
+        },
+        TxType::Eip1559 => match fetch_fee_history(chain_id).await {
+            Ok((base_fee, tip)) => {
+                let (fees, source) = eip1559_fees(base_fee, tip, settings);
+                (fees, Some(base_fee), source)
+            }
+            Err(e) => {
+                ic_cdk::println!("   ⚠️  eth_feeHistory failed, using fallback: {}", e);
+                (fallback_fees(tx_type, settings), None, FeeSource::Fallback)
+            }
+        },
+    };
+
+    if let Some(base_fee) = base_fee_per_gas {
+        if base_fee > fees.max_price_per_gas() {
+            ic_cdk::println!(
+                "   ⚠️  Base fee {} wei exceeds the fee cap {} wei; transaction may stay pending",
+                base_fee,
+                fees.max_price_per_gas()
+            );
+        }
+    }
+
+    (fees, base_fee_per_gas, fee_source)
+}
+
+// ==============================================================================
+// Replacement Fees
+// ==============================================================================
+
+fn bump(value: u64, percent: u64) -> u64 {
+    (value as u128 * (100 + percent) as u128 / 100).min(u64::MAX as u128) as u64
+}
+
+/// Fees for a transaction replacing one that paid `previous`
+///
+/// Each field is the larger of the bumped previous value and the current
+/// market value, clamped to the admin maximums. Nodes only accept a
+/// replacement that raises every fee field by at least
+/// `config::MIN_REPLACEMENT_FEE_BUMP_PERCENT`.
+///
+/// # Returns
+/// * `Result<TxFees, String>` - Replacement fees, or error if the caps leave
+///   no room for a sufficient bump
+pub fn replacement_fees(
+    previous: &TxFees,
+    market: &TxFees,
+    settings: &FeeSettings,
+) -> Result<TxFees, String> {
+    let bump_percent = config::REPLACEMENT_FEE_BUMP_PERCENT;
+    let min_percent = config::MIN_REPLACEMENT_FEE_BUMP_PERCENT;
+
+    let (fees, sufficient) = match *previous {
+        TxFees::Legacy { gas_price } => {
+            let new_price = bump(gas_price, bump_percent)
+                .max(market.max_price_per_gas())
+                .min(settings.max_fee_per_gas);
+            (
+                TxFees::Legacy {
+                    gas_price: new_price,
+                },
+                new_price >= bump(gas_price, min_percent),
+            )
+        }
+        TxFees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        } => {
+            let market_priority = match *market {
+                TxFees::Eip1559 {
+                    max_priority_fee_per_gas,
+                    ..
+                } => max_priority_fee_per_gas,
+                TxFees::Legacy { .. } => 0,
+            };
+            let new_max_fee = bump(max_fee_per_gas, bump_percent)
+                .max(market.max_price_per_gas())
+                .min(settings.max_fee_per_gas);
+            let new_priority = bump(max_priority_fee_per_gas, bump_percent)
+                .max(market_priority)
+                .min(settings.max_priority_fee_per_gas)
+                .min(new_max_fee);
+            (
+                TxFees::Eip1559 {
+                    max_fee_per_gas: new_max_fee,
+                    max_priority_fee_per_gas: new_priority,
+                },
+                new_max_fee >= bump(max_fee_per_gas, min_percent)
+                    && new_priority >= bump(max_priority_fee_per_gas, min_percent),
+            )
+        }
+    };
+
+    if !sufficient {
+        return Err(format!(
+            "Fee caps do not allow a {}% bump over {:?}; raise them with set_fee_settings",
+            min_percent, previous
+        ));
+    }
+
+    Ok(fees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gas_limit_gets_the_margin_and_the_cap() {
+        let settings = FeeSettings::default();
+
+        assert_eq!(
+            gas_limit_from_estimate(250_000, &settings),
+            (300_000, FeeSource::Estimated)
+        );
+        assert_eq!(
+            gas_limit_from_estimate(u64::MAX, &settings),
+            (settings.max_gas_limit, FeeSource::Capped)
+        );
+    }
+
+    #[test]
+    fn eip1559_max_fee_is_twice_the_base_fee_plus_the_capped_tip() {
+        let settings = FeeSettings::default();
+
+        let (fees, source) = eip1559_fees(30_000_000_000, 15_000_000_000, &settings);
+        assert_eq!(
+            fees,
+            TxFees::Eip1559 {
+                max_fee_per_gas: 70_000_000_000,
+                max_priority_fee_per_gas: 10_000_000_000,
+            }
+        );
+        assert_eq!(source, FeeSource::Capped);
+
+        let (fees, source) = eip1559_fees(1_000_000_000, 1_000_000_000, &settings);
+        assert_eq!(fees.max_price_per_gas(), 3_000_000_000);
+        assert_eq!(source, FeeSource::Estimated);
+    }
+
+    #[test]
+    fn legacy_gas_price_is_clamped() {
+        let settings = FeeSettings::default();
+
+        let (fees, source) = legacy_fees(u64::MAX, &settings);
+        assert_eq!(fees.max_price_per_gas(), settings.max_fee_per_gas);
+        assert_eq!(source, FeeSource::Capped);
+    }
+
+    #[test]
+    fn median_reward_skips_empty_blocks() {
+        let fee_history = json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x3b9aca00", "0x3b9aca00", "0x77359400"],
+            "reward": [["0x0"], ["0x3b9aca00"], ["0x77359400"], ["0x1dcd6500"]]
+        });
+        assert_eq!(median_reward(&fee_history), Some(1_000_000_000));
+
+        assert_eq!(median_reward(&json!({ "reward": [["0x0"]] })), None);
+        assert_eq!(median_reward(&json!({})), None);
+    }
+
+    #[test]
+    fn replacement_bumps_every_fee_field() {
+        let settings = FeeSettings::default();
+        let previous = TxFees::Legacy {
+            gas_price: 20_000_000_000,
+        };
+        let market = TxFees::Legacy {
+            gas_price: 18_000_000_000,
+        };
+        assert_eq!(
+            replacement_fees(&previous, &market, &settings),
+            Ok(TxFees::Legacy {
+                gas_price: 25_000_000_000
+            })
+        );
+
+        let previous = TxFees::Eip1559 {
+            max_fee_per_gas: 40_000_000_000,
+            max_priority_fee_per_gas: 2_000_000_000,
+        };
+        let market = TxFees::Eip1559 {
+            max_fee_per_gas: 60_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+        };
+        assert_eq!(
+            replacement_fees(&previous, &market, &settings),
+            Ok(TxFees::Eip1559 {
+                max_fee_per_gas: 60_000_000_000,
+                max_priority_fee_per_gas: 2_500_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn replacement_fails_when_the_caps_leave_no_room() {
+        let settings = FeeSettings {
+            max_fee_per_gas: 21_000_000_000,
+            ..FeeSettings::default()
+        };
+        let previous = TxFees::Legacy {
+            gas_price: 20_000_000_000,
+        };
+        assert!(replacement_fees(&previous, &previous, &settings).is_err());
+    }
+}
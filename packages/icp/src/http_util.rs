@@ -92,7 +92,7 @@ pub async fn make_http_request(
     match http_request(request, total_cycles).await {
         Ok((response,)) => {
             let status_code: u32 = response.status.0.try_into().unwrap_or(500);
-            if status_code >= 200 && status_code < 300 {
+            if (200..300).contains(&status_code) {
                 ic_cdk::println!("   ✅ Response: {} bytes (status {})", response.body.len(), status_code);
                 Ok(response.body)
             } else {
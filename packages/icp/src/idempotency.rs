@@ -0,0 +1,299 @@
+// Idempotency Module
+// Remembers client-supplied idempotency keys so retried calls do not repeat work
+//
+// Keys are scoped to the calling principal. A generation key maps to the job it
+// created; a `register_ip` key maps to the call's outcome, or to the hash of
+// the transaction it sent before failing so a retry can resume from there. A
+// call that fails before sending anything forgets its key. Entries expire after
+// a configurable retention window and are purged lazily on write.
+
+use crate::storage::{self, StableMap};
+use crate::{config, STATE};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Maximum accepted key length
+pub const MAX_KEY_LENGTH: usize = 128;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdempotentOperation {
+    Generation,
+    RegisterIp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Outcome {
+    /// Generation tracked by a job; its current status is the answer
+    Job(u64),
+    /// The first call has not finished yet
+    InFlight,
+    /// The first call sent this transaction, then failed; a retry resumes
+    /// after it instead of sending it again
+    Sent(String),
+    /// Final result of the first call
+    Completed(Result<String, String>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    pub operation: IdempotentOperation,
+    pub outcome: Outcome,
+    pub created_at: u64,
+}
+
+storage::impl_json_storable!(Entry);
+
+/// Retention setting (entries live in stable memory)
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdempotencyStore {
+    retention_secs: u64,
+}
+
+impl Default for IdempotencyStore {
+    fn default() -> Self {
+        Self {
+            retention_secs: config::DEFAULT_IDEMPOTENCY_RETENTION_SECS,
+        }
+    }
+}
+
+thread_local! {
+    /// "<principal>:<key>" -> entry
+    static ENTRIES: StableMap<String, Entry> =
+        storage::stable_map(storage::IDEMPOTENCY_MEMORY_ID);
+}
+
+impl IdempotencyStore {
+    pub fn retention_secs(&self) -> u64 {
+        self.retention_secs
+    }
+
+    pub fn set_retention_secs(&mut self, secs: u64) {
+        self.retention_secs = secs;
+    }
+
+    fn is_expired(&self, entry: &Entry, now: u64) -> bool {
+        now.saturating_sub(entry.created_at) > self.retention_secs.saturating_mul(NANOS_PER_SEC)
+    }
+
+    /// Return the live entry for `key`, or an error if it was used for a
+    /// different operation
+    pub fn lookup(
+        &self,
+        caller: &Principal,
+        key: &str,
+        operation: IdempotentOperation,
+        now: u64,
+    ) -> Result<Option<Entry>, String> {
+        match ENTRIES.with(|entries| entries.borrow().get(&scoped_key(caller, key))) {
+            Some(entry) if !self.is_expired(&entry, now) => {
+                if entry.operation != operation {
+                    return Err(format!(
+                        "Idempotency key '{}' was already used for {:?}",
+                        key, entry.operation
+                    ));
+                }
+                Ok(Some(entry))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        caller: &Principal,
+        key: &str,
+        operation: IdempotentOperation,
+        outcome: Outcome,
+        now: u64,
+    ) {
+        self.purge_expired(now);
+
+        let scoped = scoped_key(caller, key);
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            let created_at = entries
+                .get(&scoped)
+                .filter(|entry| !self.is_expired(entry, now))
+                .map_or(now, |entry| entry.created_at);
+
+            entries.insert(
+                scoped,
+                Entry {
+                    operation,
+                    outcome,
+                    created_at,
+                },
+            );
+        });
+    }
+
+    pub fn forget(&mut self, caller: &Principal, key: &str) {
+        ENTRIES.with(|entries| entries.borrow_mut().remove(&scoped_key(caller, key)));
+    }
+
+    fn purge_expired(&mut self, now: u64) {
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            let expired: Vec<String> = entries
+                .iter()
+                .filter(|(_, entry)| self.is_expired(entry, now))
+                .map(|(key, _)| key)
+                .collect();
+            for key in expired {
+                entries.remove(&key);
+            }
+        });
+    }
+}
+
+fn scoped_key(caller: &Principal, key: &str) -> String {
+    format!("{}:{}", caller.to_text(), key)
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+pub fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("Idempotency key must not be empty".to_string());
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return Err(format!(
+            "Idempotency key too long: {} bytes (max {})",
+            key.len(),
+            MAX_KEY_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Look up a previous call made with `key`
+pub fn lookup(
+    caller: &Principal,
+    key: &str,
+    operation: IdempotentOperation,
+) -> Result<Option<Outcome>, String> {
+    validate_key(key)?;
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        state
+            .borrow()
+            .idempotency
+            .lookup(caller, key, operation, now)
+            .map(|entry| entry.map(|e| e.outcome))
+    })
+}
+
+/// Remember the outcome (or in-flight marker) of a call made with `key`
+pub fn remember(caller: &Principal, key: &str, operation: IdempotentOperation, outcome: Outcome) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        state
+            .borrow_mut()
+            .idempotency
+            .record(caller, key, operation, outcome, now)
+    });
+}
+
+/// Drop the entry of `key`, e.g. after a call failed before sending anything
+pub fn forget(caller: &Principal, key: &str) {
+    STATE.with(|state| state.borrow_mut().idempotency.forget(caller, key));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = NANOS_PER_SEC;
+
+    fn store(retention_secs: u64) -> IdempotencyStore {
+        IdempotencyStore { retention_secs }
+    }
+
+    fn caller(byte: u8) -> Principal {
+        Principal::from_slice(&[byte])
+    }
+
+    #[test]
+    fn keys_are_scoped_to_the_caller() {
+        let mut store = store(60);
+        store.record(&caller(1), "key", IdempotentOperation::Generation, Outcome::Job(7), 0);
+
+        let entry = store
+            .lookup(&caller(1), "key", IdempotentOperation::Generation, SECOND)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(entry.outcome, Outcome::Job(7)));
+        assert!(store
+            .lookup(&caller(2), "key", IdempotentOperation::Generation, SECOND)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn a_key_cannot_change_operation() {
+        let mut store = store(60);
+        store.record(&caller(1), "key", IdempotentOperation::Generation, Outcome::Job(7), 0);
+
+        let e = store
+            .lookup(&caller(1), "key", IdempotentOperation::RegisterIp, 0)
+            .unwrap_err();
+        assert!(e.contains("Generation"));
+    }
+
+    #[test]
+    fn updates_keep_the_creation_time() {
+        let mut store = store(60);
+        let (caller, op) = (caller(1), IdempotentOperation::RegisterIp);
+        store.record(&caller, "key", op, Outcome::InFlight, 0);
+        store.record(&caller, "key", op, Outcome::Sent("0xabc".to_string()), 50 * SECOND);
+
+        let entry = store.lookup(&caller, "key", op, 60 * SECOND).unwrap().unwrap();
+        assert_eq!(entry.created_at, 0);
+        assert!(matches!(entry.outcome, Outcome::Sent(ref hash) if hash == "0xabc"));
+
+        // Retention runs from the first call, not the last update
+        assert!(store.lookup(&caller, "key", op, 61 * SECOND).unwrap().is_none());
+    }
+
+    #[test]
+    fn expired_entries_are_purged_on_write() {
+        let mut store = store(60);
+        let op = IdempotentOperation::Generation;
+        store.record(&caller(1), "old", op, Outcome::Job(1), 0);
+        store.record(&caller(1), "new", op, Outcome::Job(2), 61 * SECOND);
+
+        let keys: Vec<String> = ENTRIES.with(|entries| entries.borrow().keys().collect());
+        assert_eq!(keys, vec![scoped_key(&caller(1), "new")]);
+    }
+
+    #[test]
+    fn forgotten_keys_can_be_reused() {
+        let mut store = store(60);
+        let op = IdempotentOperation::RegisterIp;
+        store.record(&caller(1), "key", op, Outcome::InFlight, 0);
+        store.forget(&caller(1), "key");
+
+        assert!(store.lookup(&caller(1), "key", op, 0).unwrap().is_none());
+        assert!(store
+            .lookup(&caller(1), "key", IdempotentOperation::Generation, 0)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn key_length_is_validated() {
+        assert!(validate_key("").is_err());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LENGTH)).is_ok());
+        assert!(validate_key(&"k".repeat(MAX_KEY_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,959 @@
+// Orchestration Jobs Module
+// Resumable, step-by-step execution of the AI -> Story -> Constellation pipeline
+//
+// Each generation request becomes a persisted `Job`. Steps run one at a time and
+// their artifacts are written to STATE as soon as they are produced, so a failure
+// (or an upgrade) never loses work that already happened on-chain. Timers drive
+// jobs forward; failed steps are retried with exponential backoff, except for a
+// Story registration whose simulation reverted, which fails the job at once.
+
+use crate::evm_util::EvmAccount;
+use crate::simulation::RevertReason;
+use crate::storage::{self, StableMap};
+use crate::transactions::TxError;
+use crate::{
+    ai_util, config, constellation_util, derivatives, get_config, networks, quota, story_util,
+    GenerationInput, GenerationOutput, STATE,
+};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// Maximum page size for `list_jobs`
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting for a timer to pick it up
+    Queued,
+    /// A step is currently executing
+    Running,
+    /// Last step failed; another attempt is scheduled at `next_attempt_at`
+    RetryScheduled,
+    Completed,
+    /// Retries exhausted; `retry_job` can re-queue it
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStep {
+    GenerateContent,
+    RegisterOnStory,
+    LogOnConstellation,
+    RecordProvenance,
+}
+
+/// Execution order of the pipeline
+const PIPELINE: [JobStep; 4] = [
+    JobStep::GenerateContent,
+    JobStep::RegisterOnStory,
+    JobStep::LogOnConstellation,
+    JobStep::RecordProvenance,
+];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StepState {
+    pub step: JobStep,
+    pub status: StepStatus,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+/// Intermediate results produced by the pipeline steps
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct JobArtifacts {
+    pub image_url: Option<String>,
+    pub content_hash: Option<String>,
+    pub story_tx_hash: Option<String>,
+    pub story_ip_id: Option<String>,
+    pub story_token_id: Option<u64>,
+    pub story_nft_contract: Option<String>,
+    pub story_chain_id: Option<u64>,
+    /// Parent IP the work was registered as a derivative of
+    pub story_parent_ip_id: Option<String>,
+    pub constellation_tx_hash: Option<String>,
+    pub record_id: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Job {
+    pub id: u64,
+    pub creator: Principal,
+    pub input: GenerationInput,
+    pub status: JobStatus,
+    pub steps: Vec<StepState>,
+    pub artifacts: JobArtifacts,
+    /// Final result, set once the job completes
+    pub output: Option<GenerationOutput>,
+    pub last_error: Option<String>,
+    /// Why Story registration would revert, if its simulation did
+    #[serde(default)]
+    pub revert: Option<RevertReason>,
+    /// IC time (nanoseconds) of the next scheduled retry
+    pub next_attempt_at: Option<u64>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Job {
+    fn new(id: u64, creator: Principal, input: GenerationInput, now: u64) -> Self {
+        let steps = PIPELINE
+            .iter()
+            .map(|step| StepState {
+                step: *step,
+                status: StepStatus::Pending,
+                attempts: 0,
+                last_error: None,
+                started_at: None,
+                finished_at: None,
+            })
+            .collect();
+
+        Job {
+            id,
+            creator,
+            input,
+            status: JobStatus::Queued,
+            steps,
+            artifacts: JobArtifacts::default(),
+            output: None,
+            last_error: None,
+            revert: None,
+            next_attempt_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn step_mut(&mut self, step: JobStep) -> &mut StepState {
+        self.steps
+            .iter_mut()
+            .find(|s| s.step == step)
+            .expect("every pipeline step has a StepState")
+    }
+
+    /// First step that has not succeeded yet
+    fn next_step(&self) -> Option<JobStep> {
+        self.steps
+            .iter()
+            .find(|s| s.status != StepStatus::Succeeded)
+            .map(|s| s.step)
+    }
+
+    /// Mark the next step as running
+    ///
+    /// # Returns
+    /// * `Option<JobStep>` - The step to execute, None if the job is terminal
+    ///   or has just completed
+    fn start_next_step(&mut self, now: u64) -> Option<JobStep> {
+        if self.status.is_terminal() {
+            return None;
+        }
+
+        let step = match self.next_step() {
+            Some(step) => step,
+            None => {
+                self.status = JobStatus::Completed;
+                return None;
+            }
+        };
+
+        self.status = JobStatus::Running;
+        self.next_attempt_at = None;
+        let state = self.step_mut(step);
+        state.status = StepStatus::Running;
+        state.attempts += 1;
+        state.started_at = Some(now);
+        Some(step)
+    }
+
+    fn complete_step(&mut self, step: JobStep, now: u64) {
+        let state = self.step_mut(step);
+        state.status = StepStatus::Succeeded;
+        state.last_error = None;
+        state.finished_at = Some(now);
+    }
+
+    /// Record a failed attempt of `step` and schedule the next one
+    ///
+    /// # Returns
+    /// * `Option<Duration>` - Delay before the retry, None if the job stops
+    ///   (cancelled, out of attempts, or its simulation reverted)
+    fn fail_step(&mut self, step: JobStep, error: &str, now: u64) -> Option<Duration> {
+        let attempts = {
+            let state = self.step_mut(step);
+            state.status = StepStatus::Failed;
+            state.last_error = Some(error.to_string());
+            state.finished_at = Some(now);
+            state.attempts
+        };
+        self.last_error = Some(format!("{:?}: {}", step, error));
+
+        if self.status == JobStatus::Cancelled {
+            return None;
+        }
+
+        // The simulation ran against the current chain state; retrying would
+        // only hit the same revert
+        if attempts >= config::JOB_MAX_STEP_ATTEMPTS || self.revert.is_some() {
+            self.status = JobStatus::Failed;
+            return None;
+        }
+
+        let delay = retry_delay(attempts);
+        self.status = JobStatus::RetryScheduled;
+        self.next_attempt_at = Some(now + delay.as_nanos() as u64);
+        Some(delay)
+    }
+
+    /// Re-queue a failed or cancelled job from the step that stopped it
+    fn requeue(&mut self) -> Result<(), String> {
+        if !matches!(self.status, JobStatus::Failed | JobStatus::Cancelled) {
+            return Err(format!(
+                "Job #{} is {:?}; only Failed or Cancelled jobs can be retried",
+                self.id, self.status
+            ));
+        }
+
+        if let Some(step) = self.next_step() {
+            let state = self.step_mut(step);
+            state.status = StepStatus::Pending;
+            state.attempts = 0;
+        }
+
+        self.status = JobStatus::Queued;
+        self.next_attempt_at = None;
+        self.last_error = None;
+        self.revert = None;
+        Ok(())
+    }
+
+    fn cancel(&mut self) -> Result<(), String> {
+        if self.status.is_terminal() {
+            return Err(format!("Job #{} is already {:?}", self.id, self.status));
+        }
+        self.status = JobStatus::Cancelled;
+        self.next_attempt_at = None;
+        Ok(())
+    }
+}
+
+/// One page of `list_jobs` results
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct JobPage {
+    pub jobs: Vec<Job>,
+    pub next_cursor: Option<u64>,
+}
+
+storage::impl_json_storable!(Job);
+
+/// Persistent job store (jobs live in stable memory, the ID counter in the
+/// snapshot)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct JobStore {
+    next_id: u64,
+}
+
+thread_local! {
+    /// job id -> job
+    static JOBS: StableMap<u64, Job> = storage::stable_map(storage::JOBS_MEMORY_ID);
+    /// ids of the jobs that are not in a terminal state
+    static OPEN_JOBS: StableMap<u64, ()> = storage::stable_map(storage::OPEN_JOBS_MEMORY_ID);
+    /// Story registration tx hash (lowercase) -> job id
+    static JOBS_BY_STORY_TX: StableMap<String, u64> =
+        storage::stable_map(storage::JOBS_BY_STORY_TX_MEMORY_ID);
+}
+
+impl JobStore {
+    pub fn create(&mut self, creator: Principal, input: GenerationInput) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        save(Job::new(id, creator, input, ic_cdk::api::time()));
+
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        JOBS.with(|jobs| jobs.borrow().get(&id))
+    }
+
+    pub fn list(&self, cursor: Option<u64>, limit: u32) -> JobPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+        let start = cursor.map_or(0, |c| c.saturating_add(1));
+
+        let mut jobs: Vec<Job> = JOBS.with(|jobs| {
+            jobs.borrow()
+                .range(start..)
+                .take(limit + 1)
+                .map(|(_, job)| job)
+                .collect()
+        });
+
+        let next_cursor = if jobs.len() > limit {
+            jobs.truncate(limit);
+            jobs.last().map(|j| j.id)
+        } else {
+            None
+        };
+
+        JobPage { jobs, next_cursor }
+    }
+
+    /// Jobs that are not in a terminal state
+    fn pending(&self) -> Vec<Job> {
+        let ids: Vec<u64> =
+            OPEN_JOBS.with(|open| open.borrow().iter().map(|(id, ())| id).collect());
+        JOBS.with(|jobs| {
+            let jobs = jobs.borrow();
+            ids.iter().filter_map(|id| jobs.get(id)).collect()
+        })
+    }
+}
+
+/// Write `job` and keep the open-job set and the Story tx index up to date
+fn save(job: Job) {
+    OPEN_JOBS.with(|open| {
+        let mut open = open.borrow_mut();
+        if job.status.is_terminal() {
+            open.remove(&job.id);
+        } else {
+            open.insert(job.id, ());
+        }
+    });
+    if let Some(hash) = &job.artifacts.story_tx_hash {
+        JOBS_BY_STORY_TX.with(|by_tx| by_tx.borrow_mut().insert(hash.to_lowercase(), job.id));
+    }
+    JOBS.with(|jobs| jobs.borrow_mut().insert(job.id, job));
+}
+
+/// Jobs whose Story registration is one of `tx_hashes` (lowercase)
+fn sent_with(tx_hashes: &[String]) -> Vec<Job> {
+    let ids: BTreeSet<u64> = JOBS_BY_STORY_TX.with(|by_tx| {
+        let by_tx = by_tx.borrow();
+        tx_hashes
+            .iter()
+            .filter_map(|hash| by_tx.get(hash))
+            .collect()
+    });
+    // The index keeps hashes a job has since dropped; its artifacts decide
+    ids.into_iter()
+        .filter_map(|id| JOBS.with(|jobs| jobs.borrow().get(&id)))
+        .filter(|job| {
+            job.artifacts
+                .story_tx_hash
+                .as_ref()
+                .is_some_and(|hash| tx_hashes.contains(&hash.to_lowercase()))
+        })
+        .collect()
+}
+
+// ==============================================================================
+// In-flight Tracking (not persisted)
+// ==============================================================================
+
+thread_local! {
+    /// Jobs currently being driven by a call context
+    static ACTIVE_JOBS: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Ensures a job is driven by at most one call context at a time.
+/// Released on drop, including when the call context traps.
+struct JobGuard {
+    job_id: u64,
+}
+
+impl JobGuard {
+    fn acquire(job_id: u64) -> Option<Self> {
+        ACTIVE_JOBS.with(|active| {
+            if active.borrow_mut().insert(job_id) {
+                Some(JobGuard { job_id })
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        ACTIVE_JOBS.with(|active| {
+            active.borrow_mut().remove(&self.job_id);
+        });
+    }
+}
+
+fn is_active(job_id: u64) -> bool {
+    ACTIVE_JOBS.with(|active| active.borrow().contains(&job_id))
+}
+
+// ==============================================================================
+// State Helpers
+// ==============================================================================
+
+fn with_job_mut<R>(job_id: u64, f: impl FnOnce(&mut Job) -> R) -> Option<R> {
+    let mut job = JOBS.with(|jobs| jobs.borrow().get(&job_id))?;
+    let result = f(&mut job);
+    job.updated_at = ic_cdk::api::time();
+    save(job);
+    Some(result)
+}
+
+fn load_job(job_id: u64) -> Option<Job> {
+    STATE.with(|state| state.borrow().jobs.get(job_id))
+}
+
+/// Record the Story IP ID and token ID of a confirmed registration on the job
+/// that sent it (any of `tx_hashes`, see `Registry::apply_story_result`)
+pub fn apply_story_result(tx_hashes: &[String], mined_tx_hash: &str, ip_id: &str, token_id: u64) {
+    let now = ic_cdk::api::time();
+
+    for mut job in sent_with(tx_hashes) {
+        job.artifacts.story_tx_hash = Some(mined_tx_hash.to_string());
+        job.artifacts.story_ip_id = Some(ip_id.to_string());
+        job.artifacts.story_token_id = Some(token_id);
+        if let Some(output) = job.output.as_mut() {
+            output.story_tx_hash = mined_tx_hash.to_string();
+            output.story_ip_id = ip_id.to_string();
+            output.story_token_id = token_id;
+        }
+        job.updated_at = now;
+        save(job);
+    }
+}
+
+// ==============================================================================
+// Submission and Control
+// ==============================================================================
+
+/// Create a job and schedule it to start on the next timer tick
+pub fn submit(creator: Principal, input: GenerationInput) -> u64 {
+    let job_id = STATE.with(|state| state.borrow_mut().jobs.create(creator, input));
+    ic_cdk::println!("🧾 Job #{} queued", job_id);
+    schedule(job_id, Duration::ZERO);
+    job_id
+}
+
+/// Create a job without scheduling it (caller drives it inline)
+pub fn create(creator: Principal, input: GenerationInput) -> u64 {
+    STATE.with(|state| state.borrow_mut().jobs.create(creator, input))
+}
+
+/// Unfinished jobs on `chain_id` signing with `account` whose Story
+/// registration has not succeeded yet, i.e. whose gas is still to be paid
+pub fn unregistered(chain_id: u64, account: &EvmAccount) -> u64 {
+    STATE
+        .with(|state| state.borrow().jobs.pending())
+        .iter()
+        .filter(|job| job.input.chain_id.unwrap_or_else(networks::default_chain_id) == chain_id)
+        .filter(|job| signing_account(&job.creator, &job.input).as_ref() == Ok(account))
+        .filter(|job| {
+            job.steps.iter().any(|state| {
+                state.step == JobStep::RegisterOnStory && state.status != StepStatus::Succeeded
+            })
+        })
+        .count() as u64
+}
+
+/// Re-queue a failed or cancelled job, resetting the attempt counter of the
+/// step that stopped it. Completed steps are not repeated; the retry is
+/// charged to the creator's quota like a new generation.
+pub fn retry(job_id: u64) -> Result<Job, String> {
+    let job = load_job(job_id).ok_or_else(|| format!("Job #{} not found", job_id))?;
+    if matches!(job.status, JobStatus::Failed | JobStatus::Cancelled) {
+        // The remaining steps cost as much as a new generation
+        quota::charge_generation(&job.creator)
+            .map_err(|e| format!("Creator {}: {}", job.creator, quota::describe(e)))?;
+    }
+
+    let job = with_job_mut(job_id, |job| job.requeue().map(|_| job.clone()))
+    .ok_or_else(|| format!("Job #{} not found", job_id))??;
+
+    schedule(job_id, Duration::ZERO);
+    ic_cdk::println!("🔁 Job #{} re-queued", job_id);
+    Ok(job)
+}
+
+/// Cancel a job. A step that is already executing finishes (its on-chain
+/// effects cannot be undone) but no further steps are started.
+pub fn cancel(job_id: u64) -> Result<Job, String> {
+    let job = with_job_mut(job_id, |job| job.cancel().map(|_| job.clone()))
+    .ok_or_else(|| format!("Job #{} not found", job_id))??;
+
+    ic_cdk::println!("🛑 Job #{} cancelled", job_id);
+    Ok(job)
+}
+
+// ==============================================================================
+// Timers
+// ==============================================================================
+
+/// Schedule a job run after `delay`
+pub fn schedule(job_id: u64, delay: Duration) {
+    ic_cdk_timers::set_timer(delay, move || ic_cdk::spawn(run_job(job_id)));
+}
+
+/// Start the periodic sweeper that resumes overdue or interrupted jobs
+///
+/// Timers do not survive upgrades, so this must be called from both `init`
+/// and `post_upgrade`.
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config::JOB_SWEEP_INTERVAL_SECS),
+        sweep,
+    );
+    // Pick up anything left over from before an upgrade right away
+    ic_cdk_timers::set_timer(Duration::ZERO, sweep);
+}
+
+/// Resume every non-terminal job that is not currently being driven and is due
+fn sweep() {
+    let now = ic_cdk::api::time();
+    let due: Vec<u64> = STATE.with(|state| {
+        state
+            .borrow()
+            .jobs
+            .pending()
+            .into_iter()
+            .filter(|job| !is_active(job.id))
+            .filter(|job| job.next_attempt_at.is_none_or(|at| at <= now))
+            .map(|job| job.id)
+            .collect()
+    });
+
+    for job_id in due {
+        ic_cdk::spawn(run_job(job_id));
+    }
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+    let secs = config::JOB_RETRY_BASE_DELAY_SECS.saturating_mul(1u64 << exponent);
+    Duration::from_secs(secs.min(config::JOB_RETRY_MAX_DELAY_SECS))
+}
+
+// ==============================================================================
+// Job Runner
+// ==============================================================================
+
+/// Drive a job forward until it completes, fails a step, or is cancelled
+pub async fn run_job(job_id: u64) {
+    let _guard = match JobGuard::acquire(job_id) {
+        Some(guard) => guard,
+        None => return,
+    };
+
+    loop {
+        // Mark the next step as running (committed at the step's first await)
+        let next = with_job_mut(job_id, |job| job.start_next_step(ic_cdk::api::time())).flatten();
+
+        let step = match next {
+            Some(step) => step,
+            None => break,
+        };
+
+        ic_cdk::println!("⚙️  Job #{}: running {:?}", job_id, step);
+
+        match execute_step(job_id, step).await {
+            Ok(()) => {
+                with_job_mut(job_id, |job| job.complete_step(step, ic_cdk::api::time()));
+            }
+            Err(e) => {
+                handle_step_failure(job_id, step, e);
+                break;
+            }
+        }
+    }
+}
+
+fn handle_step_failure(job_id: u64, step: JobStep, error: String) {
+    ic_cdk::println!("   ❌ Job #{}: {:?} failed: {}", job_id, step, error);
+
+    let retry_in =
+        with_job_mut(job_id, |job| job.fail_step(step, &error, ic_cdk::api::time())).flatten();
+
+    match retry_in {
+        Some(delay) => {
+            ic_cdk::println!("   🔁 Job #{}: retrying in {}s", job_id, delay.as_secs());
+            schedule(job_id, delay);
+        }
+        None => ic_cdk::println!("   ⛔ Job #{}: not retrying", job_id),
+    }
+}
+
+// ==============================================================================
+// Pipeline Steps
+// ==============================================================================
+
+async fn execute_step(job_id: u64, step: JobStep) -> Result<(), String> {
+    let job = load_job(job_id).ok_or_else(|| format!("Job #{} not found", job_id))?;
+
+    match step {
+        JobStep::GenerateContent => step_generate_content(&job).await,
+        JobStep::RegisterOnStory => step_register_on_story(&job).await,
+        JobStep::LogOnConstellation => step_log_on_constellation(&job).await,
+        JobStep::RecordProvenance => step_record_provenance(&job),
+    }
+}
+
+/// STEP 1: AI Content Generation
+async fn step_generate_content(job: &Job) -> Result<(), String> {
+    let (image_url, content_hash) = ai_util::generate_ai_content(job.input.prompt.clone()).await?;
+    ic_cdk::println!("   ✅ Image URL: {}", image_url);
+    ic_cdk::println!("   ✅ Content Hash: {}", content_hash);
+
+    with_job_mut(job.id, |job| {
+        job.artifacts.image_url = Some(image_url);
+        job.artifacts.content_hash = Some(content_hash);
+    });
+    Ok(())
+}
+
+/// Account a generation signs from and mints to
+pub fn signing_account(creator: &Principal, input: &GenerationInput) -> Result<EvmAccount, String> {
+    if input.use_caller_account.unwrap_or(false) {
+        EvmAccount::user(*creator)
+    } else {
+        Ok(EvmAccount::Canister)
+    }
+}
+
+/// STEP 2: Register IP on Story Protocol using SPG (Mint + Register in one tx)
+async fn step_register_on_story(job: &Job) -> Result<(), String> {
+    let content_hash = job
+        .artifacts
+        .content_hash
+        .clone()
+        .ok_or("Missing content hash from GenerateContent step")?;
+
+    // Create metadata URI (for now, use placeholder - in production, upload to IPFS)
+    let metadata_uri = format!("ipfs://placeholder/{}/metadata.json", content_hash);
+
+    // The network is pinned on the input when the job is created
+    let network = networks::resolve(job.input.chain_id)?;
+
+    // SPG NFT contract address of the network (used for Constellation proof)
+    let spg_nft_contract = {
+        let addr = network.spg_nft_contract()?;
+        format!("0x{}", hex::encode(addr.to_fixed_bytes()))
+    };
+
+    // A previous attempt already broadcast the registration: only follow up on
+    // that transaction instead of minting a second IP
+    let story_tx_hash = match job.artifacts.story_tx_hash.clone() {
+        Some(hash) => {
+            ic_cdk::println!("   ↪️  Registration already sent: {}", hash);
+            hash
+        }
+        None => {
+            // Generated works are derivatives of the network's AI model IP
+            let parent = derivatives::parent_for(network.chain_id)
+                .ok_or_else(|| format!("No parent AI model IP is set on {}", network.name))?;
+            let account = signing_account(&job.creator, &job.input)?;
+            let registration = story_util::register_ip_on_story(
+                &network,
+                account,
+                job.input.recipient_evm_address.as_deref(),
+                content_hash,
+                metadata_uri,
+                job.input.license.as_ref(),
+                Some(&parent),
+            )
+            .await;
+            let hash = match registration {
+                Ok(hash) => hash,
+                Err(e) => {
+                    if let TxError::WouldRevert(reason) = &e {
+                        with_job_mut(job.id, |job| job.revert = Some(reason.clone()));
+                    }
+                    return Err(format!("Failed to register IP on Story Protocol: {}", e));
+                }
+            };
+            ic_cdk::println!("   ✅ Transaction Hash: {}", hash);
+
+            // Persist the hash before the next await so a retry (or a trap in
+            // the receipt check) can never broadcast the registration twice
+            with_job_mut(job.id, |job| {
+                job.artifacts.story_tx_hash = Some(hash.clone());
+                job.artifacts.story_nft_contract = Some(spg_nft_contract);
+                job.artifacts.story_chain_id = Some(network.chain_id);
+                job.artifacts.story_parent_ip_id = Some(parent.ip_id);
+            });
+            hash
+        }
+    };
+
+    // Use parsed values if the transaction is already mined; otherwise the
+    // receipt tracker fills them in once it is confirmed
+    let parsed_values = match story_util::check_registration(&network, &story_tx_hash).await {
+        Ok(parsed_values) => parsed_values,
+        Err(e) => {
+            // Nothing was minted: the next attempt sends a new registration
+            with_job_mut(job.id, |job| job.artifacts.story_tx_hash = None);
+            return Err(e);
+        }
+    };
+
+    match parsed_values {
+        Some((ip_id, token_id)) => {
+            ic_cdk::println!("   ✅ Using parsed IP ID and Token ID from receipt");
+            with_job_mut(job.id, |job| {
+                job.artifacts.story_ip_id = Some(ip_id);
+                job.artifacts.story_token_id = Some(token_id);
+            });
+        }
+        None => ic_cdk::println!("   ⏳ IP ID and Token ID pending confirmation"),
+    }
+    Ok(())
+}
+
+/// STEP 3: Log on Constellation DAG (non-critical: failures are recorded, not retried)
+async fn step_log_on_constellation(job: &Job) -> Result<(), String> {
+    let artifacts = &job.artifacts;
+
+    let proof = constellation_util::ProofOfGeneration {
+        content_hash: artifacts.content_hash.clone().unwrap_or_default(),
+        model_name: "deepseek-chat".to_string(),
+        timestamp: ic_cdk::api::time(),
+        story_ip_id: artifacts.story_ip_id.clone().unwrap_or_default(),
+        nft_contract: artifacts.story_nft_contract.clone().unwrap_or_default(),
+        nft_token_id: artifacts.story_token_id.unwrap_or_default(),
+        generator_address: "ICP-Canister".to_string(), // Placeholder for canister identity
+    };
+
+    let constellation_url = get_config().constellation_metagraph_url;
+
+    let constellation_tx_hash =
+        match constellation_util::log_proof_on_constellation(constellation_url, proof).await {
+            Ok(tx_hash) => {
+                ic_cdk::println!("   ✅ Logged on Constellation");
+                ic_cdk::println!("   TX Hash: {}", tx_hash);
+                tx_hash
+            }
+            Err(e) => {
+                ic_cdk::println!("   ⚠️  Constellation logging failed (non-critical): {}", e);
+                format!("CONST-ERROR-{}", ic_cdk::api::time())
+            }
+        };
+
+    with_job_mut(job.id, |job| {
+        job.artifacts.constellation_tx_hash = Some(constellation_tx_hash);
+    });
+    Ok(())
+}
+
+/// STEP 4: Assemble the output and persist the provenance record
+fn step_record_provenance(job: &Job) -> Result<(), String> {
+    let artifacts = &job.artifacts;
+
+    let output = GenerationOutput {
+        image_url: artifacts.image_url.clone().unwrap_or_default(),
+        content_hash: artifacts
+            .content_hash
+            .clone()
+            .ok_or("Missing content hash")?,
+        story_ip_id: artifacts.story_ip_id.clone().unwrap_or_default(),
+        story_tx_hash: artifacts.story_tx_hash.clone().unwrap_or_default(),
+        story_nft_contract: artifacts.story_nft_contract.clone().unwrap_or_default(),
+        story_token_id: artifacts.story_token_id.unwrap_or_default(),
+        constellation_tx_hash: artifacts.constellation_tx_hash.clone().unwrap_or_default(),
+        ai_model_id: "deepseek-chat".to_string(),
+        story_chain_id: artifacts
+            .story_chain_id
+            .unwrap_or_else(networks::default_chain_id),
+        story_parent_ip_id: artifacts.story_parent_ip_id.clone(),
+    };
+
+    let record_id = STATE.with(|state| {
+        state.borrow_mut().registry.insert(
+            job.creator,
+            job.input.prompt.clone(),
+            job.input.metadata.clone(),
+            output.clone(),
+        )
+    })?;
+
+    ic_cdk::println!("   🗂️  Provenance record #{} stored", record_id);
+
+    with_job_mut(job.id, |job| {
+        job.artifacts.record_id = Some(record_id);
+        job.output = Some(output);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IPMetadata;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn job() -> Job {
+        let input = GenerationInput {
+            prompt: "a lighthouse at dusk".to_string(),
+            metadata: IPMetadata {
+                title: "Lighthouse".to_string(),
+                description: String::new(),
+                tags: vec![],
+            },
+            idempotency_key: None,
+            chain_id: None,
+            use_caller_account: None,
+            recipient_evm_address: None,
+            license: None,
+        };
+        Job::new(0, Principal::anonymous(), input, 0)
+    }
+
+    fn step_status(job: &Job, step: JobStep) -> StepStatus {
+        job.steps.iter().find(|s| s.step == step).unwrap().status
+    }
+
+    #[test]
+    fn steps_run_in_pipeline_order_until_completed() {
+        let mut job = job();
+        assert_eq!(job.status, JobStatus::Queued);
+
+        for (i, expected) in PIPELINE.iter().enumerate() {
+            let step = job.start_next_step(i as u64).unwrap();
+            assert_eq!(step, *expected);
+            assert_eq!(job.status, JobStatus::Running);
+            assert_eq!(step_status(&job, step), StepStatus::Running);
+
+            job.complete_step(step, i as u64);
+            assert_eq!(step_status(&job, step), StepStatus::Succeeded);
+        }
+
+        assert_eq!(job.start_next_step(10), None);
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.start_next_step(11), None);
+    }
+
+    #[test]
+    fn failed_steps_are_retried_with_backoff_until_attempts_run_out() {
+        let mut job = job();
+        let mut now = 0;
+
+        for attempt in 1..config::JOB_MAX_STEP_ATTEMPTS {
+            let step = job.start_next_step(now).unwrap();
+            assert_eq!(step, JobStep::GenerateContent);
+
+            let delay = job.fail_step(step, "timeout", now).unwrap();
+            assert_eq!(delay, retry_delay(attempt));
+            assert_eq!(job.status, JobStatus::RetryScheduled);
+            assert_eq!(job.next_attempt_at, Some(now + delay.as_nanos() as u64));
+            now += delay.as_nanos() as u64;
+        }
+
+        let step = job.start_next_step(now).unwrap();
+        assert_eq!(job.fail_step(step, "timeout", now), None);
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.last_error.as_deref(), Some("GenerateContent: timeout"));
+        assert_eq!(job.steps[0].attempts, config::JOB_MAX_STEP_ATTEMPTS);
+    }
+
+    #[test]
+    fn a_reverted_simulation_fails_the_job_at_once() {
+        let mut job = job();
+        let step = job.start_next_step(0).unwrap();
+        job.complete_step(step, 0);
+
+        let step = job.start_next_step(0).unwrap();
+        job.revert = Some(RevertReason::Error("minting denied".to_string()));
+        assert_eq!(job.fail_step(step, "would revert", 0), None);
+        assert_eq!(job.status, JobStatus::Failed);
+    }
+
+    #[test]
+    fn a_step_failing_after_cancel_is_not_retried() {
+        let mut job = job();
+        let step = job.start_next_step(0).unwrap();
+        job.cancel().unwrap();
+
+        assert_eq!(job.fail_step(step, "timeout", 0), None);
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert!(job.cancel().is_err());
+    }
+
+    #[test]
+    fn requeue_resumes_from_the_step_that_stopped_the_job() {
+        let mut job = job();
+        assert!(job.requeue().is_err());
+
+        let step = job.start_next_step(0).unwrap();
+        job.complete_step(step, 0);
+        let step = job.start_next_step(0).unwrap();
+        job.revert = Some(RevertReason::Error("minting denied".to_string()));
+        job.fail_step(step, "would revert", 0);
+
+        job.requeue().unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.revert, None);
+        assert_eq!(job.last_error, None);
+        assert_eq!(step_status(&job, JobStep::GenerateContent), StepStatus::Succeeded);
+        assert_eq!(step_status(&job, JobStep::RegisterOnStory), StepStatus::Pending);
+        assert_eq!(job.steps[1].attempts, 0);
+        assert_eq!(job.start_next_step(SECOND), Some(JobStep::RegisterOnStory));
+    }
+
+    #[test]
+    fn open_jobs_and_story_registrations_are_indexed() {
+        let store = JobStore::default();
+        let mut sent = job();
+        sent.artifacts.story_tx_hash = Some("0xAB".to_string());
+        save(sent.clone());
+
+        let mut done = job();
+        done.id = 1;
+        done.cancel().unwrap();
+        save(done);
+
+        assert_eq!(store.pending().iter().map(|j| j.id).collect::<Vec<_>>(), vec![0]);
+        let hashes = vec!["0xcd".to_string(), "0xab".to_string()];
+        assert_eq!(sent_with(&hashes).len(), 1);
+
+        // A hash the job no longer references does not match
+        sent.artifacts.story_tx_hash = None;
+        sent.cancel().unwrap();
+        save(sent);
+        assert!(sent_with(&hashes).is_empty());
+        assert!(store.pending().is_empty());
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(1).as_secs(), config::JOB_RETRY_BASE_DELAY_SECS);
+        assert_eq!(retry_delay(2).as_secs(), 2 * config::JOB_RETRY_BASE_DELAY_SECS);
+        assert_eq!(retry_delay(3).as_secs(), 4 * config::JOB_RETRY_BASE_DELAY_SECS);
+        assert_eq!(retry_delay(64).as_secs(), config::JOB_RETRY_MAX_DELAY_SECS);
+    }
+}
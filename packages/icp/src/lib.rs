@@ -2,8 +2,9 @@
 // Main orchestrator for cross-chain IP registration and audit
 
 use candid::{CandidType, Deserialize, Principal};
-use primitive_types::U256;
+use serde::Serialize;
 use std::cell::RefCell;
+use std::str::FromStr;
 use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 
 // Custom getrandom implementation for WASM
@@ -32,32 +33,78 @@ mod evm_util;
 mod story_util;
 mod nft_deployment;
 mod constellation_util;
+mod storage;
+mod registry;
+mod jobs;
+mod access;
+mod quota;
+mod idempotency;
+mod rpc_util;
+mod evm_rpc;
+mod fees;
+mod transactions;
+mod simulation;
+mod nonce;
+mod tracker;
+mod events;
+mod networks;
+mod eip712;
+mod licensing;
+mod erc20;
+mod derivatives;
+mod royalties;
+mod disputes;
+mod wallets;
+mod monitoring;
+
+use access::{
+    guard_admin, guard_auditor, guard_authenticated, guard_generator, guard_minter, guard_operator,
+    guard_owner, Role,
+};
 
 // ==============================================================================
 // Data Structures
 // ==============================================================================
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CanisterConfig {
     pub deepseek_api_key: String,
     pub replicate_api_key: Option<String>,
     pub constellation_metagraph_url: String,
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct IPMetadata {
     pub title: String,
     pub description: String,
     pub tags: Vec<String>,
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct GenerationInput {
     pub prompt: String,
     pub metadata: IPMetadata,
+    /// Client-supplied key; repeated calls with the same key return the
+    /// original job/result instead of generating again
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Network to register on (see `list_networks`); None = default network
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Mint to and sign from the caller's derived EVM address (see
+    /// `get_my_evm_address`) instead of the shared canister address
+    #[serde(default)]
+    pub use_caller_account: Option<bool>,
+    /// EIP-55 address that receives the NFT and IP Account; None = the
+    /// caller's linked wallet, or else the signing account
+    #[serde(default)]
+    pub recipient_evm_address: Option<String>,
+    /// PIL terms to attach at registration; None = no license
+    #[serde(default)]
+    pub license: Option<licensing::LicenseChoice>,
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct GenerationOutput {
     pub image_url: String,
     pub content_hash: String,
@@ -67,31 +114,98 @@ pub struct GenerationOutput {
     pub story_token_id: u64,
     pub constellation_tx_hash: String,
     pub ai_model_id: String,
+    /// Chain the Story IP was registered on
+    #[serde(default = "default_story_chain_id")]
+    pub story_chain_id: u64,
+    /// Parent AI model IP this work is a derivative of
+    #[serde(default)]
+    pub story_parent_ip_id: Option<String>,
+}
+
+/// Records created before the network registry were all on Aeneid
+fn default_story_chain_id() -> u64 {
+    config::STORY_CHAIN_ID
+}
+
+/// Error returned by the generation endpoints
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum GenerationError {
+    /// Rejected before any outcall because a per-principal limit was hit
+    QuotaExceeded(quota::QuotaExceeded),
+    /// Rejected before any outcall because the canister is low on cycles or gas
+    InsufficientFunds(monitoring::InsufficientFunds),
+    /// A call with the same idempotency key is still being processed
+    InProgress { job_id: u64, status: jobs::JobStatus },
+    /// Story would reject the registration; nothing was broadcast
+    WouldRevert {
+        job_id: u64,
+        reason: simulation::RevertReason,
+    },
+    /// The pipeline failed (see the job for step-level details)
+    Failed(String),
+}
+
+impl From<String> for GenerationError {
+    fn from(e: String) -> Self {
+        GenerationError::Failed(e)
+    }
 }
 
 // ==============================================================================
 // Canister State
 // ==============================================================================
 
+/// Persisted across upgrades by `storage` (see `pre_upgrade` / `post_upgrade`).
+/// New fields must be `#[serde(default)]`-compatible or come with a schema migration.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct State {
     pub owner: Principal,
-    pub evm_nonce: U256,
     pub nft_contract_address: Option<String>,
+    pub registry: registry::Registry,
+    pub jobs: jobs::JobStore,
+    pub access: access::AccessControl,
+    pub quotas: quota::QuotaState,
+    pub idempotency: idempotency::IdempotencyStore,
+    pub networks: networks::NetworkRegistry,
+    pub rpc: rpc_util::RpcSettings,
+    pub fees: fees::FeeSettings,
+    pub transactions: transactions::TxHistory,
+    pub nonces: nonce::NonceManager,
+    pub wallets: wallets::WalletLinks,
+    pub monitoring: monitoring::FundsMonitor,
+    pub derivatives: derivatives::ParentIpRegistry,
+    pub royalties: royalties::RoyaltyLedger,
+    pub disputes: disputes::DisputeStore,
 }
 
 impl Default for State {
     fn default() -> Self {
         Self {
             owner: Principal::anonymous(),
-            evm_nonce: U256::zero(),
             nft_contract_address: None,
+            registry: registry::Registry::default(),
+            jobs: jobs::JobStore::default(),
+            access: access::AccessControl::default(),
+            quotas: quota::QuotaState::default(),
+            idempotency: idempotency::IdempotencyStore::default(),
+            networks: networks::NetworkRegistry::default(),
+            rpc: rpc_util::RpcSettings::default(),
+            fees: fees::FeeSettings::default(),
+            transactions: transactions::TxHistory::default(),
+            nonces: nonce::NonceManager::default(),
+            wallets: wallets::WalletLinks::default(),
+            monitoring: monitoring::FundsMonitor::default(),
+            derivatives: derivatives::ParentIpRegistry::default(),
+            royalties: royalties::RoyaltyLedger::default(),
+            disputes: disputes::DisputeStore::default(),
         }
     }
 }
 
 // Thread-local state storage
 thread_local! {
-    static CONFIG: RefCell<Option<CanisterConfig>> = RefCell::new(None);
+    static CONFIG: RefCell<Option<CanisterConfig>> = const { RefCell::new(None) };
     static STATE: RefCell<State> = RefCell::new(State::default());
 }
 
@@ -109,36 +223,56 @@ fn init(config: CanisterConfig) {
     });
 
     // Initialize state
-    // NOTE: If redeploying to an address that already has transactions,
-    // you need to set the correct nonce here
+    // Nonces are reconciled with the chain on the first transaction (see `nonce`)
     STATE.with(|state| {
-        let mut state = state.borrow_mut();
-        state.owner = ic_cdk::caller();
-        // TODO: Query this from the RPC on init in production
-        state.evm_nonce = U256::from(8); // Set to current RPC nonce (updated 2025-10-22 post-IP-registration)
+        state.borrow_mut().owner = ic_cdk::caller();
     });
 
     ic_cdk::println!("✅ Brain Canister initialized successfully");
     ic_cdk::println!("   Owner: {}", ic_cdk::caller());
     ic_cdk::println!("   Constellation URL: {}", config.constellation_metagraph_url);
+
+    jobs::start_timers();
+    transactions::start_timers();
+    tracker::start_timers();
+    monitoring::start_timers();
 }
 
 // ==============================================================================
-// Configuration Management
+// Upgrade Hooks
 // ==============================================================================
 
-#[ic_cdk::update]
-fn set_owner(new_owner: Principal) {
-    let caller = ic_cdk::caller();
-
-    STATE.with(|state| {
-        let current_owner = state.borrow().owner;
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    storage::save_to_stable_memory();
+}
 
-        // Only current owner can change owner
-        if current_owner != caller {
-            ic_cdk::trap("Unauthorized: Only current owner can change owner");
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    match storage::restore_from_stable_memory() {
+        Ok(schema_version) => {
+            ic_cdk::println!("✅ State restored from stable memory (schema v{})", schema_version);
+            ic_cdk::println!("   Owner: {}", get_owner());
         }
+        // No snapshot, or one that cannot be restored: trap so the upgrade is
+        // rolled back instead of resetting the owner and state to defaults
+        Err(e) => ic_cdk::trap(&format!("Failed to restore stable snapshot: {}", e)),
+    }
+
+    // Timers are not preserved across upgrades
+    jobs::start_timers();
+    transactions::start_timers();
+    tracker::start_timers();
+    monitoring::start_timers();
+}
+
+// ==============================================================================
+// Configuration Management
+// ==============================================================================
 
+#[ic_cdk::update(guard = "guard_owner")]
+fn set_owner(new_owner: Principal) {
+    STATE.with(|state| {
         state.borrow_mut().owner = new_owner;
     });
 
@@ -155,22 +289,13 @@ fn is_configured() -> bool {
     CONFIG.with(|c| c.borrow().is_some())
 }
 
-#[ic_cdk::update]
+#[ic_cdk::update(guard = "guard_admin")]
 fn update_config(new_config: CanisterConfig) {
-    let caller = ic_cdk::caller();
-
-    // Verify caller is owner
-    STATE.with(|state| {
-        if state.borrow().owner != caller {
-            ic_cdk::trap("Unauthorized: Only owner can update config");
-        }
-    });
-
     CONFIG.with(|c| {
         *c.borrow_mut() = Some(new_config);
     });
 
-    ic_cdk::println!("Configuration updated by owner");
+    ic_cdk::println!("Configuration updated by {}", ic_cdk::caller());
 }
 
 #[ic_cdk::query]
@@ -184,6 +309,113 @@ fn get_constellation_url() -> String {
     })
 }
 
+// ==============================================================================
+// Access Control
+// ==============================================================================
+
+#[ic_cdk::update(guard = "guard_admin")]
+fn grant_role(principal: Principal, role: Role) -> bool {
+    let granted = STATE.with(|state| state.borrow_mut().access.grant(principal, role));
+    ic_cdk::println!("Role {:?} granted to {} by {}", role, principal, ic_cdk::caller());
+    granted
+}
+
+#[ic_cdk::update(guard = "guard_admin")]
+fn revoke_role(principal: Principal, role: Role) -> bool {
+    let revoked = STATE.with(|state| state.borrow_mut().access.revoke(&principal, role));
+    ic_cdk::println!("Role {:?} revoked from {} by {}", role, principal, ic_cdk::caller());
+    revoked
+}
+
+/// List principals holding a role (the owner is an implicit Admin and not listed)
+#[ic_cdk::query(guard = "guard_auditor")]
+fn list_role_holders(role: Role) -> Vec<Principal> {
+    STATE.with(|state| state.borrow().access.holders(role))
+}
+
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_roles(principal: Principal) -> Vec<Role> {
+    STATE.with(|state| state.borrow().access.roles_of(&principal))
+}
+
+#[ic_cdk::query]
+fn get_my_roles() -> Vec<Role> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| state.borrow().access.roles_of(&caller))
+}
+
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_generation_mode(mode: access::GenerationMode) {
+    STATE.with(|state| state.borrow_mut().access.set_generation_mode(mode));
+    ic_cdk::println!("Generation mode set to {:?}", mode);
+}
+
+#[ic_cdk::update(guard = "guard_admin")]
+fn add_to_generation_allowlist(principal: Principal) -> bool {
+    STATE.with(|state| state.borrow_mut().access.add_to_allowlist(principal))
+}
+
+#[ic_cdk::update(guard = "guard_admin")]
+fn remove_from_generation_allowlist(principal: Principal) -> bool {
+    STATE.with(|state| state.borrow_mut().access.remove_from_allowlist(&principal))
+}
+
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_generation_access() -> access::GenerationAccess {
+    STATE.with(|state| state.borrow().access.generation_access())
+}
+
+// ==============================================================================
+// Quotas
+// ==============================================================================
+
+/// Set the default per-principal limits
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_default_quota(config: quota::QuotaConfig) {
+    ic_cdk::println!("Default quota updated: {:?}", config);
+    STATE.with(|state| state.borrow_mut().quotas.set_defaults(config));
+}
+
+/// Override the limits of a single principal (None restores the defaults)
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_principal_quota(principal: Principal, config: Option<quota::QuotaConfig>) {
+    ic_cdk::println!("Quota override for {}: {:?}", principal, config);
+    STATE.with(|state| state.borrow_mut().quotas.set_override(&principal, config));
+}
+
+#[ic_cdk::query]
+fn get_default_quota() -> quota::QuotaConfig {
+    STATE.with(|state| state.borrow().quotas.defaults())
+}
+
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_usage(principal: Principal) -> quota::UsageReport {
+    STATE.with(|state| state.borrow().quotas.report(&principal))
+}
+
+#[ic_cdk::query(guard = "guard_auditor")]
+fn list_usage() -> Vec<quota::UsageReport> {
+    STATE.with(|state| state.borrow().quotas.reports())
+}
+
+#[ic_cdk::query]
+fn get_my_usage() -> quota::UsageReport {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| state.borrow().quotas.report(&caller))
+}
+
+/// Set how long idempotency keys are remembered
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_idempotency_retention(secs: u64) {
+    STATE.with(|state| state.borrow_mut().idempotency.set_retention_secs(secs));
+    ic_cdk::println!("Idempotency retention set to {}s", secs);
+}
+
+#[ic_cdk::query]
+fn get_idempotency_retention() -> u64 {
+    STATE.with(|state| state.borrow().idempotency.retention_secs())
+}
+
 // ==============================================================================
 // Helper Functions
 // ==============================================================================
@@ -207,211 +439,401 @@ pub fn get_config() -> CanisterConfig {
     })
 }
 
-/// Query current nonce from blockchain via RPC
+// ==============================================================================
+// Main Orchestration Function
+// ==============================================================================
+
+/// Run the full pipeline inline and return its output
 ///
-/// This queries eth_getTransactionCount from Alchemy to get the real nonce
-/// Eliminates "nonce too low" errors by always using blockchain truth
+/// The work is tracked as a job (see `jobs`), so if a step fails the artifacts
+/// produced so far are kept and the job is retried in the background. The
+/// caller can follow it with `get_job`.
 ///
 /// # Returns
-/// * `Result<u64, String>` - Current nonce from blockchain or error
-pub async fn get_nonce_from_blockchain() -> Result<u64, String> {
-    ic_cdk::println!("   📡 Querying nonce from blockchain via RPC...");
-
-    // Get our EVM address
-    let evm_address = evm_util::get_canister_evm_address().await?;
-
-    // Story Protocol RPC URL from config
-    let rpc_url = config::STORY_RPC_URL;
-
-    // Build JSON-RPC request for eth_getTransactionCount
-    let payload = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "eth_getTransactionCount",
-        "params": [evm_address, "latest"]  // "latest" for most recent nonce
-    });
+/// * `Result<GenerationOutput, GenerationError>` - Output, or a typed error
+///   (this replaced the earlier `Err : text`; see the README)
+#[ic_cdk::update(guard = "guard_generator")]
+async fn generate_and_register_ip(
+    input: GenerationInput,
+) -> Result<GenerationOutput, GenerationError> {
+    let creator = ic_cdk::caller();
+
+    // A repeated idempotency key returns the original job's result
+    if let Some(job_id) = existing_generation_job(&creator, &input)? {
+        ic_cdk::println!("♻️  Idempotent replay of job #{}", job_id);
+        return generation_result(job_id);
+    }
 
-    let payload_str = serde_json::to_string(&payload)
-        .map_err(|e| format!("Failed to serialize RPC request: {}", e))?;
+    let input = admit_generation(&creator, input).await?;
 
-    ic_cdk::println!("   📡 RPC Request: {}", payload_str);
+    ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    ic_cdk::println!("🚀 PROVENANCE AI ORCHESTRATION STARTED");
+    ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    ic_cdk::println!("   Prompt: {}", input.prompt);
+    ic_cdk::println!("   Title: {}", input.metadata.title);
 
-    // Make HTTP POST to RPC
-    let response = http_util::http_post(rpc_url, &payload_str, 2_000_000_000_000).await?;
+    let idempotency_key = input.idempotency_key.clone();
+    let job_id = jobs::create(creator, input);
+    ic_cdk::println!("   Job ID: {}", job_id);
+
+    if let Some(key) = idempotency_key {
+        idempotency::remember(
+            &creator,
+            &key,
+            idempotency::IdempotentOperation::Generation,
+            idempotency::Outcome::Job(job_id),
+        );
+    }
 
-    ic_cdk::println!("   📡 RPC Response status: {}", response.status);
-    ic_cdk::println!("   📡 RPC Response body: {}", response.body);
+    jobs::run_job(job_id).await;
 
-    // Parse JSON response
-    let json: serde_json::Value = serde_json::from_str(&response.body)
-        .map_err(|e| format!("Failed to parse RPC response: {}", e))?;
+    let result = generation_result(job_id);
+    if result.is_ok() {
+        ic_cdk::println!("\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        ic_cdk::println!("✅ ORCHESTRATION COMPLETE");
+        ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    }
+    result
+}
 
-    // Extract nonce from result field
-    let nonce_hex = json["result"]
-        .as_str()
-        .ok_or_else(|| {
-            if let Some(error) = json.get("error") {
-                format!("RPC error: {}", error)
-            } else {
-                format!("No result in RPC response: {}", response.body)
-            }
-        })?;
+/// Job previously created with the same idempotency key, if any
+fn existing_generation_job(
+    creator: &Principal,
+    input: &GenerationInput,
+) -> Result<Option<u64>, GenerationError> {
+    let key = match &input.idempotency_key {
+        Some(key) => key,
+        None => return Ok(None),
+    };
 
-    // Convert hex string to u64
-    let nonce = u64::from_str_radix(nonce_hex.trim_start_matches("0x"), 16)
-        .map_err(|e| format!("Failed to parse nonce hex '{}': {}", nonce_hex, e))?;
+    match idempotency::lookup(creator, key, idempotency::IdempotentOperation::Generation)? {
+        Some(idempotency::Outcome::Job(job_id)) => Ok(Some(job_id)),
+        Some(idempotency::Outcome::InFlight) => Err(GenerationError::Failed(format!(
+            "A generation with idempotency key '{}' is still being admitted",
+            key
+        ))),
+        Some(_) | None => Ok(None),
+    }
+}
 
-    ic_cdk::println!("   ✅ Blockchain nonce: {}", nonce);
+/// Validate a new generation and check that it can be paid for
+///
+/// The quota is charged first because the funds check may read balances
+/// through outcalls. While that check awaits, the idempotency key is held as
+/// in flight so a concurrent retry cannot admit a second job; the caller
+/// replaces it with the job it creates.
+async fn admit_generation(
+    creator: &Principal,
+    input: GenerationInput,
+) -> Result<GenerationInput, GenerationError> {
+    let input = prepare_input(creator, input)?;
+
+    // Enforce quotas before any outcall
+    quota::charge_generation(creator).map_err(GenerationError::QuotaExceeded)?;
+
+    let operation = idempotency::IdempotentOperation::Generation;
+    if let Some(key) = &input.idempotency_key {
+        idempotency::remember(creator, key, operation, idempotency::Outcome::InFlight);
+    }
+    if let Err(e) = check_funds(creator, &input).await {
+        if let Some(key) = &input.idempotency_key {
+            idempotency::forget(creator, key);
+        }
+        return Err(e);
+    }
+    Ok(input)
+}
 
-    // Update cached nonce in state for reference
-    STATE.with(|state| {
-        let mut state = state.borrow_mut();
-        state.evm_nonce = U256::from(nonce);
-    });
+/// Validate the requested network and pin it on the input, so a later change
+/// of the default network does not move a queued job. Also rejects a request
+/// to sign from the caller's account when the caller has none, a network
+/// without a parent AI model IP, an invalid recipient address and any license
+/// (works inherit the parent's terms). Without a recipient, the caller's linked
+/// wallet receives the IP.
+fn prepare_input(
+    creator: &Principal,
+    mut input: GenerationInput,
+) -> Result<GenerationInput, GenerationError> {
+    let network = networks::resolve(input.chain_id)?;
+    input.chain_id = Some(network.chain_id);
+    jobs::signing_account(creator, &input)?;
+    let parent = derivatives::parent_for(network.chain_id).ok_or_else(|| {
+        format!(
+            "No parent AI model IP is set on {}; an admin must call set_parent_ip",
+            network.name
+        )
+    })?;
+    if input.license.is_some() {
+        return Err(format!(
+            "Works on {} are derivatives of {} and inherit its license terms",
+            network.name, parent.ip_id
+        )
+        .into());
+    }
+    match &input.recipient_evm_address {
+        Some(recipient) => {
+            let address = evm_util::parse_eip55_address(recipient)?;
+            input.recipient_evm_address = Some(evm_util::to_checksum_address(&address));
+        }
+        None => {
+            input.recipient_evm_address = wallets::linked_wallet(creator).map(|w| w.address);
+        }
+    }
+    Ok(input)
+}
 
-    Ok(nonce)
+/// Refuse a job the canister cannot pay for (see `monitoring::check_job_funds`)
+async fn check_funds(creator: &Principal, input: &GenerationInput) -> Result<(), GenerationError> {
+    let chain_id = networks::resolve(input.chain_id)?.chain_id;
+    let account = jobs::signing_account(creator, input)?;
+    monitoring::check_job_funds(chain_id, &account)
+        .await
+        .map_err(GenerationError::InsufficientFunds)
 }
 
-/// Get and atomically increment the EVM nonce (DEPRECATED)
-///
-/// # Deprecated
-/// This function uses a cached nonce which can get out of sync.
-/// Use `get_nonce_from_blockchain()` instead for reliable nonce management.
-#[deprecated(note = "Use get_nonce_from_blockchain() instead")]
-pub fn get_and_increment_nonce() -> U256 {
-    STATE.with(|state| {
-        let mut state = state.borrow_mut();
-        let current_nonce = state.evm_nonce;
-        state.evm_nonce += U256::one();
-        current_nonce
-    })
+/// Map a job's current state to the generation endpoint's result
+fn generation_result(job_id: u64) -> Result<GenerationOutput, GenerationError> {
+    let job = STATE
+        .with(|state| state.borrow().jobs.get(job_id))
+        .ok_or_else(|| format!("Job #{} not found", job_id))?;
+
+    match (job.status, job.output, job.revert) {
+        (jobs::JobStatus::Completed, Some(output), _) => Ok(output),
+        (status, _, _) if !status.is_terminal() => {
+            Err(GenerationError::InProgress { job_id, status })
+        }
+        (jobs::JobStatus::Failed, _, Some(reason)) => {
+            Err(GenerationError::WouldRevert { job_id, reason })
+        }
+        (status, _, _) => Err(GenerationError::Failed(format!(
+            "Job #{} {:?}: {}",
+            job_id,
+            status,
+            job.last_error.unwrap_or_else(|| "unknown error".to_string())
+        ))),
+    }
 }
 
 // ==============================================================================
-// Main Orchestration Function (Stubbed for Day 1-3)
+// Orchestration Jobs
 // ==============================================================================
 
-#[ic_cdk::update]
-async fn generate_and_register_ip(input: GenerationInput) -> Result<GenerationOutput, String> {
-    ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    ic_cdk::println!("🚀 PROVENANCE AI ORCHESTRATION STARTED");
-    ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    ic_cdk::println!("   Prompt: {}", input.prompt);
-    ic_cdk::println!("   Title: {}", input.metadata.title);
+/// Queue a generation job and return its ID immediately
+///
+/// Timers drive the job through AI generation, Story registration,
+/// Constellation logging and provenance recording.
+#[ic_cdk::update(guard = "guard_generator")]
+async fn submit_generation(input: GenerationInput) -> Result<u64, GenerationError> {
+    let creator = ic_cdk::caller();
+
+    if let Some(job_id) = existing_generation_job(&creator, &input)? {
+        return Ok(job_id);
+    }
 
-    // STEP 1: AI Content Generation
-    ic_cdk::println!("\n📸 STEP 1: Generating AI content...");
-    let (image_url, content_hash) = ai_util::generate_ai_content(input.prompt.clone()).await?;
-    ic_cdk::println!("   ✅ Image URL: {}", image_url);
-    ic_cdk::println!("   ✅ Content Hash: {}", content_hash);
+    let input = admit_generation(&creator, input).await?;
 
-    // STEP 2: Register IP on Story Protocol using SPG (Mint + Register in one tx)
-    ic_cdk::println!("\n📜 STEP 2: Registering IP on Story Protocol (SPG - Mint & Register)...");
+    let idempotency_key = input.idempotency_key.clone();
+    let job_id = jobs::submit(creator, input);
 
-    // Create metadata URI (for now, use placeholder - in production, upload to IPFS)
-    let metadata_uri = format!(
-        "ipfs://placeholder/{}/metadata.json",
-        content_hash
-    );
+    if let Some(key) = idempotency_key {
+        idempotency::remember(
+            &creator,
+            &key,
+            idempotency::IdempotentOperation::Generation,
+            idempotency::Outcome::Job(job_id),
+        );
+    }
 
-    let (story_tx_hash, parsed_values) = match story_util::register_ip_on_story(
-        content_hash.clone(),
-        metadata_uri,
-    ).await {
-        Ok((tx_hash, values)) => {
-            ic_cdk::println!("   ✅ Transaction Hash: {}", tx_hash);
-            (tx_hash, values)
-        }
-        Err(e) => {
-            ic_cdk::println!("   ❌ Story Protocol registration failed: {}", e);
-            return Err(format!("Failed to register IP on Story Protocol: {}", e));
-        }
-    };
+    Ok(job_id)
+}
 
-    // Extract SPG NFT contract address from config (used for Constellation proof)
-    let spg_nft_contract = {
-        let addr = config::spg_nft_contract_address();
-        format!("0x{}", hex::encode(addr.to_fixed_bytes()))
-    };
+/// Get a job by ID (visible to its creator and to Auditors and above)
+#[ic_cdk::query]
+fn get_job(job_id: u64) -> Option<jobs::Job> {
+    let caller = ic_cdk::caller();
+    let is_auditor = access::has_any_role(&caller, &[Role::Admin, Role::Operator, Role::Auditor]);
 
-    // Use parsed values if available, otherwise fallback to placeholders
-    let (story_ip_id, token_id) = match parsed_values {
-        Some((ip_id, tid)) => {
-            ic_cdk::println!("   ✅ Using parsed IP ID and Token ID from receipt");
-            (ip_id, tid)
-        }
-        None => {
-            ic_cdk::println!("   ⚠️  Using placeholder values (receipt parsing failed)");
-            (story_tx_hash.clone(), 0u64)
-        }
-    };
+    STATE
+        .with(|state| state.borrow().jobs.get(job_id))
+        .filter(|job| is_auditor || job.creator == caller)
+}
 
-    // STEP 3: Log on Constellation DAG
-    ic_cdk::println!("\n🌌 STEP 3: Logging proof on Constellation DAG...");
-
-    let proof = constellation_util::ProofOfGeneration {
-        content_hash: content_hash.clone(),
-        model_name: "deepseek-chat".to_string(),
-        timestamp: ic_cdk::api::time(),
-        story_ip_id: story_ip_id.clone(),
-        nft_contract: spg_nft_contract.clone(),
-        nft_token_id: token_id,
-        generator_address: "ICP-Canister".to_string(), // Placeholder for canister identity
-    };
+/// List jobs in submission order
+///
+/// # Arguments
+/// * `cursor` - `next_cursor` from the previous page (None for the first page)
+/// * `limit` - Page size (clamped to 1..=100)
+#[ic_cdk::query(guard = "guard_auditor")]
+fn list_jobs(cursor: Option<u64>, limit: u32) -> jobs::JobPage {
+    STATE.with(|state| state.borrow().jobs.list(cursor, limit))
+}
 
-    let constellation_url = get_config().constellation_metagraph_url;
+/// Re-queue a Failed or Cancelled job (Admin / Operator), charged to the
+/// creator's quota
+#[ic_cdk::update(guard = "guard_operator")]
+fn retry_job(job_id: u64) -> Result<jobs::Job, String> {
+    jobs::retry(job_id)
+}
 
-    let constellation_tx_hash = match constellation_util::log_proof_on_constellation(
-        constellation_url,
-        proof,
-    ).await {
-        Ok(tx_hash) => {
-            ic_cdk::println!("   ✅ Logged on Constellation (simulated)");
-            ic_cdk::println!("   TX Hash: {}", tx_hash);
-            tx_hash
-        }
-        Err(e) => {
-            ic_cdk::println!("   ⚠️  Constellation logging failed (non-critical): {}", e);
-            format!("CONST-ERROR-{}", ic_cdk::api::time())
-        }
-    };
+/// Cancel a pending job (Admin / Operator)
+#[ic_cdk::update(guard = "guard_operator")]
+fn cancel_job(job_id: u64) -> Result<jobs::Job, String> {
+    jobs::cancel(job_id)
+}
 
-    ic_cdk::println!("\n━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    ic_cdk::println!("✅ ORCHESTRATION COMPLETE");
-    ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+// ==============================================================================
+// Provenance Registry Queries
+// ==============================================================================
+
+/// Look up a provenance record by its content hash
+#[ic_cdk::query]
+fn get_record_by_hash(content_hash: String) -> Option<registry::ProvenanceRecord> {
+    STATE.with(|state| state.borrow().registry.get_by_hash(&content_hash))
+}
+
+/// Look up a provenance record by its Story Protocol IP ID
+#[ic_cdk::query]
+fn get_record_by_ip_id(ip_id: String) -> Option<registry::ProvenanceRecord> {
+    STATE.with(|state| state.borrow().registry.get_by_ip_id(&ip_id))
+}
+
+/// Records minted with the given Story token ID (token IDs are per contract),
+/// paged like `list_records`
+#[ic_cdk::query]
+fn get_records_by_token_id(token_id: u64, cursor: Option<u64>, limit: u32) -> registry::RecordPage {
+    STATE.with(|state| state.borrow().registry.get_by_token_id(token_id, cursor, limit))
+}
+
+/// Records created by the given principal, paged like `list_records`
+#[ic_cdk::query]
+fn get_records_by_creator(creator: Principal, cursor: Option<u64>, limit: u32) -> registry::RecordPage {
+    STATE.with(|state| state.borrow().registry.get_by_creator(&creator, cursor, limit))
+}
+
+/// List records in creation order
+///
+/// # Arguments
+/// * `cursor` - `next_cursor` from the previous page (None for the first page)
+/// * `limit` - Page size (clamped to 1..=100)
+#[ic_cdk::query]
+fn list_records(cursor: Option<u64>, limit: u32) -> registry::RecordPage {
+    STATE.with(|state| state.borrow().registry.list(cursor, limit))
+}
 
-    Ok(GenerationOutput {
-        image_url,
-        content_hash,
-        story_ip_id,
-        story_tx_hash,
-        story_nft_contract: spg_nft_contract,
-        story_token_id: token_id,
-        constellation_tx_hash,
-        ai_model_id: "deepseek-chat".to_string(),
+/// Records created by the principal linked to an EVM wallet, paged like
+/// `list_records`
+#[ic_cdk::query]
+fn get_records_by_wallet(address: String, cursor: Option<u64>, limit: u32) -> registry::RecordPage {
+    STATE.with(|state| {
+        let state = state.borrow();
+        match state.wallets.get_by_address(&address) {
+            Some(wallet) => state.registry.get_by_creator(&wallet.principal, cursor, limit),
+            None => registry::RecordPage {
+                records: Vec::new(),
+                next_cursor: None,
+            },
+        }
     })
 }
 
+/// Total number of provenance records
+#[ic_cdk::query]
+fn get_record_count() -> u64 {
+    STATE.with(|state| state.borrow().registry.count())
+}
+
 // ==============================================================================
-// Dispute Module (Stubbed for Phase 5)
+// Disputes
 // ==============================================================================
 
-#[ic_cdk::update]
-async fn raise_dispute(ip_id: String, evidence_ipfs_cid: String) -> Result<GenerationOutput, String> {
+/// Raise a dispute against an IP Asset on Story's DisputeModule
+///
+/// The canister's address initiates the dispute and posts the bond in WIP
+/// (approving the ArbitrationPolicyUMA first if needed).
+///
+/// # Arguments
+/// * `ip_id` - IP Asset disputed
+/// * `evidence_ipfs_cid` - IPFS CID of the evidence (CIDv0 or dag-pb CIDv1)
+/// * `tag` - Infringement claimed
+/// * `bond` - WIP bond (smallest unit)
+/// * `chain_id` - Network of the IP Asset; None = default network
+///
+/// # Returns
+/// * `Result<DisputeRecord, String>` - The dispute; `dispute_id` is set once
+///   the transaction is mined
+#[ic_cdk::update(guard = "guard_operator")]
+async fn raise_dispute(
+    ip_id: String,
+    evidence_ipfs_cid: String,
+    tag: disputes::DisputeTag,
+    bond: u64,
+    chain_id: Option<u64>,
+) -> Result<disputes::DisputeRecord, String> {
     ic_cdk::println!("🚨 DISPUTE RAISED");
     ic_cdk::println!("   IP ID: {}", ip_id);
     ic_cdk::println!("   Evidence: ipfs://{}", evidence_ipfs_cid);
-    ic_cdk::println!("   ⚠️  [STUB] - Will be implemented in Phase 5");
 
-    Err("Dispute module not yet implemented".to_string())
+    let network = networks::resolve(chain_id)?;
+    disputes::raise_dispute(&network, &ip_id, &evidence_ipfs_cid, tag, bond).await
+}
+
+/// Cancel a dispute the canister raised that is still under judgment
+#[ic_cdk::update(guard = "guard_operator")]
+async fn cancel_dispute(
+    dispute_id: u64,
+    chain_id: Option<u64>,
+) -> Result<disputes::DisputeRecord, String> {
+    let network = networks::resolve(chain_id)?;
+    disputes::settle_dispute(&network, dispute_id, transactions::TxKind::CancelDispute).await
+}
+
+/// Resolve an upheld dispute the canister raised, removing the target's tag
+#[ic_cdk::update(guard = "guard_operator")]
+async fn resolve_dispute(
+    dispute_id: u64,
+    chain_id: Option<u64>,
+) -> Result<disputes::DisputeRecord, String> {
+    let network = networks::resolve(chain_id)?;
+    disputes::settle_dispute(&network, dispute_id, transactions::TxKind::ResolveDispute).await
+}
+
+/// Read a dispute's current tag from the DisputeModule to learn its judgment
+#[ic_cdk::update(guard = "guard_generator")]
+async fn refresh_dispute(
+    dispute_id: u64,
+    chain_id: Option<u64>,
+) -> Result<disputes::DisputeRecord, String> {
+    let network = networks::resolve(chain_id)?;
+    quota::charge_outcall(&ic_cdk::caller())?;
+    disputes::refresh_dispute(&network, dispute_id).await
+}
+
+/// Look up a dispute the canister raised by its DisputeModule ID
+#[ic_cdk::query]
+fn get_dispute(dispute_id: u64, chain_id: Option<u64>) -> Option<disputes::DisputeRecord> {
+    let chain_id = networks::resolve(chain_id).ok()?.chain_id;
+    STATE.with(|state| state.borrow().disputes.get(chain_id, dispute_id))
+}
+
+/// Disputes the canister raised against an IP Asset, including pending ones
+#[ic_cdk::query]
+fn get_disputes_by_ip(ip_id: String) -> Vec<disputes::DisputeRecord> {
+    STATE.with(|state| state.borrow().disputes.by_ip(&ip_id))
 }
 
 // ==============================================================================
 // EVM Address (For Story Protocol)
 // ==============================================================================
 
-#[ic_cdk::update]
+/// The canister's shared EVM address (derived once, then served from cache)
+#[ic_cdk::update(guard = "guard_generator")]
 async fn get_canister_evm_address() -> String {
+    if !evm_util::is_derived(&evm_util::EvmAccount::Canister) {
+        if let Err(e) = quota::charge_outcall(&ic_cdk::caller()) {
+            return format!("Error: {}", e);
+        }
+    }
+
     match evm_util::get_canister_evm_address().await {
         Ok(address) => address,
         Err(e) => {
@@ -421,46 +843,330 @@ async fn get_canister_evm_address() -> String {
     }
 }
 
+/// EVM address derived for the caller's principal
+///
+/// Only this canister can sign for it; generation and registration use it when
+/// `use_caller_account` is set. An update call because deriving the key needs
+/// the management canister (once per principal; then it is cached), but it
+/// changes no state.
+#[ic_cdk::update(guard = "guard_generator")]
+async fn get_my_evm_address() -> Result<String, String> {
+    let account = evm_util::EvmAccount::user(ic_cdk::caller())?;
+    if !evm_util::is_derived(&account) {
+        quota::charge_outcall(&ic_cdk::caller())?;
+    }
+    evm_util::get_evm_address(&account).await
+}
+
+// ==============================================================================
+// Wallet Linking
+// ==============================================================================
+
+/// Issue a challenge to sign with `personal_sign` in the wallet at `address`
+///
+/// Pass the signature to `link_evm_wallet` before the challenge expires.
+#[ic_cdk::update(guard = "guard_generator")]
+async fn request_wallet_link_challenge(address: String) -> Result<String, String> {
+    quota::charge_outcall(&ic_cdk::caller())?;
+    wallets::issue_challenge(ic_cdk::caller(), &address).await
+}
+
+/// Link the caller to an EVM wallet by verifying the signed challenge
+///
+/// # Arguments
+/// * `address` - Wallet the challenge was issued for
+/// * `signature` - EIP-191 `personal_sign` signature of the challenge (0x hex)
+#[ic_cdk::update(guard = "guard_authenticated")]
+fn link_evm_wallet(address: String, signature: String) -> Result<wallets::LinkedWallet, String> {
+    wallets::link(ic_cdk::caller(), &address, &signature)
+}
+
+#[ic_cdk::update(guard = "guard_authenticated")]
+fn unlink_evm_wallet() -> Option<wallets::LinkedWallet> {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| state.borrow_mut().wallets.unlink(&caller))
+}
+
+/// Wallet linked to `principal` (default: the caller)
+#[ic_cdk::query]
+fn get_linked_wallet(principal: Option<Principal>) -> Option<wallets::LinkedWallet> {
+    wallets::linked_wallet(&principal.unwrap_or_else(ic_cdk::caller))
+}
+
+/// Principal linked to an EVM wallet
+#[ic_cdk::query]
+fn get_wallet_owner(address: String) -> Option<wallets::LinkedWallet> {
+    STATE.with(|state| state.borrow().wallets.get_by_address(&address))
+}
+
+// ==============================================================================
+// Network Registry
+// ==============================================================================
+
+/// Add a network or replace the one with the same chain ID
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_network(network: networks::Network) -> Result<(), String> {
+    let (chain_id, name) = (network.chain_id, network.name.clone());
+    STATE.with(|state| state.borrow_mut().networks.upsert(network))?;
+    ic_cdk::println!("Network {} (chain {}) saved", name, chain_id);
+    Ok(())
+}
+
+/// Remove a network (the default network cannot be removed)
+#[ic_cdk::update(guard = "guard_admin")]
+fn remove_network(chain_id: u64) -> Result<(), String> {
+    STATE.with(|state| state.borrow_mut().networks.remove(chain_id))?;
+    ic_cdk::println!("Network (chain {}) removed", chain_id);
+    Ok(())
+}
+
+/// Select the network used when a call does not specify one
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_default_network(chain_id: u64) -> Result<(), String> {
+    STATE.with(|state| state.borrow_mut().networks.set_default(chain_id))?;
+    ic_cdk::println!("Default network set to chain {}", chain_id);
+    Ok(())
+}
+
+/// Get a network by chain ID, or the default network
+///
+/// `rpc_urls` is empty unless the caller is an Operator (URLs may embed API keys).
+#[ic_cdk::query]
+fn get_network(chain_id: Option<u64>) -> Option<networks::Network> {
+    networks::resolve(chain_id).ok().map(redact_network)
+}
+
+/// All registered networks (`rpc_urls` as in `get_network`)
+#[ic_cdk::query]
+fn list_networks() -> Vec<networks::Network> {
+    STATE
+        .with(|state| state.borrow().networks.list())
+        .into_iter()
+        .map(redact_network)
+        .collect()
+}
+
+fn redact_network(network: networks::Network) -> networks::Network {
+    if guard_operator().is_ok() {
+        network
+    } else {
+        network.redacted()
+    }
+}
+
+/// Select the RPC backend (direct outcalls or the EVM RPC canister) and the
+/// consensus required between providers for reads
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_rpc_settings(settings: rpc_util::RpcSettings) -> Result<(), String> {
+    settings.validate()?;
+    ic_cdk::println!("RPC settings updated: {:?}", settings);
+    STATE.with(|state| state.borrow_mut().rpc = settings);
+    Ok(())
+}
+
+#[ic_cdk::query(guard = "guard_operator")]
+fn get_rpc_settings() -> rpc_util::RpcSettings {
+    STATE.with(|state| state.borrow().rpc.clone())
+}
+
+// ==============================================================================
+// Fees and Transaction History
+// ==============================================================================
+
+/// Set the gas margin and fee caps used for every transaction
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_fee_settings(settings: fees::FeeSettings) -> Result<(), String> {
+    settings.validate()?;
+    ic_cdk::println!("Fee settings updated: {:?}", settings);
+    STATE.with(|state| state.borrow_mut().fees = settings);
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_fee_settings() -> fees::FeeSettings {
+    fees::settings()
+}
+
+/// Look up a transaction sent by the canister by its hash
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_transaction(hash: String) -> Option<transactions::TxRecord> {
+    STATE.with(|state| state.borrow().transactions.get_by_hash(&hash))
+}
+
+/// List transactions sent by the canister, oldest first
+#[ic_cdk::query(guard = "guard_auditor")]
+fn list_transactions(cursor: Option<u64>, limit: u32) -> transactions::TxPage {
+    STATE.with(|state| state.borrow().transactions.list(cursor, limit))
+}
+
+/// Replace a pending transaction with the same call at higher fees
+#[ic_cdk::update(guard = "guard_operator")]
+async fn speed_up_transaction(hash: String) -> Result<transactions::TxRecord, String> {
+    transactions::replace(&hash, transactions::ReplacementKind::SpeedUp).await
+}
+
+/// Replace a pending transaction with a zero-value self-transfer at higher fees
+#[ic_cdk::update(guard = "guard_operator")]
+async fn cancel_transaction(hash: String) -> Result<transactions::TxRecord, String> {
+    transactions::replace(&hash, transactions::ReplacementKind::Cancel).await
+}
+
+/// Configure automatic replacement of stuck transactions
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_replacement_policy(policy: transactions::ReplacementPolicy) {
+    ic_cdk::println!("Replacement policy updated: {:?}", policy);
+    STATE.with(|state| state.borrow_mut().transactions.set_replacement_policy(policy));
+}
+
+#[ic_cdk::query]
+fn get_replacement_policy() -> transactions::ReplacementPolicy {
+    STATE.with(|state| state.borrow().transactions.replacement_policy())
+}
+
+/// Configure the confirmation depth and timeout of the receipt tracker
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_tracking_settings(settings: transactions::TrackingSettings) -> Result<(), String> {
+    settings.validate()?;
+    ic_cdk::println!("Tracking settings updated: {:?}", settings);
+    STATE.with(|state| state.borrow_mut().transactions.set_tracking_settings(settings));
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_tracking_settings() -> transactions::TrackingSettings {
+    STATE.with(|state| state.borrow().transactions.tracking_settings())
+}
+
+/// Nonce state of every sending address
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_nonce_state() -> Vec<nonce::NonceReport> {
+    STATE.with(|state| state.borrow().nonces.reports())
+}
+
+/// Reconcile sending addresses' nonces with the chain now
+///
+/// Releases dropped or abandoned nonces so that the next transactions fill the gaps.
+///
+/// # Arguments
+/// * `chain_id` - Chain to reconcile on (default network if None)
+/// * `principal` - Reconcile only this user's derived address; every tracked
+///   address plus the canister address if None
+///
+/// # Returns
+/// * `Result<Vec<NonceReport>, String>` - Nonce state of each reconciled address
+#[ic_cdk::update(guard = "guard_operator")]
+async fn reconcile_nonces(
+    chain_id: Option<u64>,
+    principal: Option<Principal>,
+) -> Result<Vec<nonce::NonceReport>, String> {
+    let network = networks::resolve(chain_id)?;
+
+    let addresses = match principal {
+        Some(principal) => {
+            let account = evm_util::EvmAccount::user(principal)?;
+            vec![evm_util::get_evm_address(&account).await?.to_lowercase()]
+        }
+        None => {
+            let mut addresses =
+                STATE.with(|state| state.borrow().nonces.addresses(network.chain_id));
+            let canister = evm_util::get_canister_evm_address().await?.to_lowercase();
+            if !addresses.contains(&canister) {
+                addresses.push(canister);
+            }
+            addresses
+        }
+    };
+
+    let mut reports = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        reports.push(nonce::reconcile(network.chain_id, &address).await?);
+    }
+    Ok(reports)
+}
+
+// ==============================================================================
+// Funds Monitoring
+// ==============================================================================
+
+/// Set the cycles and gas reserves new jobs must leave untouched
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_funds_thresholds(thresholds: monitoring::FundsThresholds) {
+    ic_cdk::println!("Funds thresholds updated: {:?}", thresholds);
+    STATE.with(|state| state.borrow_mut().monitoring.thresholds = thresholds);
+}
+
+#[ic_cdk::query]
+fn get_funds_thresholds() -> monitoring::FundsThresholds {
+    STATE.with(|state| state.borrow().monitoring.thresholds.clone())
+}
+
+/// Recent cycles and gas balance samples, newest first
+///
+/// # Arguments
+/// * `limit` - Number of samples (default and maximum: 100)
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_balance_history(limit: Option<u32>) -> Vec<monitoring::BalanceSample> {
+    let limit = limit.unwrap_or(monitoring::MAX_HISTORY_PAGE);
+    STATE.with(|state| state.borrow().monitoring.history(limit))
+}
+
+/// Sample balances now instead of waiting for the timer
+#[ic_cdk::update(guard = "guard_operator")]
+async fn refresh_balances() -> monitoring::BalanceSample {
+    monitoring::sample().await
+}
+
 // ==============================================================================
 // SimpleNFT Contract Deployment
 // ==============================================================================
 
-/// Deploy the SimpleNFT contract to Story Protocol
+/// Deploy the SimpleNFT contract to Story Protocol (default network)
 ///
 /// This should be called once during setup to deploy the NFT contract
-/// that will be used for minting IP assets.
+/// that will be used for minting IP assets. The receipt tracker stores the
+/// contract address once the deployment is confirmed.
 ///
 /// # Arguments
 /// * `name` - The name of the NFT collection
 /// * `symbol` - The symbol of the NFT collection
 ///
 /// # Returns
-/// * `Result<String, String>` - Deployed contract address or error
-#[ic_cdk::update]
+/// * `Result<String, String>` - Address the contract is deployed at (computed
+///   from the sender and nonce; live once the transaction is mined) or error
+#[ic_cdk::update(guard = "guard_admin")]
 async fn deploy_nft_contract(name: String, symbol: String) -> Result<String, String> {
-    // Check if already deployed
-    let already_deployed = STATE.with(|state| {
-        state.borrow().nft_contract_address.is_some()
+    // Check if already deployed or being deployed
+    let (already_deployed, deployment_pending) = STATE.with(|state| {
+        let state = state.borrow();
+        (
+            state.nft_contract_address.is_some(),
+            state
+                .transactions
+                .tracked()
+                .iter()
+                .any(|record| record.kind == transactions::TxKind::DeployNftContract),
+        )
     });
 
     if already_deployed {
         return Err("NFT contract already deployed. Use get_nft_contract_address() to retrieve it.".to_string());
     }
+    if deployment_pending {
+        return Err("NFT contract deployment is already in progress".to_string());
+    }
 
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     ic_cdk::println!("📦 DEPLOYING SIMPLENFT CONTRACT");
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
 
-    let contract_address = nft_deployment::deploy_simple_nft(name, symbol).await?;
-
-    // Store the contract address in state
-    STATE.with(|state| {
-        state.borrow_mut().nft_contract_address = Some(contract_address.clone());
-    });
+    let network = networks::resolve(None)?;
+    let (tx_hash, contract_address) =
+        nft_deployment::deploy_simple_nft(&network, name, symbol).await?;
 
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    ic_cdk::println!("✅ NFT CONTRACT DEPLOYED");
+    ic_cdk::println!("✅ NFT CONTRACT DEPLOYMENT SENT");
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    ic_cdk::println!("   Transaction Hash: {}", tx_hash);
 
     Ok(contract_address)
 }
@@ -472,7 +1178,7 @@ fn get_nft_contract_address() -> Option<String> {
 }
 
 /// Set NFT contract address manually (for already deployed contracts)
-#[ic_cdk::update]
+#[ic_cdk::update(guard = "guard_admin")]
 fn set_nft_contract_address(address: String) {
     STATE.with(|state| {
         state.borrow_mut().nft_contract_address = Some(address.clone());
@@ -490,18 +1196,80 @@ fn set_nft_contract_address(address: String) {
 /// # Arguments
 /// * `nft_contract_address` - The address of the NFT contract
 /// * `token_id` - The token ID of the NFT to register
+/// * `idempotency_key` - Optional key; a repeated call returns the original
+///   result. A call that failed before sending anything frees its key; one
+///   that failed after the registration was sent is resumed by the next call
+///   (only the transfer is repeated)
+/// * `chain_id` - Network the NFT lives on; None = default network
+/// * `use_caller_account` - Sign from the caller's derived EVM address instead
+///   of the canister's
+/// * `recipient_evm_address` - EIP-55 address the NFT (and with it the IP
+///   Account) is transferred to after registration; the signing account must
+///   hold the NFT
 ///
 /// # Returns
 /// * `Result<String, String>` - Transaction hash or error
-#[ic_cdk::update]
-async fn register_ip(nft_contract_address: String, token_id: u64) -> Result<String, String> {
+#[ic_cdk::update(guard = "guard_minter")]
+async fn register_ip(
+    nft_contract_address: String,
+    token_id: u64,
+    idempotency_key: Option<String>,
+    chain_id: Option<u64>,
+    use_caller_account: Option<bool>,
+    recipient_evm_address: Option<String>,
+) -> Result<String, String> {
+    let network = networks::resolve(chain_id)?;
+    let recipient = recipient_evm_address
+        .as_deref()
+        .map(evm_util::parse_eip55_address)
+        .transpose()?;
+    let caller = ic_cdk::caller();
+    let account = if use_caller_account.unwrap_or(false) {
+        evm_util::EvmAccount::user(caller)?
+    } else {
+        evm_util::EvmAccount::Canister
+    };
+    let operation = idempotency::IdempotentOperation::RegisterIp;
+
+    let mut registered = None;
+    if let Some(key) = &idempotency_key {
+        match idempotency::lookup(&caller, key, operation)? {
+            Some(idempotency::Outcome::Completed(result)) => return result,
+            Some(idempotency::Outcome::Sent(tx_hash)) => registered = Some(tx_hash),
+            Some(_) => {
+                return Err(format!(
+                    "A register_ip call with idempotency key '{}' is still in progress",
+                    key
+                ))
+            }
+            None => {}
+        }
+    }
+
+    // Replays and resumed calls are free; a new registration is charged
+    // before any outcall
+    if registered.is_none() {
+        quota::charge_transaction(&caller)?;
+    }
+    if let Some(key) = &idempotency_key {
+        idempotency::remember(&caller, key, operation, idempotency::Outcome::InFlight);
+    }
+
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     ic_cdk::println!("📜 REGISTERING NFT AS IP ASSET ON STORY PROTOCOL");
     ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     ic_cdk::println!("   NFT Contract: {}", nft_contract_address);
     ic_cdk::println!("   Token ID: {}", token_id);
 
-    let result = story_util::register_nft_as_ip(nft_contract_address, token_id).await;
+    let result = register_and_transfer(
+        &network,
+        account,
+        nft_contract_address,
+        token_id,
+        recipient,
+        registered,
+    )
+    .await;
 
     match &result {
         Ok(tx_hash) => {
@@ -510,7 +1278,7 @@ async fn register_ip(nft_contract_address: String, token_id: u64) -> Result<Stri
             ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
             ic_cdk::println!("   Transaction Hash: {}", tx_hash);
         }
-        Err(e) => {
+        Err((_, e)) => {
             ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
             ic_cdk::println!("❌ IP REGISTRATION FAILED");
             ic_cdk::println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -518,7 +1286,504 @@ async fn register_ip(nft_contract_address: String, token_id: u64) -> Result<Stri
         }
     }
 
-    result
+    if let Some(key) = &idempotency_key {
+        match &result {
+            Ok(tx_hash) => idempotency::remember(
+                &caller,
+                key,
+                operation,
+                idempotency::Outcome::Completed(Ok(tx_hash.clone())),
+            ),
+            // The registration is on its way; the next call only transfers
+            Err((Some(tx_hash), _)) => idempotency::remember(
+                &caller,
+                key,
+                operation,
+                idempotency::Outcome::Sent(tx_hash.clone()),
+            ),
+            // Nothing was sent, so the key can be used again
+            Err((None, _)) => idempotency::forget(&caller, key),
+        }
+    }
+
+    result.map_err(|(_, e)| e)
+}
+
+/// Register an NFT, then transfer it to `recipient` (if any)
+///
+/// The transfer takes the next nonce, so it is mined after the registration.
+///
+/// # Arguments
+/// * `registered` - Registration hash of an earlier attempt; the registration
+///   is not sent again
+///
+/// # Returns
+/// * `Result<String, (Option<String>, String)>` - Registration hash, or the
+///   error along with the registration hash if it was sent
+async fn register_and_transfer(
+    network: &networks::Network,
+    account: evm_util::EvmAccount,
+    nft_contract_address: String,
+    token_id: u64,
+    recipient: Option<[u8; 20]>,
+    registered: Option<String>,
+) -> Result<String, (Option<String>, String)> {
+    let nft_contract = primitive_types::H160::from_str(&nft_contract_address)
+        .map_err(|e| (None, format!("Invalid NFT contract address: {}", e)))?;
+
+    let tx_hash = match registered {
+        Some(tx_hash) => {
+            ic_cdk::println!("   ↪️  Registration already sent: {}", tx_hash);
+            tx_hash
+        }
+        None => story_util::register_nft_as_ip(network, account, nft_contract_address, token_id)
+            .await
+            .map_err(|e| (None, e))?,
+    };
+
+    if let Some(recipient) = recipient {
+        story_util::transfer_ip_nft(network, account, nft_contract, token_id, recipient)
+            .await
+            .map_err(|e| {
+                let error = format!(
+                    "Registered in {} but the transfer to {} failed: {}",
+                    tx_hash,
+                    evm_util::to_checksum_address(&recipient),
+                    e
+                );
+                (Some(tx_hash.clone()), error)
+            })?;
+    }
+
+    Ok(tx_hash)
+}
+
+/// NFT of a registered IP Asset and the canister account holding it
+struct HeldIpAsset {
+    network: networks::Network,
+    nft_contract: primitive_types::H160,
+    token_id: u64,
+    account: evm_util::EvmAccount,
+}
+
+/// Look up the IP Asset `ip_id` and find which of the canister's accounts (its
+/// own address or the record creator's derived address) holds its NFT
+async fn held_ip_asset(ip_id: &str) -> Result<HeldIpAsset, String> {
+    let record = STATE
+        .with(|state| state.borrow().registry.get_by_ip_id(ip_id))
+        .ok_or_else(|| format!("No provenance record for IP Asset {}", ip_id))?;
+    let output = &record.output;
+    if output.story_nft_contract.is_empty() {
+        return Err(format!("IP Asset {} has no NFT yet (pending confirmation)", ip_id));
+    }
+
+    let network = networks::get(output.story_chain_id)?;
+    let nft_contract = primitive_types::H160::from_str(&output.story_nft_contract)
+        .map_err(|e| format!("Invalid NFT contract address: {}", e))?;
+
+    let owner = story_util::nft_owner_of(&network, nft_contract, output.story_token_id).await?;
+    let mut candidates = vec![evm_util::EvmAccount::Canister];
+    if let Ok(account) = evm_util::EvmAccount::user(record.creator) {
+        candidates.push(account);
+    }
+
+    for account in candidates {
+        if evm_util::get_evm_address(&account).await?.eq_ignore_ascii_case(&owner) {
+            return Ok(HeldIpAsset {
+                network,
+                nft_contract,
+                token_id: output.story_token_id,
+                account,
+            });
+        }
+    }
+
+    Err(format!("IP Asset {} is held by {}, not by the canister", ip_id, owner))
+}
+
+/// Transfer an IP Asset held by the canister to another address
+///
+/// Sends ERC-721 `safeTransferFrom` for the NFT bound to `ip_id`. The NFT must
+/// be held by the canister's address or by the record creator's derived
+/// address; ownership of the IP Account follows the NFT.
+///
+/// # Arguments
+/// * `ip_id` - IP Asset ID of a provenance record
+/// * `to` - EIP-55 recipient address
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+#[ic_cdk::update(guard = "guard_owner")]
+async fn transfer_ip_asset(ip_id: String, to: String) -> Result<String, String> {
+    let recipient = evm_util::parse_eip55_address(&to)?;
+    let held = held_ip_asset(&ip_id).await?;
+
+    story_util::transfer_ip_nft(
+        &held.network,
+        held.account,
+        held.nft_contract,
+        held.token_id,
+        recipient,
+    )
+    .await
+}
+
+// ==============================================================================
+// Licensing (Programmable IP License)
+// ==============================================================================
+
+/// Register PIL terms on the PILicenseTemplate of a network
+///
+/// Terms identical to registered ones are not registered again; their ID is
+/// returned without a transaction. If the registration is still pending, call
+/// again once it is mined to get the ID.
+///
+/// # Arguments
+/// * `choice` - PIL flavor and its commercial parameters
+/// * `chain_id` - Network to register on; None = default network
+///
+/// # Returns
+/// * `Result<LicenseTermsRegistration, String>` - Terms ID and transaction hash
+#[ic_cdk::update(guard = "guard_operator")]
+async fn register_license_terms(
+    choice: licensing::LicenseChoice,
+    chain_id: Option<u64>,
+) -> Result<licensing::LicenseTermsRegistration, String> {
+    choice.validate()?;
+    let network = networks::resolve(chain_id)?;
+    licensing::register_pil_terms(&network, &choice).await
+}
+
+/// Attach registered PIL terms to an IP Asset the canister holds
+///
+/// Sends `LicensingModule.attachLicenseTerms` from the account holding the IP
+/// Asset's NFT (see `transfer_ip_asset`).
+///
+/// # Arguments
+/// * `ip_id` - IP Asset ID of a provenance record
+/// * `license_terms_id` - ID returned by `register_license_terms`
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+#[ic_cdk::update(guard = "guard_owner")]
+async fn attach_license_terms(ip_id: String, license_terms_id: u64) -> Result<String, String> {
+    let ip_account = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let held = held_ip_asset(&ip_id).await?;
+
+    licensing::attach_license_terms(&held.network, held.account, ip_account, license_terms_id).await
+}
+
+/// Mint license tokens of a registered IP Asset to a licensee
+///
+/// The canister's address pays the minting fee (approving the RoyaltyModule
+/// first if needed) and the issuance is recorded on the IP Asset's provenance
+/// record; token IDs are filled in once the transaction is confirmed.
+///
+/// # Arguments
+/// * `ip_id` - IP Asset ID of a provenance record
+/// * `license_terms_id` - PIL terms attached to the IP Asset
+/// * `amount` - Number of license tokens
+/// * `receiver` - EIP-55 address of the licensee
+///
+/// # Returns
+/// * `Result<LicenseTokenMint, String>` - Transaction hashes, fee and token IDs
+#[ic_cdk::update(guard = "guard_minter")]
+async fn mint_license_tokens(
+    ip_id: String,
+    license_terms_id: u64,
+    amount: u64,
+    receiver: String,
+) -> Result<licensing::LicenseTokenMint, String> {
+    let receiver_address = evm_util::parse_eip55_address(&receiver)?;
+    let licensor = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let record = STATE
+        .with(|state| state.borrow().registry.get_by_ip_id(&ip_id))
+        .ok_or_else(|| format!("No provenance record for IP Asset {}", ip_id))?;
+    let network = networks::get(record.output.story_chain_id)?;
+    quota::charge_transaction(&ic_cdk::caller())?;
+
+    let minted = licensing::mint_license_tokens(
+        &network,
+        licensor,
+        license_terms_id,
+        amount,
+        receiver_address,
+    )
+    .await?;
+
+    if let Some(tx_hash) = &minted.tx_hash {
+        let license = registry::IssuedLicense {
+            license_terms_id,
+            amount,
+            receiver: evm_util::to_checksum_address(&receiver_address),
+            tx_hash: tx_hash.clone(),
+            token_ids: minted.license_token_ids.clone(),
+            minting_fee: minted.minting_fee,
+            currency: minted.currency.clone(),
+            issued_by: ic_cdk::caller(),
+            issued_at: ic_cdk::api::time(),
+        };
+        STATE.with(|state| state.borrow_mut().registry.record_license(&ip_id, license))?;
+    }
+
+    Ok(minted)
+}
+
+// ==============================================================================
+// Derivatives of the Parent AI Model IP
+// ==============================================================================
+
+/// Set or clear the parent IP generated works on a network derive from
+///
+/// The license terms must be attached to the parent (see
+/// `attach_license_terms`); jobs registering afterwards become derivatives.
+/// Generation on a network is refused while it has no parent.
+///
+/// # Arguments
+/// * `chain_id` - Network of the parent IP; None = default network
+/// * `parent` - Parent IP and terms; None = clear it
+#[ic_cdk::update(guard = "guard_admin")]
+fn set_parent_ip(
+    chain_id: Option<u64>,
+    parent: Option<derivatives::ParentIp>,
+) -> Result<(), String> {
+    let network = networks::resolve(chain_id)?;
+    STATE.with(|state| state.borrow_mut().derivatives.set(network.chain_id, parent))?;
+    ic_cdk::println!(
+        "Parent IP on {} set to {:?}",
+        network.name,
+        derivatives::parent_for(network.chain_id)
+    );
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_parent_ip(chain_id: Option<u64>) -> Option<derivatives::ParentIp> {
+    derivatives::parent_for(networks::resolve(chain_id).ok()?.chain_id)
+}
+
+/// Link an IP Asset the canister holds to its network's parent IP
+///
+/// For works registered before a parent was configured. Sends
+/// `LicensingModule.registerDerivative` from the account holding the NFT.
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+#[ic_cdk::update(guard = "guard_owner")]
+async fn register_derivative(ip_id: String) -> Result<String, String> {
+    let child_ip = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let held = held_ip_asset(&ip_id).await?;
+
+    derivatives::register_derivative(&held.network, held.account, child_ip).await
+}
+
+// ==============================================================================
+// Royalties
+// ==============================================================================
+
+/// Pay royalties into an IP's vault on behalf of one of its derivatives
+///
+/// The canister's address pays (approving the RoyaltyModule first if needed)
+/// and the payment is recorded in the receiver's royalty ledger.
+///
+/// # Arguments
+/// * `receiver_ip_id` - IP Asset receiving the royalties (e.g. the AI model)
+/// * `payer_ip_id` - IP Asset paying (a derivative of the receiver)
+/// * `amount` - Smallest unit of the currency
+/// * `currency` - EIP-55 whitelisted royalty token; None = WIP
+/// * `chain_id` - Network of both IPs; None = default network
+///
+/// # Returns
+/// * `Result<RoyaltyEntry, String>` - Ledger entry, confirmed by the tracker
+#[ic_cdk::update(guard = "guard_operator")]
+async fn pay_royalty_on_behalf(
+    receiver_ip_id: String,
+    payer_ip_id: String,
+    amount: u64,
+    currency: Option<String>,
+    chain_id: Option<u64>,
+) -> Result<royalties::RoyaltyEntry, String> {
+    let network = networks::resolve(chain_id)?;
+    royalties::pay_royalty_on_behalf(
+        &network,
+        &receiver_ip_id,
+        &payer_ip_id,
+        currency.as_deref(),
+        amount,
+    )
+    .await
+}
+
+/// Revenue an IP Account can claim from its royalty vault
+///
+/// Update call: reads the vault through the EVM RPC canister, charged to the
+/// caller's quota.
+#[ic_cdk::update(guard = "guard_generator")]
+async fn get_claimable_revenue(
+    ip_id: String,
+    currency: Option<String>,
+    chain_id: Option<u64>,
+) -> Result<royalties::ClaimableRevenue, String> {
+    let ip_account = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let network = networks::resolve(chain_id)?;
+    quota::charge_outcall(&ic_cdk::caller())?;
+    royalties::claimable_revenue(&network, ip_account, currency.as_deref()).await
+}
+
+/// Claim an IP's revenue, collecting royalties owed by its derivatives first
+///
+/// For IP Assets of a provenance record, the claim is sent from the account
+/// holding the NFT (the canister's address or the creator's derived address)
+/// and the revenue is forwarded there; for other IPs (e.g. a parent AI model
+/// registered elsewhere) it is sent from the canister's address and stays in
+/// the IP Account unless the canister owns the IP.
+///
+/// # Arguments
+/// * `ip_id` - Ancestor IP Asset claiming
+/// * `child_ip_ids` - Derivatives whose royalties are collected first
+/// * `currency` - EIP-55 royalty token; None = WIP
+/// * `chain_id` - Network of IPs without a provenance record; None = default network
+///
+/// # Returns
+/// * `Result<RoyaltyEntry, String>` - Ledger entry; the claimed amount is set
+///   once the tracker confirms the transaction
+#[ic_cdk::update(guard = "guard_owner")]
+async fn claim_revenue(
+    ip_id: String,
+    child_ip_ids: Vec<String>,
+    currency: Option<String>,
+    chain_id: Option<u64>,
+) -> Result<royalties::RoyaltyEntry, String> {
+    let has_record = STATE.with(|state| state.borrow().registry.get_by_ip_id(&ip_id).is_some());
+    let (network, account) = if has_record {
+        let held = held_ip_asset(&ip_id).await?;
+        (held.network, held.account)
+    } else {
+        (networks::resolve(chain_id)?, evm_util::EvmAccount::Canister)
+    };
+
+    royalties::claim_all_revenue(
+        &network,
+        account,
+        &ip_id,
+        &child_ip_ids,
+        currency.as_deref(),
+    )
+    .await
+}
+
+/// Royalty payments and claims of an IP, oldest first
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_royalty_ledger(ip_id: String) -> Vec<royalties::RoyaltyEntry> {
+    STATE.with(|state| state.borrow().royalties.entries(&ip_id))
+}
+
+/// Confirmed royalty totals of an IP per currency (for reconciliation)
+#[ic_cdk::query(guard = "guard_auditor")]
+fn get_royalty_summary(ip_id: String) -> Vec<royalties::RoyaltySummary> {
+    STATE.with(|state| state.borrow().royalties.summary(&ip_id))
+}
+
+// ==============================================================================
+// IP Account Permissions (EIP-712)
+// ==============================================================================
+
+/// Sign an AccessController permission grant for an IP Asset the canister holds
+///
+/// The returned signature authorizes the IP Account to call
+/// `setPermission(...)` on the AccessController; any relayer (or a Story
+/// workflow taking `SignatureData`) can submit it before the deadline.
+///
+/// # Returns
+/// * `Result<SignedPermission, String>` - Signature plus the call it authorizes
+#[ic_cdk::update(guard = "guard_owner")]
+async fn sign_ip_permission(
+    request: eip712::PermissionRequest,
+) -> Result<eip712::SignedPermission, String> {
+    let ip_account = primitive_types::H160::from_str(&request.ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let signer = evm_util::parse_eip55_address(&request.signer)?;
+    let to = match &request.to {
+        Some(to) => evm_util::parse_eip55_address(to)?,
+        None => [0u8; 20],
+    };
+    let func: [u8; 4] = match &request.func {
+        Some(func) => hex::decode(func.trim_start_matches("0x"))
+            .map_err(|e| format!("Invalid function selector: {}", e))?
+            .try_into()
+            .map_err(|_| format!("Function selector must be 4 bytes: {}", func))?,
+        None => [0u8; 4],
+    };
+
+    let held = held_ip_asset(&request.ip_id).await?;
+    let access_controller = held.network.access_controller()?;
+    let data =
+        story_util::build_set_permission_calldata(ip_account, signer, to, func, request.permission)?;
+    let deadline = ic_cdk::api::time() / 1_000_000_000 + request.valid_for_secs;
+
+    let signature = eip712::sign_execute_with_sig(
+        &held.network,
+        &held.account,
+        ip_account,
+        access_controller,
+        &data,
+        deadline,
+    )
+    .await?;
+
+    Ok(eip712::SignedPermission {
+        signature,
+        to: evm_util::to_checksum_address(&access_controller.to_fixed_bytes()),
+        data: format!("0x{}", hex::encode(data)),
+    })
+}
+
+/// Current state (EIP-712 nonce base) of an IP Account
+#[ic_cdk::update(guard = "guard_generator")]
+async fn get_ip_account_state(ip_id: String, chain_id: Option<u64>) -> Result<String, String> {
+    let network = networks::resolve(chain_id)?;
+    let ip_account = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    quota::charge_outcall(&ic_cdk::caller())?;
+    let state = eip712::ip_account_state(&network, ip_account).await?;
+    Ok(format!("0x{}", hex::encode(state)))
+}
+
+/// Relay a call an IP Account owner authorized with an EIP-712 signature
+///
+/// # Arguments
+/// * `ip_id` - IP Account executing the call
+/// * `chain_id` - Network of the IP Account; None = default network
+/// * `to` - Contract the IP Account calls
+/// * `data` - Calldata (0x-prefixed hex) the owner signed
+/// * `signature` - The owner's `SignatureData`
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+#[ic_cdk::update(guard = "guard_operator")]
+async fn execute_with_signature(
+    ip_id: String,
+    chain_id: Option<u64>,
+    to: String,
+    data: String,
+    signature: eip712::SignatureData,
+) -> Result<String, String> {
+    if signature.deadline <= ic_cdk::api::time() / 1_000_000_000 {
+        return Err(format!("Signature expired at {}", signature.deadline));
+    }
+
+    let network = networks::resolve(chain_id)?;
+    let ip_account = primitive_types::H160::from_str(&ip_id)
+        .map_err(|e| format!("Invalid IP ID: {}", e))?;
+    let to = evm_util::parse_eip55_address(&to)?;
+    let data = hex::decode(data.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid calldata hex: {}", e))?;
+
+    story_util::execute_with_sig(&network, ip_account, to, data, &signature).await
 }
 
 // ==============================================================================
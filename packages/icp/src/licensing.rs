@@ -0,0 +1,721 @@
+// Licensing Module
+// Programmable IP License (PIL) terms: registration, attachment and the
+// combined mint-register-attach workflow
+//
+// Terms are built from one of the three PIL flavors Story ships presets for
+// and registered on the network's PILicenseTemplate. Registration is
+// idempotent on-chain: `getLicenseTermsId` returns the ID of identical terms
+// that are already registered, in which case nothing is sent.
+
+use crate::erc20::{self, Approval};
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::Network;
+use crate::transactions::{self, TxKind};
+use crate::{config, events, rpc_util, tracker};
+use candid::{CandidType, Deserialize};
+use ethabi::{encode, Address, ParamType, Token};
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use serde_json::json;
+
+/// PILTerms struct as an ABI tuple type
+const PIL_TERMS_TUPLE: &str = "(bool,address,uint256,uint256,bool,bool,address,bytes,uint32,uint256,bool,bool,bool,bool,uint256,address,string)";
+/// Licensing.LicensingConfig struct as an ABI tuple type
+const LICENSING_CONFIG_TUPLE: &str = "(bool,uint256,address,bytes,uint32,bool,uint32,address)";
+/// WorkflowStructs.IPMetadata struct as an ABI tuple type
+const IP_METADATA_TUPLE: &str = "(string,bytes32,string,bytes32)";
+
+/// `commercialRevShare` of 100% (the PIL counts in millionths of a percent)
+const MAX_REVENUE_SHARE: u32 = 100_000_000;
+
+/// Off-chain terms of Story's presets; using the same URIs makes the terms
+/// identical to (and deduplicated with) those registered by the Story SDK
+const NON_COMMERCIAL_SOCIAL_REMIXING_URI: &str = "https://github.com/piplabs/pil-document/blob/998c13e6ee1d04eb817aefd1fe16dfe8be3cd7a2/off-chain-terms/NCSR.json";
+const COMMERCIAL_USE_URI: &str = "https://github.com/piplabs/pil-document/blob/9a1f803fcf8101a8a78f1dcc929e6014e144ab56/off-chain-terms/CommercialUse.json";
+const COMMERCIAL_REMIX_URI: &str = "https://github.com/piplabs/pil-document/blob/ad67bb632a310d2557f8abcccd428e4c9c798db1/off-chain-terms/CommercialRemix.json";
+
+// ==============================================================================
+// License Choices
+// ==============================================================================
+
+/// PIL flavor to register or attach
+///
+/// `minting_fee` is in wei of `currency`, an EIP-55 ERC-20 address that must
+/// be whitelisted by the RoyaltyModule (None = the network's WIP token).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum LicenseChoice {
+    /// Free remixing with attribution; no commercial use
+    NonCommercialSocialRemixing,
+    /// Commercial use of the IP itself; no derivatives
+    CommercialUse {
+        minting_fee: u64,
+        currency: Option<String>,
+    },
+    /// Commercial use and derivatives, sharing `revenue_share_percent` of
+    /// derivative revenue with the parent
+    CommercialRemix {
+        minting_fee: u64,
+        currency: Option<String>,
+        revenue_share_percent: u32,
+    },
+}
+
+impl LicenseChoice {
+    /// Check the parameters that do not depend on the network
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            LicenseChoice::NonCommercialSocialRemixing => Ok(()),
+            LicenseChoice::CommercialUse { currency, .. } => validate_currency(currency),
+            LicenseChoice::CommercialRemix {
+                currency,
+                revenue_share_percent,
+                ..
+            } => {
+                revenue_share(*revenue_share_percent)?;
+                validate_currency(currency)
+            }
+        }
+    }
+}
+
+fn validate_currency(currency: &Option<String>) -> Result<(), String> {
+    if let Some(currency) = currency {
+        evm_util::parse_eip55_address(currency)?;
+    }
+    Ok(())
+}
+
+/// `commercialRevShare` for a percentage
+pub fn revenue_share(percent: u32) -> Result<u32, String> {
+    if percent > 100 {
+        return Err(format!(
+            "Invalid revenue share {}% (must be between 0 and 100)",
+            percent
+        ));
+    }
+    Ok(percent * (MAX_REVENUE_SHARE / 100))
+}
+
+/// Result of `mint_license_tokens`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LicenseTokenMint {
+    /// None if the fee approval is still pending (call again once it is mined)
+    pub tx_hash: Option<String>,
+    /// Approval of the minting fee sent by this call, if one was needed
+    pub approval_tx_hash: Option<String>,
+    /// Fee paid, in the smallest unit of `currency`
+    pub minting_fee: u64,
+    /// EIP-55 fee token; None if minting is free
+    pub currency: Option<String>,
+    /// Minted license token IDs; empty until the transaction is confirmed
+    pub license_token_ids: Vec<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LicenseTermsRegistration {
+    /// None while the registration transaction is pending
+    pub license_terms_id: Option<u64>,
+    /// None if identical terms were already registered
+    pub tx_hash: Option<String>,
+}
+
+// ==============================================================================
+// PIL Terms
+// ==============================================================================
+
+/// PILicenseTemplate `PILTerms` struct
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PilTerms {
+    pub transferable: bool,
+    pub royalty_policy: H160,
+    pub default_minting_fee: u64,
+    pub expiration: u64,
+    pub commercial_use: bool,
+    pub commercial_attribution: bool,
+    pub commercializer_checker: H160,
+    pub commercializer_checker_data: Vec<u8>,
+    pub commercial_rev_share: u32,
+    pub commercial_rev_ceiling: u64,
+    pub derivatives_allowed: bool,
+    pub derivatives_attribution: bool,
+    pub derivatives_approval: bool,
+    pub derivatives_reciprocal: bool,
+    pub derivative_rev_ceiling: u64,
+    pub currency: H160,
+    pub uri: String,
+}
+
+impl PilTerms {
+    /// Terms of `choice` on `network` (royalty policy and default currency)
+    pub fn from_choice(network: &Network, choice: &LicenseChoice) -> Result<Self, String> {
+        let currency = |currency: &Option<String>| -> Result<H160, String> {
+            match currency {
+                Some(currency) => Ok(H160::from(evm_util::parse_eip55_address(currency)?)),
+                None => network.wip_token(),
+            }
+        };
+
+        let terms = match choice {
+            LicenseChoice::NonCommercialSocialRemixing => PilTerms {
+                transferable: true,
+                royalty_policy: H160::zero(),
+                default_minting_fee: 0,
+                expiration: 0,
+                commercial_use: false,
+                commercial_attribution: false,
+                commercializer_checker: H160::zero(),
+                commercializer_checker_data: vec![],
+                commercial_rev_share: 0,
+                commercial_rev_ceiling: 0,
+                derivatives_allowed: true,
+                derivatives_attribution: true,
+                derivatives_approval: false,
+                derivatives_reciprocal: true,
+                derivative_rev_ceiling: 0,
+                currency: H160::zero(),
+                uri: NON_COMMERCIAL_SOCIAL_REMIXING_URI.to_string(),
+            },
+            LicenseChoice::CommercialUse {
+                minting_fee,
+                currency: token,
+            } => PilTerms {
+                transferable: true,
+                royalty_policy: network.royalty_policy_lap()?,
+                default_minting_fee: *minting_fee,
+                expiration: 0,
+                commercial_use: true,
+                commercial_attribution: true,
+                commercializer_checker: H160::zero(),
+                commercializer_checker_data: vec![],
+                commercial_rev_share: 0,
+                commercial_rev_ceiling: 0,
+                derivatives_allowed: false,
+                derivatives_attribution: false,
+                derivatives_approval: false,
+                derivatives_reciprocal: false,
+                derivative_rev_ceiling: 0,
+                currency: currency(token)?,
+                uri: COMMERCIAL_USE_URI.to_string(),
+            },
+            LicenseChoice::CommercialRemix {
+                minting_fee,
+                currency: token,
+                revenue_share_percent,
+            } => PilTerms {
+                transferable: true,
+                royalty_policy: network.royalty_policy_lap()?,
+                default_minting_fee: *minting_fee,
+                expiration: 0,
+                commercial_use: true,
+                commercial_attribution: true,
+                commercializer_checker: H160::zero(),
+                commercializer_checker_data: vec![],
+                commercial_rev_share: revenue_share(*revenue_share_percent)?,
+                commercial_rev_ceiling: 0,
+                derivatives_allowed: true,
+                derivatives_attribution: true,
+                derivatives_approval: false,
+                derivatives_reciprocal: true,
+                derivative_rev_ceiling: 0,
+                currency: currency(token)?,
+                uri: COMMERCIAL_REMIX_URI.to_string(),
+            },
+        };
+        Ok(terms)
+    }
+
+    pub fn to_token(&self) -> Token {
+        Token::Tuple(vec![
+            Token::Bool(self.transferable),
+            Token::Address(self.royalty_policy),
+            Token::Uint(U256::from(self.default_minting_fee)),
+            Token::Uint(U256::from(self.expiration)),
+            Token::Bool(self.commercial_use),
+            Token::Bool(self.commercial_attribution),
+            Token::Address(self.commercializer_checker),
+            Token::Bytes(self.commercializer_checker_data.clone()),
+            Token::Uint(U256::from(self.commercial_rev_share)),
+            Token::Uint(U256::from(self.commercial_rev_ceiling)),
+            Token::Bool(self.derivatives_allowed),
+            Token::Bool(self.derivatives_attribution),
+            Token::Bool(self.derivatives_approval),
+            Token::Bool(self.derivatives_reciprocal),
+            Token::Uint(U256::from(self.derivative_rev_ceiling)),
+            Token::Address(self.currency),
+            Token::String(self.uri.clone()),
+        ])
+    }
+}
+
+/// Licensing.LicensingConfig with `isSet` = false (the terms apply as-is)
+fn unset_licensing_config() -> Token {
+    Token::Tuple(vec![
+        Token::Bool(false),
+        Token::Uint(U256::zero()),
+        Token::Address(Address::zero()),
+        Token::Bytes(vec![]),
+        Token::Uint(U256::zero()),
+        Token::Bool(false),
+        Token::Uint(U256::zero()),
+        Token::Address(Address::zero()),
+    ])
+}
+
+// ==============================================================================
+// ABI Encoding
+// ==============================================================================
+
+/// Calldata for PILicenseTemplate.`function`(PILTerms)
+fn build_terms_calldata(function: &str, terms: &PilTerms) -> Vec<u8> {
+    let mut calldata =
+        evm_util::function_selector(&format!("{}({})", function, PIL_TERMS_TUPLE)).to_vec();
+    calldata.extend_from_slice(&encode(&[terms.to_token()]));
+    calldata
+}
+
+/// Build calldata for LicensingModule.attachLicenseTerms(address,address,uint256)
+pub fn build_attach_license_terms_calldata(
+    ip_id: H160,
+    license_template: H160,
+    license_terms_id: u64,
+) -> Vec<u8> {
+    let mut calldata =
+        evm_util::function_selector("attachLicenseTerms(address,address,uint256)").to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(ip_id),
+        Token::Address(license_template),
+        Token::Uint(U256::from(license_terms_id)),
+    ]));
+    calldata
+}
+
+/// Build calldata for LicenseAttachmentWorkflows.mintAndRegisterIpAndAttachPILTerms()
+///
+/// Function signature: mintAndRegisterIpAndAttachPILTerms(address spgNftContract,
+/// address recipient, IPMetadata ipMetadata, LicenseTermsData[] licenseTermsData,
+/// bool allowDuplicates)
+///
+/// `LicenseTermsData` pairs the terms with an unset licensing config. The
+/// workflow registers the terms if needed, so they need no prior registration.
+///
+/// # Arguments
+/// * `spg_nft_contract` - SPG NFT collection to mint from
+/// * `recipient` - Owner of the NFT and IP Account
+/// * `ip_metadata` - `IPMetadata` tuple (see `story_util::ip_metadata`)
+/// * `terms` - Terms to attach
+pub fn build_mint_and_register_ip_and_attach_pil_terms_calldata(
+    spg_nft_contract: H160,
+    recipient: [u8; 20],
+    ip_metadata: Token,
+    terms: &PilTerms,
+) -> Vec<u8> {
+    let signature = format!(
+        "mintAndRegisterIpAndAttachPILTerms(address,address,{},({},{})[],bool)",
+        IP_METADATA_TUPLE, PIL_TERMS_TUPLE, LICENSING_CONFIG_TUPLE
+    );
+    let mut calldata = evm_util::function_selector(&signature).to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(spg_nft_contract),
+        Token::Address(Address::from(recipient)),
+        ip_metadata,
+        Token::Array(vec![Token::Tuple(vec![
+            terms.to_token(),
+            unset_licensing_config(),
+        ])]),
+        // The content hash already makes the metadata unique
+        Token::Bool(true),
+    ]));
+    calldata
+}
+
+// ==============================================================================
+// License Terms Registration
+// ==============================================================================
+
+/// ID of identical terms already registered on the template (None if none are)
+pub async fn license_terms_id(network: &Network, terms: &PilTerms) -> Result<Option<u64>, String> {
+    let template = network.pil_license_template()?;
+    let result = rpc_util::call(
+        network.chain_id,
+        "eth_call",
+        json!([
+            {
+                "to": format!("0x{}", hex::encode(template.to_fixed_bytes())),
+                "data": format!("0x{}", hex::encode(build_terms_calldata("getLicenseTermsId", terms))),
+            },
+            "latest"
+        ]),
+    )
+    .await?;
+
+    let hex_str = result
+        .as_str()
+        .ok_or_else(|| format!("Unexpected getLicenseTermsId result: {}", result))?
+        .trim_start_matches("0x");
+    let id = U256::from_str_radix(hex_str, 16)
+        .map_err(|e| format!("Invalid getLicenseTermsId result '0x{}': {:?}", hex_str, e))?;
+    if id.bits() > 64 {
+        return Err(format!("License terms ID {} does not fit in 64 bits", id));
+    }
+
+    Ok((!id.is_zero()).then(|| id.low_u64()))
+}
+
+/// Register the terms of `choice` on the network's PILicenseTemplate
+///
+/// Sent from the canister's address. Terms that are already registered are
+/// returned without a transaction.
+///
+/// # Arguments
+/// * `network` - Network to register on
+/// * `choice` - PIL flavor and its commercial parameters
+///
+/// # Returns
+/// * `Result<LicenseTermsRegistration, String>` - Terms ID (once known) and
+///   transaction hash
+pub async fn register_pil_terms(
+    network: &Network,
+    choice: &LicenseChoice,
+) -> Result<LicenseTermsRegistration, String> {
+    let terms = PilTerms::from_choice(network, choice)?;
+    ic_cdk::println!(
+        "   📄 Registering {:?} license terms on {}...",
+        choice,
+        network.name
+    );
+
+    if let Some(id) = license_terms_id(network, &terms).await? {
+        ic_cdk::println!("   ✅ Already registered as license terms #{}", id);
+        return Ok(LicenseTermsRegistration {
+            license_terms_id: Some(id),
+            tx_hash: None,
+        });
+    }
+
+    let template = network.pil_license_template()?;
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::RegisterLicenseTerms,
+        Some(template.to_fixed_bytes()),
+        build_terms_calldata("registerLicenseTerms", &terms),
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ License terms sent: {}", network.tx_url(&tx_hash));
+
+    let license_terms_id = match tracker::fetch_receipt(network.chain_id, &tx_hash).await {
+        Ok(Some(receipt)) => events::find_license_terms_registered(&receipt, template),
+        Ok(None) => {
+            ic_cdk::println!("   ℹ️  Registration pending; call again once it is mined for the ID");
+            None
+        }
+        Err(e) => {
+            ic_cdk::println!("   ⚠️  Could not get receipt: {}", e);
+            None
+        }
+    };
+
+    Ok(LicenseTermsRegistration {
+        license_terms_id,
+        tx_hash: Some(tx_hash),
+    })
+}
+
+/// Attach registered PIL terms to an IP Asset
+///
+/// # Arguments
+/// * `network` - Network the IP Asset lives on
+/// * `account` - Account owning the IP Asset's NFT (signs the call)
+/// * `ip_id` - IP Asset to attach the terms to
+/// * `license_terms_id` - ID of terms registered on the PILicenseTemplate
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+pub async fn attach_license_terms(
+    network: &Network,
+    account: EvmAccount,
+    ip_id: H160,
+    license_terms_id: u64,
+) -> Result<String, String> {
+    let licensing_module = network.licensing_module()?;
+    let calldata = build_attach_license_terms_calldata(
+        ip_id,
+        network.pil_license_template()?,
+        license_terms_id,
+    );
+
+    ic_cdk::println!(
+        "   📎 Attaching license terms #{} to IP Asset 0x{}",
+        license_terms_id,
+        hex::encode(ip_id.to_fixed_bytes())
+    );
+    let tx_hash = transactions::send(
+        network,
+        account,
+        TxKind::AttachLicenseTerms,
+        Some(licensing_module.to_fixed_bytes()),
+        calldata,
+        0, // No value transfer
+    )
+    .await?;
+
+    ic_cdk::println!("   ✅ License terms attached: {}", network.tx_url(&tx_hash));
+    Ok(tx_hash)
+}
+
+// ==============================================================================
+// License Tokens
+// ==============================================================================
+
+/// Fee `LicensingModule.predictMintingLicenseFee` quotes for a mint
+///
+/// # Returns
+/// * `Result<(H160, u64), String>` - Fee token and amount (zero address and 0
+///   if minting is free)
+pub async fn predict_minting_fee(
+    network: &Network,
+    licensor_ip_id: H160,
+    license_terms_id: u64,
+    amount: u64,
+    receiver: [u8; 20],
+) -> Result<(H160, u64), String> {
+    let mut calldata = evm_util::function_selector(
+        "predictMintingLicenseFee(address,address,uint256,uint256,address,bytes)",
+    )
+    .to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(licensor_ip_id),
+        Token::Address(network.pil_license_template()?),
+        Token::Uint(U256::from(license_terms_id)),
+        Token::Uint(U256::from(amount)),
+        Token::Address(Address::from(receiver)),
+        Token::Bytes(vec![]), // royaltyContext
+    ]));
+
+    let data = rpc_util::eth_call(network.chain_id, network.licensing_module()?, &calldata).await?;
+    let tokens = ethabi::decode(&[ParamType::Address, ParamType::Uint(256)], &data)
+        .map_err(|e| format!("Failed to decode minting fee: {}", e))?;
+    match tokens.as_slice() {
+        [Token::Address(currency), Token::Uint(fee)] if fee.bits() <= 64 => {
+            Ok((*currency, fee.low_u64()))
+        }
+        [_, Token::Uint(fee)] => Err(format!("Minting fee {} does not fit in 64 bits", fee)),
+        _ => Err(format!("Unexpected minting fee result: {:?}", tokens)),
+    }
+}
+
+/// Build calldata for LicensingModule.mintLicenseTokens(address,address,
+/// uint256,uint256,address,bytes,uint256,uint32)
+pub fn build_mint_license_tokens_calldata(
+    licensor_ip_id: H160,
+    license_template: H160,
+    license_terms_id: u64,
+    amount: u64,
+    receiver: [u8; 20],
+    max_minting_fee: u64,
+) -> Vec<u8> {
+    let mut calldata = evm_util::function_selector(
+        "mintLicenseTokens(address,address,uint256,uint256,address,bytes,uint256,uint32)",
+    )
+    .to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(licensor_ip_id),
+        Token::Address(license_template),
+        Token::Uint(U256::from(license_terms_id)),
+        Token::Uint(U256::from(amount)),
+        Token::Address(Address::from(receiver)),
+        Token::Bytes(vec![]), // royaltyContext
+        Token::Uint(U256::from(max_minting_fee)),
+        // The licensor's own revenue share is no reason to refuse the mint
+        Token::Uint(U256::from(MAX_REVENUE_SHARE)),
+    ]));
+    calldata
+}
+
+/// Mint license tokens of an IP Asset's attached terms to a licensee
+///
+/// Sent from the canister's address, which pays the minting fee. The fee is
+/// quoted first and capped at that quote; if the canister has not approved
+/// the RoyaltyModule for it, an approval is sent, and minting waits for a
+/// later call when the approval is not mined right away.
+///
+/// # Arguments
+/// * `network` - Network the IP Asset lives on
+/// * `licensor_ip_id` - IP Asset whose terms are licensed
+/// * `license_terms_id` - PIL terms attached to the IP Asset
+/// * `amount` - Number of license tokens (1 to `MAX_LICENSE_TOKENS_PER_MINT`)
+/// * `receiver` - Licensee address
+///
+/// # Returns
+/// * `Result<LicenseTokenMint, String>` - Transaction hashes, fee and token IDs
+pub async fn mint_license_tokens(
+    network: &Network,
+    licensor_ip_id: H160,
+    license_terms_id: u64,
+    amount: u64,
+    receiver: [u8; 20],
+) -> Result<LicenseTokenMint, String> {
+    if amount == 0 || amount > config::MAX_LICENSE_TOKENS_PER_MINT {
+        return Err(format!(
+            "Amount must be between 1 and {}",
+            config::MAX_LICENSE_TOKENS_PER_MINT
+        ));
+    }
+
+    let (currency, minting_fee) =
+        predict_minting_fee(network, licensor_ip_id, license_terms_id, amount, receiver).await?;
+    let mut result = LicenseTokenMint {
+        tx_hash: None,
+        approval_tx_hash: None,
+        minting_fee,
+        currency: (minting_fee > 0)
+            .then(|| evm_util::to_checksum_address(&currency.to_fixed_bytes())),
+        license_token_ids: vec![],
+    };
+    ic_cdk::println!(
+        "   🎫 Minting {} license token(s) of terms #{} (fee {} of {:?})",
+        amount,
+        license_terms_id,
+        minting_fee,
+        result.currency
+    );
+
+    if minting_fee > 0 {
+        let approval = erc20::ensure_allowance(
+            network,
+            EvmAccount::Canister,
+            currency,
+            network.royalty_module()?,
+            minting_fee,
+        )
+        .await?;
+        match approval {
+            Approval::Sufficient => {}
+            Approval::Approved(tx_hash) => result.approval_tx_hash = Some(tx_hash),
+            Approval::Pending(tx_hash) => {
+                ic_cdk::println!("   ℹ️  Fee approval pending; call again once it is mined");
+                result.approval_tx_hash = Some(tx_hash);
+                return Ok(result);
+            }
+        }
+    }
+
+    let licensing_module = network.licensing_module()?;
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::MintLicenseTokens,
+        Some(licensing_module.to_fixed_bytes()),
+        build_mint_license_tokens_calldata(
+            licensor_ip_id,
+            network.pil_license_template()?,
+            license_terms_id,
+            amount,
+            receiver,
+            minting_fee,
+        ),
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ License tokens sent: {}", network.tx_url(&tx_hash));
+
+    // Token IDs come from the receipt now or from the receipt tracker later
+    match tracker::fetch_receipt(network.chain_id, &tx_hash).await {
+        Ok(Some(receipt)) => {
+            result.license_token_ids =
+                events::find_license_tokens_minted(&receipt, licensing_module).unwrap_or_default();
+        }
+        Ok(None) => ic_cdk::println!("   ℹ️  Mint pending; the receipt tracker will follow it"),
+        Err(e) => ic_cdk::println!("   ⚠️  Could not get receipt: {}", e),
+    }
+    result.tx_hash = Some(tx_hash);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks;
+    use ethabi::param_type::Reader;
+
+    #[test]
+    fn revenue_share_is_in_millionths_of_a_percent() {
+        assert_eq!(revenue_share(0), Ok(0));
+        assert_eq!(revenue_share(10), Ok(10_000_000));
+        assert_eq!(revenue_share(100), Ok(MAX_REVENUE_SHARE));
+        assert!(revenue_share(101).is_err());
+
+        let choice = LicenseChoice::CommercialRemix {
+            minting_fee: 0,
+            currency: None,
+            revenue_share_percent: 101,
+        };
+        assert!(choice.validate().is_err());
+    }
+
+    #[test]
+    fn terms_encode_as_the_pil_terms_tuple() {
+        let choice = LicenseChoice::CommercialRemix {
+            minting_fee: 5,
+            currency: None,
+            revenue_share_percent: 10,
+        };
+        let network = networks::aeneid();
+        let terms = PilTerms::from_choice(&network, &choice).unwrap();
+        assert_eq!(terms.currency, network.wip_token().unwrap());
+        assert_eq!(terms.royalty_policy, network.royalty_policy_lap().unwrap());
+
+        let layout = Reader::read(PIL_TERMS_TUPLE).unwrap();
+        let encoded = encode(std::slice::from_ref(&terms.to_token()));
+        assert_eq!(ethabi::decode(&[layout], &encoded).unwrap(), vec![terms.to_token()]);
+    }
+
+    #[test]
+    fn non_commercial_terms_need_no_royalty_policy() {
+        let terms = PilTerms::from_choice(
+            &networks::aeneid(),
+            &LicenseChoice::NonCommercialSocialRemixing,
+        )
+        .unwrap();
+        assert!(!terms.commercial_use);
+        assert_eq!(terms.royalty_policy, H160::zero());
+        assert_eq!(terms.currency, H160::zero());
+    }
+
+    #[test]
+    fn attach_license_terms_selector() {
+        let calldata = build_attach_license_terms_calldata(H160::zero(), H160::zero(), 1);
+        assert_eq!(hex::encode(&calldata[..4]), "2a4130c0");
+        assert_eq!(calldata.len(), 4 + 3 * 32);
+    }
+
+    #[test]
+    fn mint_license_tokens_calldata_caps_the_fee() {
+        let calldata = build_mint_license_tokens_calldata(
+            H160::repeat_byte(0x11),
+            H160::repeat_byte(0x22),
+            7,
+            2,
+            [0x33; 20],
+            500,
+        );
+        let tokens = ethabi::decode(
+            &[
+                ParamType::Address,
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+                ParamType::Address,
+                ParamType::Bytes,
+                ParamType::Uint(256),
+                ParamType::Uint(32),
+            ],
+            &calldata[4..],
+        )
+        .unwrap();
+        assert_eq!(tokens[3], Token::Uint(U256::from(2u64)));
+        assert_eq!(tokens[6], Token::Uint(U256::from(500u64)));
+        assert_eq!(tokens[7], Token::Uint(U256::from(MAX_REVENUE_SHARE)));
+    }
+}
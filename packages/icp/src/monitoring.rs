@@ -0,0 +1,421 @@
+// Funds Monitoring Module
+// Cycles and gas balance tracking with low-funds guardrails for new jobs
+//
+// A timer samples the canister's cycles balance and the `eth_getBalance` of its
+// EVM address on every registered network into a bounded history. New jobs are
+// refused when the funds left after their estimated cost (see
+// `quota::GenerationCost`) and the gas still owed by in-flight jobs would fall
+// below the admin-set thresholds, instead of failing at broadcast after the AI
+// generation was already paid for. A job signing from a caller's derived
+// account is checked against that account's live balance; a job is refused
+// when no balance can be read at all.
+
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::{self, Network};
+use crate::quota::GenerationCost;
+use crate::{config, fees, jobs, rpc_util, STATE};
+use candid::{CandidType, Deserialize};
+use primitive_types::U256;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Maximum number of samples returned by `get_balance_history`
+pub const MAX_HISTORY_PAGE: u32 = 100;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FundsThresholds {
+    /// Cycles that must remain after a job's estimated cycles cost
+    pub min_cycles: u64,
+    /// Wei the signing EVM address must keep after a job's estimated gas cost
+    pub min_gas_balance_wei: u128,
+}
+
+impl Default for FundsThresholds {
+    fn default() -> Self {
+        Self {
+            min_cycles: config::DEFAULT_MIN_CYCLES_BALANCE,
+            min_gas_balance_wei: config::DEFAULT_MIN_GAS_BALANCE_WEI,
+        }
+    }
+}
+
+/// Balance of the canister's EVM address on one network
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GasBalance {
+    pub chain_id: u64,
+    pub address: String,
+    /// Wei; None if the query failed
+    pub balance_wei: Option<u128>,
+    /// Fee cap per gas a transaction would pay at sampling time
+    pub max_fee_per_gas: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BalanceSample {
+    /// IC time (nanoseconds)
+    pub taken_at: u64,
+    /// Saturating at u64::MAX
+    pub cycles: u64,
+    pub networks: Vec<GasBalance>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FundsKind {
+    Cycles,
+    Gas,
+    /// The gas balance could not be read (`available` and `required` are 0)
+    GasUnknown,
+}
+
+/// Typed "insufficient funds" error returned to callers
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct InsufficientFunds {
+    pub kind: FundsKind,
+    /// Network of a gas shortfall
+    pub chain_id: Option<u64>,
+    /// Cycles, or wei for gas
+    pub available: u128,
+    /// Estimated job cost, the gas owed by in-flight jobs and the reserve
+    /// threshold
+    pub required: u128,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FundsMonitor {
+    pub thresholds: FundsThresholds,
+    /// Oldest first, at most `config::BALANCE_HISTORY_LIMIT` samples
+    history: VecDeque<BalanceSample>,
+}
+
+impl FundsMonitor {
+    fn record(&mut self, sample: BalanceSample) {
+        self.history.push_back(sample);
+        while self.history.len() > config::BALANCE_HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+    }
+
+    /// Newest samples first
+    pub fn history(&self, limit: u32) -> Vec<BalanceSample> {
+        let limit = limit.clamp(1, MAX_HISTORY_PAGE) as usize;
+        self.history.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Balance and fee cap of the canister's address on `chain_id` from the
+    /// latest sample, unless that sample is older than `max_age_ns` or its
+    /// reading for the network failed
+    fn recent_gas_balance(&self, chain_id: u64, now: u64, max_age_ns: u64) -> Option<(u128, u64)> {
+        let sample = self.history.back()?;
+        if now.saturating_sub(sample.taken_at) > max_age_ns {
+            return None;
+        }
+        let balance = sample.networks.iter().find(|b| b.chain_id == chain_id)?;
+        Some((balance.balance_wei?, balance.max_fee_per_gas?))
+    }
+}
+
+fn saturating_u64(value: u128) -> u64 {
+    value.min(u64::MAX as u128) as u64
+}
+
+/// Cycles balance of this canister
+pub fn cycles_balance() -> u64 {
+    saturating_u64(ic_cdk::api::canister_balance128())
+}
+
+// ==============================================================================
+// Sampling
+// ==============================================================================
+
+async fn fetch_balance(chain_id: u64, address: &str) -> Result<u128, String> {
+    let result = rpc_util::call(chain_id, "eth_getBalance", json!([address, "latest"])).await?;
+    let hex_str = result
+        .as_str()
+        .ok_or_else(|| format!("Unexpected eth_getBalance result: {}", result))?;
+    let balance = U256::from_str_radix(hex_str.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Invalid balance '{}': {:?}", hex_str, e))?;
+
+    Ok(if balance.bits() > 128 {
+        u128::MAX
+    } else {
+        balance.low_u128()
+    })
+}
+
+/// Live balance of `address` on `network` and the fee cap per gas a
+/// transaction would pay now
+async fn read_gas_balance(network: &Network, address: &str) -> Result<(u128, u64), String> {
+    let wei = fetch_balance(network.chain_id, address).await?;
+    let (fees, _, _) = fees::current_fees(network.chain_id, network.tx_type, &fees::settings()).await;
+    Ok((wei, fees.max_price_per_gas()))
+}
+
+/// Sample the cycles balance and the canister's gas balance on every network
+pub async fn sample() -> BalanceSample {
+    let networks = STATE.with(|state| state.borrow().networks.list());
+    let address = evm_util::get_canister_evm_address().await;
+    let thresholds = STATE.with(|state| state.borrow().monitoring.thresholds.clone());
+
+    let mut balances = Vec::with_capacity(networks.len());
+    for network in networks {
+        let mut balance = GasBalance {
+            chain_id: network.chain_id,
+            address: address.clone().unwrap_or_default(),
+            balance_wei: None,
+            max_fee_per_gas: None,
+            error: None,
+        };
+
+        let fetched = match &address {
+            Ok(address) => read_gas_balance(&network, address).await,
+            Err(e) => Err(e.clone()),
+        };
+        match fetched {
+            Ok((wei, max_fee_per_gas)) => {
+                balance.balance_wei = Some(wei);
+                balance.max_fee_per_gas = Some(max_fee_per_gas);
+
+                if wei < thresholds.min_gas_balance_wei {
+                    ic_cdk::println!(
+                        "⚠️  Low gas balance on {}: {} wei (threshold {} wei)",
+                        network.name,
+                        wei,
+                        thresholds.min_gas_balance_wei
+                    );
+                }
+            }
+            Err(e) => {
+                ic_cdk::println!("   ⚠️  Balance check failed on {}: {}", network.name, e);
+                balance.error = Some(e);
+            }
+        }
+        balances.push(balance);
+    }
+
+    let cycles = cycles_balance();
+    if cycles < thresholds.min_cycles {
+        ic_cdk::println!(
+            "⚠️  Low cycles balance: {} (threshold {})",
+            cycles,
+            thresholds.min_cycles
+        );
+    }
+
+    let sample = BalanceSample {
+        taken_at: ic_cdk::api::time(),
+        cycles,
+        networks: balances,
+    };
+    STATE.with(|state| state.borrow_mut().monitoring.record(sample.clone()));
+    sample
+}
+
+/// Start the periodic balance sampler
+///
+/// Timers do not survive upgrades, so this must be called from both `init`
+/// and `post_upgrade`.
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config::FUNDS_SAMPLE_INTERVAL_SECS),
+        || {
+            ic_cdk::spawn(async {
+                sample().await;
+            })
+        },
+    );
+    // Have a reading right after deployment or an upgrade
+    ic_cdk_timers::set_timer(Duration::ZERO, || {
+        ic_cdk::spawn(async {
+            sample().await;
+        })
+    });
+}
+
+// ==============================================================================
+// Guardrails
+// ==============================================================================
+
+/// Refuse a new job the canister cannot pay for
+///
+/// Cycles are read live. The gas balance of the canister's address comes from
+/// the latest sample while it is at most `config::FUNDS_SAMPLE_MAX_AGE_SECS`
+/// old and is read live otherwise; a job signing from a caller's account is
+/// checked against that account's live balance. The job must leave the
+/// threshold after its own gas and the gas of the unfinished jobs signing from
+/// the same account on the same network. A balance that cannot be read
+/// refuses the job.
+///
+/// # Arguments
+/// * `chain_id` - Network the job registers on
+/// * `account` - Account the job signs with
+///
+/// # Returns
+/// * `Result<(), InsufficientFunds>` - Ok if the job's estimated cost is covered
+pub async fn check_job_funds(chain_id: u64, account: &EvmAccount) -> Result<(), InsufficientFunds> {
+    let cost = GenerationCost::estimate();
+    let now = ic_cdk::api::time();
+    let max_age_ns = config::FUNDS_SAMPLE_MAX_AGE_SECS * 1_000_000_000;
+    let (thresholds, sampled) = STATE.with(|state| {
+        let state = state.borrow();
+        let sampled = match account {
+            EvmAccount::Canister => state.monitoring.recent_gas_balance(chain_id, now, max_age_ns),
+            EvmAccount::User(_) => None,
+        };
+        (state.monitoring.thresholds.clone(), sampled)
+    });
+
+    let available = cycles_balance() as u128;
+    let required = (thresholds.min_cycles as u128).saturating_add(cost.cycles as u128);
+    if available < required {
+        ic_cdk::println!(
+            "⛔ Refusing job: {} cycles left, {} needed",
+            available,
+            required
+        );
+        return Err(InsufficientFunds {
+            kind: FundsKind::Cycles,
+            chain_id: None,
+            available,
+            required,
+        });
+    }
+
+    let (available, max_fee_per_gas) = match sampled {
+        Some(sampled) => sampled,
+        None => live_gas_balance(chain_id, account).await.map_err(|e| {
+            ic_cdk::println!(
+                "⛔ Refusing job: gas balance of {:?} on chain {} unknown: {}",
+                account,
+                chain_id,
+                e
+            );
+            InsufficientFunds {
+                kind: FundsKind::GasUnknown,
+                chain_id: Some(chain_id),
+                available: 0,
+                required: 0,
+            }
+        })?,
+    };
+
+    // This job plus the unfinished ones that have not paid for their
+    // registration yet
+    let jobs = jobs::unregistered(chain_id, account).saturating_add(1);
+    let required = gas_required(cost.gas, max_fee_per_gas, jobs, thresholds.min_gas_balance_wei);
+    if available < required {
+        ic_cdk::println!(
+            "⛔ Refusing job: {} wei on chain {}, {} wei needed ({} jobs)",
+            available,
+            chain_id,
+            required,
+            jobs
+        );
+        return Err(InsufficientFunds {
+            kind: FundsKind::Gas,
+            chain_id: Some(chain_id),
+            available,
+            required,
+        });
+    }
+
+    Ok(())
+}
+
+/// Wei needed for `jobs` registrations of `gas` each at `max_fee_per_gas`,
+/// on top of the `reserve` that must remain
+fn gas_required(gas: u64, max_fee_per_gas: u64, jobs: u64, reserve: u128) -> u128 {
+    (gas as u128)
+        .saturating_mul(max_fee_per_gas as u128)
+        .saturating_mul(jobs as u128)
+        .saturating_add(reserve)
+}
+
+/// Read the balance of `account` on `chain_id` now
+async fn live_gas_balance(chain_id: u64, account: &EvmAccount) -> Result<(u128, u64), String> {
+    let network = networks::get(chain_id)?;
+    let address = evm_util::get_evm_address(account).await?;
+    read_gas_balance(&network, &address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECS: u64 = 1_000_000_000;
+
+    fn sample(taken_at: u64, balance_wei: Option<u128>) -> BalanceSample {
+        BalanceSample {
+            taken_at,
+            cycles: 0,
+            networks: vec![GasBalance {
+                chain_id: 1315,
+                address: "0x0000000000000000000000000000000000000001".to_string(),
+                balance_wei,
+                max_fee_per_gas: Some(7),
+                error: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn recent_gas_balance_ignores_stale_and_failed_samples() {
+        let mut monitor = FundsMonitor::default();
+        assert_eq!(monitor.recent_gas_balance(1315, 100 * SECS, 60 * SECS), None);
+
+        monitor.record(sample(50 * SECS, Some(42)));
+        assert_eq!(monitor.recent_gas_balance(1315, 100 * SECS, 60 * SECS), Some((42, 7)));
+        assert_eq!(monitor.recent_gas_balance(1315, 200 * SECS, 60 * SECS), None);
+        assert_eq!(monitor.recent_gas_balance(1, 100 * SECS, 60 * SECS), None);
+
+        // A failed reading in the latest sample is not papered over by an older one
+        monitor.record(sample(90 * SECS, None));
+        assert_eq!(monitor.recent_gas_balance(1315, 100 * SECS, 60 * SECS), None);
+    }
+
+    #[test]
+    fn gas_requirement_covers_every_unregistered_job_and_the_reserve() {
+        // 3M gas at 1 gwei for this job and one in flight, plus a 0.01 IP reserve
+        assert_eq!(
+            gas_required(3_000_000, 1_000_000_000, 2, 10u128.pow(16)),
+            2 * 3 * 10u128.pow(15) + 10u128.pow(16)
+        );
+        assert_eq!(gas_required(u64::MAX, u64::MAX, u64::MAX, u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn history_is_bounded_and_newest_first() {
+        let mut monitor = FundsMonitor::default();
+        for taken_at in 0..config::BALANCE_HISTORY_LIMIT as u64 + 5 {
+            monitor.record(sample(taken_at, Some(1)));
+        }
+
+        assert_eq!(monitor.history.len(), config::BALANCE_HISTORY_LIMIT);
+        let newest: Vec<u64> = monitor.history(3).iter().map(|s| s.taken_at).collect();
+        let last = config::BALANCE_HISTORY_LIMIT as u64 + 4;
+        assert_eq!(newest, vec![last, last - 1, last - 2]);
+        assert_eq!(monitor.history(u32::MAX).len(), MAX_HISTORY_PAGE as usize);
+    }
+
+    #[test]
+    fn wei_above_u64_survives_the_snapshot_value_round_trip() {
+        let wei = 20 * 10u128.pow(18);
+        let mut monitor = FundsMonitor::default();
+        monitor.thresholds.min_gas_balance_wei = wei;
+        monitor.record(sample(1, Some(wei)));
+
+        let value: serde_json::Value =
+            serde_json::from_slice(&serde_json::to_vec(&monitor).unwrap()).unwrap();
+        let restored: FundsMonitor = serde_json::from_value(value).unwrap();
+
+        assert_eq!(restored.thresholds.min_gas_balance_wei, wei);
+        assert_eq!(restored.history(1)[0].networks[0].balance_wei, Some(wei));
+    }
+}
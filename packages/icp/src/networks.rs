@@ -0,0 +1,346 @@
+// Network Registry Module
+// Runtime registry of the EVM networks the canister transacts on
+//
+// Each network has its chain ID, RPC providers, explorer base URL,
+// transaction envelope and Story Protocol contract address book. The
+// registry lives in STATE and is seeded with Aeneid from `config`. Admins add
+// networks (Story mainnet, local anvil forks) and choose the default; calls
+// that accept an optional chain ID fall back to that default.
+
+use crate::config;
+use crate::evm_util::TxType;
+use crate::STATE;
+use candid::{CandidType, Deserialize};
+use primitive_types::H160;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// Story Protocol contract addresses on one network
+///
+/// An empty string means the contract is not available on that network.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ContractAddresses {
+    pub ip_asset_registry: String,
+    pub registration_workflows: String,
+    pub spg_nft_contract: String,
+    pub licensing_module: String,
+    pub royalty_module: String,
+    pub dispute_module: String,
+    pub access_controller: String,
+    pub pil_license_template: String,
+    pub license_attachment_workflows: String,
+    pub royalty_policy_lap: String,
+    pub wip_token: String,
+    pub derivative_workflows: String,
+    pub royalty_workflows: String,
+    pub arbitration_policy_uma: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Network {
+    pub chain_id: u64,
+    pub name: String,
+    /// JSON-RPC providers; reads are cross-checked across all of them. URLs
+    /// may embed API keys, so queries return them to operators only
+    pub rpc_urls: Vec<String>,
+    /// Block explorer base URL (no trailing slash)
+    pub explorer_url: String,
+    pub tx_type: TxType,
+    pub contracts: ContractAddresses,
+}
+
+/// Persistent set of networks plus the default selection
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkRegistry {
+    default_chain_id: u64,
+    networks: BTreeMap<u64, Network>,
+}
+
+impl Default for NetworkRegistry {
+    fn default() -> Self {
+        let aeneid = aeneid();
+        Self {
+            default_chain_id: aeneid.chain_id,
+            networks: BTreeMap::from([(aeneid.chain_id, aeneid)]),
+        }
+    }
+}
+
+/// Built-in Aeneid testnet entry
+pub fn aeneid() -> Network {
+    Network {
+        chain_id: config::STORY_CHAIN_ID,
+        name: "Story Aeneid Testnet".to_string(),
+        rpc_urls: vec![config::STORY_RPC_URL.to_string()],
+        explorer_url: config::STORY_EXPLORER_URL.to_string(),
+        tx_type: TxType::Eip1559,
+        contracts: ContractAddresses {
+            ip_asset_registry: config::AENEID_IP_ASSET_REGISTRY.to_string(),
+            registration_workflows: config::AENEID_REGISTRATION_WORKFLOWS.to_string(),
+            spg_nft_contract: config::AENEID_SPG_NFT_CONTRACT.to_string(),
+            licensing_module: config::AENEID_LICENSING_MODULE.to_string(),
+            royalty_module: config::AENEID_ROYALTY_MODULE.to_string(),
+            dispute_module: config::AENEID_DISPUTE_MODULE.to_string(),
+            access_controller: config::AENEID_ACCESS_CONTROLLER.to_string(),
+            pil_license_template: config::AENEID_PIL_LICENSE_TEMPLATE.to_string(),
+            license_attachment_workflows: config::AENEID_LICENSE_ATTACHMENT_WORKFLOWS.to_string(),
+            royalty_policy_lap: config::AENEID_ROYALTY_POLICY_LAP.to_string(),
+            wip_token: config::AENEID_WIP_TOKEN.to_string(),
+            derivative_workflows: config::AENEID_DERIVATIVE_WORKFLOWS.to_string(),
+            royalty_workflows: config::AENEID_ROYALTY_WORKFLOWS.to_string(),
+            arbitration_policy_uma: config::AENEID_ARBITRATION_POLICY_UMA.to_string(),
+        },
+    }
+}
+
+// ==============================================================================
+// Network Helpers
+// ==============================================================================
+
+impl Network {
+    /// Copy safe to return to any caller (RPC URLs removed)
+    pub fn redacted(mut self) -> Self {
+        self.rpc_urls.clear();
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chain_id == 0 {
+            return Err("chain_id must be non-zero".to_string());
+        }
+        if self.rpc_urls.is_empty() || self.rpc_urls.iter().any(|url| url.trim().is_empty()) {
+            return Err("At least one non-empty RPC URL is required".to_string());
+        }
+
+        let contracts = &self.contracts;
+        for (name, address) in [
+            ("ip_asset_registry", &contracts.ip_asset_registry),
+            ("registration_workflows", &contracts.registration_workflows),
+            ("spg_nft_contract", &contracts.spg_nft_contract),
+            ("licensing_module", &contracts.licensing_module),
+            ("royalty_module", &contracts.royalty_module),
+            ("dispute_module", &contracts.dispute_module),
+            ("access_controller", &contracts.access_controller),
+            ("pil_license_template", &contracts.pil_license_template),
+            (
+                "license_attachment_workflows",
+                &contracts.license_attachment_workflows,
+            ),
+            ("royalty_policy_lap", &contracts.royalty_policy_lap),
+            ("wip_token", &contracts.wip_token),
+            ("derivative_workflows", &contracts.derivative_workflows),
+            ("royalty_workflows", &contracts.royalty_workflows),
+            ("arbitration_policy_uma", &contracts.arbitration_policy_uma),
+        ] {
+            if !address.is_empty() {
+                parse_address(address).map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn contract(&self, name: &str, address: &str) -> Result<H160, String> {
+        if address.is_empty() {
+            return Err(format!("{} is not configured on {}", name, self.name));
+        }
+        parse_address(address)
+    }
+
+    pub fn ip_asset_registry(&self) -> Result<H160, String> {
+        self.contract("IPAssetRegistry", &self.contracts.ip_asset_registry)
+    }
+
+    pub fn registration_workflows(&self) -> Result<H160, String> {
+        self.contract("RegistrationWorkflows", &self.contracts.registration_workflows)
+    }
+
+    pub fn spg_nft_contract(&self) -> Result<H160, String> {
+        self.contract("SPG NFT contract", &self.contracts.spg_nft_contract)
+    }
+
+    pub fn licensing_module(&self) -> Result<H160, String> {
+        self.contract("LicensingModule", &self.contracts.licensing_module)
+    }
+
+    pub fn royalty_module(&self) -> Result<H160, String> {
+        self.contract("RoyaltyModule", &self.contracts.royalty_module)
+    }
+
+    pub fn dispute_module(&self) -> Result<H160, String> {
+        self.contract("DisputeModule", &self.contracts.dispute_module)
+    }
+
+    pub fn access_controller(&self) -> Result<H160, String> {
+        self.contract("AccessController", &self.contracts.access_controller)
+    }
+
+    pub fn pil_license_template(&self) -> Result<H160, String> {
+        self.contract("PILicenseTemplate", &self.contracts.pil_license_template)
+    }
+
+    pub fn license_attachment_workflows(&self) -> Result<H160, String> {
+        self.contract(
+            "LicenseAttachmentWorkflows",
+            &self.contracts.license_attachment_workflows,
+        )
+    }
+
+    pub fn royalty_policy_lap(&self) -> Result<H160, String> {
+        self.contract("RoyaltyPolicyLAP", &self.contracts.royalty_policy_lap)
+    }
+
+    pub fn wip_token(&self) -> Result<H160, String> {
+        self.contract("WIP token", &self.contracts.wip_token)
+    }
+
+    pub fn derivative_workflows(&self) -> Result<H160, String> {
+        self.contract("DerivativeWorkflows", &self.contracts.derivative_workflows)
+    }
+
+    pub fn royalty_workflows(&self) -> Result<H160, String> {
+        self.contract("RoyaltyWorkflows", &self.contracts.royalty_workflows)
+    }
+
+    pub fn arbitration_policy_uma(&self) -> Result<H160, String> {
+        self.contract("ArbitrationPolicyUMA", &self.contracts.arbitration_policy_uma)
+    }
+
+    pub fn tx_url(&self, tx_hash: &str) -> String {
+        format!("{}/tx/{}", self.explorer_url, tx_hash)
+    }
+
+    pub fn address_url(&self, address: &str) -> String {
+        format!("{}/address/{}", self.explorer_url, address)
+    }
+
+    pub fn token_url(&self, contract: &str, token_id: u64) -> String {
+        format!("{}/token/{}/instance/{}", self.explorer_url, contract, token_id)
+    }
+}
+
+fn parse_address(address: &str) -> Result<H160, String> {
+    H160::from_str(address).map_err(|e| format!("Invalid address '{}': {}", address, e))
+}
+
+// ==============================================================================
+// Registry Operations
+// ==============================================================================
+
+impl NetworkRegistry {
+    pub fn default_chain_id(&self) -> u64 {
+        self.default_chain_id
+    }
+
+    pub fn get(&self, chain_id: u64) -> Option<Network> {
+        self.networks.get(&chain_id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Network> {
+        self.networks.values().cloned().collect()
+    }
+
+    /// Add a network or replace the entry with the same chain ID
+    pub fn upsert(&mut self, mut network: Network) -> Result<(), String> {
+        network.explorer_url = network.explorer_url.trim_end_matches('/').to_string();
+        network.validate()?;
+        self.networks.insert(network.chain_id, network);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, chain_id: u64) -> Result<(), String> {
+        if chain_id == self.default_chain_id {
+            return Err(format!(
+                "Chain {} is the default network; select another default first",
+                chain_id
+            ));
+        }
+        self.networks
+            .remove(&chain_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("Unknown network: chain {}", chain_id))
+    }
+
+    pub fn set_default(&mut self, chain_id: u64) -> Result<(), String> {
+        if !self.networks.contains_key(&chain_id) {
+            return Err(format!("Unknown network: chain {}", chain_id));
+        }
+        self.default_chain_id = chain_id;
+        Ok(())
+    }
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+/// Chain ID of the default network
+pub fn default_chain_id() -> u64 {
+    STATE.with(|state| state.borrow().networks.default_chain_id())
+}
+
+/// Look up a registered network
+pub fn get(chain_id: u64) -> Result<Network, String> {
+    STATE
+        .with(|state| state.borrow().networks.get(chain_id))
+        .ok_or_else(|| format!("Unknown network: chain {}", chain_id))
+}
+
+/// Network selected by a call: `chain_id` if given, otherwise the default
+pub fn resolve(chain_id: Option<u64>) -> Result<Network, String> {
+    get(chain_id.unwrap_or_else(default_chain_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mainnet() -> Network {
+        Network {
+            chain_id: 1514,
+            name: "Story Mainnet".to_string(),
+            explorer_url: "https://www.storyscan.io/".to_string(),
+            ..aeneid()
+        }
+    }
+
+    #[test]
+    fn upsert_validates_and_trims_the_explorer_url() {
+        let mut registry = NetworkRegistry::default();
+        registry.upsert(mainnet()).unwrap();
+
+        let network = registry.get(1514).unwrap();
+        assert_eq!(network.tx_url("0xabc"), "https://www.storyscan.io/tx/0xabc");
+
+        let invalid = Network {
+            rpc_urls: vec![" ".to_string()],
+            ..mainnet()
+        };
+        assert!(registry.upsert(invalid).is_err());
+    }
+
+    #[test]
+    fn the_default_network_cannot_be_removed() {
+        let mut registry = NetworkRegistry::default();
+        registry.upsert(mainnet()).unwrap();
+
+        assert!(registry.remove(config::STORY_CHAIN_ID).is_err());
+        assert!(registry.set_default(31337).is_err());
+
+        registry.set_default(1514).unwrap();
+        registry.remove(config::STORY_CHAIN_ID).unwrap();
+        assert_eq!(registry.default_chain_id(), 1514);
+        assert!(registry.get(config::STORY_CHAIN_ID).is_none());
+    }
+
+    #[test]
+    fn redacted_networks_carry_no_rpc_urls() {
+        assert!(aeneid().redacted().rpc_urls.is_empty());
+    }
+}
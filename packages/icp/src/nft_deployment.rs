@@ -2,12 +2,10 @@
 // This module handles the deployment of SimpleNFT contracts
 // Uses existing EVM signing code for consistency
 
-use crate::config::{self, STORY_CHAIN_ID, STORY_RPC_URL};
-use crate::evm_util::{build_evm_transaction_for_creation, build_signed_transaction_for_creation, sign_evm_transaction};
-use crate::http_util::{json_header, make_http_request};
-use ic_cdk::api::management_canister::http_request::HttpMethod;
-use serde_json::json;
-use sha3::{Digest, Keccak256};
+use crate::evm_util::EvmAccount;
+use crate::networks::Network;
+use crate::transactions::{self, TxKind, TxReceipt};
+use crate::{events, tracker, STATE};
 // Imports for future signature verification features
 #[allow(unused_imports)]
 use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
@@ -27,17 +25,22 @@ const SIMPLE_NFT_BYTECODE: &str = include_str!("../../../out/SimpleNFT.sol/Simpl
 /// Deploy a SimpleNFT contract to Story Protocol Aeneid testnet
 ///
 /// This function deploys the SimpleNFT contract using our existing EVM signing infrastructure.
+/// The contract address is stored by the receipt tracker once the deployment
+/// is confirmed.
 ///
 /// # Arguments
+/// * `network` - Network to deploy on
 /// * `name` - The name of the NFT collection
 /// * `symbol` - The symbol of the NFT collection
 ///
 /// # Returns
-/// * `Result<String, String>` - The deployed contract address or error
+/// * `Result<(String, String), String>` - (deployment transaction hash,
+///   address the contract is created at) or error
 pub async fn deploy_simple_nft(
+    network: &Network,
     name: String,
     symbol: String,
-) -> Result<String, String> {
+) -> Result<(String, String), String> {
     ic_cdk::println!("🚀 Deploying SimpleNFT contract...");
     ic_cdk::println!("   Name: {}", name);
     ic_cdk::println!("   Symbol: {}", symbol);
@@ -71,105 +74,38 @@ pub async fn deploy_simple_nft(
 
     ic_cdk::println!("   Total deployment size: {} bytes", deployment_data.len());
 
-    // Get fresh nonce from blockchain via RPC
-    let nonce = crate::get_nonce_from_blockchain().await?;
-    ic_cdk::println!("   Nonce (from blockchain): {}", nonce);
-
     // Get the canister's EVM address
     let evm_address = crate::evm_util::get_canister_evm_address().await?;
     ic_cdk::println!("   Deploying from: {}", evm_address);
 
-    // Build unsigned transaction for contract creation (EIP-155 format)
-    let unsigned_tx = build_evm_transaction_for_creation(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        0, // No value transfer
-        deployment_data.clone(),
-        STORY_CHAIN_ID,
-    );
-
-    ic_cdk::println!("   Unsigned TX: {} bytes", unsigned_tx.len());
-
-    // Hash the unsigned transaction for signing
-    let tx_hash = Keccak256::digest(&unsigned_tx);
-    let tx_hash_bytes = tx_hash.to_vec();
-
-    ic_cdk::println!("   TX Hash for signing: 0x{}", hex::encode(&tx_hash_bytes));
-
-    // Sign the transaction using Chain-Key ECDSA
-    let signature = sign_evm_transaction(tx_hash_bytes.clone()).await?;
-
-    if signature.len() != 64 {
-        return Err(format!(
-            "Invalid signature length: {} (expected 64)",
-            signature.len()
-        ));
-    }
-
-    ic_cdk::println!("   Signature: {} bytes", signature.len());
-
-    // Get the IC's public key for recovery ID determination
-    let ic_public_key = crate::evm_util::get_canister_public_key().await?;
-
-    // Determine recovery ID
-    let recovery_id = match crate::story_util::determine_recovery_id_with_pubkey(&tx_hash_bytes, &signature, &ic_public_key) {
-        Ok(rid) => {
-            ic_cdk::println!("   ✅ Recovery ID: {}", rid);
-            rid
-        }
-        Err(e) => {
-            ic_cdk::println!("   ⚠️  Could not determine recovery ID: {}", e);
-            0u8
-        }
-    };
-
-    // Build signed transaction
-    let signed_tx = build_signed_transaction_for_creation(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        0,
+    // Contract creation: sign and broadcast with estimated gas and fees
+    let tx_hash_result = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::DeployNftContract,
+        None, // Contract creation
         deployment_data,
-        &signature,
-        STORY_CHAIN_ID,
-        recovery_id,
-    );
-
-    // Broadcast transaction to Story Protocol
-    let tx_hash_result = broadcast_transaction(signed_tx).await?;
+        0, // No value transfer
+    )
+    .await?;
 
     ic_cdk::println!("   ✅ Deployment transaction sent!");
     ic_cdk::println!("   📍 Transaction Hash: {}", tx_hash_result);
     ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/tx/{}", tx_hash_result);
-
-    // Wait for transaction to be mined and get receipt
-    ic_cdk::println!("   ⏳ Waiting for transaction to be mined...");
-
-    let receipt = get_transaction_receipt(&tx_hash_result).await?;
-
-    // Check if deployment was successful
-    let status = receipt.get("status")
-        .and_then(|s| s.as_str())
-        .ok_or("Missing status in receipt")?;
-
-    if status != "0x1" {
-        return Err("Contract deployment failed".to_string());
-    }
-
-    // Get the deployed contract address
-    let contract_address = receipt.get("contractAddress")
-        .and_then(|a| a.as_str())
-        .ok_or("No contract address in receipt")?
-        .to_string();
-
-    ic_cdk::println!("   ✅ SimpleNFT deployed successfully!");
-    ic_cdk::println!("   📍 Contract Address: {}", contract_address);
-    ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/address/{}", contract_address);
+    ic_cdk::println!("      {}", network.tx_url(&tx_hash_result));
+
+    // The address follows from the sender and the nonce `send` reserved
+    let record = STATE
+        .with(|state| state.borrow().transactions.get_by_hash(&tx_hash_result))
+        .ok_or_else(|| format!("Deployment {} missing from the history", tx_hash_result))?;
+    let sender = crate::evm_util::parse_eip55_address(&record.from)?;
+    let contract_address = crate::evm_util::to_checksum_address(
+        &crate::evm_util::contract_creation_address(&sender, record.nonce),
+    );
+    ic_cdk::println!("   📍 Contract address: {}", contract_address);
+    ic_cdk::println!("   ⏳ It will be stored once the deployment is confirmed");
 
-    Ok(contract_address)
+    Ok((tx_hash_result, contract_address))
 }
 
 // ==============================================================================
@@ -179,17 +115,23 @@ pub async fn deploy_simple_nft(
 /// Mint a new NFT from the deployed SimpleNFT contract
 ///
 /// This function mints an NFT with the given content hash and metadata URI.
-/// The NFT will be minted to the canister's EVM address.
+/// The canister (contract owner) signs; the NFT goes to `recipient`, or to the
+/// canister's EVM address when None.
 ///
 /// # Arguments
+/// * `network` - Network the contract is deployed on
 /// * `nft_contract_address` - The deployed SimpleNFT contract address
+/// * `recipient` - Validated recipient address (see `evm_util::parse_eip55_address`)
 /// * `content_hash` - The hash of the AI-generated content
 /// * `metadata_uri` - IPFS or HTTP URL pointing to metadata JSON
 ///
 /// # Returns
 /// * `Result<u64, String>` - The minted token ID or error
+#[allow(dead_code)] // SimpleNFT flow; generation registers through SPG
 pub async fn mint_nft(
+    network: &Network,
     nft_contract_address: String,
+    recipient: Option<&str>,
     content_hash: String,
     metadata_uri: String,
 ) -> Result<u64, String> {
@@ -198,8 +140,11 @@ pub async fn mint_nft(
     ic_cdk::println!("   Content Hash: {}", content_hash);
     ic_cdk::println!("   Metadata URI: {}", metadata_uri);
 
-    // Get the canister's EVM address (this will be the NFT recipient)
-    let recipient = crate::evm_util::get_canister_evm_address().await?;
+    // Default recipient: the canister's EVM address
+    let recipient = match recipient {
+        Some(recipient) => recipient.to_string(),
+        None => crate::evm_util::get_canister_evm_address().await?,
+    };
     ic_cdk::println!("   Minting to: {}", recipient);
 
     // Parse contract address
@@ -211,100 +156,41 @@ pub async fn mint_nft(
     let call_data = build_mint_calldata(&recipient, content_hash, metadata_uri)?;
     ic_cdk::println!("   Call Data: {} bytes", call_data.len());
 
-    // Get fresh nonce from blockchain via RPC
-    let nonce = crate::get_nonce_from_blockchain().await?;
-    ic_cdk::println!("   Nonce (from blockchain): {}", nonce);
-
-    // Build unsigned transaction (EIP-155 format)
-    let to_bytes: [u8; 20] = contract_address.to_fixed_bytes();
-    let unsigned_tx = crate::evm_util::build_evm_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0, // No value transfer
-        call_data.clone(),
-        STORY_CHAIN_ID,
-    );
-
-    ic_cdk::println!("   Unsigned TX: {} bytes", unsigned_tx.len());
-
-    // Hash the unsigned transaction for signing
-    let tx_hash = Keccak256::digest(&unsigned_tx);
-    let tx_hash_bytes = tx_hash.to_vec();
-
-    ic_cdk::println!("   TX Hash for signing: 0x{}", hex::encode(&tx_hash_bytes));
-
-    // Sign the transaction using Chain-Key ECDSA
-    let signature = crate::evm_util::sign_evm_transaction(tx_hash_bytes.clone()).await?;
-
-    if signature.len() != 64 {
-        return Err(format!(
-            "Invalid signature length: {} (expected 64)",
-            signature.len()
-        ));
-    }
-
-    ic_cdk::println!("   Signature: {} bytes", signature.len());
-
-    // Get the IC's public key for recovery ID determination
-    let ic_public_key = crate::evm_util::get_canister_public_key().await?;
-
-    // Determine recovery ID
-    let recovery_id = match crate::story_util::determine_recovery_id_with_pubkey(&tx_hash_bytes, &signature, &ic_public_key) {
-        Ok(rid) => {
-            ic_cdk::println!("   ✅ Recovery ID: {}", rid);
-            rid
-        }
-        Err(e) => {
-            ic_cdk::println!("   ⚠️  Could not determine recovery ID: {}", e);
-            0u8
-        }
-    };
-
-    // Build signed transaction
-    let signed_tx = crate::evm_util::build_signed_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0,
+    // Sign and broadcast with estimated gas and fees
+    let tx_hash_result = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::MintNft,
+        Some(contract_address.to_fixed_bytes()),
         call_data,
-        &signature,
-        STORY_CHAIN_ID,
-        recovery_id,
-    );
-
-    // Broadcast transaction to Story Protocol
-    let tx_hash_result = broadcast_transaction(signed_tx).await?;
+        0, // No value transfer
+    )
+    .await?;
 
     ic_cdk::println!("   ✅ Mint transaction sent!");
     ic_cdk::println!("   📍 Transaction Hash: {}", tx_hash_result);
     ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/tx/{}", tx_hash_result);
-
-    // Wait for transaction to be mined and get receipt
-    ic_cdk::println!("   ⏳ Waiting for transaction to be mined...");
+    ic_cdk::println!("      {}", network.tx_url(&tx_hash_result));
 
-    let receipt = get_transaction_receipt(&tx_hash_result).await?;
+    // Check whether the transaction is already mined
+    ic_cdk::println!("   ⏳ Checking for transaction receipt...");
 
-    // Check if mint was successful
-    let status = receipt.get("status")
-        .and_then(|s| s.as_str())
-        .ok_or("Missing status in receipt")?;
+    let receipt = tracker::fetch_receipt(network.chain_id, &tx_hash_result)
+        .await?
+        .ok_or_else(|| format!("Mint transaction {} is still pending", tx_hash_result))?;
 
-    if status != "0x1" {
+    if !receipt.success {
         return Err("NFT minting failed".to_string());
     }
 
     // Parse logs to extract the token ID
     // The NFTMinted event emits: event NFTMinted(address indexed to, uint256 indexed tokenId, string contentHash)
-    let token_id = extract_token_id_from_receipt(&receipt)?;
+    let token_id = extract_token_id_from_receipt(&receipt, contract_address)?;
 
     ic_cdk::println!("   ✅ NFT minted successfully!");
     ic_cdk::println!("   🎫 Token ID: {}", token_id);
     ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/token/{}/instance/{}", nft_contract_address, token_id);
+    ic_cdk::println!("      {}", network.token_url(&nft_contract_address, token_id));
 
     Ok(token_id)
 }
@@ -332,7 +218,7 @@ fn build_mint_calldata(
 
     // Encode parameters
     let params = ethabi::encode(&[
-        ethabi::Token::Address(to_address.into()),
+        ethabi::Token::Address(to_address),
         ethabi::Token::String(content_hash),
         ethabi::Token::String(metadata_uri),
     ]);
@@ -344,124 +230,14 @@ fn build_mint_calldata(
     Ok(calldata)
 }
 
-/// Extract token ID from transaction receipt logs
+/// Extract the token ID minted by `contract` from transaction receipt logs
 ///
-/// The NFTMinted event signature is:
+/// Uses the NFTMinted event (see `events::find_minted_token`):
 /// event NFTMinted(address indexed to, uint256 indexed tokenId, string contentHash)
-fn extract_token_id_from_receipt(receipt: &serde_json::Value) -> Result<u64, String> {
-    let logs = receipt.get("logs")
-        .and_then(|l| l.as_array())
-        .ok_or("No logs in receipt")?;
-
-    if logs.is_empty() {
-        return Err("No logs emitted by mint transaction".to_string());
-    }
-
-    // The NFTMinted event should be the first (or only) log
-    let log = &logs[0];
-
-    // Get topics (indexed parameters)
-    let topics = log.get("topics")
-        .and_then(|t| t.as_array())
-        .ok_or("No topics in log")?;
-
-    if topics.len() < 3 {
-        return Err("Not enough topics in NFTMinted event".to_string());
-    }
-
-    // topics[0] = event signature hash
-    // topics[1] = indexed to address
-    // topics[2] = indexed tokenId
-    let token_id_hex = topics[2]
-        .as_str()
-        .ok_or("Token ID topic is not a string")?;
-
-    // Parse token ID from hex
-    let token_id_hex = token_id_hex.trim_start_matches("0x");
-    let token_id = u64::from_str_radix(token_id_hex, 16)
-        .map_err(|e| format!("Failed to parse token ID: {}", e))?;
-
-    Ok(token_id)
-}
-
-/// Broadcast a signed transaction to the Story Protocol RPC
-async fn broadcast_transaction(signed_tx: Vec<u8>) -> Result<String, String> {
-    ic_cdk::println!("   Broadcasting transaction to Story RPC...");
-
-    // Convert transaction to hex
-    let tx_hex = format!("0x{}", hex::encode(&signed_tx));
-
-    let payload = json!({
-        "jsonrpc": "2.0",
-        "method": "eth_sendRawTransaction",
-        "params": [tx_hex],
-        "id": 1
-    });
-
-    let headers = vec![json_header()];
-
-    let response_body = make_http_request(
-        STORY_RPC_URL.to_string(),
-        HttpMethod::POST,
-        headers,
-        Some(payload.to_string().into_bytes()),
-    )
-    .await?;
-
-    let response_str = String::from_utf8(response_body)
-        .map_err(|e| format!("Failed to parse response as UTF-8: {}", e))?;
-
-    let response_json: serde_json::Value = serde_json::from_str(&response_str)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-
-    // Check for error
-    if let Some(error) = response_json.get("error") {
-        return Err(format!("RPC error: {}", error));
-    }
-
-    let tx_hash = response_json["result"]
-        .as_str()
-        .ok_or("No result in response")?
-        .to_string();
-
-    Ok(tx_hash)
-}
-
-/// Get transaction receipt from Story Protocol RPC
-async fn get_transaction_receipt(tx_hash: &str) -> Result<serde_json::Value, String> {
-    ic_cdk::println!("   Fetching transaction receipt...");
-
-    let payload = json!({
-        "jsonrpc": "2.0",
-        "method": "eth_getTransactionReceipt",
-        "params": [tx_hash],
-        "id": 1
-    });
-
-    let headers = vec![json_header()];
-
-    let response_body = make_http_request(
-        STORY_RPC_URL.to_string(),
-        HttpMethod::POST,
-        headers,
-        Some(payload.to_string().into_bytes()),
-    )
-    .await?;
-
-    let response_str = String::from_utf8(response_body)
-        .map_err(|e| format!("Failed to parse response as UTF-8: {}", e))?;
-
-    let response_json: serde_json::Value = serde_json::from_str(&response_str)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-
-    // Check for error
-    if let Some(error) = response_json.get("error") {
-        return Err(format!("RPC error: {}", error));
-    }
-
-    let receipt = response_json.get("result")
-        .ok_or("No result in response")?
-        .clone();
-
-    Ok(receipt)
+fn extract_token_id_from_receipt(
+    receipt: &TxReceipt,
+    contract: primitive_types::H160,
+) -> Result<u64, String> {
+    events::find_minted_token(receipt, contract)
+        .ok_or_else(|| "No NFTMinted event emitted by the NFT contract".to_string())
 }
@@ -0,0 +1,369 @@
+// Nonce Manager Module
+// Local nonce reservation for canister-signed transactions
+//
+// Nonces are handed out from STATE without an outcall, so concurrent update
+// calls never receive the same value. Each reserved nonce is tracked until the
+// chain's `latest` transaction count passes it (mined) or it is found missing
+// from the `pending` pool (dropped). Dropped or abandoned nonces become gaps and
+// are reused before new nonces are issued. Reconciliation against
+// `eth_getTransactionCount` happens on first use, after errors and periodically.
+// Nonces are tracked separately per network (chain ID) and sending address.
+
+use crate::{config, rpc_util, STATE};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum NonceStatus {
+    /// Handed out; the transaction has not been broadcast yet
+    Reserved,
+    /// Broadcast and waiting to be mined
+    Broadcast { tx_hash: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingNonce {
+    pub nonce: u64,
+    pub status: NonceStatus,
+    /// IC time (nanoseconds) of the last status change
+    pub updated_at: u64,
+}
+
+/// Nonce bookkeeping for one sending address
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AccountNonces {
+    /// Next never-used nonce (None until the first reconciliation)
+    next_nonce: Option<u64>,
+    /// Reserved or broadcast nonces that are not yet mined
+    in_flight: BTreeMap<u64, PendingNonce>,
+    /// Unused nonces below `next_nonce` (released or dropped), reused first
+    gaps: BTreeSet<u64>,
+    chain_latest: Option<u64>,
+    chain_pending: Option<u64>,
+    last_reconciled_at: u64,
+    /// Forces a reconciliation before the next reservation
+    stale: bool,
+}
+
+/// Snapshot of an account's nonce state for operators
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct NonceReport {
+    pub chain_id: u64,
+    pub address: String,
+    pub next_nonce: Option<u64>,
+    pub in_flight: Vec<PendingNonce>,
+    pub gaps: Vec<u64>,
+    pub chain_latest: Option<u64>,
+    pub chain_pending: Option<u64>,
+    pub last_reconciled_at: u64,
+}
+
+/// Persistent nonce state, keyed by chain ID and sending address (lowercase)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct NonceManager {
+    chains: BTreeMap<u64, BTreeMap<String, AccountNonces>>,
+}
+
+// ==============================================================================
+// Account Operations
+// ==============================================================================
+
+impl AccountNonces {
+    fn needs_reconcile(&self, now: u64) -> bool {
+        self.next_nonce.is_none()
+            || self.stale
+            || now.saturating_sub(self.last_reconciled_at)
+                > config::NONCE_RECONCILE_INTERVAL_SECS * NANOS_PER_SEC
+    }
+
+    /// Hand out the lowest gap, or the next new nonce
+    fn reserve(&mut self, now: u64) -> Option<u64> {
+        let next = self.next_nonce?;
+
+        let nonce = match self.gaps.pop_first() {
+            Some(gap) => gap,
+            None => {
+                self.next_nonce = Some(next + 1);
+                next
+            }
+        };
+
+        self.in_flight.insert(
+            nonce,
+            PendingNonce {
+                nonce,
+                status: NonceStatus::Reserved,
+                updated_at: now,
+            },
+        );
+        Some(nonce)
+    }
+
+    fn mark_broadcast(&mut self, nonce: u64, tx_hash: String, now: u64) {
+        self.gaps.remove(&nonce);
+        self.in_flight.insert(
+            nonce,
+            PendingNonce {
+                nonce,
+                status: NonceStatus::Broadcast { tx_hash },
+                updated_at: now,
+            },
+        );
+    }
+
+    /// Give a nonce back after its transaction could not be broadcast
+    fn release(&mut self, nonce: u64) {
+        if self.in_flight.remove(&nonce).is_some() {
+            self.gaps.insert(nonce);
+        }
+        self.stale = true;
+    }
+
+    /// Apply the chain's `latest` (mined) and `pending` transaction counts
+    fn reconcile(&mut self, latest: u64, pending: u64, now: u64) {
+        let drop_grace = config::NONCE_DROP_GRACE_SECS * NANOS_PER_SEC;
+        let reservation_timeout = config::NONCE_RESERVATION_TIMEOUT_SECS * NANOS_PER_SEC;
+
+        // Everything below `latest` is mined (or replaced) and therefore consumed
+        self.in_flight.retain(|nonce, _| *nonce >= latest);
+        self.gaps.retain(|nonce| *nonce >= latest);
+
+        // Broadcast transactions the node no longer knows about were dropped;
+        // reservations that never got broadcast were abandoned (e.g. trap)
+        let released: Vec<u64> = self
+            .in_flight
+            .values()
+            .filter(|pending_nonce| {
+                let age = now.saturating_sub(pending_nonce.updated_at);
+                match pending_nonce.status {
+                    NonceStatus::Broadcast { .. } => {
+                        pending_nonce.nonce >= pending && age > drop_grace
+                    }
+                    NonceStatus::Reserved => age > reservation_timeout,
+                }
+            })
+            .map(|pending_nonce| pending_nonce.nonce)
+            .collect();
+
+        for nonce in released {
+            ic_cdk::println!("   ⚠️  Nonce {} was dropped or abandoned; marking as gap", nonce);
+            self.in_flight.remove(&nonce);
+        }
+
+        // Next nonce: beyond both the chain's pool and our own in-flight nonces
+        let highest_in_flight = self.in_flight.keys().next_back().map(|n| n + 1);
+        let next = pending.max(highest_in_flight.unwrap_or(0));
+
+        // Nonces below `pending` are in the pool; any unused one above it is a
+        // gap that would block every later transaction until filled
+        self.gaps = (pending..next)
+            .filter(|nonce| !self.in_flight.contains_key(nonce))
+            .collect();
+        self.next_nonce = Some(next);
+        self.chain_latest = Some(latest);
+        self.chain_pending = Some(pending);
+        self.last_reconciled_at = now;
+        self.stale = false;
+    }
+
+    fn report(&self, chain_id: u64, address: &str) -> NonceReport {
+        NonceReport {
+            chain_id,
+            address: address.to_string(),
+            next_nonce: self.next_nonce,
+            in_flight: self.in_flight.values().cloned().collect(),
+            gaps: self.gaps.iter().copied().collect(),
+            chain_latest: self.chain_latest,
+            chain_pending: self.chain_pending,
+            last_reconciled_at: self.last_reconciled_at,
+        }
+    }
+}
+
+impl NonceManager {
+    fn account_mut(&mut self, chain_id: u64, address: &str) -> &mut AccountNonces {
+        self.chains
+            .entry(chain_id)
+            .or_default()
+            .entry(address.to_lowercase())
+            .or_default()
+    }
+
+    /// Addresses with nonce state on `chain_id`
+    pub fn addresses(&self, chain_id: u64) -> Vec<String> {
+        self.chains
+            .get(&chain_id)
+            .map(|accounts| accounts.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn reports(&self) -> Vec<NonceReport> {
+        self.chains
+            .iter()
+            .flat_map(|(chain_id, accounts)| {
+                accounts
+                    .iter()
+                    .map(|(address, account)| account.report(*chain_id, address))
+            })
+            .collect()
+    }
+}
+
+fn with_account<R>(chain_id: u64, address: &str, f: impl FnOnce(&mut AccountNonces) -> R) -> R {
+    STATE.with(|state| f(state.borrow_mut().nonces.account_mut(chain_id, address)))
+}
+
+// ==============================================================================
+// RPC Queries
+// ==============================================================================
+
+/// `eth_getTransactionCount` for `address` at `block_tag` ("latest" / "pending")
+async fn fetch_transaction_count(
+    chain_id: u64,
+    address: &str,
+    block_tag: &str,
+) -> Result<u64, String> {
+    let result = rpc_util::call(
+        chain_id,
+        "eth_getTransactionCount",
+        json!([address, block_tag]),
+    )
+    .await?;
+    rpc_util::parse_quantity(&result)
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+/// Reconcile the local nonce state of `address` on `chain_id` with the chain
+///
+/// # Returns
+/// * `Result<NonceReport, String>` - Nonce state after reconciliation
+pub async fn reconcile(chain_id: u64, address: &str) -> Result<NonceReport, String> {
+    ic_cdk::println!("   📡 Reconciling nonces for {} on chain {}...", address, chain_id);
+
+    let latest = fetch_transaction_count(chain_id, address, "latest").await?;
+    let pending = fetch_transaction_count(chain_id, address, "pending").await?;
+    let now = ic_cdk::api::time();
+
+    let report = with_account(chain_id, address, |account| {
+        account.reconcile(latest, pending, now);
+        account.report(chain_id, address)
+    });
+
+    ic_cdk::println!(
+        "   ✅ Chain nonces: latest {}, pending {}; next local nonce {:?}, {} in flight, {} gaps",
+        latest,
+        pending,
+        report.next_nonce,
+        report.in_flight.len(),
+        report.gaps.len()
+    );
+
+    Ok(report)
+}
+
+/// Reserve a nonce for a new transaction from `address` on `chain_id`
+///
+/// The reservation itself is synchronous, so concurrent callers always get
+/// distinct nonces. Must be followed by `mark_broadcast` or `release`.
+pub async fn reserve(chain_id: u64, address: &str) -> Result<u64, String> {
+    let now = ic_cdk::api::time();
+    if with_account(chain_id, address, |account| account.needs_reconcile(now)) {
+        reconcile(chain_id, address).await?;
+    }
+
+    let now = ic_cdk::api::time();
+    with_account(chain_id, address, |account| account.reserve(now)).ok_or_else(|| {
+        format!(
+            "Nonce state for {} on chain {} is not initialized",
+            address, chain_id
+        )
+    })
+}
+
+/// Record that the transaction using `nonce` was broadcast
+pub fn mark_broadcast(chain_id: u64, address: &str, nonce: u64, tx_hash: &str) {
+    let now = ic_cdk::api::time();
+    with_account(chain_id, address, |account| {
+        account.mark_broadcast(nonce, tx_hash.to_string(), now)
+    });
+}
+
+/// Return `nonce` after a failed send; it is reused by the next reservation
+pub fn release(chain_id: u64, address: &str, nonce: u64) {
+    with_account(chain_id, address, |account| account.release(nonce));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000 * NANOS_PER_SEC;
+
+    #[test]
+    fn nothing_is_reserved_before_the_first_reconciliation() {
+        let mut account = AccountNonces::default();
+        assert!(account.needs_reconcile(NOW));
+        assert_eq!(account.reserve(NOW), None);
+    }
+
+    #[test]
+    fn released_nonces_are_reused_first() {
+        let mut account = AccountNonces::default();
+        account.reconcile(5, 5, NOW);
+
+        assert_eq!(account.reserve(NOW), Some(5));
+        assert_eq!(account.reserve(NOW), Some(6));
+        account.release(5);
+        assert!(account.needs_reconcile(NOW));
+
+        assert_eq!(account.reserve(NOW), Some(5));
+        assert_eq!(account.reserve(NOW), Some(7));
+    }
+
+    #[test]
+    fn unused_nonces_between_pending_and_in_flight_are_gaps() {
+        let mut account = AccountNonces::default();
+        account.reconcile(0, 0, NOW);
+        for _ in 0..10 {
+            account.reserve(NOW);
+        }
+        for nonce in [0, 1, 2, 3, 4, 5, 6, 8] {
+            account.release(nonce);
+        }
+
+        // The chain's pool ends at 5; nonces 7 and 9 are still in flight
+        account.reconcile(5, 5, NOW);
+        let report = account.report(1315, "0xabc");
+        assert_eq!(report.next_nonce, Some(10));
+        assert_eq!(report.gaps, vec![5, 6, 8]);
+        assert_eq!(
+            report.in_flight.iter().map(|pending| pending.nonce).collect::<Vec<_>>(),
+            vec![7, 9]
+        );
+    }
+
+    #[test]
+    fn mined_nonces_leave_the_in_flight_set() {
+        let mut account = AccountNonces::default();
+        account.reconcile(0, 0, NOW);
+        let nonce = account.reserve(NOW).unwrap();
+        account.mark_broadcast(nonce, "0x01".to_string(), NOW);
+
+        account.reconcile(1, 1, NOW);
+        assert!(account.in_flight.is_empty());
+        assert_eq!(account.reserve(NOW), Some(1));
+    }
+}
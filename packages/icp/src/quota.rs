@@ -0,0 +1,494 @@
+// Quota Module
+// Per-principal rate limiting and daily cycles / gas budgets for generation
+//
+// Every generation is charged its estimated cost up front, before any HTTP
+// outcall or transaction is made, and again when a failed job is retried; so
+// is every other endpoint that makes outcalls or sends transactions for its
+// caller (`charge_outcall`, `charge_transaction`). Usage of principals idle
+// for a whole window and day is evicted. Limits come from a global
+// `QuotaConfig` which can be overridden per principal. A limit of 0 disables
+// that check.
+
+use crate::storage::{self, StableMap};
+use crate::{config, STATE};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_DAY: u64 = 86_400 * NANOS_PER_SEC;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QuotaConfig {
+    /// Maximum charged calls (generations and outcall endpoints) per window
+    /// (0 = unlimited)
+    pub calls_per_window: u32,
+    /// Length of the rate-limit window in seconds
+    pub window_secs: u64,
+    /// Maximum estimated cycles spent per UTC day (0 = unlimited)
+    pub daily_cycles_budget: u64,
+    /// Maximum EVM gas reserved per UTC day (0 = unlimited)
+    pub daily_gas_budget: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            calls_per_window: config::DEFAULT_QUOTA_CALLS_PER_WINDOW,
+            window_secs: config::DEFAULT_QUOTA_WINDOW_SECS,
+            daily_cycles_budget: config::DEFAULT_QUOTA_DAILY_CYCLES,
+            daily_gas_budget: config::DEFAULT_QUOTA_DAILY_GAS,
+        }
+    }
+}
+
+/// Which limit rejected the call
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaKind {
+    CallRate,
+    DailyCycles,
+    DailyGas,
+}
+
+/// Typed "quota exceeded" error returned to callers
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct QuotaExceeded {
+    pub kind: QuotaKind,
+    pub limit: u64,
+    pub used: u64,
+    pub requested: u64,
+    /// IC time (nanoseconds) at which the call can be retried
+    pub retry_at: u64,
+    pub retry_after_secs: u64,
+}
+
+/// Usage counters of a single principal
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Usage {
+    pub window_start: u64,
+    pub calls_in_window: u32,
+    /// Day index (IC time / 1 day) the daily counters belong to
+    pub day: u64,
+    pub cycles_today: u64,
+    pub gas_today: u64,
+    pub total_calls: u64,
+    pub last_call_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UsageReport {
+    pub principal: Principal,
+    pub usage: Usage,
+    /// Effective limits (override or global)
+    pub limits: QuotaConfig,
+}
+
+/// Estimated cost of a single generation, charged at admission
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationCost {
+    pub cycles: u64,
+    pub gas: u64,
+}
+
+impl GenerationCost {
+    pub fn estimate() -> Self {
+        Self {
+            cycles: config::ESTIMATED_GENERATION_CYCLES,
+            gas: config::GAS_LIMIT,
+        }
+    }
+
+    /// Cost of an endpoint that only reads through outcalls (no transaction)
+    pub fn outcall() -> Self {
+        Self {
+            cycles: config::ESTIMATED_OUTCALL_CYCLES,
+            gas: 0,
+        }
+    }
+
+    /// Cost of an endpoint that sends a single transaction
+    pub fn transaction() -> Self {
+        Self {
+            cycles: config::ESTIMATED_OUTCALL_CYCLES,
+            gas: config::GAS_LIMIT,
+        }
+    }
+}
+
+storage::impl_json_storable!(Usage);
+
+/// Persistent quota configuration (counters live in stable memory)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct QuotaState {
+    defaults: QuotaConfig,
+    /// principal (text) -> override
+    overrides: BTreeMap<String, QuotaConfig>,
+}
+
+thread_local! {
+    /// principal -> usage
+    static USAGE: StableMap<Principal, Usage> =
+        storage::stable_map(storage::QUOTA_USAGE_MEMORY_ID);
+}
+
+impl QuotaState {
+    pub fn defaults(&self) -> QuotaConfig {
+        self.defaults.clone()
+    }
+
+    pub fn set_defaults(&mut self, config: QuotaConfig) {
+        self.defaults = config;
+    }
+
+    pub fn set_override(&mut self, principal: &Principal, config: Option<QuotaConfig>) {
+        match config {
+            Some(config) => {
+                self.overrides.insert(principal.to_text(), config);
+            }
+            None => {
+                self.overrides.remove(&principal.to_text());
+            }
+        }
+    }
+
+    pub fn limits_for(&self, principal: &Principal) -> QuotaConfig {
+        self.overrides
+            .get(&principal.to_text())
+            .cloned()
+            .unwrap_or_else(|| self.defaults.clone())
+    }
+
+    pub fn report(&self, principal: &Principal) -> UsageReport {
+        UsageReport {
+            principal: *principal,
+            usage: USAGE
+                .with(|usage| usage.borrow().get(principal))
+                .unwrap_or_default(),
+            limits: self.limits_for(principal),
+        }
+    }
+
+    pub fn reports(&self) -> Vec<UsageReport> {
+        USAGE
+            .with(|usage| usage.borrow().keys().collect::<Vec<_>>())
+            .iter()
+            .map(|principal| self.report(principal))
+            .collect()
+    }
+
+    /// Check all limits for `principal` and, if they pass, charge `cost`
+    pub fn check_and_charge(
+        &mut self,
+        principal: &Principal,
+        cost: GenerationCost,
+        now: u64,
+    ) -> Result<(), QuotaExceeded> {
+        self.purge_expired(now);
+
+        let limits = self.limits_for(principal);
+        let mut usage = USAGE
+            .with(|usage| usage.borrow().get(principal))
+            .unwrap_or_default();
+
+        // Rolled-over counters are kept even if the call is rejected
+        let result = charge(&mut usage, &limits, cost, now);
+        USAGE.with(|stored| stored.borrow_mut().insert(*principal, usage));
+        result
+    }
+
+    /// Drop the counters of principals whose window and day have both ended
+    /// (their next call starts from zero anyway)
+    fn purge_expired(&mut self, now: u64) {
+        USAGE.with(|usage| {
+            let mut usage = usage.borrow_mut();
+            let expired: Vec<Principal> = usage
+                .iter()
+                .filter(|(principal, counters)| {
+                    is_expired(counters, &self.limits_for(principal), now)
+                })
+                .map(|(principal, _)| principal)
+                .collect();
+            for principal in expired {
+                usage.remove(&principal);
+            }
+        });
+    }
+}
+
+/// True once the rate-limit window and the day of `usage` are both over
+fn is_expired(usage: &Usage, limits: &QuotaConfig, now: u64) -> bool {
+    let window_ns = limits.window_secs.max(1).saturating_mul(NANOS_PER_SEC);
+    usage.window_start.saturating_add(window_ns) <= now
+        && (usage.day + 1).saturating_mul(NANOS_PER_DAY) <= now
+}
+
+/// Roll the counters of `usage` to `now`, check them against `limits` and, if
+/// they pass, charge `cost`
+fn charge(
+    usage: &mut Usage,
+    limits: &QuotaConfig,
+    cost: GenerationCost,
+    now: u64,
+) -> Result<(), QuotaExceeded> {
+    // Roll the rate-limit window
+    let window_ns = limits.window_secs.max(1).saturating_mul(NANOS_PER_SEC);
+    let window_start = now - (now % window_ns);
+    if usage.window_start != window_start {
+        usage.window_start = window_start;
+        usage.calls_in_window = 0;
+    }
+
+    // Roll the daily counters
+    let day = now / NANOS_PER_DAY;
+    if usage.day != day {
+        usage.day = day;
+        usage.cycles_today = 0;
+        usage.gas_today = 0;
+    }
+
+    if limits.calls_per_window > 0 && usage.calls_in_window >= limits.calls_per_window {
+        return Err(exceeded(
+            QuotaKind::CallRate,
+            limits.calls_per_window as u64,
+            usage.calls_in_window as u64,
+            1,
+            window_start + window_ns,
+            now,
+        ));
+    }
+
+    let next_day = (day + 1) * NANOS_PER_DAY;
+
+    if limits.daily_cycles_budget > 0
+        && usage.cycles_today.saturating_add(cost.cycles) > limits.daily_cycles_budget
+    {
+        return Err(exceeded(
+            QuotaKind::DailyCycles,
+            limits.daily_cycles_budget,
+            usage.cycles_today,
+            cost.cycles,
+            next_day,
+            now,
+        ));
+    }
+
+    if limits.daily_gas_budget > 0
+        && usage.gas_today.saturating_add(cost.gas) > limits.daily_gas_budget
+    {
+        return Err(exceeded(
+            QuotaKind::DailyGas,
+            limits.daily_gas_budget,
+            usage.gas_today,
+            cost.gas,
+            next_day,
+            now,
+        ));
+    }
+
+    usage.calls_in_window += 1;
+    usage.cycles_today = usage.cycles_today.saturating_add(cost.cycles);
+    usage.gas_today = usage.gas_today.saturating_add(cost.gas);
+    usage.total_calls += 1;
+    usage.last_call_at = now;
+
+    Ok(())
+}
+
+fn exceeded(
+    kind: QuotaKind,
+    limit: u64,
+    used: u64,
+    requested: u64,
+    retry_at: u64,
+    now: u64,
+) -> QuotaExceeded {
+    QuotaExceeded {
+        kind,
+        limit,
+        used,
+        requested,
+        retry_at,
+        retry_after_secs: retry_at.saturating_sub(now).div_ceil(NANOS_PER_SEC),
+    }
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+/// Charge one generation to `principal`, or return why it is not allowed
+///
+/// Must be called before any outcall is made on behalf of the caller.
+pub fn charge_generation(principal: &Principal) -> Result<(), QuotaExceeded> {
+    charge_principal(principal, GenerationCost::estimate())
+}
+
+/// Charge one transaction-sending endpoint call to `principal`
+///
+/// # Returns
+/// * `Result<(), String>` - Err describing the exceeded limit
+pub fn charge_transaction(principal: &Principal) -> Result<(), String> {
+    charge_principal(principal, GenerationCost::transaction()).map_err(describe)
+}
+
+/// Charge one outcall endpoint call to `principal`
+///
+/// # Returns
+/// * `Result<(), String>` - Err describing the exceeded limit
+pub fn charge_outcall(principal: &Principal) -> Result<(), String> {
+    charge_principal(principal, GenerationCost::outcall()).map_err(describe)
+}
+
+/// Text form of a quota error, for endpoints returning `Result<_, String>`
+pub fn describe(e: QuotaExceeded) -> String {
+    format!(
+        "Quota exceeded: {:?} limit {} reached, retry in {}s",
+        e.kind, e.limit, e.retry_after_secs
+    )
+}
+
+fn charge_principal(principal: &Principal, cost: GenerationCost) -> Result<(), QuotaExceeded> {
+    let now = ic_cdk::api::time();
+
+    let result = STATE.with(|state| {
+        state
+            .borrow_mut()
+            .quotas
+            .check_and_charge(principal, cost, now)
+    });
+
+    if let Err(e) = &result {
+        ic_cdk::println!(
+            "⛔ Quota exceeded for {}: {:?} (retry in {}s)",
+            principal,
+            e.kind,
+            e.retry_after_secs
+        );
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = NANOS_PER_SEC;
+
+    fn limits(calls_per_window: u32, window_secs: u64) -> QuotaConfig {
+        QuotaConfig {
+            calls_per_window,
+            window_secs,
+            daily_cycles_budget: 0,
+            daily_gas_budget: 0,
+        }
+    }
+
+    fn cost(cycles: u64, gas: u64) -> GenerationCost {
+        GenerationCost { cycles, gas }
+    }
+
+    #[test]
+    fn call_rate_resets_when_the_window_rolls_over() {
+        let limits = limits(2, 60);
+        let mut usage = Usage::default();
+        let now = 10 * NANOS_PER_DAY + 5 * SECOND;
+
+        charge(&mut usage, &limits, cost(0, 0), now).unwrap();
+        charge(&mut usage, &limits, cost(0, 0), now + SECOND).unwrap();
+        let e = charge(&mut usage, &limits, cost(0, 0), now + 2 * SECOND).unwrap_err();
+        assert_eq!(e.kind, QuotaKind::CallRate);
+        assert_eq!((e.limit, e.used), (2, 2));
+        assert_eq!(e.retry_at, 10 * NANOS_PER_DAY + 60 * SECOND);
+        assert_eq!(e.retry_after_secs, 53);
+
+        charge(&mut usage, &limits, cost(0, 0), e.retry_at).unwrap();
+        assert_eq!(usage.calls_in_window, 1);
+        assert_eq!(usage.total_calls, 3);
+    }
+
+    #[test]
+    fn daily_budgets_reset_on_the_next_day() {
+        let limits = QuotaConfig {
+            daily_cycles_budget: 100,
+            daily_gas_budget: 1_000,
+            ..limits(0, 60)
+        };
+        let mut usage = Usage::default();
+        let now = 3 * NANOS_PER_DAY;
+
+        charge(&mut usage, &limits, cost(60, 400), now).unwrap();
+        let e = charge(&mut usage, &limits, cost(60, 400), now).unwrap_err();
+        assert_eq!(e.kind, QuotaKind::DailyCycles);
+        assert_eq!((e.used, e.requested), (60, 60));
+        assert_eq!(e.retry_at, 4 * NANOS_PER_DAY);
+
+        let e = charge(&mut usage, &limits, cost(10, 700), now).unwrap_err();
+        assert_eq!(e.kind, QuotaKind::DailyGas);
+
+        // A rejected call is not charged
+        assert_eq!((usage.cycles_today, usage.gas_today), (60, 400));
+
+        charge(&mut usage, &limits, cost(60, 400), 4 * NANOS_PER_DAY).unwrap();
+        assert_eq!((usage.day, usage.cycles_today, usage.gas_today), (4, 60, 400));
+    }
+
+    #[test]
+    fn zero_limits_are_unlimited() {
+        let limits = limits(0, 0);
+        let mut usage = Usage::default();
+        for i in 0..100 {
+            charge(&mut usage, &limits, cost(u64::MAX, u64::MAX), i).unwrap();
+        }
+        assert_eq!(usage.cycles_today, u64::MAX);
+    }
+
+    #[test]
+    fn usage_expires_after_its_window_and_day() {
+        let limits = limits(5, 60);
+        let usage = Usage {
+            window_start: NANOS_PER_DAY - 30 * SECOND,
+            day: 0,
+            ..Usage::default()
+        };
+
+        assert!(!is_expired(&usage, &limits, NANOS_PER_DAY - SECOND));
+        // The day is over but the window is not
+        assert!(!is_expired(&usage, &limits, NANOS_PER_DAY + 10 * SECOND));
+        assert!(is_expired(&usage, &limits, NANOS_PER_DAY + 30 * SECOND));
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_defaults() {
+        let mut quotas = QuotaState::default();
+        let principal = Principal::from_slice(&[1]);
+
+        quotas.set_override(&principal, Some(limits(1, 1)));
+        assert_eq!(quotas.limits_for(&principal), limits(1, 1));
+        assert_eq!(quotas.limits_for(&Principal::anonymous()), QuotaConfig::default());
+
+        quotas.set_override(&principal, None);
+        assert_eq!(quotas.limits_for(&principal), QuotaConfig::default());
+    }
+
+    #[test]
+    fn charges_are_persisted_per_principal() {
+        let mut quotas = QuotaState::default();
+        quotas.set_defaults(limits(1, 60));
+        let principal = Principal::from_slice(&[1]);
+
+        quotas.check_and_charge(&principal, cost(0, 0), 0).unwrap();
+        assert!(quotas.check_and_charge(&principal, cost(0, 0), SECOND).is_err());
+        quotas.check_and_charge(&principal, cost(0, 0), 60 * SECOND).unwrap();
+
+        let usage = quotas.report(&principal).usage;
+        assert_eq!(usage.window_start, 60 * SECOND);
+        assert_eq!(usage.total_calls, 2);
+    }
+}
@@ -0,0 +1,500 @@
+// Provenance Registry Module
+// Durable record of every generated and registered work, with secondary indexes
+//
+// Records are keyed by `content_hash`. Secondary indexes map Story IP IDs,
+// Story token IDs, creator principals and the hashes of the transactions a
+// record references back to content hashes. Every record
+// also gets a monotonically increasing `id` used as a stable pagination cursor.
+// Records and indexes live in stable memory (see `storage`); only the ID
+// counter is part of the snapshot.
+
+use crate::storage::{self, StableMap};
+use crate::{GenerationOutput, IPMetadata};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+
+/// Maximum page size for paginated registry queries
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// A single provenance entry: the inputs of a generation plus everything
+/// that was produced and registered for it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ProvenanceRecord {
+    /// Sequential record ID (pagination cursor)
+    pub id: u64,
+    /// Principal that requested the generation
+    pub creator: Principal,
+    /// Original user prompt
+    pub prompt: String,
+    /// User-supplied IP metadata
+    pub metadata: IPMetadata,
+    /// Full orchestration output (content hash, Story IDs, Constellation tx)
+    pub output: GenerationOutput,
+    /// IC time (nanoseconds) when the record was created
+    pub created_at: u64,
+    /// IC time (nanoseconds) of the last update
+    pub updated_at: u64,
+    /// License tokens issued for this IP Asset (see `mint_license_tokens`)
+    #[serde(default)]
+    pub licenses: Vec<IssuedLicense>,
+}
+
+/// A `mintLicenseTokens` call made for a record's IP Asset
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IssuedLicense {
+    pub license_terms_id: u64,
+    pub amount: u64,
+    /// EIP-55 address the license tokens were minted to
+    pub receiver: String,
+    pub tx_hash: String,
+    /// Minted license token IDs; empty until the transaction is confirmed
+    pub token_ids: Vec<u64>,
+    /// Minting fee paid, in the smallest unit of `currency`
+    pub minting_fee: u64,
+    pub currency: Option<String>,
+    /// Principal that requested the tokens
+    pub issued_by: Principal,
+    /// IC time (nanoseconds)
+    pub issued_at: u64,
+}
+
+/// One page of `list_records` / `get_records_by_*` results
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RecordPage {
+    pub records: Vec<ProvenanceRecord>,
+    /// Pass this back as `cursor` to fetch the next page (None = end)
+    pub next_cursor: Option<u64>,
+}
+
+storage::impl_json_storable!(ProvenanceRecord);
+
+/// Persistent registry with secondary indexes
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Registry {
+    next_id: u64,
+}
+
+thread_local! {
+    /// content_hash -> record
+    static RECORDS: StableMap<String, ProvenanceRecord> =
+        storage::stable_map(storage::REGISTRY_RECORDS_MEMORY_ID);
+    /// record id -> content_hash
+    static BY_ID: StableMap<u64, String> =
+        storage::stable_map(storage::REGISTRY_BY_ID_MEMORY_ID);
+    /// story_ip_id (lowercase) -> content_hash
+    static BY_IP_ID: StableMap<String, String> =
+        storage::stable_map(storage::REGISTRY_BY_IP_ID_MEMORY_ID);
+    /// (story_token_id, record id) -> content_hash (token IDs are per NFT contract)
+    static BY_TOKEN_ID: StableMap<(u64, u64), String> =
+        storage::stable_map(storage::REGISTRY_BY_TOKEN_ID_MEMORY_ID);
+    /// (creator, record id) -> content_hash
+    static BY_CREATOR: StableMap<(Principal, u64), String> =
+        storage::stable_map(storage::REGISTRY_BY_CREATOR_MEMORY_ID);
+    /// Story registration or license mint tx hash (lowercase) -> content_hash
+    static BY_TX_HASH: StableMap<String, String> =
+        storage::stable_map(storage::REGISTRY_BY_TX_HASH_MEMORY_ID);
+}
+
+// ==============================================================================
+// Registry Operations
+// ==============================================================================
+
+impl Registry {
+    /// Insert a new record for a completed generation
+    ///
+    /// # Returns
+    /// * `Result<u64, String>` - Assigned record ID, or error if the content hash
+    ///   is already registered
+    pub fn insert(
+        &mut self,
+        creator: Principal,
+        prompt: String,
+        metadata: IPMetadata,
+        output: GenerationOutput,
+    ) -> Result<u64, String> {
+        let content_hash = output.content_hash.clone();
+
+        if load(&content_hash).is_some() {
+            return Err(format!("Content hash already registered: {}", content_hash));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let now = ic_cdk::api::time();
+        let record = ProvenanceRecord {
+            id,
+            creator,
+            prompt,
+            metadata,
+            output,
+            created_at: now,
+            updated_at: now,
+            licenses: Vec::new(),
+        };
+
+        index(&record);
+        BY_ID.with(|by_id| by_id.borrow_mut().insert(id, content_hash));
+        store(record);
+
+        Ok(id)
+    }
+
+    pub fn get_by_hash(&self, content_hash: &str) -> Option<ProvenanceRecord> {
+        load(content_hash)
+    }
+
+    pub fn get_by_ip_id(&self, ip_id: &str) -> Option<ProvenanceRecord> {
+        BY_IP_ID
+            .with(|by_ip_id| by_ip_id.borrow().get(&ip_id.to_lowercase()))
+            .and_then(|hash| load(&hash))
+    }
+
+    /// Records minted with `token_id`, in creation order, starting after the
+    /// record ID `cursor`
+    pub fn get_by_token_id(&self, token_id: u64, cursor: Option<u64>, limit: u32) -> RecordPage {
+        let start = start_after(cursor);
+        BY_TOKEN_ID.with(|by_token_id| {
+            page(
+                by_token_id
+                    .borrow()
+                    .range((token_id, start)..=(token_id, u64::MAX))
+                    .map(|(_, hash)| hash),
+                limit,
+            )
+        })
+    }
+
+    /// Records created by `creator`, in creation order, starting after the
+    /// record ID `cursor`
+    pub fn get_by_creator(&self, creator: &Principal, cursor: Option<u64>, limit: u32) -> RecordPage {
+        let start = start_after(cursor);
+        BY_CREATOR.with(|by_creator| {
+            page(
+                by_creator
+                    .borrow()
+                    .range((*creator, start)..=(*creator, u64::MAX))
+                    .map(|(_, hash)| hash),
+                limit,
+            )
+        })
+    }
+
+    /// List records in creation order, starting after `cursor`
+    pub fn list(&self, cursor: Option<u64>, limit: u32) -> RecordPage {
+        let start = start_after(cursor);
+        BY_ID.with(|by_id| page(by_id.borrow().range(start..).map(|(_, hash)| hash), limit))
+    }
+
+    pub fn count(&self) -> u64 {
+        RECORDS.with(|records| records.borrow().len())
+    }
+
+    /// Fill in the Story IP ID and token ID of the record registered by one of
+    /// `tx_hashes` once its transaction is confirmed
+    ///
+    /// # Arguments
+    /// * `tx_hashes` - Lowercase hashes sharing the registration nonce (original
+    ///   and replacements); the record may reference any of them
+    /// * `mined_tx_hash` - Hash of the transaction that was actually mined
+    ///
+    /// # Returns
+    /// * `Option<u64>` - ID of the updated record, if one references the hashes
+    pub fn apply_story_result(
+        &mut self,
+        tx_hashes: &[String],
+        mined_tx_hash: &str,
+        ip_id: &str,
+        token_id: u64,
+    ) -> Option<u64> {
+        let mut record = find(tx_hashes, |record| {
+            tx_hashes.contains(&record.output.story_tx_hash.to_lowercase())
+        })?;
+        unindex(&record);
+
+        record.output.story_ip_id = ip_id.to_string();
+        record.output.story_token_id = token_id;
+        record.output.story_tx_hash = mined_tx_hash.to_string();
+        record.updated_at = ic_cdk::api::time();
+
+        let id = record.id;
+        index(&record);
+        store(record);
+        Some(id)
+    }
+
+    /// Record license tokens issued for the IP Asset `ip_id`
+    ///
+    /// # Returns
+    /// * `Result<u64, String>` - ID of the updated record, or error if no record
+    ///   has that IP Asset
+    pub fn record_license(&mut self, ip_id: &str, license: IssuedLicense) -> Result<u64, String> {
+        let mut record = self
+            .get_by_ip_id(ip_id)
+            .ok_or_else(|| format!("No provenance record for IP Asset {}", ip_id))?;
+
+        index_tx(&license.tx_hash, &record.output.content_hash);
+        record.licenses.push(license);
+        record.updated_at = ic_cdk::api::time();
+
+        let id = record.id;
+        store(record);
+        Ok(id)
+    }
+
+    /// Fill in the token IDs of a license issuance once one of `tx_hashes`
+    /// (the mint and its replacements) is confirmed
+    ///
+    /// # Returns
+    /// * `Option<u64>` - ID of the updated record, if one references the hashes
+    pub fn apply_license_tokens(
+        &mut self,
+        tx_hashes: &[String],
+        mined_tx_hash: &str,
+        token_ids: Vec<u64>,
+    ) -> Option<u64> {
+        let mut record = find(tx_hashes, |record| {
+            record
+                .licenses
+                .iter()
+                .any(|license| tx_hashes.contains(&license.tx_hash.to_lowercase()))
+        })?;
+        let license = record
+            .licenses
+            .iter_mut()
+            .find(|license| tx_hashes.contains(&license.tx_hash.to_lowercase()))?;
+
+        license.tx_hash = mined_tx_hash.to_string();
+        license.token_ids = token_ids;
+        record.updated_at = ic_cdk::api::time();
+        index_tx(mined_tx_hash, &record.output.content_hash);
+
+        let id = record.id;
+        store(record);
+        Some(id)
+    }
+}
+
+// ==============================================================================
+// Stable Map Access
+// ==============================================================================
+
+/// First record ID of the page following `cursor`
+fn start_after(cursor: Option<u64>) -> u64 {
+    cursor.map_or(0, |c| c.saturating_add(1))
+}
+
+/// Load up to `limit` (clamped to 1..=MAX_PAGE_SIZE) records of `hashes`,
+/// which must be in record ID order
+fn page(hashes: impl Iterator<Item = String>, limit: u32) -> RecordPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let mut records: Vec<ProvenanceRecord> = hashes
+        .take(limit + 1)
+        .filter_map(|hash| load(&hash))
+        .collect();
+
+    let next_cursor = if records.len() > limit {
+        records.truncate(limit);
+        records.last().map(|r| r.id)
+    } else {
+        None
+    };
+
+    RecordPage { records, next_cursor }
+}
+
+fn load(content_hash: &str) -> Option<ProvenanceRecord> {
+    RECORDS.with(|records| records.borrow().get(&content_hash.to_string()))
+}
+
+fn store(record: ProvenanceRecord) {
+    RECORDS.with(|records| {
+        records
+            .borrow_mut()
+            .insert(record.output.content_hash.clone(), record)
+    });
+}
+
+/// First record referencing one of `tx_hashes` (lowercase) that matches
+/// `predicate`
+fn find(
+    tx_hashes: &[String],
+    predicate: impl Fn(&ProvenanceRecord) -> bool,
+) -> Option<ProvenanceRecord> {
+    tx_hashes
+        .iter()
+        .filter_map(|hash| BY_TX_HASH.with(|by_tx_hash| by_tx_hash.borrow().get(hash)))
+        .filter_map(|content_hash| load(&content_hash))
+        .find(|record| predicate(record))
+}
+
+fn index_tx(tx_hash: &str, content_hash: &str) {
+    if !tx_hash.is_empty() {
+        BY_TX_HASH.with(|by_tx_hash| {
+            by_tx_hash
+                .borrow_mut()
+                .insert(tx_hash.to_lowercase(), content_hash.to_string())
+        });
+    }
+}
+
+fn index(record: &ProvenanceRecord) {
+    let hash = record.output.content_hash.clone();
+    index_tx(&record.output.story_tx_hash, &hash);
+
+    // Story IDs are only known once the registration is confirmed
+    if !record.output.story_ip_id.is_empty() {
+        BY_IP_ID.with(|by_ip_id| {
+            by_ip_id
+                .borrow_mut()
+                .insert(record.output.story_ip_id.to_lowercase(), hash.clone())
+        });
+        BY_TOKEN_ID.with(|by_token_id| {
+            by_token_id
+                .borrow_mut()
+                .insert((record.output.story_token_id, record.id), hash.clone())
+        });
+    }
+
+    BY_CREATOR.with(|by_creator| {
+        by_creator
+            .borrow_mut()
+            .insert((record.creator, record.id), hash)
+    });
+}
+
+fn unindex(record: &ProvenanceRecord) {
+    let hash = &record.output.content_hash;
+
+    let ip_id = record.output.story_ip_id.to_lowercase();
+    BY_IP_ID.with(|by_ip_id| {
+        let mut by_ip_id = by_ip_id.borrow_mut();
+        if by_ip_id.get(&ip_id).as_ref() == Some(hash) {
+            by_ip_id.remove(&ip_id);
+        }
+    });
+
+    BY_TOKEN_ID.with(|by_token_id| {
+        by_token_id
+            .borrow_mut()
+            .remove(&(record.output.story_token_id, record.id))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(id: u64, creator: Principal, token_id: u64) {
+        let record = ProvenanceRecord {
+            id,
+            creator,
+            prompt: String::new(),
+            metadata: IPMetadata {
+                title: String::new(),
+                description: String::new(),
+                tags: Vec::new(),
+            },
+            output: GenerationOutput {
+                image_url: String::new(),
+                content_hash: format!("hash-{}", id),
+                story_ip_id: format!("0x{:040x}", id + 1),
+                story_tx_hash: format!("0x{:064X}", id),
+                story_nft_contract: String::new(),
+                story_token_id: token_id,
+                constellation_tx_hash: String::new(),
+                ai_model_id: String::new(),
+                story_chain_id: 1315,
+                story_parent_ip_id: None,
+            },
+            created_at: 0,
+            updated_at: 0,
+            licenses: Vec::new(),
+        };
+        index(&record);
+        BY_ID.with(|by_id| by_id.borrow_mut().insert(id, record.output.content_hash.clone()));
+        store(record);
+    }
+
+    fn ids(page: &RecordPage) -> Vec<u64> {
+        page.records.iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn secondary_lookups_page_by_record_id() {
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+        for id in 0..5 {
+            add(id, if id % 2 == 0 { alice } else { bob }, 7);
+        }
+        add(5, alice, 8);
+        let registry = Registry { next_id: 6 };
+
+        let first = registry.get_by_creator(&alice, None, 2);
+        assert_eq!(ids(&first), vec![0, 2]);
+        assert_eq!(first.next_cursor, Some(2));
+        let second = registry.get_by_creator(&alice, first.next_cursor, 2);
+        assert_eq!(ids(&second), vec![4, 5]);
+        assert_eq!(second.next_cursor, None);
+
+        let by_token = registry.get_by_token_id(7, Some(1), 10);
+        assert_eq!(ids(&by_token), vec![2, 3, 4]);
+        assert_eq!(by_token.next_cursor, None);
+
+        // Page size is clamped to 1..=MAX_PAGE_SIZE
+        assert_eq!(ids(&registry.list(None, 0)), vec![0]);
+        assert_eq!(registry.list(None, u32::MAX).records.len(), 6);
+    }
+
+    #[test]
+    fn records_are_found_by_hash_and_ip_id() {
+        add(10, Principal::anonymous(), 7);
+        let registry = Registry { next_id: 11 };
+
+        assert_eq!(registry.count(), 1);
+        assert_eq!(registry.get_by_hash("hash-10").map(|r| r.id), Some(10));
+        assert!(registry.get_by_hash("hash-11").is_none());
+        // IP IDs are matched regardless of checksum casing
+        let ip_id = format!("0x{:040X}", 11);
+        assert_eq!(registry.get_by_ip_id(&ip_id).map(|r| r.id), Some(10));
+    }
+
+    #[test]
+    fn records_are_found_by_the_transactions_they_reference() {
+        add(20, Principal::anonymous(), 7);
+        add(21, Principal::anonymous(), 7);
+
+        // Any hash under the registration nonce finds the record
+        let hashes = vec![format!("0x{:064x}", 99), format!("0x{:064x}", 21)];
+        assert_eq!(find(&hashes, |_| true).map(|r| r.id), Some(21));
+        assert!(find(&hashes, |record| record.id == 20).is_none());
+        assert!(find(&[format!("0x{:064x}", 99)], |_| true).is_none());
+    }
+
+    #[test]
+    fn a_content_hash_is_registered_once() {
+        add(0, Principal::anonymous(), 7);
+        let mut registry = Registry { next_id: 1 };
+
+        let duplicate = GenerationOutput {
+            content_hash: "hash-0".to_string(),
+            ..GenerationOutput::default()
+        };
+        let metadata = IPMetadata {
+            title: String::new(),
+            description: String::new(),
+            tags: Vec::new(),
+        };
+        let e = registry
+            .insert(Principal::anonymous(), String::new(), metadata, duplicate)
+            .unwrap_err();
+        assert!(e.contains("hash-0"));
+        assert_eq!(registry.next_id, 1);
+        assert_eq!(registry.count(), 1);
+    }
+}
@@ -0,0 +1,592 @@
+// Royalties Module
+// Royalty payments and revenue claims through Story's RoyaltyModule, with a
+// per-IP ledger for reconciliation
+//
+// Payments go to the receiver IP's royalty vault with `payRoyaltyOnBehalf`
+// (the canister pays, approving the RoyaltyModule for the amount first).
+// Claims go through `RoyaltyWorkflows.claimAllRevenue`, which first moves the
+// royalties owed by the given child IPs into the ancestor's vault and then
+// claims for the ancestor's IP Account; the revenue is forwarded to the
+// sending account when that account owns the IP. Every payment and claim is
+// recorded in the ledger under the IP that earned it, and confirmed (with the
+// amount actually claimed) by the receipt tracker.
+
+use crate::erc20::{self, Approval};
+use crate::evm_util::{self, EvmAccount};
+use crate::networks::Network;
+use crate::storage::{self, StableMap};
+use crate::transactions::{self, TxKind, TxReceipt};
+use crate::{events, rpc_util, STATE};
+use candid::{CandidType, Deserialize, Principal};
+use ethabi::{encode, ParamType, Token};
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RoyaltyEntryKind {
+    /// Paid into the IP's vault on behalf of `payer_ip_id`
+    Paid { payer_ip_id: String },
+    /// Claimed from the IP's vault (and its children's) for `claimer`
+    Claimed { claimer: String },
+}
+
+/// One payment or claim, recorded under the IP that earned it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RoyaltyEntry {
+    pub kind: RoyaltyEntryKind,
+    pub chain_id: u64,
+    /// EIP-55 ERC-20 token
+    pub currency: String,
+    /// Smallest unit of `currency`; for claims the claimable amount quoted
+    /// before sending, replaced by the amount received once confirmed
+    pub amount: u64,
+    pub tx_hash: String,
+    /// Set by the receipt tracker once the transaction is final
+    pub confirmed: bool,
+    /// Principal that made the call
+    pub requested_by: Principal,
+    /// IC time (nanoseconds)
+    pub created_at: u64,
+}
+
+/// Confirmed totals of one IP in one currency
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoyaltySummary {
+    pub currency: String,
+    pub total_paid: u64,
+    pub total_claimed: u64,
+    /// Entries not confirmed yet (not included in the totals)
+    pub pending_entries: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ClaimableRevenue {
+    /// EIP-55 address of the IP's royalty vault
+    pub vault: String,
+    pub claimer: String,
+    pub currency: String,
+    pub amount: u64,
+}
+
+storage::impl_json_storable!(RoyaltyEntry);
+
+/// Per-IP royalty ledger (entries live in stable memory, the sequence counter
+/// in the snapshot)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RoyaltyLedger {
+    next_seq: u64,
+}
+
+thread_local! {
+    /// (IP ID, sequence number) -> entry; oldest first per IP
+    static ENTRIES: StableMap<([u8; 20], u64), RoyaltyEntry> =
+        storage::stable_map(storage::ROYALTIES_MEMORY_ID);
+}
+
+impl RoyaltyLedger {
+    pub fn record(&mut self, ip_id: &str, entry: RoyaltyEntry) -> Result<(), String> {
+        let ip = ip_key(ip_id).ok_or_else(|| format!("Invalid IP ID: {}", ip_id))?;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        ENTRIES.with(|entries| entries.borrow_mut().insert((ip, seq), entry));
+        Ok(())
+    }
+
+    pub fn entries(&self, ip_id: &str) -> Vec<RoyaltyEntry> {
+        let Some(ip) = ip_key(ip_id) else {
+            return Vec::new();
+        };
+        ENTRIES.with(|entries| {
+            entries
+                .borrow()
+                .range((ip, 0)..=(ip, u64::MAX))
+                .map(|(_, entry)| entry)
+                .collect()
+        })
+    }
+
+    /// Confirmed totals per currency
+    pub fn summary(&self, ip_id: &str) -> Vec<RoyaltySummary> {
+        let mut totals: BTreeMap<String, RoyaltySummary> = BTreeMap::new();
+
+        for entry in self.entries(ip_id) {
+            let summary = totals
+                .entry(entry.currency.clone())
+                .or_insert_with(|| RoyaltySummary {
+                    currency: entry.currency.clone(),
+                    total_paid: 0,
+                    total_claimed: 0,
+                    pending_entries: 0,
+                });
+
+            if !entry.confirmed {
+                summary.pending_entries += 1;
+                continue;
+            }
+            match entry.kind {
+                RoyaltyEntryKind::Paid { .. } => {
+                    summary.total_paid = summary.total_paid.saturating_add(entry.amount)
+                }
+                RoyaltyEntryKind::Claimed { .. } => {
+                    summary.total_claimed = summary.total_claimed.saturating_add(entry.amount)
+                }
+            }
+        }
+
+        totals.into_values().collect()
+    }
+
+    /// Confirm the entry sent as one of `tx_hashes` (the original and its
+    /// replacements); `claimed` replaces the quoted amount of a claim
+    ///
+    /// # Returns
+    /// * `Option<String>` - IP ID of the confirmed entry, if one references the hashes
+    pub fn confirm(
+        &mut self,
+        tx_hashes: &[String],
+        mined_tx_hash: &str,
+        claimed: impl Fn(&RoyaltyEntry) -> Option<u64>,
+    ) -> Option<String> {
+        ENTRIES.with(|entries| {
+            let mut entries = entries.borrow_mut();
+            let (key, mut entry) = entries
+                .iter()
+                .find(|(_, entry)| tx_hashes.contains(&entry.tx_hash.to_lowercase()))?;
+
+            if let Some(amount) = claimed(&entry) {
+                entry.amount = amount;
+            }
+            entry.tx_hash = mined_tx_hash.to_string();
+            entry.confirmed = true;
+
+            entries.insert(key, entry);
+            Some(format!("0x{}", hex::encode(key.0)))
+        })
+    }
+}
+
+/// Ledger key of an IP ID (any case, `0x`-prefixed or not)
+fn ip_key(ip_id: &str) -> Option<[u8; 20]> {
+    let digits = ip_id.strip_prefix("0x").unwrap_or(ip_id);
+    hex::decode(digits).ok()?.try_into().ok()
+}
+
+fn parse_address(address: &str) -> Result<H160, String> {
+    Ok(H160::from(evm_util::parse_eip55_address(address)?))
+}
+
+fn checksum(address: H160) -> String {
+    evm_util::to_checksum_address(&address.to_fixed_bytes())
+}
+
+/// `currency`, or the network's WIP token
+fn currency_or_wip(network: &Network, currency: Option<&str>) -> Result<H160, String> {
+    match currency {
+        Some(currency) => parse_address(currency),
+        None => network.wip_token(),
+    }
+}
+
+// ==============================================================================
+// Vault Queries
+// ==============================================================================
+
+fn decode_address(data: &[u8], what: &str) -> Result<H160, String> {
+    match ethabi::decode(&[ParamType::Address], data)
+        .map_err(|e| format!("Failed to decode {}: {}", what, e))?
+        .first()
+    {
+        Some(Token::Address(address)) => Ok(*address),
+        other => Err(format!("Unexpected {} result: {:?}", what, other)),
+    }
+}
+
+/// Royalty vault of `ip_id` (`RoyaltyModule.ipRoyaltyVaults`)
+pub async fn royalty_vault(network: &Network, ip_id: H160) -> Result<H160, String> {
+    let mut calldata = evm_util::function_selector("ipRoyaltyVaults(address)").to_vec();
+    calldata.extend_from_slice(&encode(&[Token::Address(ip_id)]));
+
+    let data = rpc_util::eth_call(network.chain_id, network.royalty_module()?, &calldata).await?;
+    let vault = decode_address(&data, "ipRoyaltyVaults")?;
+    if vault.is_zero() {
+        return Err(format!(
+            "IP Asset {} has no royalty vault yet (it is created with the first commercial license)",
+            checksum(ip_id)
+        ));
+    }
+    Ok(vault)
+}
+
+/// Revenue `ip_id`'s IP Account can claim from its own vault right now
+///
+/// Royalties still owed by derivatives are not included until a claim (or
+/// `transferToVault`) moves them into the vault.
+pub async fn claimable_revenue(
+    network: &Network,
+    ip_id: H160,
+    currency: Option<&str>,
+) -> Result<ClaimableRevenue, String> {
+    let currency = currency_or_wip(network, currency)?;
+    let vault = royalty_vault(network, ip_id).await?;
+
+    let mut calldata = evm_util::function_selector("claimableRevenue(address,address)").to_vec();
+    calldata.extend_from_slice(&encode(&[Token::Address(ip_id), Token::Address(currency)]));
+    let data = rpc_util::eth_call(network.chain_id, vault, &calldata).await?;
+
+    let amount = match ethabi::decode(&[ParamType::Uint(256)], &data)
+        .map_err(|e| format!("Failed to decode claimableRevenue: {}", e))?
+        .first()
+    {
+        Some(Token::Uint(value)) if value.bits() > 64 => u64::MAX,
+        Some(Token::Uint(value)) => value.low_u64(),
+        other => return Err(format!("Unexpected claimableRevenue result: {:?}", other)),
+    };
+
+    Ok(ClaimableRevenue {
+        vault: checksum(vault),
+        claimer: checksum(ip_id),
+        currency: checksum(currency),
+        amount,
+    })
+}
+
+// ==============================================================================
+// Payments and Claims
+// ==============================================================================
+
+/// Build calldata for RoyaltyModule.payRoyaltyOnBehalf(address,address,address,uint256)
+pub fn build_pay_royalty_on_behalf_calldata(
+    receiver_ip_id: H160,
+    payer_ip_id: H160,
+    currency: H160,
+    amount: u64,
+) -> Vec<u8> {
+    let mut calldata =
+        evm_util::function_selector("payRoyaltyOnBehalf(address,address,address,uint256)").to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(receiver_ip_id),
+        Token::Address(payer_ip_id),
+        Token::Address(currency),
+        Token::Uint(U256::from(amount)),
+    ]));
+    calldata
+}
+
+/// Build calldata for RoyaltyWorkflows.claimAllRevenue(address,address,
+/// address[],address[],address[])
+///
+/// Every child is claimed through the LAP royalty policy.
+pub fn build_claim_all_revenue_calldata(
+    ancestor_ip_id: H160,
+    claimer: H160,
+    child_ip_ids: &[H160],
+    royalty_policy: H160,
+    currency: H160,
+) -> Vec<u8> {
+    let mut calldata = evm_util::function_selector(
+        "claimAllRevenue(address,address,address[],address[],address[])",
+    )
+    .to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(ancestor_ip_id),
+        Token::Address(claimer),
+        Token::Array(child_ip_ids.iter().map(|id| Token::Address(*id)).collect()),
+        Token::Array(
+            child_ip_ids
+                .iter()
+                .map(|_| Token::Address(royalty_policy))
+                .collect(),
+        ),
+        Token::Array(vec![Token::Address(currency)]),
+    ]));
+    calldata
+}
+
+/// Pay royalties to `receiver_ip_id` on behalf of `payer_ip_id`
+///
+/// The canister's address pays; the RoyaltyModule is approved for `amount`
+/// first if needed. When that approval is not mined right away, nothing is
+/// paid and the approval hash is returned in the error.
+///
+/// # Arguments
+/// * `network` - Network of both IPs
+/// * `receiver_ip_id` - IP whose vault receives the payment
+/// * `payer_ip_id` - IP paying (usually a derivative of the receiver)
+/// * `currency` - EIP-55 whitelisted royalty token; None = WIP
+/// * `amount` - Smallest unit of `currency`
+///
+/// # Returns
+/// * `Result<RoyaltyEntry, String>` - Ledger entry of the (unconfirmed) payment
+pub async fn pay_royalty_on_behalf(
+    network: &Network,
+    receiver_ip_id: &str,
+    payer_ip_id: &str,
+    currency: Option<&str>,
+    amount: u64,
+) -> Result<RoyaltyEntry, String> {
+    if amount == 0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+    let receiver = parse_address(receiver_ip_id)?;
+    let payer = parse_address(payer_ip_id)?;
+    let currency = currency_or_wip(network, currency)?;
+    let royalty_module = network.royalty_module()?;
+
+    let approval = erc20::ensure_allowance(
+        network,
+        EvmAccount::Canister,
+        currency,
+        royalty_module,
+        amount,
+    )
+    .await?;
+    if let Approval::Pending(tx_hash) = approval {
+        return Err(format!(
+            "Approval {} is pending; pay again once it is mined",
+            tx_hash
+        ));
+    }
+
+    ic_cdk::println!(
+        "   💸 Paying {} of {} to {} on behalf of {}",
+        amount,
+        checksum(currency),
+        checksum(receiver),
+        checksum(payer)
+    );
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::PayRoyalty,
+        Some(royalty_module.to_fixed_bytes()),
+        build_pay_royalty_on_behalf_calldata(receiver, payer, currency, amount),
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ Royalty payment sent: {}", network.tx_url(&tx_hash));
+
+    let entry = RoyaltyEntry {
+        kind: RoyaltyEntryKind::Paid {
+            payer_ip_id: checksum(payer),
+        },
+        chain_id: network.chain_id,
+        currency: checksum(currency),
+        amount,
+        tx_hash,
+        confirmed: false,
+        requested_by: ic_cdk::caller(),
+        created_at: ic_cdk::api::time(),
+    };
+    STATE.with(|state| {
+        state
+            .borrow_mut()
+            .royalties
+            .record(receiver_ip_id, entry.clone())
+    })?;
+    Ok(entry)
+}
+
+/// Claim an IP's revenue, collecting what its derivatives owe first
+///
+/// # Arguments
+/// * `network` - Network of the IP
+/// * `account` - Sending account; the revenue is forwarded to it if it owns
+///   the IP, and otherwise stays in the IP Account
+/// * `ip_id` - Ancestor IP (claimer)
+/// * `child_ip_ids` - Derivatives whose royalties are collected first
+/// * `currency` - EIP-55 royalty token; None = WIP
+///
+/// # Returns
+/// * `Result<RoyaltyEntry, String>` - Ledger entry of the (unconfirmed) claim
+pub async fn claim_all_revenue(
+    network: &Network,
+    account: EvmAccount,
+    ip_id: &str,
+    child_ip_ids: &[String],
+    currency: Option<&str>,
+) -> Result<RoyaltyEntry, String> {
+    let ancestor = parse_address(ip_id)?;
+    let children = child_ip_ids
+        .iter()
+        .map(|child| parse_address(child))
+        .collect::<Result<Vec<_>, _>>()?;
+    let currency = currency_or_wip(network, currency)?;
+
+    // Quote the vault's own balance; the confirmed amount replaces it
+    let quoted = claimable_revenue(network, ancestor, Some(&checksum(currency)))
+        .await?
+        .amount;
+
+    ic_cdk::println!(
+        "   💰 Claiming {} revenue of {} ({} children, {} claimable in its vault)",
+        checksum(currency),
+        checksum(ancestor),
+        children.len(),
+        quoted
+    );
+    let tx_hash = transactions::send(
+        network,
+        account,
+        TxKind::ClaimRevenue,
+        Some(network.royalty_workflows()?.to_fixed_bytes()),
+        build_claim_all_revenue_calldata(
+            ancestor,
+            ancestor,
+            &children,
+            network.royalty_policy_lap()?,
+            currency,
+        ),
+        0, // No value transfer
+    )
+    .await?;
+    ic_cdk::println!("   ✅ Claim sent: {}", network.tx_url(&tx_hash));
+
+    let entry = RoyaltyEntry {
+        kind: RoyaltyEntryKind::Claimed {
+            claimer: checksum(ancestor),
+        },
+        chain_id: network.chain_id,
+        currency: checksum(currency),
+        amount: quoted,
+        tx_hash,
+        confirmed: false,
+        requested_by: ic_cdk::caller(),
+        created_at: ic_cdk::api::time(),
+    };
+    STATE.with(|state| state.borrow_mut().royalties.record(ip_id, entry.clone()))?;
+    Ok(entry)
+}
+
+/// Amount of a confirmed claim: the currency the claimer's IP Account received
+pub fn claimed_amount(receipt: &TxReceipt, entry: &RoyaltyEntry) -> Option<u64> {
+    let RoyaltyEntryKind::Claimed { claimer } = &entry.kind else {
+        return None;
+    };
+    Some(events::erc20_received(
+        receipt,
+        parse_address(&entry.currency).ok()?,
+        parse_address(claimer).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transactions::TxLog;
+
+    const IP: &str = "0xAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+    fn entry(kind: RoyaltyEntryKind, amount: u64, tx_hash: &str) -> RoyaltyEntry {
+        RoyaltyEntry {
+            kind,
+            chain_id: 1315,
+            currency: checksum(H160::repeat_byte(0x15)),
+            amount,
+            tx_hash: tx_hash.to_string(),
+            confirmed: false,
+            requested_by: Principal::anonymous(),
+            created_at: 0,
+        }
+    }
+
+    fn claim(amount: u64, tx_hash: &str) -> RoyaltyEntry {
+        entry(
+            RoyaltyEntryKind::Claimed {
+                claimer: checksum(H160::repeat_byte(0xaa)),
+            },
+            amount,
+            tx_hash,
+        )
+    }
+
+    fn erc20_transfer(token: H160, to: H160, value: u64) -> TxLog {
+        let topic = |address: H160| format!("0x{}{}", "0".repeat(24), hex::encode(address));
+        TxLog {
+            address: format!("0x{}", hex::encode(token)),
+            topics: vec![
+                events::topic0("Transfer(address,address,uint256)"),
+                topic(H160::repeat_byte(0x99)),
+                topic(to),
+            ],
+            data: format!("0x{}", hex::encode(encode(&[Token::Uint(value.into())]))),
+            log_index: 0,
+        }
+    }
+
+    #[test]
+    fn summary_counts_only_confirmed_entries() {
+        let mut ledger = RoyaltyLedger::default();
+        let paid = entry(
+            RoyaltyEntryKind::Paid {
+                payer_ip_id: IP.to_string(),
+            },
+            500,
+            "0x01",
+        );
+        ledger.record(IP, paid).unwrap();
+        ledger.record(IP, claim(100, "0x02")).unwrap();
+        assert!(ledger.record("not an ip", claim(1, "0x03")).is_err());
+
+        // Confirmation replaces the quoted claim with the received amount
+        let confirmed = ledger.confirm(&["0x02".to_string()], "0x02", |_| Some(120));
+        assert_eq!(confirmed, Some(IP.to_lowercase()));
+
+        let summary = ledger.summary(&IP.to_lowercase());
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].total_claimed, 120);
+        assert_eq!(summary[0].total_paid, 0);
+        assert_eq!(summary[0].pending_entries, 1);
+    }
+
+    #[test]
+    fn claimed_amount_counts_only_the_claimer() {
+        let (wip, other_token) = (H160::repeat_byte(0x15), H160::repeat_byte(0x16));
+        let (claimer, owner) = (H160::repeat_byte(0xaa), H160::repeat_byte(0xbb));
+        let receipt = TxReceipt {
+            success: true,
+            block_number: 1,
+            block_hash: String::new(),
+            gas_used: 0,
+            effective_gas_price: None,
+            contract_address: None,
+            logs: vec![
+                erc20_transfer(wip, claimer, 700),
+                erc20_transfer(wip, claimer, 300),
+                erc20_transfer(wip, owner, 1_000),
+                erc20_transfer(other_token, claimer, 50),
+            ],
+        };
+
+        assert_eq!(claimed_amount(&receipt, &claim(0, "0x01")), Some(1_000));
+        let paid = entry(
+            RoyaltyEntryKind::Paid {
+                payer_ip_id: IP.to_string(),
+            },
+            0,
+            "0x01",
+        );
+        assert_eq!(claimed_amount(&receipt, &paid), None);
+    }
+
+    #[test]
+    fn royalty_selectors() {
+        let pay = build_pay_royalty_on_behalf_calldata(H160::zero(), H160::zero(), H160::zero(), 1);
+        assert_eq!(hex::encode(&pay[..4]), "d2577f3b");
+
+        let children = [H160::repeat_byte(1), H160::repeat_byte(2)];
+        let claim = build_claim_all_revenue_calldata(
+            H160::zero(),
+            H160::zero(),
+            &children,
+            H160::zero(),
+            H160::zero(),
+        );
+        assert_eq!(hex::encode(&claim[..4]), "c4485199");
+    }
+}
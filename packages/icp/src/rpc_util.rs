@@ -0,0 +1,423 @@
+// Story RPC Utility Module
+// JSON-RPC client for the RPC endpoints of registered networks
+//
+// Requests go through one of two backends (admin-selectable, see `RpcSettings`):
+// direct HTTPS outcalls from this canister to every provider of the network,
+// or the DFINITY EVM RPC canister (`evm_rpc`). Either way, read calls are
+// cross-checked across the network's providers with the configured
+// `ConsensusStrategy`; broadcasts only need one provider to accept them.
+
+use crate::config;
+use crate::evm_rpc::{self, ConsensusStrategy};
+use crate::evm_util;
+use crate::http_util::{json_header, make_http_request};
+use crate::networks::{self, Network};
+use crate::STATE;
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::http_request::HttpMethod;
+use primitive_types::H160;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// ==============================================================================
+// Settings
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RpcBackend {
+    /// HTTPS outcalls from this canister to each provider
+    Direct,
+    /// The EVM RPC canister queries the providers and applies the consensus
+    /// strategy (None = defaults from `config`)
+    EvmRpcCanister {
+        canister_id: Option<Principal>,
+        cycles_per_provider: Option<u64>,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RpcSettings {
+    pub backend: RpcBackend,
+    /// Agreement required between providers for read calls
+    pub read_consensus: ConsensusStrategy,
+}
+
+impl Default for RpcSettings {
+    fn default() -> Self {
+        Self {
+            backend: RpcBackend::Direct,
+            read_consensus: ConsensusStrategy::Equality,
+        }
+    }
+}
+
+impl RpcSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let ConsensusStrategy::Threshold { total, min } = self.read_consensus {
+            if min == 0 {
+                return Err("Threshold min must be at least 1".to_string());
+            }
+            if total.is_some_and(|total| total < min) {
+                return Err("Threshold total cannot be smaller than min".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Broadcasts succeed as soon as one provider accepts the transaction (the
+/// others may answer "already known")
+const WRITE_CONSENSUS: ConsensusStrategy = ConsensusStrategy::Threshold {
+    total: None,
+    min: 1,
+};
+
+// ==============================================================================
+// Consensus
+// ==============================================================================
+
+/// Reduce per-provider results to one result according to `strategy`
+///
+/// Identical results (successes or errors) form a group. Equality needs a
+/// single group; Threshold needs a group of at least `min`, preferring
+/// successes over errors (transport or JSON-RPC) and larger groups over
+/// smaller ones.
+fn reduce(
+    method: &str,
+    results: Vec<Result<Value, String>>,
+    strategy: &ConsensusStrategy,
+) -> Result<Value, String> {
+    let mut groups: Vec<(Result<Value, String>, usize)> = Vec::new();
+    for result in results {
+        match groups.iter_mut().find(|(r, _)| *r == result) {
+            Some((_, count)) => *count += 1,
+            None => groups.push((result, 1)),
+        }
+    }
+
+    let agreed = match strategy {
+        ConsensusStrategy::Equality if groups.len() == 1 => groups.pop().map(|(r, _)| r),
+        ConsensusStrategy::Equality => None,
+        ConsensusStrategy::Threshold { min, .. } => {
+            groups.sort_by_key(|(result, count)| (is_failure(result), std::cmp::Reverse(*count)));
+            groups
+                .into_iter()
+                .find(|(_, count)| *count >= *min as usize)
+                .map(|(r, _)| r)
+        }
+    };
+
+    agreed.unwrap_or_else(|| Err(format!("Providers disagree on {} ({:?})", method, strategy)))
+}
+
+fn is_failure(result: &Result<Value, String>) -> bool {
+    result.as_ref().map_or(true, |response| response.get("error").is_some())
+}
+
+// ==============================================================================
+// Backends
+// ==============================================================================
+
+/// Reduce a response body to `{"result": ...}` or `{"error": ...}`
+///
+/// JSON-RPC errors stay values rather than becoming `Err`, so they take part
+/// in consensus like results and callers can inspect their `data` (e.g. the
+/// revert data of an `eth_call`).
+fn parse_response(method: &str, body: &[u8]) -> Result<Value, String> {
+    let response_json: Value = serde_json::from_slice(body)
+        .map_err(|e| format!("Failed to parse {} response: {}", method, e))?;
+
+    if let Some(error) = response_json.get("error") {
+        return Ok(json!({ "error": error }));
+    }
+
+    response_json
+        .get("result")
+        .map(|result| json!({ "result": result }))
+        .ok_or_else(|| format!("No result in {} response", method))
+}
+
+/// Split a reduced response into its result or JSON-RPC error object
+fn into_outcome(method: &str, response: Value) -> Result<Result<Value, Value>, String> {
+    if let Some(error) = response.get("error") {
+        return Ok(Err(error.clone()));
+    }
+    response
+        .get("result")
+        .cloned()
+        .map(Ok)
+        .ok_or_else(|| format!("No result in {} response", method))
+}
+
+/// Query every provider with an HTTPS outcall and reduce the results locally
+async fn request_direct(
+    network: &Network,
+    method: &str,
+    payload: &str,
+    consensus: &ConsensusStrategy,
+) -> Result<Value, String> {
+    let mut results = Vec::with_capacity(network.rpc_urls.len());
+
+    for url in &network.rpc_urls {
+        let result = make_http_request(
+            url.clone(),
+            HttpMethod::POST,
+            vec![json_header()],
+            Some(payload.as_bytes().to_vec()),
+        )
+        .await
+        .and_then(|body| parse_response(method, &body));
+
+        match &result {
+            Err(e) => ic_cdk::println!("   ⚠️  {} via {}: {}", method, url, e),
+            Ok(response) => {
+                if let Some(error) = response.get("error") {
+                    ic_cdk::println!("   ⚠️  {} via {}: RPC error {}", method, url, error);
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    reduce(method, results, consensus)
+}
+
+async fn request(
+    chain_id: u64,
+    method: &str,
+    params: Value,
+    consensus: &ConsensusStrategy,
+) -> Result<Value, String> {
+    let network = networks::get(chain_id)?;
+    let backend = STATE.with(|state| state.borrow().rpc.backend.clone());
+
+    let payload = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+        "id": 1
+    })
+    .to_string();
+
+    match backend {
+        RpcBackend::Direct => request_direct(&network, method, &payload, consensus).await,
+        RpcBackend::EvmRpcCanister {
+            canister_id,
+            cycles_per_provider,
+        } => {
+            let canister_id = match canister_id {
+                Some(id) => id,
+                None => Principal::from_text(config::EVM_RPC_CANISTER_ID)
+                    .map_err(|e| format!("Invalid EVM RPC canister ID: {}", e))?,
+            };
+            let body = evm_rpc::multi_request(
+                canister_id,
+                chain_id,
+                &network.rpc_urls,
+                &payload,
+                consensus,
+                cycles_per_provider.unwrap_or(config::DEFAULT_EVM_RPC_CYCLES_PER_PROVIDER),
+            )
+            .await?;
+            parse_response(method, body.as_bytes())
+        }
+    }
+}
+
+// ==============================================================================
+// JSON-RPC Calls
+// ==============================================================================
+
+/// Call a read-only JSON-RPC method on the network with `chain_id`
+///
+/// The result is cross-checked across the network's providers using the
+/// configured read consensus.
+///
+/// # Arguments
+/// * `chain_id` - Registered network to call (see `networks`)
+/// * `method` - RPC method name (e.g. "eth_gasPrice")
+/// * `params` - JSON array of parameters
+///
+/// # Returns
+/// * `Result<Value, String>` - The agreed `result` field, or the RPC/transport/consensus error
+pub async fn call(chain_id: u64, method: &str, params: Value) -> Result<Value, String> {
+    call_raw(chain_id, method, params)
+        .await?
+        .map_err(|error| format!("RPC error ({}): {}", method, error))
+}
+
+/// Like `call`, but hands back the agreed JSON-RPC error object instead of
+/// flattening it into a message
+///
+/// # Returns
+/// * `Result<Result<Value, Value>, String>` - `Ok(Ok(result))`, `Ok(Err(error object))`,
+///   or the transport/consensus error
+pub async fn call_raw(
+    chain_id: u64,
+    method: &str,
+    params: Value,
+) -> Result<Result<Value, Value>, String> {
+    let consensus = STATE.with(|state| state.borrow().rpc.read_consensus.clone());
+    let response = request(chain_id, method, params, &consensus).await?;
+    into_outcome(method, response)
+}
+
+/// Read-only contract call (`eth_call` at the latest block)
+///
+/// # Returns
+/// * `Result<Vec<u8>, String>` - ABI-encoded return data or error
+pub async fn eth_call(chain_id: u64, to: H160, data: &[u8]) -> Result<Vec<u8>, String> {
+    let result = call(
+        chain_id,
+        "eth_call",
+        json!([
+            {
+                "to": format!("0x{}", hex::encode(to.to_fixed_bytes())),
+                "data": format!("0x{}", hex::encode(data)),
+            },
+            "latest"
+        ]),
+    )
+    .await?;
+
+    let hex_str = result
+        .as_str()
+        .ok_or_else(|| format!("Unexpected eth_call result: {}", result))?;
+    hex::decode(hex_str.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid eth_call result '{}': {}", hex_str, e))
+}
+
+/// Why `send_raw_transaction` returned no transaction hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BroadcastError {
+    /// The providers answered with a JSON-RPC error: the transaction was not accepted
+    Rejected(String),
+    /// Transport, consensus or response errors: the transaction may still have
+    /// reached the pool under its hash
+    Unknown(String),
+}
+
+impl std::fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BroadcastError::Rejected(e) => write!(f, "{}", e),
+            BroadcastError::Unknown(e) => write!(f, "Broadcast outcome unknown: {}", e),
+        }
+    }
+}
+
+impl From<BroadcastError> for String {
+    fn from(e: BroadcastError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Whether a JSON-RPC error only says the node already has the transaction
+fn is_already_known(error: &Value) -> bool {
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_lowercase();
+    message.contains("already known") || message.contains("known transaction")
+}
+
+/// Broadcast a signed transaction with `eth_sendRawTransaction` to every provider
+///
+/// # Returns
+/// * `Result<String, BroadcastError>` - Transaction hash, `Rejected` if the
+///   providers refused it, or `Unknown` if the outcome could not be determined
+pub async fn send_raw_transaction(chain_id: u64, signed_tx: &[u8]) -> Result<String, BroadcastError> {
+    ic_cdk::println!("      Broadcasting transaction to chain {}...", chain_id);
+
+    let tx_hex = format!("0x{}", hex::encode(signed_tx));
+    let method = "eth_sendRawTransaction";
+    let response = request(chain_id, method, json!([tx_hex]), &WRITE_CONSENSUS)
+        .await
+        .and_then(|response| into_outcome(method, response))
+        .map_err(BroadcastError::Unknown)?;
+
+    let result = match response {
+        Ok(result) => result,
+        Err(error) if is_already_known(&error) => {
+            return Ok(evm_util::transaction_hash(signed_tx))
+        }
+        Err(error) => {
+            return Err(BroadcastError::Rejected(format!(
+                "RPC error ({}): {}",
+                method, error
+            )))
+        }
+    };
+
+    result
+        .as_str()
+        .map(|hash| hash.to_string())
+        .ok_or_else(|| {
+            BroadcastError::Unknown(format!("Unexpected eth_sendRawTransaction result: {}", result))
+        })
+}
+
+// ==============================================================================
+// Quantity Parsing
+// ==============================================================================
+
+/// Parse a hex quantity ("0x1a") into a u64
+pub fn parse_quantity(value: &Value) -> Result<u64, String> {
+    let hex_str = value
+        .as_str()
+        .ok_or_else(|| format!("Expected hex quantity, got {}", value))?;
+
+    u64::from_str_radix(hex_str.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Failed to parse hex quantity '{}': {}", hex_str, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(value: u64) -> Result<Value, String> {
+        Ok(json!({ "result": value }))
+    }
+
+    fn threshold(min: u8) -> ConsensusStrategy {
+        ConsensusStrategy::Threshold { total: None, min }
+    }
+
+    #[test]
+    fn equality_requires_all_providers_to_agree() {
+        let strategy = ConsensusStrategy::Equality;
+        assert_eq!(reduce("m", vec![ok(7), ok(7)], &strategy), ok(7));
+        assert!(reduce("m", vec![ok(7), ok(8)], &strategy).is_err());
+        assert!(reduce("m", vec![ok(7), Err("timeout".into())], &strategy).is_err());
+    }
+
+    #[test]
+    fn threshold_takes_the_largest_successful_group() {
+        assert_eq!(reduce("m", vec![ok(7), ok(8), ok(7)], &threshold(2)), ok(7));
+        assert!(reduce("m", vec![ok(7), ok(8), Err("timeout".into())], &threshold(2)).is_err());
+    }
+
+    #[test]
+    fn broadcast_needs_one_acceptance() {
+        let rejected = Ok(json!({ "error": { "code": -32000, "message": "already known" } }));
+        let results = vec![rejected.clone(), ok(1), rejected];
+        assert_eq!(reduce("m", results, &WRITE_CONSENSUS), ok(1));
+
+        assert!(is_already_known(&json!({ "message": "already known" })));
+        assert!(is_already_known(&json!({ "message": "Known transaction: 0xabc" })));
+        assert!(!is_already_known(&json!({ "message": "nonce too low" })));
+    }
+
+    #[test]
+    fn json_rpc_errors_stay_values() {
+        let response = parse_response("m", br#"{"jsonrpc":"2.0","id":1,"error":{"code":3}}"#).unwrap();
+        assert_eq!(into_outcome("m", response), Ok(Err(json!({ "code": 3 }))));
+
+        let response = parse_response("m", br#"{"jsonrpc":"2.0","id":1,"result":"0x15"}"#).unwrap();
+        assert_eq!(parse_quantity(&into_outcome("m", response).unwrap().unwrap()), Ok(21));
+
+        assert!(parse_response("m", b"<html>").is_err());
+    }
+}
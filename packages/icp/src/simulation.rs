@@ -0,0 +1,352 @@
+// Transaction Simulation Module
+// Dry-runs outgoing transactions with `eth_call` and decodes revert reasons
+//
+// `transactions::send` simulates every transaction against the `pending`
+// block before it reserves a nonce, so a call Story would reject never costs
+// gas. Revert data is decoded as `Error(string)`, `Panic(uint256)` or one of
+// the custom errors in `KNOWN_ERRORS`; anything else is returned raw.
+
+use crate::transactions::TxError;
+use crate::{evm_util, fees, rpc_util};
+use candid::{CandidType, Deserialize};
+use ethabi::param_type::{ParamType, Reader};
+use ethabi::Token;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Selector of `Error(string)` (`revert("...")`, `require(cond, "...")`)
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)` (compiler-inserted checks)
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Custom errors the canister's transactions can run into: Story core and
+/// periphery errors, plus the OpenZeppelin errors of the NFT contracts.
+/// Only elementary parameter types are supported.
+const KNOWN_ERRORS: &[&str] = &[
+    // IPAssetRegistry
+    "IPAssetRegistry__AlreadyRegistered()",
+    "IPAssetRegistry__InvalidToken(address,uint256)",
+    "IPAssetRegistry__UnsupportedIERC721(address)",
+    "IPAssetRegistry__UnsupportedIERC721Metadata(address)",
+    "IPAssetRegistry__ZeroAddress(string)",
+    // AccessController / IP Account
+    "AccessController__PermissionDenied(address,address,address,bytes4)",
+    "AccessController__SignerIsZeroAddress()",
+    "AccessController__PermissionIsNotValid()",
+    "IPAccount__InvalidSigner()",
+    "IPAccount__InvalidSignature()",
+    "IPAccount__ExpiredSignature()",
+    "IPAccount__InvalidCalldata()",
+    // LicensingModule / LicenseRegistry
+    "LicensingModule__DerivativesCannotAddLicenseTerms()",
+    "LicenseRegistry__ParentIpHasNoLicenseTerms(address,uint256)",
+    "LicenseRegistry__DerivativeAlreadyRegistered(address)",
+    // DisputeModule / ArbitrationPolicyUMA
+    "DisputeModule__NotWhitelistedDisputeTag()",
+    "DisputeModule__ZeroDisputeEvidenceHash()",
+    "DisputeModule__EvidenceHashAlreadyUsed()",
+    "DisputeModule__NotDisputeInitiator()",
+    "DisputeModule__NotInDisputeState()",
+    "DisputeModule__NotAbleToResolve()",
+    "ArbitrationPolicyUMA__CannotCancel()",
+    "ArbitrationPolicyUMA__LivenessBelowMin()",
+    "ArbitrationPolicyUMA__LivenessAboveMax()",
+    "ArbitrationPolicyUMA__BondAboveMax()",
+    // Periphery workflows and SPG NFT collections
+    "Workflow__CallerNotAuthorizedToMint()",
+    "SPGNFT__CallerNotFeeRecipientOrAdmin()",
+    "SPGNFT__MaxSupplyReached()",
+    "SPGNFT__MintingDenied()",
+    "SPGNFT__MintingClosed()",
+    "SPGNFT__ZeroAddressParam()",
+    "SPGNFT__DuplicatedNFTMetadataHash(address,uint256,bytes32)",
+    // OpenZeppelin
+    "ERC20InsufficientBalance(address,uint256,uint256)",
+    "ERC20InsufficientAllowance(address,uint256,uint256)",
+    "ERC721NonexistentToken(uint256)",
+    "ERC721IncorrectOwner(address,uint256,address)",
+    "ERC721InsufficientApproval(address,uint256)",
+    "ERC721InvalidReceiver(address)",
+    "ERC721InvalidSender(address)",
+    "OwnableUnauthorizedAccount(address)",
+    "AccessControlUnauthorizedAccount(address,bytes32)",
+];
+
+// ==============================================================================
+// Revert Reasons
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RevertReason {
+    /// `Error(string)` message
+    Error(String),
+    /// `Panic(uint256)` code and what the compiler uses it for
+    Panic { code: u64, description: String },
+    /// Custom error from the registry, e.g. `SPGNFT__MintingDenied()`
+    Custom {
+        signature: String,
+        args: Vec<String>,
+    },
+    /// Revert data that could not be decoded (0x-prefixed hex, "0x" if none)
+    Unknown { data: String },
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Error(message) => write!(f, "reverted: {}", message),
+            RevertReason::Panic { code, description } => {
+                write!(f, "panic 0x{:02x} ({})", code, description)
+            }
+            RevertReason::Custom { signature, args } => {
+                let name = signature.split('(').next().unwrap_or(signature);
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            RevertReason::Unknown { data } => {
+                write!(f, "reverted without a known reason ({})", data)
+            }
+        }
+    }
+}
+
+/// Meaning of a Solidity panic code
+fn panic_description(code: u64) -> &'static str {
+    match code {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+/// Parameter types of a signature such as "Foo(address,uint256)"
+fn param_types(signature: &str) -> Option<Vec<ParamType>> {
+    let params = signature.split_once('(')?.1.strip_suffix(')')?;
+    if params.is_empty() {
+        return Some(vec![]);
+    }
+    params
+        .split(',')
+        .map(|param| Reader::read(param).ok())
+        .collect()
+}
+
+fn format_token(token: Token) -> String {
+    match token {
+        Token::Address(address) => evm_util::to_checksum_address(&address.to_fixed_bytes()),
+        Token::Uint(value) => value.to_string(),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => format!("0x{}", hex::encode(bytes)),
+        Token::String(value) => format!("{:?}", value),
+        other => other.to_string(),
+    }
+}
+
+/// Decode the return data of a reverted call
+pub fn decode_revert(data: &[u8]) -> RevertReason {
+    let unknown = || RevertReason::Unknown {
+        data: format!("0x{}", hex::encode(data)),
+    };
+    if data.len() < 4 {
+        return unknown();
+    }
+    let (selector, payload) = data.split_at(4);
+
+    if selector == ERROR_STRING_SELECTOR {
+        if let Ok(Token::String(message)) = decode_single(ParamType::String, payload) {
+            return RevertReason::Error(message);
+        }
+    } else if selector == PANIC_SELECTOR {
+        if let Ok(Token::Uint(code)) = decode_single(ParamType::Uint(256), payload) {
+            let code = if code.bits() > 64 {
+                u64::MAX
+            } else {
+                code.low_u64()
+            };
+            return RevertReason::Panic {
+                code,
+                description: panic_description(code).to_string(),
+            };
+        }
+    } else if let Some(signature) = KNOWN_ERRORS
+        .iter()
+        .find(|signature| evm_util::function_selector(signature) == selector)
+    {
+        let decoded = param_types(signature).and_then(|types| ethabi::decode(&types, payload).ok());
+        if let Some(tokens) = decoded {
+            return RevertReason::Custom {
+                signature: signature.to_string(),
+                args: tokens.into_iter().map(format_token).collect(),
+            };
+        }
+    }
+
+    unknown()
+}
+
+fn decode_single(param: ParamType, payload: &[u8]) -> Result<Token, ethabi::Error> {
+    ethabi::decode(&[param], payload)?
+        .pop()
+        .ok_or(ethabi::Error::InvalidData)
+}
+
+/// Revert data carried by a JSON-RPC error, if it is a revert
+///
+/// Nodes put the return data in `error.data`, either as a hex string or (some
+/// providers) as an object with its own `data` field. Errors without data are
+/// treated as reverts when the message says so.
+fn revert_data(error: &Value) -> Option<Vec<u8>> {
+    let data = error.get("data").and_then(|data| {
+        data.as_str()
+            .or_else(|| data.get("data").and_then(Value::as_str))
+    });
+
+    if let Some(data) = data {
+        if let Ok(bytes) = hex::decode(data.trim_start_matches("0x")) {
+            return Some(bytes);
+        }
+    }
+
+    let message = error.get("message").and_then(Value::as_str).unwrap_or("");
+    message.contains("revert").then(Vec::new)
+}
+
+// ==============================================================================
+// Simulation
+// ==============================================================================
+
+/// Simulate a transaction with `eth_call` against the `pending` block
+///
+/// # Arguments
+/// * `chain_id` - Network to simulate on
+/// * `from` - Sending address
+/// * `to` - Recipient, or None for contract creation
+/// * `data` - Calldata / init code
+/// * `value` - Value in wei
+///
+/// # Returns
+/// * `Result<(), TxError>` - Ok if the call succeeds, `WouldRevert` with the
+///   decoded reason, or `Failed` if the simulation itself failed
+pub async fn simulate(
+    chain_id: u64,
+    from: &str,
+    to: Option<&[u8; 20]>,
+    data: &[u8],
+    value: u64,
+) -> Result<(), TxError> {
+    let outcome = rpc_util::call_raw(
+        chain_id,
+        "eth_call",
+        json!([fees::call_object(from, to, data, value), "pending"]),
+    )
+    .await
+    .map_err(TxError::Failed)?;
+
+    match outcome {
+        Ok(_) => Ok(()),
+        Err(error) => match revert_data(&error) {
+            Some(data) => {
+                let reason = decode_revert(&data);
+                ic_cdk::println!("      🧪 Simulation reverted: {}", reason);
+                Err(TxError::WouldRevert(reason))
+            }
+            None => Err(TxError::Failed(format!(
+                "Simulation failed: RPC error (eth_call): {}",
+                error
+            ))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revert(signature: &str, args: &[Token]) -> Vec<u8> {
+        let mut data = evm_util::function_selector(signature).to_vec();
+        data.extend_from_slice(&ethabi::encode(args));
+        data
+    }
+
+    #[test]
+    fn builtin_selectors_match_their_signatures() {
+        assert_eq!(evm_util::function_selector("Error(string)"), ERROR_STRING_SELECTOR);
+        assert_eq!(evm_util::function_selector("Panic(uint256)"), PANIC_SELECTOR);
+    }
+
+    #[test]
+    fn error_strings_and_panics_are_decoded() {
+        let data = revert("Error(string)", &[Token::String("Not owner".to_string())]);
+        assert_eq!(decode_revert(&data), RevertReason::Error("Not owner".to_string()));
+
+        let data = revert("Panic(uint256)", &[Token::Uint(0x11u64.into())]);
+        assert_eq!(
+            decode_revert(&data),
+            RevertReason::Panic {
+                code: 0x11,
+                description: "arithmetic overflow or underflow".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn known_custom_errors_are_decoded_with_their_arguments() {
+        let owner = [0xab; 20];
+        let data = revert(
+            "ERC721IncorrectOwner(address,uint256,address)",
+            &[
+                Token::Address(owner.into()),
+                Token::Uint(7u64.into()),
+                Token::Address(owner.into()),
+            ],
+        );
+        let reason = decode_revert(&data);
+        assert_eq!(
+            reason,
+            RevertReason::Custom {
+                signature: "ERC721IncorrectOwner(address,uint256,address)".to_string(),
+                args: vec![
+                    evm_util::to_checksum_address(&owner),
+                    "7".to_string(),
+                    evm_util::to_checksum_address(&owner),
+                ],
+            }
+        );
+        assert!(reason.to_string().starts_with("ERC721IncorrectOwner(0x"));
+
+        assert_eq!(
+            decode_revert(&evm_util::function_selector("SPGNFT__MintingDenied()")),
+            RevertReason::Custom {
+                signature: "SPGNFT__MintingDenied()".to_string(),
+                args: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn undecodable_data_is_kept_as_hex() {
+        assert_eq!(
+            decode_revert(&[0xde, 0xad]),
+            RevertReason::Unknown {
+                data: "0xdead".to_string()
+            }
+        );
+        assert!(param_types("Foo").is_none());
+        assert!(KNOWN_ERRORS.iter().all(|signature| param_types(signature).is_some()));
+    }
+
+    #[test]
+    fn revert_data_is_read_from_either_error_shape() {
+        assert_eq!(revert_data(&json!({ "data": "0x08c379a0" })), Some(vec![0x08, 0xc3, 0x79, 0xa0]));
+        assert_eq!(revert_data(&json!({ "data": { "data": "0x01" } })), Some(vec![0x01]));
+        assert_eq!(revert_data(&json!({ "message": "execution reverted" })), Some(vec![]));
+        assert_eq!(revert_data(&json!({ "message": "insufficient funds" })), None);
+    }
+}
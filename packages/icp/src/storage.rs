@@ -0,0 +1,335 @@
+// Stable Storage Module
+// Persists canister STATE and CONFIG across upgrades using versioned snapshots
+//
+// Layout: stable memory is split by a `MemoryManager`. Unbounded collections
+// (registry, jobs, transactions, quota usage, idempotency keys, royalties,
+// disputes) live in their own `StableBTreeMap`s and stay in place across
+// upgrades. The snapshot memory holds a length-prefixed candid tuple
+// `(schema_version, snapshot_json)` with everything else (config, settings,
+// counters). The snapshot is JSON so that new fields with `#[serde(default)]`
+// load cleanly from older snapshots, and structural changes can be migrated on
+// the untyped `serde_json::Value` before it is decoded into the current types.
+// Stable map values are JSON for the same reason.
+
+use crate::{CanisterConfig, State, CONFIG, STATE};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::writer::Writer;
+use ic_stable_structures::{DefaultMemoryImpl, Memory, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+// ==============================================================================
+// Schema Version
+// ==============================================================================
+
+/// Current schema version of the stable snapshot
+///
+/// Bump this and add a step to `migrate_snapshot` whenever a persisted type
+/// changes in a way that `#[serde(default)]` cannot absorb (renames, moves,
+/// type changes).
+pub const STABLE_SCHEMA_VERSION: u32 = 1;
+
+// ==============================================================================
+// Stable Memory Layout
+// ==============================================================================
+
+/// Virtual memory handed out by the memory manager
+pub type StableMemory = VirtualMemory<DefaultMemoryImpl>;
+
+/// Snapshot of the bounded state (see `save_to_stable_memory`)
+const SNAPSHOT_MEMORY_ID: MemoryId = MemoryId::new(0);
+
+// Collections stored outside the snapshot. IDs are permanent: never reuse or
+// renumber one, only append.
+pub const REGISTRY_RECORDS_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const REGISTRY_BY_ID_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const REGISTRY_BY_IP_ID_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const REGISTRY_BY_TOKEN_ID_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const REGISTRY_BY_CREATOR_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const JOBS_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const TX_RECORDS_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const TX_BY_HASH_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const QUOTA_USAGE_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const IDEMPOTENCY_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const ROYALTIES_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const DISPUTES_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const TX_TRACKED_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const TX_BY_NONCE_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const REGISTRY_BY_TX_HASH_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const OPEN_JOBS_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const JOBS_BY_STORY_TX_MEMORY_ID: MemoryId = MemoryId::new(17);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+/// Virtual memory with the given ID
+pub fn memory(id: MemoryId) -> StableMemory {
+    MEMORY_MANAGER.with(|manager| manager.borrow().get(id))
+}
+
+/// Stable map held in a `thread_local!`
+pub type StableMap<K, V> = RefCell<StableBTreeMap<K, V, StableMemory>>;
+
+/// Open the stable map kept in memory `id` (created empty on first use)
+pub fn stable_map<K: Storable + Ord + Clone, V: Storable>(id: MemoryId) -> StableMap<K, V> {
+    RefCell::new(StableBTreeMap::init(memory(id)))
+}
+
+/// Implement `Storable` for serde types by encoding them as JSON
+macro_rules! impl_json_storable {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl ic_stable_structures::Storable for $ty {
+                fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                    match serde_json::to_vec(self) {
+                        Ok(bytes) => std::borrow::Cow::Owned(bytes),
+                        Err(e) => ic_cdk::trap(&format!(
+                            "Failed to encode {}: {}",
+                            stringify!($ty),
+                            e
+                        )),
+                    }
+                }
+
+                fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                    match serde_json::from_slice(&bytes) {
+                        Ok(value) => value,
+                        Err(e) => ic_cdk::trap(&format!(
+                            "Failed to decode {}: {}",
+                            stringify!($ty),
+                            e
+                        )),
+                    }
+                }
+
+                const BOUND: ic_stable_structures::storable::Bound =
+                    ic_stable_structures::storable::Bound::Unbounded;
+            }
+        )+
+    };
+}
+pub(crate) use impl_json_storable;
+
+// ==============================================================================
+// Snapshot Types
+// ==============================================================================
+
+/// Borrowed view of the canister state used when saving (avoids cloning)
+#[derive(Serialize)]
+struct StableSnapshotRef<'a> {
+    config: &'a Option<CanisterConfig>,
+    state: &'a State,
+}
+
+/// Owned snapshot decoded on restore
+#[derive(Deserialize)]
+struct StableSnapshot {
+    #[serde(default)]
+    config: Option<CanisterConfig>,
+    #[serde(default)]
+    state: State,
+}
+
+// ==============================================================================
+// Save / Restore
+// ==============================================================================
+
+/// Serialize CONFIG and the bounded part of STATE into the snapshot memory
+///
+/// Called from `pre_upgrade`. The stable collections are already in stable
+/// memory and are not touched. Traps on failure so the upgrade is aborted
+/// instead of silently discarding state.
+pub fn save_to_stable_memory() {
+    let snapshot_bytes = CONFIG.with(|config| {
+        STATE.with(|state| {
+            let config = config.borrow();
+            let state = state.borrow();
+            serde_json::to_vec(&StableSnapshotRef {
+                config: &config,
+                state: &state,
+            })
+        })
+    });
+
+    let snapshot_bytes = match snapshot_bytes {
+        Ok(bytes) => bytes,
+        Err(e) => ic_cdk::trap(&format!("Failed to serialize stable snapshot: {}", e)),
+    };
+
+    ic_cdk::println!(
+        "💾 Saving stable snapshot (schema v{}, {} bytes)",
+        STABLE_SCHEMA_VERSION,
+        snapshot_bytes.len()
+    );
+
+    if let Err(e) = write_snapshot(STABLE_SCHEMA_VERSION, snapshot_bytes) {
+        ic_cdk::trap(&e);
+    }
+}
+
+/// Restore CONFIG and STATE from stable memory
+///
+/// Called from `post_upgrade`. Older snapshots are migrated to the current
+/// schema before being decoded.
+///
+/// # Returns
+/// * `Result<u32, String>` - Schema version found in stable memory, or error
+///   if there is no snapshot or it cannot be restored
+pub fn restore_from_stable_memory() -> Result<u32, String> {
+    let (schema_version, snapshot_bytes) =
+        read_snapshot()?.ok_or_else(|| "No stable snapshot found".to_string())?;
+
+    if schema_version > STABLE_SCHEMA_VERSION {
+        return Err(format!(
+            "Stable snapshot schema v{} is newer than supported v{} (downgrade not supported)",
+            schema_version, STABLE_SCHEMA_VERSION
+        ));
+    }
+
+    let raw: serde_json::Value = serde_json::from_slice(&snapshot_bytes)
+        .map_err(|e| format!("Failed to parse stable snapshot: {}", e))?;
+
+    if schema_version < STABLE_SCHEMA_VERSION {
+        ic_cdk::println!(
+            "   🔁 Migrating stable snapshot v{} -> v{}",
+            schema_version,
+            STABLE_SCHEMA_VERSION
+        );
+    }
+    let migrated = migrate_snapshot(schema_version, raw)?;
+
+    let snapshot: StableSnapshot = serde_json::from_value(migrated)
+        .map_err(|e| format!("Failed to decode stable snapshot: {}", e))?;
+
+    CONFIG.with(|c| *c.borrow_mut() = snapshot.config);
+    STATE.with(|s| *s.borrow_mut() = snapshot.state);
+
+    Ok(schema_version)
+}
+
+/// Write `(schema_version, snapshot_json)` to the snapshot memory,
+/// length-prefixed
+fn write_snapshot(schema_version: u32, snapshot_bytes: Vec<u8>) -> Result<(), String> {
+    let encoded = candid::encode_args((schema_version, snapshot_bytes))
+        .map_err(|e| format!("Failed to encode stable snapshot: {}", e))?;
+
+    let mut memory = memory(SNAPSHOT_MEMORY_ID);
+    let mut writer = Writer::new(&mut memory, 0);
+    writer
+        .write(&(encoded.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write(&encoded))
+        .map_err(|e| format!("Failed to write stable snapshot: {:?}", e))
+}
+
+/// `(schema_version, snapshot_json)` from the snapshot memory, if one was saved
+fn read_snapshot() -> Result<Option<(u32, Vec<u8>)>, String> {
+    let memory = memory(SNAPSHOT_MEMORY_ID);
+    if memory.size() == 0 {
+        return Ok(None);
+    }
+
+    let mut len = [0u8; 8];
+    memory.read(0, &mut len);
+    let mut encoded = vec![0u8; u64::from_le_bytes(len) as usize];
+    memory.read(len.len() as u64, &mut encoded);
+
+    candid::decode_args(&encoded)
+        .map(Some)
+        .map_err(|e| format!("Failed to read stable snapshot: {}", e))
+}
+
+// ==============================================================================
+// Migrations
+// ==============================================================================
+
+/// Migrate a raw snapshot from `from_version` up to `STABLE_SCHEMA_VERSION`
+///
+/// Each step transforms the JSON produced by version N into the layout
+/// expected by version N + 1.
+fn migrate_snapshot(
+    from_version: u32,
+    mut snapshot: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let mut version = from_version;
+
+    while version < STABLE_SCHEMA_VERSION {
+        snapshot = match version {
+            // v0 never reached stable memory (pre-persistence builds had no
+            // pre_upgrade hook), so there is nothing to transform.
+            0 => snapshot,
+            _ => return Err(format!("No migration defined from schema v{}", version)),
+        };
+
+        version += 1;
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use serde_json::json;
+
+    #[test]
+    fn a_saved_snapshot_is_restored() {
+        let owner = Principal::from_slice(&[1]);
+        let snapshot = json!({
+            "config": {
+                "deepseek_api_key": "key",
+                "replicate_api_key": null,
+                "constellation_metagraph_url": "http://localhost:9000"
+            },
+            "state": { "owner": owner, "nft_contract_address": "0xabc" }
+        });
+        write_snapshot(STABLE_SCHEMA_VERSION, serde_json::to_vec(&snapshot).unwrap()).unwrap();
+
+        assert_eq!(restore_from_stable_memory(), Ok(STABLE_SCHEMA_VERSION));
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.owner, owner);
+            assert_eq!(state.nft_contract_address.as_deref(), Some("0xabc"));
+        });
+        CONFIG.with(|config| {
+            assert_eq!(config.borrow().as_ref().unwrap().deepseek_api_key, "key");
+        });
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let snapshot: StableSnapshot = serde_json::from_value(json!({ "state": {} })).unwrap();
+
+        assert!(snapshot.config.is_none());
+        assert_eq!(snapshot.state.owner, Principal::anonymous());
+        assert_eq!(snapshot.state.registry.count(), 0);
+    }
+
+    #[test]
+    fn a_missing_snapshot_is_an_error() {
+        assert_eq!(
+            restore_from_stable_memory(),
+            Err("No stable snapshot found".to_string())
+        );
+    }
+
+    #[test]
+    fn newer_snapshots_are_rejected() {
+        write_snapshot(STABLE_SCHEMA_VERSION + 1, b"{}".to_vec()).unwrap();
+
+        let e = restore_from_stable_memory().unwrap_err();
+        assert!(e.contains("newer than supported"));
+    }
+
+    #[test]
+    fn pre_persistence_snapshots_pass_through() {
+        let snapshot = json!({ "state": { "nft_contract_address": null } });
+        assert_eq!(migrate_snapshot(0, snapshot.clone()).unwrap(), snapshot);
+        assert_eq!(
+            migrate_snapshot(STABLE_SCHEMA_VERSION, snapshot.clone()).unwrap(),
+            snapshot
+        );
+    }
+}
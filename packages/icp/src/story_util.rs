@@ -1,11 +1,15 @@
 // Story Protocol Integration Module
 // Handles IP registration, licensing, royalties, and disputes on Story Protocol
 
-use crate::config::{self, STORY_CHAIN_ID, STORY_RPC_URL};
-use crate::evm_util::{build_evm_transaction, build_signed_transaction, sign_evm_transaction};
-use crate::http_util::{json_header, make_http_request};
+use crate::derivatives::{self, ParentIp};
+use crate::eip712::SignatureData;
+use crate::evm_util::{self, EvmAccount};
+use crate::licensing::{self, LicenseChoice, PilTerms};
+use crate::networks::Network;
+use crate::transactions::{self, TxError, TxKind, TxReceipt};
+use crate::{events, rpc_util, tracker};
 use ethabi::{encode, Address, Token};
-use ic_cdk::api::management_canister::http_request::HttpMethod;
+use primitive_types::{H160, U256};
 use serde_json::json;
 use sha3::{Digest, Keccak256};
 use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
@@ -16,161 +20,177 @@ use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
 
 /// Register a new IP asset on Story Protocol
 ///
-/// This function:
-/// 1. Gets and increments the canister's nonce atomically
-/// 2. Gets the canister's EVM address
-/// 3. Builds the contract call data for registerRootIp
-/// 4. Creates and signs an EVM transaction using Chain-Key ECDSA
-/// 5. Broadcasts it to Story Protocol
-/// 6. Returns the transaction hash
+/// Mints an NFT from the network's SPG collection and registers it as an IP
+/// Asset in one workflow call:
+/// 1. `RegistrationWorkflows.mintAndRegisterIp` for a plain root IP
+/// 2. `LicenseAttachmentWorkflows.mintAndRegisterIpAndAttachPILTerms` when
+///    `license` is set
+/// 3. `DerivativeWorkflows.mintAndRegisterIpAndMakeDerivative` when `parent`
+///    is set
+///
+/// The call goes through `transactions::send` (simulation, nonce, fees,
+/// Chain-Key ECDSA signing, broadcast). The hash is returned as soon as the
+/// transaction is broadcast so the caller can persist it before awaiting
+/// anything else; `check_registration` follows it up.
 ///
 /// # Arguments
+/// * `network` - Network to register on
+/// * `account` - Account that signs (canister or caller)
+/// * `recipient` - Owner of the NFT and IP Account; None = the signing account
 /// * `content_hash` - The keccak256 hash of the content
 /// * `metadata_uri` - IPFS or HTTP URL pointing to metadata JSON
+/// * `license` - PIL terms to attach in the same transaction; None = no license
+/// * `parent` - Parent IP to register the work as a derivative of;
+///   derivatives inherit its terms, so this excludes `license`
 ///
 /// # Returns
-/// * `Result<String, String>` - Transaction hash or error
-/// Register IP using mintAndRegisterIp (Phase 4 - SPG NFT)
-/// Returns (tx_hash, Option<(ipId, tokenId)>)
-#[allow(dead_code)]
+/// * `Result<String, TxError>` - Transaction hash; `WouldRevert` if Story would
+///   reject the call (nothing is broadcast then)
 pub async fn register_ip_on_story(
+    network: &Network,
+    account: EvmAccount,
+    recipient: Option<&str>,
     content_hash: String,
     metadata_uri: String,
-) -> Result<(String, Option<(String, u64)>), String> {
-    ic_cdk::println!("   📜 Registering IP on Story Protocol...");
+    license: Option<&LicenseChoice>,
+    parent: Option<&ParentIp>,
+) -> Result<String, TxError> {
+    ic_cdk::println!("   📜 Registering IP on Story Protocol ({})...", network.name);
     ic_cdk::println!("      Content Hash: {}", content_hash);
     ic_cdk::println!("      Metadata URI: {}", metadata_uri);
 
-    // Step 1: Get fresh nonce from blockchain via RPC
-    // This ensures we always use the correct nonce even after canister reinstalls
-    let nonce = crate::get_nonce_from_blockchain().await?;
-    ic_cdk::println!("      Nonce (from blockchain): {}", nonce);
-
-    // Step 2: Get the canister's EVM address
-    let evm_address = crate::evm_util::get_canister_evm_address().await?;
-    ic_cdk::println!("      Canister EVM Address: {}", evm_address);
-
-    // Step 3: Build contract call data for mintAndRegisterIp
-    let call_data = build_mint_and_register_ip_calldata(metadata_uri, &evm_address)?;
-    ic_cdk::println!("      Call Data: {} bytes", call_data.len());
-
-    // Step 4: Build unsigned transaction (EIP-155 format)
-    let to = config::registration_workflows_address();
-    let to_bytes: [u8; 20] = to.to_fixed_bytes();
-
-    let unsigned_tx = build_evm_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0, // No value transfer
-        call_data.clone(),
-        STORY_CHAIN_ID,
-    );
-
-    ic_cdk::println!("      Unsigned TX: {} bytes", unsigned_tx.len());
-    ic_cdk::println!("      Unsigned TX hex: 0x{}", hex::encode(&unsigned_tx));
-
-    // Step 5: Hash the unsigned transaction for signing
-    let tx_hash = Keccak256::digest(&unsigned_tx);
-    let tx_hash_bytes = tx_hash.to_vec();
-
-    ic_cdk::println!("      TX Hash for signing: 0x{}", hex::encode(&tx_hash_bytes));
-
-    // Step 6: Sign the transaction using Chain-Key ECDSA
-    let signature = sign_evm_transaction(tx_hash_bytes.clone()).await?;
-
-    if signature.len() != 64 {
-        return Err(format!(
-            "Invalid signature length: {} (expected 64)",
-            signature.len()
-        ));
-    }
-
-    ic_cdk::println!("      Signature: {} bytes", signature.len());
-    ic_cdk::println!("         r: 0x{}", hex::encode(&signature[0..32]));
-    ic_cdk::println!("         s: 0x{}", hex::encode(&signature[32..64]));
-
-    // Step 7: Verify signature and determine recovery ID
-    ic_cdk::println!("      Verifying signature with IC public key...");
-
-    // Get the IC's public key that was used for signing
-    let ic_public_key = crate::evm_util::get_canister_public_key().await?;
-    ic_cdk::println!("      IC Public Key: {} bytes", ic_public_key.len());
-    ic_cdk::println!("      IC Public Key hex: 0x{}", hex::encode(&ic_public_key));
-
-    // First, verify the signature is valid for this public key
-    match verify_signature(&tx_hash_bytes, &signature, &ic_public_key) {
-        Ok(true) => ic_cdk::println!("      ✅ Signature is valid for IC public key"),
-        Ok(false) => ic_cdk::println!("      ⚠️  Signature verification FAILED!"),
-        Err(e) => ic_cdk::println!("      ⚠️  Signature verification error: {}", e),
-    }
-
-    let recovery_id = match determine_recovery_id_with_pubkey(&tx_hash_bytes, &signature, &ic_public_key) {
-        Ok(rid) => {
-            ic_cdk::println!("      ✅ Recovery ID: {}", rid);
-            rid
+    // Step 1: Get the signing account's EVM address
+    let evm_address = evm_util::get_evm_address(&account).await?;
+    ic_cdk::println!("      EVM Address: {} ({:?})", evm_address, account);
+
+    // Step 2: Build contract call data for mintAndRegisterIp, or for
+    // mintAndRegisterIpAndAttachPILTerms when a license is requested, or for
+    // mintAndRegisterIpAndMakeDerivative when there is a parent IP
+    let recipient = recipient.unwrap_or(&evm_address);
+    let (to, kind, call_data) = match (license, parent) {
+        (Some(_), Some(parent)) => {
+            return Err(format!(
+                "Derivatives of {} inherit its license terms; no license can be attached",
+                parent.ip_id
+            )
+            .into())
         }
-        Err(e) => {
-            ic_cdk::println!("      ⚠️  Could not determine recovery ID: {}", e);
-            ic_cdk::println!("      ⚠️  Trying both recovery IDs...");
-            // If recovery fails, try both and see which works
-            0u8
+        (None, None) => (
+            network.registration_workflows()?,
+            TxKind::MintAndRegisterIp,
+            build_mint_and_register_ip_calldata(
+                metadata_uri,
+                recipient,
+                network.spg_nft_contract()?,
+            )?,
+        ),
+        (None, Some(parent)) => {
+            ic_cdk::println!(
+                "      Parent IP: {} (terms #{})",
+                parent.ip_id,
+                parent.license_terms_id
+            );
+            (
+                network.derivative_workflows()?,
+                TxKind::MintAndRegisterIpAndMakeDerivative,
+                derivatives::build_mint_and_register_ip_and_make_derivative_calldata(
+                    network,
+                    parent,
+                    ip_metadata(metadata_uri),
+                    evm_util::parse_eip55_address(recipient)?,
+                )?,
+            )
+        }
+        (Some(choice), None) => {
+            ic_cdk::println!("      License: {:?}", choice);
+            let terms = PilTerms::from_choice(network, choice)?;
+            (
+                network.license_attachment_workflows()?,
+                TxKind::MintAndRegisterIpAndAttachPilTerms,
+                licensing::build_mint_and_register_ip_and_attach_pil_terms_calldata(
+                    network.spg_nft_contract()?,
+                    evm_util::parse_eip55_address(recipient)?,
+                    ip_metadata(metadata_uri),
+                    &terms,
+                ),
+            )
         }
     };
+    ic_cdk::println!("      Call Data: {} bytes", call_data.len());
 
-    let signed_tx = build_signed_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0,
+    // Step 3: Nonce, fee estimation, signing and broadcast
+    let tx_hash_result = transactions::send(
+        network,
+        account,
+        kind,
+        Some(to.to_fixed_bytes()),
         call_data,
-        &signature,
-        STORY_CHAIN_ID,
-        recovery_id,
-    );
-
-    // Step 8: Broadcast transaction to Story Protocol
-    let tx_hash_result = broadcast_transaction(signed_tx).await?;
+        0, // No value transfer
+    )
+    .await?;
 
     ic_cdk::println!("   ✅ NFT minted and IP registered! TX Hash: {}", tx_hash_result);
     ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/tx/{}", tx_hash_result);
+    ic_cdk::println!("      {}", network.tx_url(&tx_hash_result));
+
+    Ok(tx_hash_result)
+}
 
-    // Step 9: Get transaction receipt and parse return values
-    ic_cdk::println!("   ⏳ Waiting for transaction receipt...");
-    let parsed_values = match get_transaction_receipt(&tx_hash_result).await {
-        Ok(receipt) => {
+/// Check once for the receipt of a registration sent by `register_ip_on_story`
+///
+/// The receipt tracker fills in ipId / tokenId later if the transaction is
+/// not mined yet, so a pending transaction or an RPC failure is not an error.
+///
+/// # Arguments
+/// * `network` - Network the registration was sent on
+/// * `tx_hash` - Transaction hash returned by `register_ip_on_story`
+///
+/// # Returns
+/// * `Result<Option<(String, u64)>, String>` - (ipId, tokenId) if mined and
+///   parsed, None otherwise; error if the transaction reverted on-chain
+pub async fn check_registration(
+    network: &Network,
+    tx_hash: &str,
+) -> Result<Option<(String, u64)>, String> {
+    ic_cdk::println!("   ⏳ Checking for transaction receipt...");
+    match tracker::fetch_receipt(network.chain_id, tx_hash).await {
+        Ok(Some(receipt)) => {
             ic_cdk::println!("   ✅ Receipt obtained!");
-            ic_cdk::println!("      Status: {}", receipt.status);
+            ic_cdk::println!("      Success: {}", receipt.success);
             ic_cdk::println!("      Block Number: {}", receipt.block_number);
             ic_cdk::println!("      Gas Used: {}", receipt.gas_used);
 
-            // Parse the return values from logs
-            // mintAndRegisterIp returns (address ipId, uint256 tokenId)
-            // These should be in the receipt logs
-            match parse_mint_and_register_return_values(&receipt) {
+            if !receipt.success {
+                return Err(format!(
+                    "Registration transaction {} reverted in block {}",
+                    tx_hash, receipt.block_number
+                ));
+            }
+
+            // mintAndRegisterIp returns (address ipId, uint256 tokenId); both
+            // are parsed from the receipt logs
+            match parse_mint_and_register_return_values(&receipt, network) {
                 Some((ip_id, token_id)) => {
                     ic_cdk::println!("   📝 Parsed return values:");
                     ic_cdk::println!("      IP ID: {}", ip_id);
                     ic_cdk::println!("      Token ID: {}", token_id);
-                    Some((ip_id, token_id))
+                    Ok(Some((ip_id, token_id)))
                 }
                 None => {
                     ic_cdk::println!("   ⚠️  Could not parse return values from receipt");
-                    None
+                    Ok(None)
                 }
             }
         }
+        Ok(None) => {
+            ic_cdk::println!("   ℹ️  Transaction still pending; the receipt tracker will follow it");
+            Ok(None)
+        }
         Err(e) => {
             ic_cdk::println!("   ⚠️  Could not get receipt: {}", e);
-            ic_cdk::println!("   ℹ️  Transaction may still be pending");
-            None
+            Ok(None)
         }
-    };
-
-    Ok((tx_hash_result, parsed_values))
+    }
 }
 
 // ==============================================================================
@@ -186,8 +206,7 @@ pub async fn register_ip_on_story(
 ///
 /// # Returns
 /// * `Result<bool, String>` - true if signature is valid, false otherwise
-/// Verify ECDSA signature (kept for future use)
-#[allow(dead_code)]
+#[allow(dead_code)] // Kept for future use
 fn verify_signature(
     message_hash: &[u8],
     signature: &[u8],
@@ -331,6 +350,41 @@ pub fn determine_recovery_id_with_pubkey(
     ))
 }
 
+/// Recover the address that produced an Ethereum signature
+///
+/// # Arguments
+/// * `message_hash` - The hash that was signed (32 bytes)
+/// * `signature` - r ‖ s ‖ v (65 bytes); v may be 0/1 or 27/28
+///
+/// # Returns
+/// * `Result<String, String>` - Lowercase 0x-prefixed signer address or error
+pub fn recover_signer_address(message_hash: &[u8], signature: &[u8]) -> Result<String, String> {
+    if message_hash.len() != 32 {
+        return Err(format!("Invalid message hash length: {}", message_hash.len()));
+    }
+
+    if signature.len() != 65 {
+        return Err(format!("Invalid signature length: {} (expected 65)", signature.len()));
+    }
+
+    let v = match signature[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        v => return Err(format!("Invalid signature v value: {}", v)),
+    };
+
+    let rid = RecoveryId::try_from(v).map_err(|e| format!("Invalid recovery ID: {:?}", e))?;
+    let sig = Signature::try_from(&signature[..64])
+        .map_err(|e| format!("Failed to parse signature: {:?}", e))?;
+
+    let recovered_key = VerifyingKey::recover_from_prehash(message_hash, &sig, rid)
+        .map_err(|e| format!("Failed to recover key: {:?}", e))?;
+
+    let recovered_key_point = recovered_key.to_encoded_point(false);
+    let recovered_hash = Keccak256::digest(&recovered_key_point.as_bytes()[1..]);
+    Ok(format!("0x{}", hex::encode(&recovered_hash[12..])))
+}
+
 // ==============================================================================
 // ABI Encoding for Story Protocol Contracts
 // ==============================================================================
@@ -350,12 +404,16 @@ pub fn determine_recovery_id_with_pubkey(
 /// # Arguments
 /// * `metadata_uri` - IPFS or HTTP URL to IP metadata JSON
 /// * `recipient` - EVM address of the IP owner (canister address)
+/// * `spg_nft_contract` - SPG NFT collection to mint from
 ///
 /// # Returns
 /// * `Result<Vec<u8>, String>` - ABI-encoded calldata or error
-/// Build calldata for mintAndRegisterIp (Phase 4 - SPG NFT)
 #[allow(dead_code)]
-fn build_mint_and_register_ip_calldata(metadata_uri: String, recipient: &str) -> Result<Vec<u8>, String> {
+fn build_mint_and_register_ip_calldata(
+    metadata_uri: String,
+    recipient: &str,
+    spg_nft_contract: primitive_types::H160,
+) -> Result<Vec<u8>, String> {
     // Parse recipient address from hex string
     let recipient_hex = recipient.trim_start_matches("0x");
     let recipient_bytes = hex::decode(recipient_hex)
@@ -369,38 +427,19 @@ fn build_mint_and_register_ip_calldata(metadata_uri: String, recipient: &str) ->
     recipient_addr_array.copy_from_slice(&recipient_bytes);
     let recipient_address = Address::from(recipient_addr_array);
 
-    // Get SPG NFT Contract address
-    let spg_nft_contract = config::spg_nft_contract_address();
+    // SPG NFT Contract address
     let spg_nft_bytes: [u8; 20] = spg_nft_contract.to_fixed_bytes();
     let spg_nft_address = Address::from(spg_nft_bytes);
 
-    // Calculate metadata hash (simple keccak256 of the URI for now)
-    let metadata_hash = Keccak256::digest(metadata_uri.as_bytes());
-    let mut metadata_hash_array = [0u8; 32];
-    metadata_hash_array.copy_from_slice(&metadata_hash);
-
-    // For NFT metadata, we'll use the same URI and hash
-    let nft_metadata_uri = metadata_uri.clone();
-    let nft_metadata_hash = metadata_hash_array;
-
     // Function selector for mintAndRegisterIp(address,address,(string,bytes32,string,bytes32))
     // keccak256("mintAndRegisterIp(address,address,(string,bytes32,string,bytes32))") = 0xa392aa86
     let function_selector = [0xa3, 0x92, 0xaa, 0x86];
 
-    // Encode the IPMetadata struct as a tuple
-    // The struct becomes a tuple: (string, bytes32, string, bytes32)
-    let ip_metadata_tuple = Token::Tuple(vec![
-        Token::String(metadata_uri),
-        Token::FixedBytes(metadata_hash_array.to_vec()),
-        Token::String(nft_metadata_uri),
-        Token::FixedBytes(nft_metadata_hash.to_vec()),
-    ]);
-
     // Encode all parameters
     let tokens = vec![
         Token::Address(spg_nft_address),
         Token::Address(recipient_address),
-        ip_metadata_tuple,
+        ip_metadata(metadata_uri),
     ];
 
     let encoded_params = encode(&tokens);
@@ -417,77 +456,19 @@ fn build_mint_and_register_ip_calldata(metadata_uri: String, recipient: &str) ->
     Ok(calldata)
 }
 
-// ==============================================================================
-// Story Protocol RPC Helpers
-// ==============================================================================
-
-/// Broadcast a signed transaction to Story Protocol
-///
-/// # Arguments
-/// * `signed_tx` - RLP-encoded signed transaction
+/// Encode the IPMetadata struct as a tuple: (string, bytes32, string, bytes32)
 ///
-/// # Returns
-/// * `Result<String, String>` - Transaction hash or error
-async fn broadcast_transaction(signed_tx: Vec<u8>) -> Result<String, String> {
-    ic_cdk::println!("      Broadcasting transaction to Story RPC...");
-
-    // Convert transaction to hex
-    let tx_hex = format!("0x{}", hex::encode(&signed_tx));
-
-    let payload = json!({
-        "jsonrpc": "2.0",
-        "method": "eth_sendRawTransaction",
-        "params": [tx_hex],
-        "id": 1
-    });
-
-    let headers = vec![json_header()];
-
-    let response_body = make_http_request(
-        STORY_RPC_URL.to_string(),
-        HttpMethod::POST,
-        headers,
-        Some(payload.to_string().into_bytes()),
-    )
-    .await?;
-
-    let response_str = String::from_utf8(response_body)
-        .map_err(|e| format!("Failed to parse response as UTF-8: {}", e))?;
-
-    let response_json: serde_json::Value = serde_json::from_str(&response_str)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-
-    // Check for error
-    if let Some(error) = response_json.get("error") {
-        return Err(format!("RPC error: {}", error));
-    }
-
-    let tx_hash = response_json["result"]
-        .as_str()
-        .ok_or("No result in response")?
-        .to_string();
-
-    Ok(tx_hash)
-}
-
-// ==============================================================================
-// Attach License (Phase 2.5 - Optional)
-// ==============================================================================
-
-#[allow(dead_code)]
-pub async fn attach_license_stub() -> Result<String, String> {
-    ic_cdk::println!("   ⚠️  [STUB] License attachment - Phase 2.5");
-    Ok("LICENSE_ATTACHED_STUB".to_string())
-}
-
-// ==============================================================================
-// Raise Dispute (Phase 5)
-// ==============================================================================
-
-#[allow(dead_code)]
-pub async fn raise_dispute_stub() -> Result<String, String> {
-    ic_cdk::println!("   ⚠️  [STUB] Dispute filing - Phase 5");
-    Ok("DISPUTE_ID_STUB".to_string())
+/// The IP and NFT metadata share the URI and its hash (a simple keccak256 of
+/// the URI for now).
+fn ip_metadata(metadata_uri: String) -> Token {
+    let metadata_hash = Keccak256::digest(metadata_uri.as_bytes()).to_vec();
+
+    Token::Tuple(vec![
+        Token::String(metadata_uri.clone()),
+        Token::FixedBytes(metadata_hash.clone()),
+        Token::String(metadata_uri),
+        Token::FixedBytes(metadata_hash),
+    ])
 }
 
 // ==============================================================================
@@ -497,15 +478,19 @@ pub async fn raise_dispute_stub() -> Result<String, String> {
 /// Register an existing NFT as an IP Asset on Story Protocol
 ///
 /// This function uses IPAssetRegistry.register() which is permissionless.
-/// The NFT must already be minted and owned by the canister.
+/// The NFT must already be minted.
 ///
 /// # Arguments
+/// * `network` - Network the NFT lives on (and is registered on)
+/// * `account` - Account that signs the registration
 /// * `nft_contract_address` - The address of the NFT contract
 /// * `token_id` - The token ID to register
 ///
 /// # Returns
 /// * `Result<String, String>` - The IP Asset ID or error
 pub async fn register_nft_as_ip(
+    network: &Network,
+    account: EvmAccount,
     nft_contract_address: String,
     token_id: u64,
 ) -> Result<String, String> {
@@ -513,89 +498,29 @@ pub async fn register_nft_as_ip(
     ic_cdk::println!("      NFT Contract: {}", nft_contract_address);
     ic_cdk::println!("      Token ID: {}", token_id);
 
-    // Get fresh nonce from blockchain via RPC
-    let nonce = crate::get_nonce_from_blockchain().await?;
-    ic_cdk::println!("      Nonce (from blockchain): {}", nonce);
-
-    // Get the canister's EVM address
-    let evm_address = crate::evm_util::get_canister_evm_address().await?;
-    ic_cdk::println!("      Canister EVM Address: {}", evm_address);
-
     // Build contract call data for IPAssetRegistry.register(chainId, tokenContract, tokenId)
     let call_data = build_ip_asset_registry_register_calldata(
-        STORY_CHAIN_ID,
+        network.chain_id,
         &nft_contract_address,
         token_id,
     )?;
     ic_cdk::println!("      Call Data: {} bytes", call_data.len());
 
-    // Build unsigned transaction (EIP-155 format)
-    let to = config::ip_asset_registry_address();
-    let to_bytes: [u8; 20] = to.to_fixed_bytes();
-
-    let unsigned_tx = build_evm_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0, // No value transfer
-        call_data.clone(),
-        STORY_CHAIN_ID,
-    );
-
-    ic_cdk::println!("      Unsigned TX: {} bytes", unsigned_tx.len());
-
-    // Hash the unsigned transaction for signing
-    let tx_hash = Keccak256::digest(&unsigned_tx);
-    let tx_hash_bytes = tx_hash.to_vec();
-
-    ic_cdk::println!("      TX Hash for signing: 0x{}", hex::encode(&tx_hash_bytes));
-
-    // Sign the transaction using Chain-Key ECDSA
-    let signature = sign_evm_transaction(tx_hash_bytes.clone()).await?;
-
-    if signature.len() != 64 {
-        return Err(format!(
-            "Invalid signature length: {} (expected 64)",
-            signature.len()
-        ));
-    }
-
-    ic_cdk::println!("      Signature: {} bytes", signature.len());
-
-    // Get the IC's public key for recovery ID determination
-    let ic_public_key = crate::evm_util::get_canister_public_key().await?;
-
-    // Determine recovery ID
-    let recovery_id = match determine_recovery_id_with_pubkey(&tx_hash_bytes, &signature, &ic_public_key) {
-        Ok(rid) => {
-            ic_cdk::println!("      ✅ Recovery ID: {}", rid);
-            rid
-        }
-        Err(e) => {
-            ic_cdk::println!("      ⚠️  Could not determine recovery ID: {}", e);
-            0u8
-        }
-    };
-
-    let signed_tx = build_signed_transaction(
-        nonce,
-        config::GAS_PRICE,
-        config::GAS_LIMIT,
-        &to_bytes,
-        0,
+    // Sign and broadcast with estimated gas and fees
+    let to = network.ip_asset_registry()?;
+    let tx_hash_result = transactions::send(
+        network,
+        account,
+        TxKind::RegisterNftAsIp,
+        Some(to.to_fixed_bytes()),
         call_data,
-        &signature,
-        STORY_CHAIN_ID,
-        recovery_id,
-    );
-
-    // Broadcast transaction to Story Protocol
-    let tx_hash_result = broadcast_transaction(signed_tx).await?;
+        0, // No value transfer
+    )
+    .await?;
 
     ic_cdk::println!("   ✅ IP Asset registered! TX Hash: {}", tx_hash_result);
     ic_cdk::println!("   🔍 View on Story Explorer:");
-    ic_cdk::println!("      https://aeneid.storyscan.io/tx/{}", tx_hash_result);
+    ic_cdk::println!("      {}", network.tx_url(&tx_hash_result));
     ic_cdk::println!("   💡 Note: Transaction returns ipId on success");
 
     // TODO: In production, we should wait for receipt and extract the ipId from logs
@@ -625,7 +550,7 @@ fn build_ip_asset_registry_register_calldata(
     // Encode parameters
     let params = ethabi::encode(&[
         ethabi::Token::Uint(primitive_types::U256::from(chain_id)),
-        ethabi::Token::Address(token_contract_address.into()),
+        ethabi::Token::Address(token_contract_address),
         ethabi::Token::Uint(primitive_types::U256::from(token_id)),
     ]);
 
@@ -637,181 +562,218 @@ fn build_ip_asset_registry_register_calldata(
 }
 
 // ==============================================================================
-// Transaction Receipt Parsing
+// IP Asset Transfer (ERC-721)
 // ==============================================================================
 
-/// Structure to hold transaction receipt data
-#[derive(Debug)]
-pub struct TransactionReceipt {
-    pub status: String,
-    pub block_number: String,
-    pub gas_used: String,
-    pub logs: Vec<serde_json::Value>,
+/// Current owner of an ERC-721 token (`ownerOf(uint256)`), lowercase
+pub async fn nft_owner_of(
+    network: &Network,
+    nft_contract: H160,
+    token_id: u64,
+) -> Result<String, String> {
+    // keccak256("ownerOf(uint256)") = 0x6352211e
+    let mut call_data = vec![0x63, 0x52, 0x21, 0x1e];
+    call_data.extend_from_slice(&encode(&[Token::Uint(U256::from(token_id))]));
+
+    let result = rpc_util::call(
+        network.chain_id,
+        "eth_call",
+        json!([
+            {
+                "to": format!("0x{}", hex::encode(nft_contract.to_fixed_bytes())),
+                "data": format!("0x{}", hex::encode(&call_data)),
+            },
+            "latest"
+        ]),
+    )
+    .await?;
+
+    let word = result
+        .as_str()
+        .ok_or_else(|| format!("Unexpected ownerOf result: {}", result))?
+        .trim_start_matches("0x");
+    if word.len() != 64 {
+        return Err(format!("Unexpected ownerOf result: 0x{}", word));
+    }
+    Ok(format!("0x{}", &word[24..]).to_lowercase())
+}
+
+/// Build calldata for ERC-721 safeTransferFrom(address,address,uint256)
+fn build_safe_transfer_from_calldata(from: [u8; 20], to: [u8; 20], token_id: u64) -> Vec<u8> {
+    // keccak256("safeTransferFrom(address,address,uint256)") = 0x42842e0e
+    let mut calldata = vec![0x42, 0x84, 0x2e, 0x0e];
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(Address::from(from)),
+        Token::Address(Address::from(to)),
+        Token::Uint(U256::from(token_id)),
+    ]));
+    calldata
 }
 
-/// Get transaction receipt from Story Protocol RPC
+/// Transfer the NFT behind an IP Asset; ownership of the IP Account follows
+/// the NFT
 ///
 /// # Arguments
-/// * `tx_hash` - Transaction hash (with 0x prefix)
+/// * `network` - Network the NFT lives on
+/// * `account` - Account that currently holds the NFT and signs
+/// * `nft_contract` - ERC-721 contract
+/// * `token_id` - Token to transfer
+/// * `to` - Validated recipient address
 ///
 /// # Returns
-/// * `Result<TransactionReceipt, String>` - Receipt or error
-async fn get_transaction_receipt(tx_hash: &str) -> Result<TransactionReceipt, String> {
-    let payload = json!({
-        "jsonrpc": "2.0",
-        "method": "eth_getTransactionReceipt",
-        "params": [tx_hash],
-        "id": 1
-    });
-
-    let headers = vec![json_header()];
-
-    let response_body = make_http_request(
-        STORY_RPC_URL.to_string(),
-        HttpMethod::POST,
-        headers,
-        Some(payload.to_string().into_bytes()),
+/// * `Result<String, String>` - Transaction hash or error
+pub async fn transfer_ip_nft(
+    network: &Network,
+    account: EvmAccount,
+    nft_contract: H160,
+    token_id: u64,
+    to: [u8; 20],
+) -> Result<String, String> {
+    let from = evm_util::get_evm_address(&account).await?;
+    let from_bytes: [u8; 20] = hex::decode(from.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid sender address: {}", e))?
+        .try_into()
+        .map_err(|_| format!("Invalid sender address: {}", from))?;
+
+    ic_cdk::println!(
+        "   📦 Transferring token #{} of 0x{} from {} to {}",
+        token_id,
+        hex::encode(nft_contract.to_fixed_bytes()),
+        from,
+        evm_util::to_checksum_address(&to)
+    );
+
+    let tx_hash = transactions::send(
+        network,
+        account,
+        TxKind::TransferIpAsset,
+        Some(nft_contract.to_fixed_bytes()),
+        build_safe_transfer_from_calldata(from_bytes, to, token_id),
+        0, // No value transfer
     )
     .await?;
 
-    let response_str = String::from_utf8(response_body)
-        .map_err(|e| format!("Failed to parse response as UTF-8: {}", e))?;
+    ic_cdk::println!("   ✅ Transfer sent: {}", network.tx_url(&tx_hash));
+    Ok(tx_hash)
+}
+
+// ==============================================================================
+// IP Account Permissions (WithSignature flows)
+// ==============================================================================
 
-    let response_json: serde_json::Value = serde_json::from_str(&response_str)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+/// Highest AccessController permission value (0 = abstain, 1 = allow, 2 = deny)
+pub const PERMISSION_DENY: u8 = 2;
 
-    // Check for error
-    if let Some(error) = response_json.get("error") {
-        return Err(format!("RPC error: {}", error));
+/// Build calldata for AccessController.setPermission(address,address,address,bytes4,uint8)
+///
+/// Grants `signer` `permission` to call `func` on `to` for `ip_account`
+/// (`to` = zero address and `func` = 0x00000000 act as wildcards).
+pub fn build_set_permission_calldata(
+    ip_account: H160,
+    signer: [u8; 20],
+    to: [u8; 20],
+    func: [u8; 4],
+    permission: u8,
+) -> Result<Vec<u8>, String> {
+    if permission > PERMISSION_DENY {
+        return Err(format!(
+            "Invalid permission {} (0 = abstain, 1 = allow, 2 = deny)",
+            permission
+        ));
     }
 
-    let result = response_json
-        .get("result")
-        .ok_or("No result in response")?;
+    let mut calldata =
+        evm_util::function_selector("setPermission(address,address,address,bytes4,uint8)").to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(Address::from(ip_account.to_fixed_bytes())),
+        Token::Address(Address::from(signer)),
+        Token::Address(Address::from(to)),
+        Token::FixedBytes(func.to_vec()),
+        Token::Uint(U256::from(permission)),
+    ]));
+    Ok(calldata)
+}
 
-    // If result is null, transaction is still pending
-    if result.is_null() {
-        return Err("Transaction pending".to_string());
+/// Relay a call an IP Account owner authorized with an EIP-712 signature
+///
+/// Sends IPAccount.executeWithSig(to, 0, data, signer, deadline, signature)
+/// from the canister's address; the IP Account checks the signature.
+///
+/// # Returns
+/// * `Result<String, String>` - Transaction hash or error
+pub async fn execute_with_sig(
+    network: &Network,
+    ip_account: H160,
+    to: [u8; 20],
+    data: Vec<u8>,
+    signature: &SignatureData,
+) -> Result<String, String> {
+    let signer = evm_util::parse_eip55_address(&signature.signer)?;
+    let signature_bytes = hex::decode(signature.signature.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid signature hex: {}", e))?;
+    if signature_bytes.len() != 65 {
+        return Err(format!(
+            "Invalid signature length: {} (expected 65)",
+            signature_bytes.len()
+        ));
     }
 
-    // Extract receipt fields
-    let status = result
-        .get("status")
-        .and_then(|s| s.as_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    let block_number = result
-        .get("blockNumber")
-        .and_then(|s| s.as_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    let gas_used = result
-        .get("gasUsed")
-        .and_then(|s| s.as_str())
-        .unwrap_or("unknown")
-        .to_string();
-
-    let logs = result
-        .get("logs")
-        .and_then(|l| l.as_array())
-        .map(|arr| arr.clone())
-        .unwrap_or_default();
-
-    Ok(TransactionReceipt {
-        status,
-        block_number,
-        gas_used,
-        logs,
-    })
+    let mut calldata = evm_util::function_selector(
+        "executeWithSig(address,uint256,bytes,address,uint256,bytes)",
+    )
+    .to_vec();
+    calldata.extend_from_slice(&encode(&[
+        Token::Address(Address::from(to)),
+        Token::Uint(U256::zero()),
+        Token::Bytes(data),
+        Token::Address(Address::from(signer)),
+        Token::Uint(U256::from(signature.deadline)),
+        Token::Bytes(signature_bytes),
+    ]));
+
+    let tx_hash = transactions::send(
+        network,
+        EvmAccount::Canister,
+        TxKind::ExecuteWithSig,
+        Some(ip_account.to_fixed_bytes()),
+        calldata,
+        0, // No value transfer
+    )
+    .await?;
+
+    ic_cdk::println!("   ✅ executeWithSig relayed: {}", network.tx_url(&tx_hash));
+    Ok(tx_hash)
 }
 
+// ==============================================================================
+// Transaction Receipt Parsing
+// ==============================================================================
+
 /// Parse ipId and tokenId from mintAndRegisterIp transaction receipt
 ///
-/// The function returns (address ipId, uint256 tokenId) which should be
-/// encoded in the logs. We look for specific event signatures.
+/// Both come from the `IPRegistered` event the network's IPAssetRegistry
+/// emits for a token of the network's SPG NFT collection (see `events`).
 ///
 /// # Arguments
 /// * `receipt` - The transaction receipt
+/// * `network` - Network the transaction was sent on (for the contract addresses)
 ///
 /// # Returns
-/// * `Option<(String, u64)>` - (ipId, tokenId) or None if parsing fails
-fn parse_mint_and_register_return_values(receipt: &TransactionReceipt) -> Option<(String, u64)> {
-    // Look for IPRegistered event or Transfer events
-    // IPRegistered(address indexed ipId, uint256 indexed chainId, address indexed tokenContract, uint256 tokenId, ...)
-    // Transfer(address indexed from, address indexed to, uint256 indexed tokenId)
-
-    // For SPG, we need to find:
-    // 1. Transfer event from SPG NFT contract to get tokenId
-    // 2. IPRegistered event to get ipId
-
-    let mut token_id: Option<u64> = None;
-    let mut ip_id: Option<String> = None;
-
-    // SPG NFT contract address
-    let spg_nft_address = config::spg_nft_contract_address();
-    let spg_nft_hex = format!("0x{}", hex::encode(spg_nft_address.to_fixed_bytes())).to_lowercase();
-
-    for log in &receipt.logs {
-        let address = log
-            .get("address")
-            .and_then(|a| a.as_str())
-            .unwrap_or("")
-            .to_lowercase();
-
-        let topics = log
-            .get("topics")
-            .and_then(|t| t.as_array())
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or_default();
-
-        // Check if this is a Transfer event from SPG NFT contract
-        // Transfer event signature: keccak256("Transfer(address,address,uint256)") = 0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef
-        if address == spg_nft_hex && !topics.is_empty() {
-            let event_sig = &topics[0];
-            if event_sig == "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef" {
-                // Topic 3 is the tokenId (indexed)
-                if topics.len() >= 4 {
-                    let token_id_hex = topics[3].trim_start_matches("0x");
-                    if let Ok(id) = u64::from_str_radix(token_id_hex, 16) {
-                        token_id = Some(id);
-                        ic_cdk::println!("      Found Transfer event: tokenId = {}", id);
-                    }
-                }
-            }
-        }
-
-        // Check for IPRegistered event
-        // IPRegistered event signature: keccak256("IPRegistered(address,uint256,address,uint256,...)")
-        // Topic 1 is the ipId (indexed)
-        if !topics.is_empty() {
-            let event_sig = &topics[0];
-            // IPRegistered signature may vary, but ipId is typically in topic[1]
-            if event_sig.starts_with("0x") && topics.len() >= 2 {
-                // Try to extract address from topic[1]
-                let potential_ip_id = &topics[1];
-                // Convert indexed address (32 bytes) to address (20 bytes)
-                if potential_ip_id.len() == 66 {
-                    // Take last 40 chars (20 bytes)
-                    let addr = format!("0x{}", &potential_ip_id[26..]);
-                    // Validate it looks like an address
-                    if addr.len() == 42 {
-                        ip_id = Some(addr.clone());
-                        ic_cdk::println!("      Found potential ipId in logs: {}", addr);
-                    }
-                }
-            }
-        }
-    }
+/// * `Option<(String, u64)>` - (ipId, tokenId) or None if the event is missing
+pub fn parse_mint_and_register_return_values(
+    receipt: &TxReceipt,
+    network: &Network,
+) -> Option<(String, u64)> {
+    let registered = events::find_ip_registered(
+        receipt,
+        network.ip_asset_registry().ok()?,
+        network.spg_nft_contract().ok()?,
+    )?;
 
-    // Return both if found
-    match (ip_id, token_id) {
-        (Some(id), Some(tid)) => Some((id, tid)),
-        _ => None,
-    }
+    ic_cdk::println!(
+        "      Found IPRegistered event: ipId = {}, tokenId = {}",
+        registered.ip_id,
+        registered.token_id
+    );
+    Some((registered.ip_id, registered.token_id))
 }
@@ -0,0 +1,468 @@
+// Receipt Tracker Module
+// Timer-driven lifecycle tracking of canister-sent transactions
+//
+// Every tracked transaction (Pending, Replaced or Mined) has its receipt polled
+// until it is final: Confirmed or Failed once `confirmation_depth` blocks are
+// reached, Dropped when another transaction consumed its nonce, or TimedOut.
+// A receipt that disappears (reorg) sends the transaction back to polling.
+// When a transaction is final its effects are applied to the rest of the
+// canister state (provenance registry, jobs, NFT contract address).
+
+use crate::transactions::{TxKind, TxReceipt, TxRecord, TxStatus};
+use crate::{
+    config, disputes, events, jobs, networks, nonce, royalties, rpc_util, story_util, STATE,
+};
+use serde_json::json;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+thread_local! {
+    /// Set while a polling pass is running so that passes never overlap
+    static POLLING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Clears `POLLING` on drop, including when the call context traps
+struct PollGuard;
+
+impl PollGuard {
+    fn acquire() -> Option<Self> {
+        POLLING.with(|polling| {
+            if polling.replace(true) {
+                None
+            } else {
+                Some(PollGuard)
+            }
+        })
+    }
+}
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        POLLING.with(|polling| polling.set(false));
+    }
+}
+
+// ==============================================================================
+// RPC Queries
+// ==============================================================================
+
+/// Fetch the receipt of `hash` on `chain_id` (None while not mined)
+pub async fn fetch_receipt(chain_id: u64, hash: &str) -> Result<Option<TxReceipt>, String> {
+    let result = rpc_util::call(chain_id, "eth_getTransactionReceipt", json!([hash])).await?;
+    if result.is_null() {
+        return Ok(None);
+    }
+    TxReceipt::from_json(&result).map(Some)
+}
+
+async fn fetch_block_number(chain_id: u64) -> Result<u64, String> {
+    let result = rpc_util::call(chain_id, "eth_blockNumber", json!([])).await?;
+    rpc_util::parse_quantity(&result)
+}
+
+// ==============================================================================
+// Polling
+// ==============================================================================
+
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config::TX_RECEIPT_POLL_INTERVAL_SECS),
+        || ic_cdk::spawn(poll()),
+    );
+}
+
+fn set_status(hash: &str, status: TxStatus) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        state.borrow_mut().transactions.update(hash, |record| {
+            record.status = status;
+            if !status.is_tracked() {
+                record.finalized_at = Some(now);
+            }
+        })
+    });
+}
+
+/// Status a tracked transaction returns to when its receipt disappears
+fn unmined_status(record: &TxRecord) -> TxStatus {
+    if record.replaced_by.is_some() {
+        TxStatus::Replaced
+    } else {
+        TxStatus::Pending
+    }
+}
+
+/// Blocks since (and including) the receipt's block
+///
+/// A head behind the receipt (load-balanced RPC nodes) still counts as one.
+fn confirmations(head: u64, block_number: u64) -> u64 {
+    head.saturating_sub(block_number) + 1
+}
+
+/// One pass over all tracked transactions
+async fn poll() {
+    let Some(_guard) = PollGuard::acquire() else {
+        return;
+    };
+
+    let (settings, tracked) = STATE.with(|state| {
+        let state = state.borrow();
+        (
+            state.transactions.tracking_settings(),
+            state.transactions.tracked(),
+        )
+    });
+
+    if tracked.is_empty() {
+        return;
+    }
+
+    // Chain head per network, fetched once per pass (None if unreachable)
+    let mut heads: BTreeMap<u64, Option<u64>> = BTreeMap::new();
+    let mut unmined = Vec::new();
+
+    for record in tracked {
+        let head = match heads.get(&record.chain_id) {
+            Some(head) => *head,
+            None => {
+                let head = match fetch_block_number(record.chain_id).await {
+                    Ok(head) => Some(head),
+                    Err(e) => {
+                        ic_cdk::println!("⚠️  Receipt tracker (chain {}): {}", record.chain_id, e);
+                        None
+                    }
+                };
+                heads.insert(record.chain_id, head);
+                head
+            }
+        };
+        let Some(head) = head else {
+            continue;
+        };
+
+        let receipt = match fetch_receipt(record.chain_id, &record.hash).await {
+            Ok(receipt) => receipt,
+            Err(e) => {
+                ic_cdk::println!("⚠️  Receipt tracker ({}): {}", record.hash, e);
+                continue;
+            }
+        };
+
+        match receipt {
+            Some(receipt) => {
+                let confirmations = confirmations(head, receipt.block_number);
+                let final_status = (confirmations >= settings.confirmation_depth).then_some(
+                    if receipt.success {
+                        TxStatus::Confirmed
+                    } else {
+                        TxStatus::Failed
+                    },
+                );
+
+                STATE.with(|state| {
+                    state.borrow_mut().transactions.update(&record.hash, |r| {
+                        r.receipt = Some(receipt);
+                        r.confirmations = confirmations;
+                        r.status = TxStatus::Mined;
+                    })
+                });
+
+                if let Some(status) = final_status {
+                    finalize(&record, status);
+                }
+            }
+            None => {
+                if record.status == TxStatus::Mined {
+                    ic_cdk::println!("   ⚠️  Receipt of {} disappeared (reorg)", record.hash);
+                    STATE.with(|state| {
+                        state.borrow_mut().transactions.update(&record.hash, |r| {
+                            r.receipt = None;
+                            r.confirmations = 0;
+                            r.status = unmined_status(r);
+                        })
+                    });
+                }
+                unmined.push(record);
+            }
+        }
+    }
+
+    detect_dropped_and_timed_out(unmined, settings.timeout_secs).await;
+}
+
+/// Mark a mined transaction final, drop the others under its nonce and apply
+/// its effects
+fn finalize(record: &TxRecord, status: TxStatus) {
+    set_status(&record.hash, status);
+
+    let siblings = STATE.with(|state| {
+        state
+            .borrow()
+            .transactions
+            .same_nonce(record.chain_id, &record.from, record.nonce)
+    });
+    for sibling in siblings
+        .iter()
+        .filter(|s| s.hash != record.hash && s.status.is_tracked())
+    {
+        set_status(&sibling.hash, TxStatus::Dropped);
+    }
+
+    let Some(mined) = STATE.with(|state| state.borrow().transactions.get_by_hash(&record.hash))
+    else {
+        return;
+    };
+
+    ic_cdk::println!(
+        "📬 Transaction {} {:?} ({} confirmations)",
+        mined.hash,
+        status,
+        mined.confirmations
+    );
+
+    if status == TxStatus::Confirmed {
+        apply_effects(&mined, &siblings);
+    }
+}
+
+/// Transactions without a receipt: Dropped if their nonce was consumed by
+/// something else, TimedOut if pending for too long
+async fn detect_dropped_and_timed_out(unmined: Vec<TxRecord>, timeout_secs: u64) {
+    let now = ic_cdk::api::time();
+    let timeout = timeout_secs.saturating_mul(NANOS_PER_SEC);
+
+    for record in &unmined {
+        if record.status == TxStatus::Pending && now.saturating_sub(record.created_at) > timeout
+        {
+            ic_cdk::println!("   ⌛ Transaction {} timed out without a receipt", record.hash);
+
+            // The replaced transactions under this nonce stop being polled too
+            let siblings = STATE.with(|state| {
+                state
+                    .borrow()
+                    .transactions
+                    .same_nonce(record.chain_id, &record.from, record.nonce)
+            });
+            for sibling in siblings
+                .iter()
+                .filter(|s| s.status.is_tracked() && s.receipt.is_none())
+            {
+                set_status(&sibling.hash, TxStatus::TimedOut);
+            }
+        }
+    }
+
+    // Reconcile once per chain and sender
+    let mut senders: Vec<(u64, String)> = unmined
+        .iter()
+        .map(|r| (r.chain_id, r.from.to_lowercase()))
+        .collect();
+    senders.sort();
+    senders.dedup();
+
+    for (chain_id, from) in senders {
+        let latest = match nonce::reconcile(chain_id, &from).await {
+            Ok(report) => report.chain_latest.unwrap_or(0),
+            Err(e) => {
+                ic_cdk::println!("⚠️  Receipt tracker: {}", e);
+                continue;
+            }
+        };
+
+        for record in unmined.iter().filter(|r| {
+            r.chain_id == chain_id && r.from.eq_ignore_ascii_case(&from) && r.nonce < latest
+        }) {
+            // Skip records that already timed out, and nonces where a sibling
+            // is mined (it decides the outcome)
+            let settled = STATE.with(|state| {
+                state
+                    .borrow()
+                    .transactions
+                    .same_nonce(record.chain_id, &record.from, record.nonce)
+                    .iter()
+                    .any(|s| {
+                        s.receipt.is_some() || (s.hash == record.hash && !s.status.is_tracked())
+                    })
+            });
+            if settled {
+                continue;
+            }
+
+            // The nonce may have been mined between the two queries
+            if let Ok(None) = fetch_receipt(chain_id, &record.hash).await {
+                ic_cdk::println!("   🗑️  Transaction {} was dropped", record.hash);
+                set_status(&record.hash, TxStatus::Dropped);
+            }
+        }
+    }
+}
+
+// ==============================================================================
+// Effects of Confirmed Transactions
+// ==============================================================================
+
+fn apply_effects(mined: &TxRecord, siblings: &[TxRecord]) {
+    let Some(receipt) = &mined.receipt else {
+        return;
+    };
+
+    match mined.kind {
+        TxKind::MintAndRegisterIp
+        | TxKind::MintAndRegisterIpAndAttachPilTerms
+        | TxKind::MintAndRegisterIpAndMakeDerivative => {
+            let parsed = networks::get(mined.chain_id).ok().and_then(|network| {
+                story_util::parse_mint_and_register_return_values(receipt, &network)
+            });
+            let Some((ip_id, token_id)) = parsed else {
+                ic_cdk::println!("   ⚠️  Could not parse ipId / tokenId from {}", mined.hash);
+                return;
+            };
+
+            // Records and jobs reference the hash that was originally returned,
+            // which may be any transaction under this nonce
+            let hashes: Vec<String> = siblings.iter().map(|s| s.hash.to_lowercase()).collect();
+
+            let record_id = STATE.with(|state| {
+                state.borrow_mut().registry.apply_story_result(
+                    &hashes,
+                    &mined.hash,
+                    &ip_id,
+                    token_id,
+                )
+            });
+            jobs::apply_story_result(&hashes, &mined.hash, &ip_id, token_id);
+
+            match record_id {
+                Some(id) => ic_cdk::println!(
+                    "   🗂️  Record #{} updated: ipId {}, tokenId {}",
+                    id,
+                    ip_id,
+                    token_id
+                ),
+                None => ic_cdk::println!("   ℹ️  No provenance record references {}", mined.hash),
+            }
+        }
+        TxKind::MintLicenseTokens => {
+            let token_ids = networks::get(mined.chain_id)
+                .and_then(|network| network.licensing_module())
+                .ok()
+                .and_then(|module| events::find_license_tokens_minted(receipt, module));
+            let Some(token_ids) = token_ids else {
+                ic_cdk::println!("   ⚠️  Could not parse license token IDs from {}", mined.hash);
+                return;
+            };
+
+            let hashes: Vec<String> = siblings.iter().map(|s| s.hash.to_lowercase()).collect();
+            let record_id = STATE.with(|state| {
+                state
+                    .borrow_mut()
+                    .registry
+                    .apply_license_tokens(&hashes, &mined.hash, token_ids.clone())
+            });
+            match record_id {
+                Some(id) => ic_cdk::println!(
+                    "   🎫 Record #{}: license tokens {:?} minted",
+                    id,
+                    token_ids
+                ),
+                None => ic_cdk::println!("   ℹ️  No issued license references {}", mined.hash),
+            }
+        }
+        TxKind::PayRoyalty | TxKind::ClaimRevenue => {
+            let hashes: Vec<String> = siblings.iter().map(|s| s.hash.to_lowercase()).collect();
+            let ip_id = STATE.with(|state| {
+                state
+                    .borrow_mut()
+                    .royalties
+                    .confirm(&hashes, &mined.hash, |entry| {
+                        royalties::claimed_amount(receipt, entry)
+                    })
+            });
+            match ip_id {
+                Some(ip_id) => ic_cdk::println!("   💰 Royalty ledger of {} updated", ip_id),
+                None => ic_cdk::println!("   ℹ️  No royalty entry references {}", mined.hash),
+            }
+        }
+        TxKind::RaiseDispute => {
+            let dispute_id = networks::get(mined.chain_id)
+                .and_then(|network| network.dispute_module())
+                .ok()
+                .and_then(|module| events::find_dispute_raised(receipt, module));
+            let Some(dispute_id) = dispute_id else {
+                ic_cdk::println!("   ⚠️  Could not parse the dispute ID from {}", mined.hash);
+                return;
+            };
+
+            let hashes: Vec<String> = siblings.iter().map(|s| s.hash.to_lowercase()).collect();
+            let applied = STATE.with(|state| {
+                state
+                    .borrow_mut()
+                    .disputes
+                    .apply_raised(&hashes, &mined.hash, dispute_id)
+            });
+            match applied {
+                Some(id) => ic_cdk::println!("   🚨 Dispute #{} raised", id),
+                None => ic_cdk::println!("   ℹ️  No dispute references {}", mined.hash),
+            }
+        }
+        TxKind::CancelDispute | TxKind::ResolveDispute => {
+            let status = if mined.kind == TxKind::CancelDispute {
+                disputes::DisputeStatus::Cancelled
+            } else {
+                disputes::DisputeStatus::Resolved
+            };
+
+            let hashes: Vec<String> = siblings.iter().map(|s| s.hash.to_lowercase()).collect();
+            let applied = STATE.with(|state| {
+                state
+                    .borrow_mut()
+                    .disputes
+                    .apply_settled(&hashes, &mined.hash, status)
+            });
+            match applied {
+                Some(id) => ic_cdk::println!("   ⚖️  Dispute #{} {:?}", id, status),
+                None => ic_cdk::println!("   ℹ️  No dispute references {}", mined.hash),
+            }
+        }
+        TxKind::DeployNftContract => {
+            if let Some(address) = &receipt.contract_address {
+                let stored = STATE.with(|state| {
+                    let mut state = state.borrow_mut();
+                    if state.nft_contract_address.is_none() {
+                        state.nft_contract_address = Some(address.clone());
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if stored {
+                    ic_cdk::println!("   📍 NFT contract address set to {}", address);
+                }
+            }
+        }
+        TxKind::MintNft
+        | TxKind::RegisterNftAsIp
+        | TxKind::RegisterLicenseTerms
+        | TxKind::AttachLicenseTerms
+        | TxKind::RegisterDerivative
+        | TxKind::ApproveToken
+        | TxKind::TransferIpAsset
+        | TxKind::ExecuteWithSig
+        | TxKind::Cancel => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmations_count_the_inclusion_block() {
+        assert_eq!(confirmations(100, 100), 1);
+        assert_eq!(confirmations(101, 100), 2);
+        assert_eq!(confirmations(99, 100), 1);
+    }
+}
@@ -0,0 +1,1017 @@
+// Transactions Module
+// Shared send pipeline for canister-signed Story transactions and their history
+//
+// Every transaction goes through `send`: simulation (see `simulation`), nonce
+// reservation, fee quote, signing, broadcast. The chosen gas limit and fees are stored in a persistent history
+// so operators can audit what the canister paid and why.
+//
+// A transaction stuck in the mempool blocks every later nonce. It can be
+// replaced under the same nonce with bumped fees, either re-sending the same
+// call (speed-up) or a zero-value self-transfer (cancel). Replacements are
+// linked to the transaction they replace, and a timer applies the
+// `ReplacementPolicy` automatically.
+
+use crate::config;
+use crate::evm_util::{self, EvmAccount, EvmTransaction, TxFees, TxType};
+use crate::fees::{self, FeeQuote, FeeSource};
+use crate::networks::Network;
+use crate::simulation::{self, RevertReason};
+use crate::rpc_util::BroadcastError;
+use crate::storage::{self, StableMap};
+use crate::{nonce, rpc_util, STATE};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Maximum page size for `list_transactions`
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+/// What a transaction was sent for
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxKind {
+    DeployNftContract,
+    MintNft,
+    MintAndRegisterIp,
+    /// mintAndRegisterIp plus PIL terms attachment in one workflow call
+    MintAndRegisterIpAndAttachPilTerms,
+    /// mintAndRegisterIp as a derivative of a parent IP in one workflow call
+    MintAndRegisterIpAndMakeDerivative,
+    RegisterNftAsIp,
+    /// PILicenseTemplate.registerLicenseTerms
+    RegisterLicenseTerms,
+    /// LicensingModule.attachLicenseTerms
+    AttachLicenseTerms,
+    /// LicensingModule.registerDerivative
+    RegisterDerivative,
+    /// LicensingModule.mintLicenseTokens
+    MintLicenseTokens,
+    /// ERC-20 approve (fees pulled by Story modules)
+    ApproveToken,
+    /// RoyaltyModule.payRoyaltyOnBehalf
+    PayRoyalty,
+    /// RoyaltyWorkflows.claimAllRevenue
+    ClaimRevenue,
+    /// DisputeModule.raiseDispute
+    RaiseDispute,
+    /// DisputeModule.cancelDispute
+    CancelDispute,
+    /// DisputeModule.resolveDispute
+    ResolveDispute,
+    /// ERC-721 safeTransferFrom of an IP Asset's NFT
+    TransferIpAsset,
+    /// IP Account call relayed with its owner's EIP-712 signature
+    ExecuteWithSig,
+    /// Zero-value self-transfer replacing a stuck transaction
+    Cancel,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TxStatus {
+    /// Broadcast; not known to be mined
+    #[default]
+    Pending,
+    /// Superseded by a speed-up or cancel under the same nonce (may still be
+    /// the one that gets mined)
+    Replaced,
+    /// Included in a block, waiting for the confirmation depth
+    Mined,
+    /// Succeeded and reached the confirmation depth
+    Confirmed,
+    /// Reverted and reached the confirmation depth
+    Failed,
+    /// Its nonce was consumed by another transaction
+    Dropped,
+    /// No receipt within the tracking timeout
+    TimedOut,
+}
+
+impl TxStatus {
+    /// Still polled by the receipt tracker
+    pub fn is_tracked(&self) -> bool {
+        matches!(self, TxStatus::Pending | TxStatus::Replaced | TxStatus::Mined)
+    }
+}
+
+/// Event log from a transaction receipt
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TxLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    /// 0x-prefixed hex
+    pub data: String,
+    pub log_index: u64,
+}
+
+/// Typed `eth_getTransactionReceipt` result
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TxReceipt {
+    /// `status == 0x1`
+    pub success: bool,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub gas_used: u64,
+    pub effective_gas_price: Option<u64>,
+    /// Set for contract creations
+    pub contract_address: Option<String>,
+    pub logs: Vec<TxLog>,
+}
+
+impl TxReceipt {
+    /// Parse a receipt object returned by the RPC
+    pub fn from_json(receipt: &serde_json::Value) -> Result<Self, String> {
+        let quantity = |field: &str| {
+            receipt
+                .get(field)
+                .ok_or_else(|| format!("Missing {} in receipt", field))
+                .and_then(rpc_util::parse_quantity)
+        };
+        let text = |value: &serde_json::Value, field: &str| {
+            value
+                .get(field)
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+        };
+
+        let logs = receipt
+            .get("logs")
+            .and_then(|logs| logs.as_array())
+            .map(|logs| {
+                logs.iter()
+                    .map(|log| TxLog {
+                        address: text(log, "address").unwrap_or_default().to_lowercase(),
+                        topics: log
+                            .get("topics")
+                            .and_then(|topics| topics.as_array())
+                            .map(|topics| {
+                                topics
+                                    .iter()
+                                    .filter_map(|topic| topic.as_str())
+                                    .map(|topic| topic.to_lowercase())
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        data: text(log, "data").unwrap_or_else(|| "0x".to_string()),
+                        log_index: log
+                            .get("logIndex")
+                            .and_then(|index| rpc_util::parse_quantity(index).ok())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(TxReceipt {
+            success: quantity("status")? == 1,
+            block_number: quantity("blockNumber")?,
+            block_hash: text(receipt, "blockHash").unwrap_or_default(),
+            gas_used: quantity("gasUsed")?,
+            effective_gas_price: quantity("effectiveGasPrice").ok(),
+            contract_address: text(receipt, "contractAddress").map(|a| a.to_lowercase()),
+            logs,
+        })
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplacementKind {
+    /// Same call, higher fees
+    SpeedUp,
+    /// Zero-value self-transfer, higher fees
+    Cancel,
+}
+
+/// A broadcast transaction and the fees chosen for it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TxRecord {
+    pub id: u64,
+    pub hash: String,
+    pub kind: TxKind,
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub from: String,
+    /// Chain-Key account that signed (and re-signs replacements)
+    #[serde(default)]
+    pub account: EvmAccount,
+    /// None for contract creation
+    pub to: Option<String>,
+    pub value: u64,
+    pub tx_type: TxType,
+    pub gas_limit: u64,
+    pub gas_estimate: Option<u64>,
+    pub gas_limit_source: FeeSource,
+    pub fees: TxFees,
+    pub base_fee_per_gas: Option<u64>,
+    pub fee_source: FeeSource,
+    /// IC time (nanoseconds) of the broadcast
+    pub created_at: u64,
+    /// Calldata / init code (0x-prefixed hex), kept for speed-ups
+    #[serde(default)]
+    pub data: String,
+    #[serde(default)]
+    pub status: TxStatus,
+    /// Hash of the transaction this one replaced
+    #[serde(default)]
+    pub replaces: Option<String>,
+    /// Hash of the transaction that replaced this one
+    #[serde(default)]
+    pub replaced_by: Option<String>,
+    /// How this transaction replaced its predecessor
+    #[serde(default)]
+    pub replacement: Option<ReplacementKind>,
+    /// Number of replacements before this one under the same nonce
+    #[serde(default)]
+    pub replacement_count: u32,
+    /// Receipt once mined (see `tracker`)
+    #[serde(default)]
+    pub receipt: Option<TxReceipt>,
+    /// Blocks on top of (and including) the receipt's block at the last poll
+    #[serde(default)]
+    pub confirmations: u64,
+    /// IC time (nanoseconds) the record reached a final status
+    #[serde(default)]
+    pub finalized_at: Option<u64>,
+}
+
+/// When and how stuck transactions are replaced automatically
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct ReplacementPolicy {
+    pub enabled: bool,
+    /// Age after which a pending transaction counts as stuck
+    pub stuck_after_secs: u64,
+    /// Automatic speed-ups per nonce before giving up
+    pub max_speed_ups: u32,
+    /// Cancel instead of giving up once `max_speed_ups` is reached
+    pub cancel_when_exhausted: bool,
+}
+
+impl Default for ReplacementPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            stuck_after_secs: config::DEFAULT_STUCK_TX_AFTER_SECS,
+            max_speed_ups: config::DEFAULT_MAX_SPEED_UPS,
+            cancel_when_exhausted: false,
+        }
+    }
+}
+
+/// How receipts are polled and when a transaction counts as final
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct TrackingSettings {
+    /// Blocks (including the inclusion block) before Confirmed / Failed
+    pub confirmation_depth: u64,
+    /// Pending transactions without a receipt after this long are TimedOut
+    pub timeout_secs: u64,
+}
+
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        Self {
+            confirmation_depth: config::DEFAULT_CONFIRMATION_DEPTH,
+            timeout_secs: config::DEFAULT_TX_TRACKING_TIMEOUT_SECS,
+        }
+    }
+}
+
+impl TrackingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.confirmation_depth == 0 {
+            return Err("confirmation_depth must be at least 1".to_string());
+        }
+        if self.timeout_secs == 0 {
+            return Err("timeout_secs must be non-zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TxPage {
+    pub transactions: Vec<TxRecord>,
+    pub next_cursor: Option<u64>,
+}
+
+storage::impl_json_storable!(TxRecord);
+
+/// Persistent transaction history (records live in stable memory; the ID
+/// counter and settings in the snapshot)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TxHistory {
+    next_id: u64,
+    replacement_policy: ReplacementPolicy,
+    tracking: TrackingSettings,
+}
+
+/// (chain_id, from, (nonce, record id))
+type NonceKey = (u64, [u8; 20], (u64, u64));
+
+thread_local! {
+    /// record id -> record
+    static TX_RECORDS: StableMap<u64, TxRecord> =
+        storage::stable_map(storage::TX_RECORDS_MEMORY_ID);
+    /// tx hash (lowercase) -> record id
+    static TX_BY_HASH: StableMap<String, u64> =
+        storage::stable_map(storage::TX_BY_HASH_MEMORY_ID);
+    /// ids of the records the receipt tracker still polls
+    static TX_TRACKED: StableMap<u64, ()> =
+        storage::stable_map(storage::TX_TRACKED_MEMORY_ID);
+    /// (chain_id, from, (nonce, record id)) -> tx hash
+    static TX_BY_NONCE: StableMap<NonceKey, String> =
+        storage::stable_map(storage::TX_BY_NONCE_MEMORY_ID);
+}
+
+impl TxHistory {
+    fn insert(&mut self, mut record: TxRecord) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        record.id = id;
+        TX_BY_HASH.with(|by_hash| by_hash.borrow_mut().insert(record.hash.to_lowercase(), id));
+        TX_BY_NONCE.with(|by_nonce| {
+            by_nonce.borrow_mut().insert(
+                (record.chain_id, address_key(&record.from), (record.nonce, id)),
+                record.hash.clone(),
+            )
+        });
+        save(record);
+        id
+    }
+
+    /// Mark `hash` as replaced by `replacement_hash`
+    fn mark_replaced(&mut self, hash: &str, replacement_hash: &str) {
+        self.update(hash, |record| {
+            record.status = TxStatus::Replaced;
+            record.replaced_by = Some(replacement_hash.to_string());
+        });
+    }
+
+    pub fn replacement_policy(&self) -> ReplacementPolicy {
+        self.replacement_policy.clone()
+    }
+
+    pub fn set_replacement_policy(&mut self, policy: ReplacementPolicy) {
+        self.replacement_policy = policy;
+    }
+
+    pub fn tracking_settings(&self) -> TrackingSettings {
+        self.tracking.clone()
+    }
+
+    pub fn set_tracking_settings(&mut self, settings: TrackingSettings) {
+        self.tracking = settings;
+    }
+
+    /// Apply `f` to the record with `hash`
+    pub fn update<R>(&mut self, hash: &str, f: impl FnOnce(&mut TxRecord) -> R) -> Option<R> {
+        let mut record = self.get_by_hash(hash)?;
+        let result = f(&mut record);
+        save(record);
+        Some(result)
+    }
+
+    /// Records the receipt tracker still has to poll
+    pub fn tracked(&self) -> Vec<TxRecord> {
+        let ids: Vec<u64> =
+            TX_TRACKED.with(|tracked| tracked.borrow().iter().map(|(id, ())| id).collect());
+        TX_RECORDS.with(|records| {
+            let records = records.borrow();
+            ids.iter().filter_map(|id| records.get(id)).collect()
+        })
+    }
+
+    /// Every transaction sent from `from` with `nonce` on `chain_id` (an
+    /// original and its replacements)
+    pub fn same_nonce(&self, chain_id: u64, from: &str, nonce: u64) -> Vec<TxRecord> {
+        let from = address_key(from);
+        let hashes: Vec<String> = TX_BY_NONCE.with(|by_nonce| {
+            by_nonce
+                .borrow()
+                .range((chain_id, from, (nonce, 0))..=(chain_id, from, (nonce, u64::MAX)))
+                .map(|(_, hash)| hash)
+                .collect()
+        });
+        hashes.iter().filter_map(|hash| self.get_by_hash(hash)).collect()
+    }
+
+    /// Pending (not replaced) transactions broadcast before `cutoff`
+    fn pending_before(&self, cutoff: u64) -> Vec<TxRecord> {
+        self.tracked()
+            .into_iter()
+            .filter(|record| record.status == TxStatus::Pending && record.created_at < cutoff)
+            .collect()
+    }
+
+    pub fn get_by_hash(&self, hash: &str) -> Option<TxRecord> {
+        TX_BY_HASH
+            .with(|by_hash| by_hash.borrow().get(&hash.to_lowercase()))
+            .and_then(|id| TX_RECORDS.with(|records| records.borrow().get(&id)))
+    }
+
+    pub fn list(&self, cursor: Option<u64>, limit: u32) -> TxPage {
+        let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+        let start = cursor.map_or(0, |c| c.saturating_add(1));
+
+        let mut transactions: Vec<TxRecord> = TX_RECORDS.with(|records| {
+            records
+                .borrow()
+                .range(start..)
+                .take(limit + 1)
+                .map(|(_, record)| record)
+                .collect()
+        });
+
+        let next_cursor = if transactions.len() > limit {
+            transactions.truncate(limit);
+            transactions.last().map(|t| t.id)
+        } else {
+            None
+        };
+
+        TxPage {
+            transactions,
+            next_cursor,
+        }
+    }
+}
+
+/// Write `record` and keep it in the tracked set while its status is polled
+fn save(record: TxRecord) {
+    TX_TRACKED.with(|tracked| {
+        let mut tracked = tracked.borrow_mut();
+        if record.status.is_tracked() {
+            tracked.insert(record.id, ());
+        } else {
+            tracked.remove(&record.id);
+        }
+    });
+    TX_RECORDS.with(|records| records.borrow_mut().insert(record.id, record));
+}
+
+/// `from` as a fixed-size key (records always hold an address derived by the
+/// canister, so it parses)
+fn address_key(from: &str) -> [u8; 20] {
+    parse_address(from).unwrap_or_default()
+}
+
+// ==============================================================================
+// Send Pipeline
+// ==============================================================================
+
+/// Why `send` did not broadcast a transaction
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TxError {
+    /// The simulation reverted; nothing was signed or broadcast
+    WouldRevert(RevertReason),
+    Failed(String),
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::WouldRevert(reason) => write!(f, "Transaction would revert: {}", reason),
+            TxError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<String> for TxError {
+    fn from(e: String) -> Self {
+        TxError::Failed(e)
+    }
+}
+
+impl From<TxError> for String {
+    fn from(e: TxError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Sign and broadcast a transaction from the EVM address of `account`
+///
+/// # Arguments
+/// * `network` - Network to send on (chain ID, RPC endpoints, envelope type)
+/// * `account` - Signing account (shared canister address or a caller's)
+/// * `kind` - Purpose of the transaction (recorded in the history)
+/// * `to` - Recipient, or None for contract creation
+/// * `data` - Calldata / init code
+/// * `value` - Value in wei
+///
+/// # Returns
+/// * `Result<String, TxError>` - Transaction hash, `WouldRevert` if the
+///   simulation reverted, or another error
+pub async fn send(
+    network: &Network,
+    account: EvmAccount,
+    kind: TxKind,
+    to: Option<[u8; 20]>,
+    data: Vec<u8>,
+    value: u64,
+) -> Result<String, TxError> {
+    let from = evm_util::get_evm_address(&account).await?;
+
+    let chain_id = network.chain_id;
+
+    // Dry-run first: a revert costs nothing here and blocks no nonce
+    simulation::simulate(chain_id, &from, to.as_ref(), &data, value).await?;
+
+    // Reserve a nonce locally so concurrent sends never collide
+    let nonce = nonce::reserve(chain_id, &from).await?;
+    ic_cdk::println!("      Nonce (reserved): {} on {}", nonce, network.name);
+
+    let tx_type = network.tx_type;
+    let data_hex = format!("0x{}", hex::encode(&data));
+    let (signed_tx, quote) =
+        match sign(chain_id, &account, &from, nonce, tx_type, to, data, value).await {
+            Ok(signed) => signed,
+            Err(e) => {
+                nonce::release(chain_id, &from, nonce);
+                return Err(e.into());
+            }
+        };
+
+    // Only a definitive rejection frees the nonce. After an ambiguous error the
+    // transaction may be in the pool, so it is tracked under its own hash and
+    // reconciliation releases the nonce if the chain never saw it.
+    let hash = match rpc_util::send_raw_transaction(chain_id, &signed_tx).await {
+        Ok(hash) => hash,
+        Err(BroadcastError::Rejected(e)) => {
+            nonce::release(chain_id, &from, nonce);
+            return Err(e.into());
+        }
+        Err(BroadcastError::Unknown(e)) => {
+            let hash = evm_util::transaction_hash(&signed_tx);
+            ic_cdk::println!("   ⚠️  {}; tracking {} as pending", e, hash);
+            hash
+        }
+    };
+    nonce::mark_broadcast(chain_id, &from, nonce, &hash);
+
+    let record = TxRecord {
+        id: 0,
+        hash: hash.clone(),
+        kind,
+        chain_id,
+        nonce,
+        from,
+        account,
+        to: to.map(|to| format!("0x{}", hex::encode(to))),
+        value,
+        tx_type,
+        gas_limit: quote.gas_limit,
+        gas_estimate: quote.gas_estimate,
+        gas_limit_source: quote.gas_limit_source,
+        fees: quote.fees,
+        base_fee_per_gas: quote.base_fee_per_gas,
+        fee_source: quote.fee_source,
+        created_at: ic_cdk::api::time(),
+        data: data_hex,
+        status: TxStatus::Pending,
+        replaces: None,
+        replaced_by: None,
+        replacement: None,
+        replacement_count: 0,
+        receipt: None,
+        confirmations: 0,
+        finalized_at: None,
+    };
+    STATE.with(|state| state.borrow_mut().transactions.insert(record));
+
+    Ok(hash)
+}
+
+/// Estimate fees and sign a transaction with an already reserved nonce
+#[allow(clippy::too_many_arguments)]
+async fn sign(
+    chain_id: u64,
+    account: &EvmAccount,
+    from: &str,
+    nonce: u64,
+    tx_type: TxType,
+    to: Option<[u8; 20]>,
+    data: Vec<u8>,
+    value: u64,
+) -> Result<(Vec<u8>, FeeQuote), String> {
+    // Estimate gas and choose fees for the chain's envelope type
+    let quote = fees::quote(chain_id, tx_type, from, to.as_ref(), &data, value).await;
+
+    let tx = EvmTransaction {
+        chain_id,
+        nonce,
+        gas_limit: quote.gas_limit,
+        to,
+        value,
+        data,
+        fees: quote.fees,
+        access_list: vec![],
+    };
+
+    // Sign with Chain-Key ECDSA
+    let signed_tx = evm_util::sign_transaction(&tx, account).await?;
+
+    Ok((signed_tx, quote))
+}
+
+// ==============================================================================
+// Replacement (Speed-up / Cancel)
+// ==============================================================================
+
+fn parse_address(address: &str) -> Result<[u8; 20], String> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("Invalid address length: {}", address))
+}
+
+/// Re-sign and rebroadcast the nonce of a pending transaction with bumped fees
+///
+/// # Arguments
+/// * `hash` - Hash of the pending transaction to replace
+/// * `kind` - Speed-up (same call) or cancel (zero-value self-transfer)
+///
+/// # Returns
+/// * `Result<TxRecord, String>` - Record of the replacement transaction or error
+pub async fn replace(hash: &str, kind: ReplacementKind) -> Result<TxRecord, String> {
+    let original = STATE
+        .with(|state| state.borrow().transactions.get_by_hash(hash))
+        .ok_or_else(|| format!("Unknown transaction: {}", hash))?;
+
+    if original.status != TxStatus::Pending {
+        return Err(format!(
+            "Transaction {} was already replaced by {}",
+            hash,
+            original.replaced_by.as_deref().unwrap_or("unknown")
+        ));
+    }
+
+    // Claimed before the first await so that the monitor and an operator never
+    // replace the same transaction concurrently
+    let _guard = ReplacementGuard::acquire(&original.hash)
+        .ok_or_else(|| format!("Transaction {} is already being replaced", hash))?;
+
+    let account = original.account;
+    let from = evm_util::get_evm_address(&account).await?;
+    if !original.from.eq_ignore_ascii_case(&from) {
+        return Err(format!(
+            "Transaction {} was not sent from the address of {:?} ({})",
+            hash, account, from
+        ));
+    }
+
+    // A nonce below the chain's `latest` count is already mined
+    let report = nonce::reconcile(original.chain_id, &from).await?;
+    if report.chain_latest.is_some_and(|latest| original.nonce < latest) {
+        return Err(format!(
+            "Nonce {} of transaction {} is already mined",
+            original.nonce, hash
+        ));
+    }
+
+    // Bump the previous fees, but never pay less than the market
+    let settings = fees::settings();
+    let (market, base_fee_per_gas, fee_source) =
+        fees::current_fees(original.chain_id, original.tx_type, &settings).await;
+    let new_fees = fees::replacement_fees(&original.fees, &market, &settings)?;
+
+    let (tx_kind, to, data, value, gas_limit) = match kind {
+        ReplacementKind::SpeedUp => (
+            original.kind,
+            original.to.as_deref().map(parse_address).transpose()?,
+            hex::decode(original.data.trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid stored calldata: {}", e))?,
+            original.value,
+            original.gas_limit,
+        ),
+        ReplacementKind::Cancel => (
+            TxKind::Cancel,
+            Some(parse_address(&from)?),
+            vec![],
+            0,
+            config::TRANSFER_GAS_LIMIT,
+        ),
+    };
+
+    ic_cdk::println!(
+        "   🔁 {:?} of {} (nonce {}): {:?} -> {:?}",
+        kind,
+        hash,
+        original.nonce,
+        original.fees,
+        new_fees
+    );
+
+    let tx = EvmTransaction {
+        chain_id: original.chain_id,
+        nonce: original.nonce,
+        gas_limit,
+        to,
+        value,
+        data: data.clone(),
+        fees: new_fees,
+        access_list: vec![],
+    };
+
+    let signed_tx = evm_util::sign_transaction(&tx, &account).await?;
+
+    // As in `send`, only a definitive rejection means nothing reached the pool
+    let new_hash = match rpc_util::send_raw_transaction(original.chain_id, &signed_tx).await {
+        Ok(hash) => hash,
+        Err(BroadcastError::Rejected(e)) => return Err(e),
+        Err(BroadcastError::Unknown(e)) => {
+            let hash = evm_util::transaction_hash(&signed_tx);
+            ic_cdk::println!("   ⚠️  {}; tracking {} as pending", e, hash);
+            hash
+        }
+    };
+
+    nonce::mark_broadcast(original.chain_id, &from, original.nonce, &new_hash);
+
+    let mut record = TxRecord {
+        id: 0,
+        hash: new_hash.clone(),
+        kind: tx_kind,
+        chain_id: original.chain_id,
+        nonce: original.nonce,
+        from,
+        account,
+        to: to.map(|to| format!("0x{}", hex::encode(to))),
+        value,
+        tx_type: original.tx_type,
+        gas_limit,
+        gas_estimate: None,
+        gas_limit_source: original.gas_limit_source,
+        fees: new_fees,
+        base_fee_per_gas,
+        fee_source,
+        created_at: ic_cdk::api::time(),
+        data: format!("0x{}", hex::encode(&data)),
+        status: TxStatus::Pending,
+        replaces: Some(original.hash.clone()),
+        replaced_by: None,
+        replacement: Some(kind),
+        replacement_count: original.replacement_count + 1,
+        receipt: None,
+        confirmations: 0,
+        finalized_at: None,
+    };
+
+    record.id = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.transactions.mark_replaced(&original.hash, &new_hash);
+        state.transactions.insert(record.clone())
+    });
+
+    ic_cdk::println!("   ✅ Replacement broadcast: {}", new_hash);
+
+    Ok(record)
+}
+
+thread_local! {
+    /// Hashes (lowercase) of transactions a `replace` call is working on
+    static REPLACING: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Marks a transaction as being replaced. Released on drop, including when the
+/// call context traps.
+struct ReplacementGuard {
+    hash: String,
+}
+
+impl ReplacementGuard {
+    fn acquire(hash: &str) -> Option<Self> {
+        let hash = hash.to_lowercase();
+        REPLACING.with(|replacing| {
+            if replacing.borrow_mut().insert(hash.clone()) {
+                Some(ReplacementGuard { hash })
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl Drop for ReplacementGuard {
+    fn drop(&mut self) {
+        REPLACING.with(|replacing| {
+            replacing.borrow_mut().remove(&self.hash);
+        });
+    }
+}
+
+// ==============================================================================
+// Stuck Transaction Monitor
+// ==============================================================================
+
+thread_local! {
+    /// Set while a monitor pass is running so that passes never overlap
+    static MONITOR_RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Clears `MONITOR_RUNNING` on drop, including when the call context traps
+struct MonitorGuard;
+
+impl MonitorGuard {
+    fn acquire() -> Option<Self> {
+        MONITOR_RUNNING.with(|running| {
+            if running.replace(true) {
+                None
+            } else {
+                Some(MonitorGuard)
+            }
+        })
+    }
+}
+
+impl Drop for MonitorGuard {
+    fn drop(&mut self) {
+        MONITOR_RUNNING.with(|running| running.set(false));
+    }
+}
+
+pub fn start_timers() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config::TX_MONITOR_INTERVAL_SECS),
+        || ic_cdk::spawn(monitor_stuck_transactions()),
+    );
+}
+
+/// Speed up (or cancel) pending transactions older than the policy threshold
+async fn monitor_stuck_transactions() {
+    let Some(_guard) = MonitorGuard::acquire() else {
+        return;
+    };
+
+    let (policy, stuck) = STATE.with(|state| {
+        let state = state.borrow();
+        let policy = state.transactions.replacement_policy();
+        let cutoff = ic_cdk::api::time()
+            .saturating_sub(policy.stuck_after_secs.saturating_mul(NANOS_PER_SEC));
+        (policy, state.transactions.pending_before(cutoff))
+    });
+
+    if !policy.enabled || stuck.is_empty() {
+        return;
+    }
+
+    // Transactions whose nonce is below `latest` are mined, not stuck
+    let mut latest_by_sender: BTreeMap<(u64, String), u64> = BTreeMap::new();
+
+    for record in stuck {
+        let sender = (record.chain_id, record.from.to_lowercase());
+        let latest = match latest_by_sender.get(&sender) {
+            Some(latest) => *latest,
+            None => match nonce::reconcile(record.chain_id, &record.from).await {
+                Ok(report) => {
+                    let latest = report.chain_latest.unwrap_or(0);
+                    latest_by_sender.insert(sender, latest);
+                    latest
+                }
+                Err(e) => {
+                    ic_cdk::println!("⚠️  Transaction monitor: {}", e);
+                    continue;
+                }
+            },
+        };
+        if record.nonce < latest {
+            continue;
+        }
+
+        let kind = if record.replacement_count < policy.max_speed_ups {
+            ReplacementKind::SpeedUp
+        } else if policy.cancel_when_exhausted && record.kind != TxKind::Cancel {
+            ReplacementKind::Cancel
+        } else {
+            continue;
+        };
+
+        ic_cdk::println!(
+            "⏰ Transaction {} (nonce {}) is stuck; sending {:?}",
+            record.hash,
+            record.nonce,
+            kind
+        );
+
+        if let Err(e) = replace(&record.hash, kind).await {
+            ic_cdk::println!("   ❌ Replacement failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FROM: &str = "0x00000000000000000000000000000000000000aa";
+
+    fn record(hash: &str, nonce: u64, created_at: u64) -> TxRecord {
+        TxRecord {
+            id: 0,
+            hash: hash.to_string(),
+            kind: TxKind::MintAndRegisterIp,
+            chain_id: 1315,
+            nonce,
+            from: FROM.to_string(),
+            account: EvmAccount::Canister,
+            to: None,
+            value: 0,
+            tx_type: TxType::Eip1559,
+            gas_limit: 21_000,
+            gas_estimate: None,
+            gas_limit_source: FeeSource::Estimated,
+            fees: TxFees::Legacy { gas_price: 1 },
+            base_fee_per_gas: None,
+            fee_source: FeeSource::Estimated,
+            created_at,
+            data: "0x".to_string(),
+            status: TxStatus::Pending,
+            replaces: None,
+            replaced_by: None,
+            replacement: None,
+            replacement_count: 0,
+            receipt: None,
+            confirmations: 0,
+            finalized_at: None,
+        }
+    }
+
+    fn hashes(records: &[TxRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.hash.as_str()).collect()
+    }
+
+    #[test]
+    fn tracked_records_and_nonce_siblings_come_from_the_indexes() {
+        let mut history = TxHistory::default();
+        history.insert(record("0xa", 4, 10));
+        history.insert(record("0xb", 5, 20));
+        history.insert(record("0xc", 5, 30));
+        history.mark_replaced("0xb", "0xc");
+        history.update("0xa", |record| record.status = TxStatus::Confirmed);
+
+        assert_eq!(hashes(&history.tracked()), vec!["0xb", "0xc"]);
+        assert_eq!(hashes(&history.pending_before(40)), vec!["0xc"]);
+        assert!(history.pending_before(30).is_empty());
+
+        let siblings = history.same_nonce(1315, &FROM.to_uppercase().replace("0X", "0x"), 5);
+        assert_eq!(hashes(&siblings), vec!["0xb", "0xc"]);
+        assert!(history.same_nonce(1, FROM, 5).is_empty());
+        assert_eq!(hashes(&history.same_nonce(1315, FROM, 4)), vec!["0xa"]);
+    }
+
+    #[test]
+    fn receipt_status_logs_and_addresses_are_parsed() {
+        let receipt = TxReceipt::from_json(&json!({
+            "status": "0x0",
+            "blockNumber": "0x1b4",
+            "blockHash": "0xbeef",
+            "gasUsed": "0x5208",
+            "contractAddress": null,
+            "logs": [{
+                "address": "0xABCDEF0000000000000000000000000000000001",
+                "topics": [
+                    "0xDDF252AD1BE2C89B69C2B068FC378DAA952BA7F163C4A11628F55A4DF523B3EF",
+                    "0x0000000000000000000000000000000000000000000000000000000000000000"
+                ],
+                "data": "0x",
+                "logIndex": "0x2"
+            }]
+        }))
+        .unwrap();
+
+        assert!(!receipt.success);
+        assert_eq!(receipt.block_number, 436);
+        assert_eq!(receipt.gas_used, 21_000);
+        assert_eq!(receipt.effective_gas_price, None);
+        assert_eq!(receipt.contract_address, None);
+        assert_eq!(receipt.logs[0].address, "0xabcdef0000000000000000000000000000000001");
+        assert!(receipt.logs[0].topics[0].starts_with("0xddf252ad"));
+        assert_eq!(receipt.logs[0].log_index, 2);
+    }
+
+    #[test]
+    fn receipt_without_status_is_rejected() {
+        assert!(TxReceipt::from_json(&json!({ "blockNumber": "0x1", "gasUsed": "0x1" })).is_err());
+    }
+
+    #[test]
+    fn a_transaction_is_replaced_by_one_call_at_a_time() {
+        let guard = ReplacementGuard::acquire("0xAB").unwrap();
+        assert!(ReplacementGuard::acquire("0xab").is_none());
+        assert!(ReplacementGuard::acquire("0xcd").is_some());
+
+        drop(guard);
+        assert!(ReplacementGuard::acquire("0xab").is_some());
+    }
+}
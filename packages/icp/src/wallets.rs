@@ -0,0 +1,300 @@
+// Wallet Linking Module
+// Links ICP principals to external EVM wallets proven with EIP-191 signatures
+//
+// A principal asks for a challenge naming its principal, the wallet and a
+// random nonce, signs it with `personal_sign` in the wallet, and submits the
+// signature. The recovered signer must be the wallet. Each principal has at
+// most one linked wallet and each wallet belongs to at most one principal; a
+// new proof for a wallet moves it to the new principal. Generation defaults
+// the recipient to the linked wallet.
+
+use crate::{config, evm_util, story_util, STATE};
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeMap;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// ==============================================================================
+// Data Structures
+// ==============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LinkedWallet {
+    pub principal: Principal,
+    /// EIP-55 checksummed address
+    pub address: String,
+    /// IC time (nanoseconds) the link was proven
+    pub linked_at: u64,
+}
+
+/// Outstanding challenge of one principal
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Challenge {
+    /// Lowercase address the challenge was issued for
+    address: String,
+    message: String,
+    expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct WalletLinks {
+    /// principal (text) -> pending challenge
+    challenges: BTreeMap<String, Challenge>,
+    /// principal (text) -> link
+    by_principal: BTreeMap<String, LinkedWallet>,
+    /// lowercase address -> principal (text)
+    by_address: BTreeMap<String, String>,
+}
+
+// ==============================================================================
+// Link Operations
+// ==============================================================================
+
+impl WalletLinks {
+    pub fn get(&self, principal: &Principal) -> Option<LinkedWallet> {
+        self.by_principal.get(&principal.to_text()).cloned()
+    }
+
+    pub fn get_by_address(&self, address: &str) -> Option<LinkedWallet> {
+        self.by_address
+            .get(&address.to_lowercase())
+            .and_then(|principal| self.by_principal.get(principal))
+            .cloned()
+    }
+
+    fn issue(&mut self, principal: &Principal, challenge: Challenge, now: u64) {
+        self.challenges.retain(|_, c| c.expires_at > now);
+        self.challenges.insert(principal.to_text(), challenge);
+    }
+
+    /// Consume the principal's challenge for `address`
+    fn take_challenge(
+        &mut self,
+        principal: &Principal,
+        address: &str,
+        now: u64,
+    ) -> Result<Challenge, String> {
+        let challenge = self
+            .challenges
+            .remove(&principal.to_text())
+            .ok_or("No wallet link challenge pending; request one first")?;
+
+        if challenge.expires_at <= now {
+            return Err("Wallet link challenge expired; request a new one".to_string());
+        }
+        if challenge.address != address.to_lowercase() {
+            return Err(format!(
+                "The pending challenge was issued for {}, not {}",
+                challenge.address, address
+            ));
+        }
+        Ok(challenge)
+    }
+
+    fn link(&mut self, wallet: LinkedWallet) {
+        let principal = wallet.principal.to_text();
+        let address = wallet.address.to_lowercase();
+
+        // One wallet per principal, one principal per wallet
+        if let Some(previous) = self.by_principal.remove(&principal) {
+            self.by_address.remove(&previous.address.to_lowercase());
+        }
+        if let Some(previous_owner) = self.by_address.insert(address, principal.clone()) {
+            self.by_principal.remove(&previous_owner);
+        }
+        self.by_principal.insert(principal, wallet);
+    }
+
+    pub fn unlink(&mut self, principal: &Principal) -> Option<LinkedWallet> {
+        let wallet = self.by_principal.remove(&principal.to_text())?;
+        self.by_address.remove(&wallet.address.to_lowercase());
+        Some(wallet)
+    }
+}
+
+// ==============================================================================
+// EIP-191
+// ==============================================================================
+
+/// Hash signed by `personal_sign`:
+/// keccak256("\x19Ethereum Signed Message:\n" ‖ len(message) ‖ message)
+pub fn personal_message_hash(message: &str) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", message.len()).as_bytes());
+    hasher.update(message.as_bytes());
+    hasher.finalize().into()
+}
+
+fn challenge_message(principal: &Principal, address: &str, nonce: &str, expires_at: u64) -> String {
+    format!(
+        "ProvenanceAI wallet link\n\nPrincipal: {}\nWallet: {}\nCanister: {}\nNonce: {}\nExpires: {}",
+        principal.to_text(),
+        address,
+        ic_cdk::id().to_text(),
+        nonce,
+        expires_at / NANOS_PER_SEC
+    )
+}
+
+// ==============================================================================
+// Public API
+// ==============================================================================
+
+/// Issue a challenge for `principal` to sign with `address`
+///
+/// # Returns
+/// * `Result<String, String>` - Message to sign with `personal_sign`
+pub async fn issue_challenge(principal: Principal, address: &str) -> Result<String, String> {
+    let address = evm_util::to_checksum_address(&evm_util::parse_eip55_address(address)?);
+
+    let (random,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("Failed to get randomness: {:?} - {}", code, msg))?;
+    let nonce = hex::encode(&random[..16]);
+
+    let now = ic_cdk::api::time();
+    let expires_at = now + config::WALLET_CHALLENGE_TTL_SECS * NANOS_PER_SEC;
+    let message = challenge_message(&principal, &address, &nonce, expires_at);
+
+    STATE.with(|state| {
+        state.borrow_mut().wallets.issue(
+            &principal,
+            Challenge {
+                address: address.to_lowercase(),
+                message: message.clone(),
+                expires_at,
+            },
+            now,
+        )
+    });
+
+    Ok(message)
+}
+
+/// Verify the signed challenge and link `address` to `principal`
+///
+/// # Arguments
+/// * `principal` - Caller linking the wallet
+/// * `address` - Wallet the challenge was issued for
+/// * `signature` - `personal_sign` signature (0x-prefixed hex, 65 bytes)
+///
+/// # Returns
+/// * `Result<LinkedWallet, String>` - The new link or error
+pub fn link(principal: Principal, address: &str, signature: &str) -> Result<LinkedWallet, String> {
+    let address_bytes = evm_util::parse_eip55_address(address)?;
+    let signature = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid signature hex: {}", e))?;
+    let now = ic_cdk::api::time();
+
+    // The challenge is consumed even if verification fails
+    let challenge =
+        STATE.with(|state| state.borrow_mut().wallets.take_challenge(&principal, address, now))?;
+
+    let hash = personal_message_hash(&challenge.message);
+    let signer = story_util::recover_signer_address(&hash, &signature)?;
+    if signer != challenge.address {
+        return Err(format!(
+            "Signature was made by {}, not by {}",
+            signer, address
+        ));
+    }
+
+    let wallet = LinkedWallet {
+        principal,
+        address: evm_util::to_checksum_address(&address_bytes),
+        linked_at: now,
+    };
+    STATE.with(|state| state.borrow_mut().wallets.link(wallet.clone()));
+
+    ic_cdk::println!("🔗 Wallet {} linked to {}", wallet.address, principal);
+    Ok(wallet)
+}
+
+/// Wallet linked to `principal`, if any
+pub fn linked_wallet(principal: &Principal) -> Option<LinkedWallet> {
+    STATE.with(|state| state.borrow().wallets.get(principal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    fn challenge(address: &str, expires_at: u64) -> Challenge {
+        Challenge {
+            address: address.to_string(),
+            message: "sign me".to_string(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn personal_message_hash_matches_ethers() {
+        // ethers.utils.hashMessage("Hello World")
+        assert_eq!(
+            hex::encode(personal_message_hash("Hello World")),
+            "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2"
+        );
+    }
+
+    #[test]
+    fn personal_sign_signatures_recover_the_wallet() {
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let key = SigningKey::from_bytes(&secret.into()).unwrap();
+
+        let hash = personal_message_hash("sign me");
+        let (signature, recovery_id) = key.sign_prehash_recoverable(&hash).unwrap();
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+
+        assert_eq!(
+            story_util::recover_signer_address(&hash, &bytes).unwrap(),
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+        );
+
+        bytes[64] = 37;
+        assert!(story_util::recover_signer_address(&hash, &bytes).is_err());
+    }
+
+    #[test]
+    fn challenges_are_single_use_and_bound_to_the_address() {
+        let mut links = WalletLinks::default();
+        let alice = Principal::from_slice(&[1]);
+
+        links.issue(&alice, challenge("0xaa", 100), 0);
+        assert!(links.take_challenge(&alice, "0xBB", 50).is_err());
+        assert!(links.take_challenge(&alice, "0xAA", 50).is_err());
+
+        links.issue(&alice, challenge("0xaa", 100), 0);
+        assert!(links.take_challenge(&alice, "0xAA", 100).is_err());
+
+        links.issue(&alice, challenge("0xaa", 100), 0);
+        assert!(links.take_challenge(&alice, "0xAA", 50).is_ok());
+    }
+
+    #[test]
+    fn a_wallet_links_to_one_principal_at_a_time() {
+        let mut links = WalletLinks::default();
+        let (alice, bob) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let wallet = |principal, address: &str| LinkedWallet {
+            principal,
+            address: address.to_string(),
+            linked_at: 0,
+        };
+
+        links.link(wallet(alice, "0xAA"));
+        links.link(wallet(alice, "0xBB"));
+        assert!(links.get_by_address("0xaa").is_none());
+
+        links.link(wallet(bob, "0xbb"));
+        assert!(links.get(&alice).is_none());
+        assert_eq!(links.get_by_address("0xBB").unwrap().principal, bob);
+
+        assert!(links.unlink(&bob).is_some());
+        assert!(links.get_by_address("0xbb").is_none());
+    }
+}
@@ -8,13 +8,12 @@ mod integration_tests {
 
     #[test]
     fn test_module_compiles() {
-        // Basic sanity test
-        assert!(true);
+        // Basic sanity test: the crate and this harness build
     }
 
     // TODO: Add Pocket IC integration tests
     // - Test canister initialization
-    // - Test get_nonce_from_blockchain with mocked HTTP
+    // - Test nonce reconciliation with mocked HTTP
     // - Test full flow: generate -> deploy NFT -> register IP -> log Constellation
 }
 
@@ -56,7 +55,7 @@ mod nonce_tests {
 // Unit tests for Constellation payload building
 #[cfg(test)]
 mod constellation_tests {
-    use serde_json::{json, Value};
+    use serde_json::json;
 
     #[test]
     fn test_proof_of_generation_payload_structure() {
@@ -114,6 +113,6 @@ mod abi_tests {
         let params = encode(&[Token::String("test".to_string())]);
 
         // String encoding is dynamic, should have offset + length + data
-        assert!(params.len() > 0);
+        assert!(!params.is_empty());
     }
 }
@@ -1,6 +1,10 @@
 #!/bin/bash
 # Backup canister state to preserve canister ID and EVM address
 # Run this BEFORE doing any 'dfx stop' or 'make clean'
+#
+# NOTE: Canister state (owner, config, NFT address) is persisted in stable
+# memory and survives 'dfx deploy --upgrade'. This backup is only needed when
+# the local replica itself is wiped (e.g. 'dfx start --clean').
 
 set -e
 